@@ -0,0 +1,51 @@
+import ReconnectingWebSocket from 'reconnecting-websocket';
+
+/**
+ * @typedef {{
+ *   time: number,
+ *   level: string,
+ *   target: string,
+ *   spans?: string,
+ *   message: string,
+ * }} LogLine
+ */
+
+/** Lines beyond this are dropped from the top. */
+const MAX_LINES = 1000;
+
+const list = /** @type {HTMLOListElement} */(document.getElementById('logs'));
+
+const url = new URL(window.location.href);
+url.protocol = url.protocol === "https:" ? "wss" : "ws";
+url.pathname = "/logs";
+const websocket = new ReconnectingWebSocket(url.href, [], {
+  minReconnectionDelay: 500,
+  maxReconnectionDelay: 8000,
+  reconnectionDelayGrowFactor: 2,
+  maxEnqueuedMessages: 0,
+});
+
+// The server starts every connection with its recent history
+websocket.addEventListener('open', () => {
+  list.replaceChildren();
+});
+
+websocket.addEventListener('message', (event) => {
+  /** @type {LogLine} */
+  const line = JSON.parse(event.data);
+  const following = window.innerHeight + window.scrollY >= document.body.scrollHeight - 10;
+
+  const item = document.createElement('li');
+  item.className = `logs__line logs__line--${line.level.toLowerCase()}`;
+  const time = new Date(line.time).toLocaleTimeString();
+  const context = [line.level, line.spans, line.target].filter(Boolean).join(' ');
+  item.textContent = `${time} ${context}: ${line.message}`;
+  list.append(item);
+  while (list.childElementCount > MAX_LINES) {
+    list.firstElementChild?.remove();
+  }
+
+  if (following) {
+    window.scrollTo(0, document.body.scrollHeight);
+  }
+});
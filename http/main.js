@@ -54,6 +54,29 @@ function App({mock}) {
     send({ reconnect: { devices: [id] } });
   }
 
+  /**
+   * @param {string} id
+   */
+  function onRepair(id) {
+    send({ repair: { devices: [id] } });
+  }
+
+  /**
+   * @param {string} id
+   */
+  function onWake(id) {
+    send({ wake: { devices: [id] } });
+  }
+
+  /**
+   * @param {string} id
+   * @param {number} x
+   * @param {number} y
+   */
+  function onFocusPoint(id, x, y) {
+    send({ command: { devices: [id], ...ZERO_STATE, autofocus: false, focusPoint: { x, y } } });
+  }
+
   /**
    * @param {Mappings} m
    */
@@ -82,6 +105,9 @@ function App({mock}) {
           controlStates=${controlStates}
           onDisconnect=${onDisconnect}
           onReconnect=${onReconnect}
+          onRepair=${onRepair}
+          onWake=${onWake}
+          onFocusPoint=${onFocusPoint}
           buttonMapper=${buttonMapper}
         />
       `)}
@@ -98,11 +124,15 @@ function App({mock}) {
  *   controlStates: ControlStates,
  *   onDisconnect: function(string): void,
  *   onReconnect: function(string): void,
+ *   onRepair: function(string): void,
+ *   onWake: function(string): void,
+ *   onFocusPoint: function(string, number, number): void,
  *   buttonMapper: ReturnType<html>,
  * }} props
  */
-function DeviceGroup({state, groupId, deviceIds, controlStates, onDisconnect, onReconnect, buttonMapper}) {
+function DeviceGroup({state, groupId, deviceIds, controlStates, onDisconnect, onReconnect, onRepair, onWake, onFocusPoint, buttonMapper}) {
   const s = controlStates[groupId] || ZERO_STATE;
+  const [previewing, setPreviewing] = useState(/** @type {string|null} */ (null));
   return html`
     <div class="control js-control"
       data-group-id=${groupId}
@@ -153,7 +183,67 @@ function DeviceGroup({state, groupId, deviceIds, controlStates, onDisconnect, on
           const d = state.devices[id];
           return html`
             <div class="control__device">
+              ${d.thumbnail && html`
+                <img class="control__device-thumbnail" src=${thumbnailUrl(d.thumbnail)} alt="" />
+              `}
               <span class="control__device-name">${d.name}</span>
+              ${d.lens && html`
+                <span class="control__device-lens">${d.lens}</span>
+              `}
+              ${d.preview && d.connected && html`
+                <button
+                  type="button"
+                  class="control__device-preview-toggle"
+                  onClick=${() => setPreviewing(previewing === d.id ? null : d.id)}
+                  aria-pressed=${previewing === d.id}
+                  title="Show live view"
+                >
+                  Live
+                </button>
+              `}
+              ${d.position && formatAngles(d.position) && html`
+                <span class="control__device-position" title="Pan, tilt, and roll">
+                  ${formatAngles(d.position)}
+                </span>
+              `}
+              ${d.owner && html`
+                <span class="control__device-owner" title=${`Being moved by ${d.owner}`}>${d.owner}</span>
+              `}
+              ${d.reconnecting && html`
+                <span class="control__device-reconnecting">Reconnecting…</span>
+              `}
+              ${d.lockedBy && html`
+                <span class="control__device-locked" title=${`Locked by ${d.lockedBy}`}>Locked</span>
+              `}
+              ${d.calibrating && html`
+                <span class="control__device-calibrating" title="Locked to another client">Calibrating…</span>
+              `}
+              ${d.lastError && html`
+                <span
+                  class="control__device-error"
+                  title=${`${new Date(d.lastError.time).toLocaleTimeString()}: ${d.lastError.message}`}
+                >⚠</span>
+              `}
+              ${d.repairable && html`
+                <button
+                  type="button"
+                  class="control__device-repair"
+                  onClick=${() => onRepair(d.id)}
+                  title="Forget and re-pair"
+                >
+                  Re-pair
+                </button>
+              `}
+              ${d.wakeable && !d.connected && html`
+                <button
+                  type="button"
+                  class="control__device-wake"
+                  onClick=${() => onWake(d.id)}
+                  title="Wake and connect"
+                >
+                  Wake
+                </button>
+              `}
               <button
                 type="button"
                 class=${`control__device-connection ${d.connected ? 'control__device-connection--connected' : 'control__device-connection--disconnected'}`}
@@ -173,6 +263,18 @@ function DeviceGroup({state, groupId, deviceIds, controlStates, onDisconnect, on
                 />
               </button>
             </div>
+            ${d.preview && d.connected && previewing === d.id && html`
+              <img
+                class="control__device-preview"
+                src=${thumbnailUrl(d.preview)}
+                alt=${`Live view from ${d.name}`}
+                title="Tap to focus"
+                onClick=${(/** @type {MouseEvent} */ e) => {
+                  const rect = /** @type {HTMLElement} */ (e.currentTarget).getBoundingClientRect();
+                  onFocusPoint(d.id, (e.clientX - rect.left) / rect.width, (e.clientY - rect.top) / rect.height);
+                }}
+              />
+            `}
           `;
         })}
       </div>
@@ -180,6 +282,31 @@ function DeviceGroup({state, groupId, deviceIds, controlStates, onDisconnect, on
   `;
 }
 
+/**
+ * @param {{ pan?: number, tilt?: number, roll?: number }} position
+ * @returns {string}
+ */
+function formatAngles({ pan, tilt, roll }) {
+  return [pan, tilt, roll]
+    .filter((a) => a !== undefined)
+    .map((a) => `${Math.round(/** @type {number} */ (a))}°`)
+    .join(' ');
+}
+
+/**
+ * Images can't send an Authorization header, so pass on the page's token if it has one.
+ * @param {string} path
+ * @returns {string}
+ */
+function thumbnailUrl(path) {
+  const url = new URL(path, window.location.href);
+  const token = new URLSearchParams(window.location.search).get('token');
+  if (token !== null) {
+    url.searchParams.set('token', token);
+  }
+  return url.href;
+}
+
 /**
  * @returns {RawServerState|undefined}
  */
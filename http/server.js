@@ -23,16 +23,37 @@ import { EMPTY_MAPPING } from './mapping.js';
  * }} ReconnectMessage
  */
 
+/**
+ * @typedef {{
+ *   repair: { devices: string[] },
+ * }} RepairMessage
+ */
+
+/**
+ * @typedef {{
+ *   wake: { devices: string[] },
+ * }} WakeMessage
+ */
+
 /**
  * @typedef {{
  *   saveDefaultControls: Mapping[],
  * }} SaveDefaultControlsMessage
  */
 
+/**
+ * @typedef {{
+ *   pan?: number,
+ *   tilt?: number,
+ *   roll?: number,
+ * }} Axes
+ */
+
 /**
  * @typedef {Omit<ControlState, 'autofocus'> & {
  *   devices: string[],
  *   autofocus: boolean,
+ *   focusPoint?: { x: number, y: number },
  * }} Data
  */
 
@@ -44,6 +65,21 @@ import { EMPTY_MAPPING } from './mapping.js';
  *     id: string,
  *     name: string,
  *     connected: boolean,
+ *     reconnecting: boolean,
+ *     repairable: boolean,
+ *     wakeable: boolean,
+ *     telemetry?: Record<string, any>,
+ *     guidance?: { slot: number, pan?: number, tilt?: number, roll?: number, zoom?: number },
+ *     tags?: string[],
+ *     thumbnail?: string,
+ *     preview?: string,
+ *     calibrating?: boolean,
+ *     lastError?: { message: string, time: number },
+ *     trim?: { velocity?: Axes, position?: Axes },
+ *     lens?: string,
+ *     position?: { pan?: number, tilt?: number, roll?: number, zoom?: number },
+ *     owner?: string,
+ *     lockedBy?: string,
  *   }>,
  *   defaultControls?: Mapping[],
  * }} RawServerState
@@ -57,11 +93,56 @@ import { EMPTY_MAPPING } from './mapping.js';
  *     id: string,
  *     name: string,
  *     connected: boolean,
+ *     reconnecting: boolean,
+ *     repairable: boolean,
+ *     wakeable: boolean,
+ *     telemetry?: Record<string, any>,
+ *     guidance?: { slot: number, pan?: number, tilt?: number, roll?: number, zoom?: number },
+ *     tags?: string[],
+ *     thumbnail?: string,
+ *     preview?: string,
+ *     calibrating?: boolean,
+ *     lastError?: { message: string, time: number },
+ *     trim?: { velocity?: Axes, position?: Axes },
+ *     lens?: string,
+ *     position?: { pan?: number, tilt?: number, roll?: number, zoom?: number },
+ *     owner?: string,
+ *     lockedBy?: string,
  *   }>,
  *   defaultControls: Mappings|null,
  * }} ServerState
  */
 
+/**
+ * Sent instead of the whole state when only some devices have changed.
+ * @typedef {{
+ *   devicesPatch: RawServerState['devices'],
+ * }} DevicesPatch
+ */
+
+/**
+ * How a request with a `requestId` went. The UI doesn't send any, but ignores them anyway.
+ * @typedef {{
+ *   result: {
+ *     requestId: unknown,
+ *     ok: boolean,
+ *     error?: string,
+ *     devices: Record<string, {
+ *       ok: boolean,
+ *       error?: string,
+ *       kind?: 'notConnected'|'timeout'|'transport'|'protocol'|'unsupported',
+ *     }>,
+ *   },
+ * }} RequestResult
+ */
+
+/**
+ * Sent once on connecting, with the ID other clients can hand locks off to this one with.
+ * @typedef {{
+ *   client: string,
+ * }} ClientMessage
+ */
+
 /**
  * @typedef {{
  *   name: string;
@@ -72,7 +153,7 @@ import { EMPTY_MAPPING } from './mapping.js';
 /**
  * @return {{
  *   state: ServerState,
- *   send: function(CommandMessage|DisconnectMessage|ReconnectMessage|SaveDefaultControlsMessage): void,
+ *   send: function(CommandMessage|DisconnectMessage|ReconnectMessage|RepairMessage|WakeMessage|SaveDefaultControlsMessage): void,
  * }}
  */
 export function useServer() {
@@ -85,7 +166,7 @@ export function useServer() {
   const ws = useRef(/** @type {WebSocket|null} */(null));
   useEffect(() => {
     const url = new URL(window.location.href);
-    url.protocol = "ws";
+    url.protocol = url.protocol === "https:" ? "wss" : "ws";
     url.pathname = "/control";
     const websocket = new ReconnectingWebSocket(url.href, [], {
       minReconnectionDelay: 500,
@@ -96,9 +177,30 @@ export function useServer() {
 
     /** @type {string|null} */
     let instanceId = null;
+    // Render from a snapshot while the websocket is still connecting. Whatever the socket sends
+    // takes over, so a snapshot that arrives late is dropped.
+    fetchSnapshot().then((rawData) => {
+      if (rawData == null) {
+        return;
+      }
+      setState((/** @type {ServerState} */ state) =>
+        state.instance === '' ? convertRawData(rawData) : state
+      );
+    });
     websocket.addEventListener('message', (event) => {
-      /** @type {RawServerState} */
-      const rawData = JSON.parse(event.data);
+      /** @type {RawServerState|DevicesPatch|RequestResult|ClientMessage} */
+      const message = JSON.parse(event.data);
+      if ('result' in message || 'client' in message) {
+        return;
+      }
+      if ('devicesPatch' in message) {
+        setState((/** @type {ServerState} */ state) => ({
+          ...state,
+          devices: { ...state.devices, ...message.devicesPatch },
+        }));
+        return;
+      }
+      const rawData = message;
       if (instanceId == null) {
         instanceId = rawData.instance;
       } else if (instanceId !== rawData.instance) {
@@ -131,6 +233,21 @@ export function useServer() {
   };
 }
 
+/**
+ * The current state from `/api/state`, passing on the page's token like the websocket does.
+ * @returns {Promise<RawServerState|null>}
+ */
+async function fetchSnapshot() {
+  const url = new URL('/api/state', window.location.href);
+  url.search = window.location.search;
+  try {
+    const response = await fetch(url, { cache: 'no-store' });
+    return response.ok ? await response.json() : null;
+  } catch (e) {
+    return null;
+  }
+}
+
 /**
  * @param {RawServerState} rawData
  * @returns {ServerState}
@@ -171,7 +288,7 @@ export function unmapDefaultControls(groups, controls) {
  * @param {RawServerState|undefined} initialState
  * @return {{
  *   state: ServerState,
- *   send: function(CommandMessage|DisconnectMessage|ReconnectMessage|SaveDefaultControlsMessage): void,
+ *   send: function(CommandMessage|DisconnectMessage|ReconnectMessage|RepairMessage|WakeMessage|SaveDefaultControlsMessage): void,
  * }}
  */
 export function useMockServer(initialState=DEFAULT_STATE) {
@@ -191,12 +308,15 @@ export function useMockServer(initialState=DEFAULT_STATE) {
           }
         }));
       }
-      if ('reconnect' in command) {
+      if ('reconnect' in command || 'repair' in command || 'wake' in command) {
+        const devices = 'reconnect' in command ? command.reconnect.devices
+          : 'repair' in command ? command.repair.devices
+          : command.wake.devices;
         setState((/** @type {ServerState} */ state) => ({
           ...state,
           devices: {
             ...state.devices,
-            ...Object.fromEntries(command.reconnect.devices.map(id => [id, {
+            ...Object.fromEntries(devices.map(id => [id, {
               ...state.devices[id],
               connected: true,
             }]))
@@ -229,31 +349,49 @@ export const DEFAULT_STATE = {
       id: 'ronin1',
       name: 'Ronin[DJI RSC 2]',
       connected: true,
+      reconnecting: false,
+      repairable: true,
+      wakeable: false,
     },
     ronin2: {
       id: 'ronin2',
       name: 'Ronin[DJI RS 3]',
       connected: true,
+      reconnecting: false,
+      repairable: true,
+      wakeable: false,
     },
     lumix1: {
       id: 'lumix1',
       name: 'Lumix[DC-BGH1]',
       connected: true,
+      reconnecting: false,
+      repairable: false,
+      wakeable: true,
     },
     lumix2: {
       id: 'lumix2',
       name: 'Lumix[DC-BS1H]',
       connected: true,
+      reconnecting: false,
+      repairable: false,
+      wakeable: false,
     },
     lanc1: {
       id: 'lanc1',
       name: 'LANC[COM1]',
       connected: true,
+      reconnecting: false,
+      repairable: false,
+      wakeable: false,
     },
     lanc2: {
       id: 'lanc2',
       name: 'LANC[COM2]',
       connected: true,
+      reconnecting: false,
+      repairable: false,
+      wakeable: false,
     },
   },
   defaultControls: [
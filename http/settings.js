@@ -80,6 +80,10 @@ export function Settings() {
               Dark
             </label>
           </fieldset>
+          <fieldset class="settings__section">
+            <legend>Diagnostics</legend>
+            <a href="logs.html" target="_blank">Server logs</a>
+          </fieldset>
           <div class="settings__actions">
             <button type="submit">Close</button>
           </div>
@@ -0,0 +1,204 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use indexmap::IndexMap;
+
+use crate::config::Group;
+
+/// Which command sources may control each device. Sources are web clients (`token:<name>`,
+/// `user:<name>`, or `web`), server-side inputs by their `source` name, and `timecode` for
+/// timecode cues. Devices without a rule accept commands from anywhere.
+#[derive(Debug, Default)]
+pub struct Access {
+    rules: HashMap<String, HashSet<String>>,
+}
+
+impl Access {
+    /// Rules are keyed by device ID or group name. A rule for a device replaces the rules of its
+    /// groups, and a device in several restricted groups accepts any of their sources.
+    pub fn new<T>(
+        rules: &IndexMap<String, Vec<String>>,
+        groups: &[Group],
+        devices: &IndexMap<String, T>,
+    ) -> Access {
+        let mut access = Access::default();
+        for (target, sources) in rules.iter() {
+            if devices.contains_key(target) {
+                continue;
+            }
+            let Some(group) = groups.iter().find(|g| &g.name == target) else {
+                continue;
+            };
+            for device in group.devices.iter() {
+                access
+                    .rules
+                    .entry(device.clone())
+                    .or_default()
+                    .extend(sources.iter().cloned());
+            }
+        }
+        for (target, sources) in rules.iter() {
+            if devices.contains_key(target) {
+                access
+                    .rules
+                    .insert(target.clone(), sources.iter().cloned().collect());
+            }
+        }
+        access
+    }
+
+    pub fn allows(&self, source: &str, device: &str) -> bool {
+        self.rules
+            .get(device)
+            .is_none_or(|sources| sources.contains(source))
+    }
+
+    /// Drops the devices the source isn't allowed to control, returning them.
+    pub fn retain_allowed(&self, source: &str, devices: &mut Vec<String>) -> Vec<String> {
+        let (allowed, denied) = devices.drain(..).partition(|d| self.allows(source, d));
+        *devices = allowed;
+        denied
+    }
+}
+
+/// Which devices each web client is shown, for operators who should only see their own cameras.
+/// Rules are keyed by source, like `token:<name>`, and list the groups and devices it can see.
+/// Sources without a rule see everything. This only hides devices; `Access` is what stops them
+/// being controlled. It's shared with the web server, so it can be updated in place when groups
+/// change.
+#[derive(Debug, Default)]
+pub struct Visibility {
+    rules: RwLock<HashMap<String, HashSet<String>>>,
+}
+
+impl Visibility {
+    pub fn new(rules: &IndexMap<String, Vec<String>>, groups: &[Group]) -> Visibility {
+        let visibility = Visibility::default();
+        visibility.update(rules, groups);
+        visibility
+    }
+
+    pub fn update(&self, rules: &IndexMap<String, Vec<String>>, groups: &[Group]) {
+        let rules = rules
+            .iter()
+            .map(|(source, targets)| {
+                let devices = targets
+                    .iter()
+                    .flat_map(|t| match groups.iter().find(|g| &g.name == t) {
+                        Some(group) => group.devices.clone(),
+                        None => vec![t.clone()],
+                    })
+                    .collect();
+                (source.clone(), devices)
+            })
+            .collect();
+        *self.rules.write().unwrap() = rules;
+    }
+
+    pub fn shows(&self, source: &str, device: &str) -> bool {
+        self.rules
+            .read()
+            .unwrap()
+            .get(source)
+            .is_none_or(|devices| devices.contains(device))
+    }
+
+    /// Whether the source is shown a log line about `device`. Sources with a rule only see lines
+    /// about their own devices, since lines about no device in particular can still name others.
+    pub fn shows_log(&self, source: &str, device: Option<&str>) -> bool {
+        match self.rules.read().unwrap().get(source) {
+            None => true,
+            Some(devices) => device.is_some_and(|d| devices.contains(d)),
+        }
+    }
+
+    /// The groups as the source sees them, without the devices it can't see and without the
+    /// groups that leaves empty.
+    pub fn groups(&self, source: &str, groups: &[Group]) -> Vec<Group> {
+        groups
+            .iter()
+            .map(|g| Group {
+                name: g.name.clone(),
+                devices: g
+                    .devices
+                    .iter()
+                    .filter(|d| self.shows(source, d))
+                    .cloned()
+                    .collect(),
+            })
+            .filter(|g| !g.devices.is_empty())
+            .collect()
+    }
+}
+
+#[test]
+fn test_access() {
+    let groups = vec![
+        Group {
+            name: "stage".to_owned(),
+            devices: vec!["gimbal".to_owned(), "broll".to_owned()],
+        },
+        Group {
+            name: "floor".to_owned(),
+            devices: vec!["broll".to_owned(), "wide".to_owned()],
+        },
+    ];
+    let devices: IndexMap<String, ()> = ["gimbal", "broll", "wide", "spare"]
+        .into_iter()
+        .map(|id| (id.to_owned(), ()))
+        .collect();
+    let rules: IndexMap<String, Vec<String>> = serde_json::from_str(
+        r#"{
+            "stage": ["token:director"],
+            "floor": ["osc"],
+            "gimbal": ["user:op1"]
+        }"#,
+    )
+    .unwrap();
+    let access = Access::new(&rules, &groups, &devices);
+
+    assert!(access.allows("user:op1", "gimbal"));
+    assert!(!access.allows("token:director", "gimbal"));
+    assert!(access.allows("token:director", "broll"));
+    assert!(access.allows("osc", "broll"));
+    assert!(!access.allows("osc", "gimbal"));
+    assert!(!access.allows("web", "wide"));
+    assert!(access.allows("web", "spare"));
+
+    let mut devices = vec!["gimbal".to_owned(), "broll".to_owned(), "spare".to_owned()];
+    let denied = access.retain_allowed("osc", &mut devices);
+    assert_eq!(devices, vec!["broll", "spare"]);
+    assert_eq!(denied, vec!["gimbal"]);
+}
+
+#[test]
+fn test_visibility() {
+    let groups = vec![
+        Group {
+            name: "stage".to_owned(),
+            devices: vec!["gimbal".to_owned(), "broll".to_owned()],
+        },
+        Group {
+            name: "floor".to_owned(),
+            devices: vec!["broll".to_owned(), "wide".to_owned()],
+        },
+    ];
+    let rules: IndexMap<String, Vec<String>> =
+        serde_json::from_str(r#"{ "token:volunteer": ["stage"], "token:wide": ["wide"] }"#)
+            .unwrap();
+    let visibility = Visibility::new(&rules, &groups);
+
+    assert!(visibility.shows("token:volunteer", "broll"));
+    assert!(!visibility.shows("token:volunteer", "wide"));
+    assert!(visibility.shows("token:director", "wide"));
+    assert_eq!(visibility.groups("token:director", &groups), groups);
+    assert_eq!(
+        visibility.groups("token:wide", &groups),
+        vec![Group {
+            name: "floor".to_owned(),
+            devices: vec!["wide".to_owned()],
+        }]
+    );
+}
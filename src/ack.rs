@@ -0,0 +1,208 @@
+use std::fmt::Display;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::device::DeviceError;
+
+/// How a request went on one device. Errors from the device itself come with their `kind`, so
+/// that clients can e.g. tell a device that isn't connected from one that answered oddly.
+#[derive(Serialize)]
+struct Outcome {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+}
+
+impl Outcome {
+    const OK: Outcome = Outcome {
+        ok: true,
+        error: None,
+        kind: None,
+    };
+
+    fn failed(error: impl Display, kind: Option<&'static str>) -> Outcome {
+        Outcome {
+            ok: false,
+            error: Some(error.to_string()),
+            kind,
+        }
+    }
+
+    fn from_result(result: Result<(), DeviceError>) -> Outcome {
+        match result {
+            Ok(()) => Outcome::OK,
+            Err(e) => Outcome::failed(&e, Some(e.kind())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Reply {
+    request_id: Value,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    devices: IndexMap<String, Outcome>,
+}
+
+#[derive(Serialize)]
+struct ReplyMessage {
+    result: Reply,
+}
+
+struct Pending {
+    reply: Reply,
+    reply_tx: mpsc::UnboundedSender<String>,
+    sends: Vec<(String, oneshot::Receiver<Result<(), DeviceError>>)>,
+}
+
+/// Collects how a request went on each device it named, and answers the client that sent it
+/// with a `{"result": ...}` message once dropped. Answering on drop means that every way out of
+/// handling a request answers it, including the ones that give up early. Requests without an ID
+/// get `Ack::default()`, which doesn't answer anyone.
+#[derive(Default)]
+pub struct Ack(Option<Pending>);
+
+impl std::fmt::Debug for Ack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(pending) => write!(f, "Ack({})", pending.reply.request_id),
+            None => write!(f, "Ack(None)"),
+        }
+    }
+}
+
+impl Ack {
+    pub fn new(request_id: Value, reply_tx: mpsc::UnboundedSender<String>) -> Ack {
+        Ack(Some(Pending {
+            reply: Reply {
+                request_id,
+                ok: true,
+                error: None,
+                devices: IndexMap::new(),
+            },
+            reply_tx,
+            sends: vec![],
+        }))
+    }
+
+    /// Notes the devices the request is for, which count as done unless something goes wrong.
+    pub fn target<'a>(&mut self, devices: impl IntoIterator<Item = &'a String>) {
+        if let Some(pending) = &mut self.0 {
+            for device in devices {
+                pending
+                    .reply
+                    .devices
+                    .entry(device.clone())
+                    .or_insert(Outcome::OK);
+            }
+        }
+    }
+
+    /// Fails a device for a reason of webptz's own, like an access rule.
+    pub fn fail(&mut self, device: &str, error: impl Display) {
+        if let Some(pending) = &mut self.0 {
+            let outcome = Outcome::failed(error, None);
+            pending.reply.devices.insert(device.to_owned(), outcome);
+        }
+    }
+
+    /// Fails a device for something that went wrong with the device itself.
+    pub fn device_error(&mut self, device: &str, error: &DeviceError) {
+        if let Some(pending) = &mut self.0 {
+            let outcome = Outcome::from_result(Err(error.clone()));
+            pending.reply.devices.insert(device.to_owned(), outcome);
+        }
+    }
+
+    /// Fails the whole request, for problems that aren't down to any one device.
+    pub fn reject(&mut self, error: impl Display) {
+        if let Some(pending) = &mut self.0 {
+            pending.reply.error = Some(error.to_string());
+        }
+    }
+
+    /// Holds the answer until a command handed to the device's task has been sent. Commands
+    /// that were replaced by a newer one before they were sent count as done.
+    pub fn wait_for(&mut self, device: &str, sent: oneshot::Receiver<Result<(), DeviceError>>) {
+        if let Some(pending) = &mut self.0 {
+            pending.sends.push((device.to_owned(), sent));
+        }
+    }
+}
+
+impl Drop for Ack {
+    fn drop(&mut self) {
+        let Some(Pending {
+            mut reply,
+            reply_tx,
+            sends,
+        }) = self.0.take()
+        else {
+            return;
+        };
+        let send = move |mut reply: Reply| {
+            reply.ok = reply.error.is_none() && reply.devices.values().all(|o| o.ok);
+            let message = serde_json::to_string(&ReplyMessage { result: reply }).unwrap();
+            // The client may have gone away in the meantime
+            let _ = reply_tx.send(message);
+        };
+        if sends.is_empty() {
+            send(reply);
+            return;
+        }
+        tokio::spawn(async move {
+            for (device, sent) in sends {
+                let outcome = Outcome::from_result(sent.await.unwrap_or(Ok(())));
+                reply.devices.insert(device, outcome);
+            }
+            send(reply);
+        });
+    }
+}
+
+#[test]
+fn test_ack() {
+    let (reply_tx, mut reply_rx) = mpsc::unbounded_channel();
+    let devices = ["cam1".to_owned(), "cam2".to_owned()];
+    let mut ack = Ack::new(serde_json::json!(7), reply_tx.clone());
+    ack.target(&devices);
+    drop(ack);
+    assert_eq!(
+        reply_rx.try_recv().unwrap(),
+        r#"{"result":{"requestId":7,"ok":true,"devices":{"cam1":{"ok":true},"cam2":{"ok":true}}}}"#
+    );
+
+    let mut ack = Ack::new(serde_json::json!("a"), reply_tx.clone());
+    ack.target(&devices);
+    ack.device_error("cam2", &DeviceError::NotConnected);
+    drop(ack);
+    let result: Value = serde_json::from_str(&reply_rx.try_recv().unwrap()).unwrap();
+    assert_eq!(
+        result,
+        serde_json::json!({ "result": {
+            "requestId": "a",
+            "ok": false,
+            "devices": {
+                "cam1": { "ok": true },
+                "cam2": { "ok": false, "error": "not connected", "kind": "notConnected" },
+            },
+        } })
+    );
+
+    let mut ack = Ack::new(serde_json::json!("b"), reply_tx);
+    ack.reject("invalid request");
+    drop(ack);
+    let result: Value = serde_json::from_str(&reply_rx.try_recv().unwrap()).unwrap();
+    assert_eq!(result["result"]["ok"], false);
+    assert_eq!(result["result"]["error"], "invalid request");
+
+    drop(Ack::default());
+    assert!(reply_rx.try_recv().is_err());
+}
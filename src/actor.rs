@@ -0,0 +1,396 @@
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, oneshot, watch, Mutex, OwnedMutexGuard},
+    task::JoinHandle,
+    time::timeout,
+};
+use tracing::{debug, warn, Instrument as _, Span};
+
+use crate::device::{Command, Device, DeviceError, LiveView, Position};
+
+/// How long a device in a synchronized move waits for the others before sending anyway.
+const SYNC_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The last thing that went wrong with a device, so that clients can show it instead of it only
+/// ending up in the log.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ErrorReport {
+    pub message: String,
+    /// Milliseconds since the Unix epoch.
+    pub time: u64,
+}
+
+impl ErrorReport {
+    pub fn new(error: impl Display) -> ErrorReport {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        ErrorReport {
+            message: error.to_string(),
+            time: time.as_millis() as u64,
+        }
+    }
+}
+
+/// An error from a device's task, which the main loop doesn't otherwise hear about.
+pub struct TaskError {
+    pub device: String,
+    pub report: ErrorReport,
+}
+
+/// Owns a device and sends it commands from a dedicated task, so a slow device never holds up
+/// the main loop or other devices. Commands are coalesced rather than queued: a device that's
+/// still busy with a previous command only ever receives the latest one.
+///
+/// Connection status is cached so it can be reported without waiting on a busy device, and
+/// has to be refreshed with `update` after using the device directly. Anything done with the
+/// device should be instrumented with `span`, so that what the backend logs says which device
+/// it's about.
+pub struct DeviceHandle {
+    pub id: String,
+    pub name: String,
+    pub connected: bool,
+    pub repairable: bool,
+    pub wakeable: bool,
+    pub uses_bluetooth: bool,
+    pub lens: Option<String>,
+    pub position: Option<Position>,
+    pub telemetry: Map<String, Value>,
+    pub live_view: Option<LiveView>,
+    pub last_error: Option<ErrorReport>,
+    pub span: Span,
+    device: Arc<Mutex<Box<dyn Device>>>,
+    /// How far behind the first device this one's last synchronized command went out.
+    sync_skew: Skew,
+    commands: watch::Sender<Option<Dispatch>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Clone)]
+struct Dispatch {
+    command: Command,
+    sync: Arc<SyncSlot>,
+    /// Told how sending went. Dropped unused if the command is replaced before it's sent.
+    sent: Arc<std::sync::Mutex<Option<SentTx>>>,
+}
+
+type SentTx = oneshot::Sender<Result<(), DeviceError>>;
+type Skew = Arc<std::sync::Mutex<Option<Duration>>>;
+
+/// A device's place in a synchronized move, taken by its task when it gets to the command. If the
+/// command is replaced first, the place is given up so the other devices don't wait for it.
+struct SyncSlot(std::sync::Mutex<Option<Arc<SyncGroup>>>);
+
+impl SyncSlot {
+    fn take(&self) -> Option<Arc<SyncGroup>> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+impl Drop for SyncSlot {
+    fn drop(&mut self) {
+        if let Some(sync) = self.take() {
+            sync.release();
+        }
+    }
+}
+
+impl DeviceHandle {
+    pub fn spawn(
+        device: Box<dyn Device>,
+        runtime: &Handle,
+        errors: mpsc::UnboundedSender<TaskError>,
+    ) -> DeviceHandle {
+        let (commands, rx) = watch::channel(None);
+        let id = device.id();
+        let name = device.name();
+        let connected = device.is_connected();
+        let repairable = device.supports_repair();
+        let wakeable = device.supports_wake();
+        let uses_bluetooth = device.uses_bluetooth();
+        let lens = device.lens();
+        let position = device.position();
+        let telemetry = device.telemetry();
+        let live_view = device.live_view();
+        let span = crate::device::span(&id);
+        let device = Arc::new(Mutex::new(device));
+        let sync_skew = Skew::default();
+        let task = runtime
+            .spawn(run(device.clone(), rx, sync_skew.clone(), errors).instrument(span.clone()));
+        DeviceHandle {
+            id,
+            name,
+            connected,
+            repairable,
+            wakeable,
+            uses_bluetooth,
+            lens,
+            position,
+            telemetry,
+            live_view,
+            last_error: None,
+            span,
+            device,
+            sync_skew,
+            commands,
+            task,
+        }
+    }
+
+    /// Waits for the device to finish what it's currently doing and takes exclusive access.
+    pub async fn lock(&self) -> OwnedMutexGuard<Box<dyn Device>> {
+        self.device.clone().lock_owned().await
+    }
+
+    /// Takes exclusive access to the device like `lock`, after stopping it, for when the link
+    /// is about to be torn down. Any command still waiting to be sent is dropped, since it would
+    /// only be sent after the stop.
+    pub async fn stop_and_lock(&self) -> OwnedMutexGuard<Box<dyn Device>> {
+        self.commands.send_replace(None);
+        let mut device = self.lock().await;
+        if let Err(e) = device.send_stop().instrument(self.span.clone()).await {
+            warn!(device = %self.id, "Error stopping before disconnecting: {}", e);
+        }
+        device
+    }
+
+    /// Takes exclusive access to the device, unless it's busy.
+    pub fn try_lock(&self) -> Option<OwnedMutexGuard<Box<dyn Device>>> {
+        self.device.clone().try_lock_owned().ok()
+    }
+
+    /// Lets a background task follow the device's position without going through the main loop.
+    pub fn position_reader(&self) -> PositionReader {
+        PositionReader(self.device.clone())
+    }
+
+    fn is_idle(&self) -> bool {
+        self.device.try_lock().is_ok()
+    }
+
+    pub fn record_error(&mut self, error: impl Display) {
+        self.last_error = Some(ErrorReport::new(error));
+    }
+
+    /// Refreshes the cached status. Returns whether anything changed.
+    pub fn update(&mut self, device: &dyn Device) -> bool {
+        let previous = (
+            self.connected,
+            self.repairable,
+            self.wakeable,
+            self.lens.clone(),
+            self.position,
+            self.telemetry.clone(),
+        );
+        self.connected = device.is_connected();
+        self.repairable = device.supports_repair();
+        self.wakeable = device.supports_wake();
+        self.lens = device.lens();
+        self.position = device.position();
+        self.telemetry = device.telemetry();
+        if let Some(skew) = *self.sync_skew.lock().unwrap() {
+            let micros = skew.as_micros() as u64;
+            self.telemetry
+                .insert("syncSkewUs".to_owned(), micros.into());
+        }
+        previous
+            != (
+                self.connected,
+                self.repairable,
+                self.wakeable,
+                self.lens.clone(),
+                self.position,
+                self.telemetry.clone(),
+            )
+    }
+
+    fn dispatch(
+        &self,
+        command: Command,
+        sync: Option<Arc<SyncGroup>>,
+    ) -> oneshot::Receiver<Result<(), DeviceError>> {
+        let (sent_tx, sent_rx) = oneshot::channel();
+        let sent = Arc::new(std::sync::Mutex::new(Some(sent_tx)));
+        self.commands.send_replace(Some(Dispatch {
+            command,
+            sync: Arc::new(SyncSlot(std::sync::Mutex::new(sync))),
+            sent,
+        }));
+        sent_rx
+    }
+}
+
+impl Drop for DeviceHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+pub struct PositionReader(Arc<Mutex<Box<dyn Device>>>);
+
+impl PositionReader {
+    pub async fn read(&self) -> Option<Position> {
+        self.0.lock().await.position()
+    }
+}
+
+/// Hands each device's command to its task. Devices that are connected and idle prepare their
+/// commands first and then send them together, once the slowest of them is ready. Returns a
+/// receiver for each device that's told how sending went, or closed if the command was replaced
+/// by a newer one first.
+pub fn dispatch(
+    targets: &[(&DeviceHandle, Command)],
+) -> Vec<oneshot::Receiver<Result<(), DeviceError>>> {
+    let synced = targets
+        .iter()
+        .map(|(h, _)| h.connected && h.is_idle())
+        .collect_vec();
+    let participants = synced.iter().filter(|s| **s).count();
+    let sync = (participants > 1).then(|| {
+        Arc::new(SyncGroup {
+            remaining: watch::Sender::new(participants),
+            fired: std::sync::Mutex::new(vec![]),
+        })
+    });
+    targets
+        .iter()
+        .zip(synced)
+        .map(|((handle, command), synced)| {
+            handle.dispatch(*command, sync.clone().filter(|_| synced))
+        })
+        .collect()
+}
+
+async fn run(
+    device: Arc<Mutex<Box<dyn Device>>>,
+    mut commands: watch::Receiver<Option<Dispatch>>,
+    sync_skew: Skew,
+    errors: mpsc::UnboundedSender<TaskError>,
+) {
+    let report = |device: &dyn Device, error: String| {
+        let _ = errors.send(TaskError {
+            device: device.id(),
+            report: ErrorReport::new(error),
+        });
+    };
+    while commands.changed().await.is_ok() {
+        let dispatch = commands.borrow_and_update().clone();
+        let Some(dispatch) = dispatch else {
+            continue;
+        };
+        let mut device = device.lock().await;
+        if let Some(sync) = dispatch.sync.take() {
+            if let Err(e) = device.prepare_command(dispatch.command).await {
+                warn!("Error preparing command: {}", e);
+                report(&**device, e.to_string());
+            }
+            sync.wait(device.id(), &sync_skew).await;
+        }
+        let result = device.send_command(dispatch.command).await;
+        if let Err(e) = &result {
+            warn!("Error sending command: {}", e);
+            report(&**device, e.to_string());
+        }
+        let sent = dispatch.sent.lock().unwrap().take();
+        if let Some(sent) = sent {
+            let _ = sent.send(result);
+        }
+    }
+}
+
+/// Devices sending a command together, each of which waits for the others to be ready.
+struct SyncGroup {
+    /// How many devices haven't got their command ready yet.
+    remaining: watch::Sender<usize>,
+    fired: std::sync::Mutex<Vec<(String, Skew, Instant)>>,
+}
+
+impl SyncGroup {
+    async fn wait(&self, id: String, skew: &Skew) {
+        self.release();
+        let mut remaining = self.remaining.subscribe();
+        if timeout(SYNC_TIMEOUT, remaining.wait_for(|r| *r == 0))
+            .await
+            .is_err()
+        {
+            warn!("Timed out waiting for synchronized devices");
+        }
+        let fired = (id, skew.clone(), Instant::now());
+        self.fired.lock().unwrap().push(fired);
+    }
+
+    /// Counts a device as ready, or as not taking part after all.
+    fn release(&self) {
+        self.remaining.send_modify(|r| *r = r.saturating_sub(1));
+    }
+}
+
+impl Drop for SyncGroup {
+    // Only once every device has sent its command or dropped out
+    fn drop(&mut self) {
+        record_skew(self.fired.get_mut().unwrap());
+    }
+}
+
+/// Records how far apart a synchronized command actually went out to each device.
+fn record_skew(fired: &[(String, Skew, Instant)]) {
+    let Some(first) = fired.iter().map(|(_, _, t)| *t).min() else {
+        return;
+    };
+    if fired.len() < 2 {
+        return;
+    }
+    for (_, skew, t) in fired {
+        *skew.lock().unwrap() = Some(t.duration_since(first));
+    }
+    let skew = fired
+        .iter()
+        .map(|(id, _, t)| format!("{} +{}µs", id, t.duration_since(first).as_micros()))
+        .join(", ");
+    debug!("Command skew: {}", skew);
+}
+
+#[tokio::test]
+async fn test_replaced_sync_command() {
+    let (errors, _errors_rx) = mpsc::unbounded_channel();
+    let mut handles = vec![];
+    for id in ["cam1", "cam2", "cam3"] {
+        let mut device = crate::device::dummy::create_with_id_and_name(id, id);
+        device.connect().await.unwrap();
+        handles.push(DeviceHandle::spawn(
+            Box::new(device),
+            &Handle::current(),
+            errors.clone(),
+        ));
+    }
+    let targets = handles
+        .iter()
+        .map(|h| (h, Command::default()))
+        .collect_vec();
+    let started = Instant::now();
+    let mut sent = dispatch(&targets);
+    // Replaced before cam3's task gets to it, so the others shouldn't wait for it
+    let replaced = dispatch(&[(&handles[2], Command::default())]);
+    assert!(sent.pop().unwrap().await.is_err());
+    for sent in sent.into_iter().chain(replaced) {
+        sent.await.unwrap().unwrap();
+    }
+    assert!(started.elapsed() < SYNC_TIMEOUT);
+
+    for handle in handles.iter_mut() {
+        let device = handle.lock().await;
+        handle.update(&**device);
+    }
+    assert!(handles[0].telemetry.contains_key("syncSkewUs"));
+    assert!(handles[1].telemetry.contains_key("syncSkewUs"));
+    assert!(!handles[2].telemetry.contains_key("syncSkewUs"));
+}
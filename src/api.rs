@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use indexmap::IndexMap;
+use itertools::Itertools as _;
+use serde_json::Value;
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+    access::Visibility, auth::Client, expand_tags, CommandRequest, Operation, Origin,
+    ReconnectRequest, State, TAG_PREFIX,
+};
+
+/// Plain JSON routes for integrations that would rather not speak the websocket protocol.
+/// Request bodies use the same format as the corresponding websocket requests.
+pub fn router(
+    command_tx: mpsc::UnboundedSender<Operation>,
+    state_rx: watch::Receiver<State>,
+    visibility: Arc<Visibility>,
+) -> Router {
+    let devices_rx = state_rx.clone();
+    let devices_visibility = visibility.clone();
+    let command_rx = state_rx.clone();
+    let command_visibility = visibility.clone();
+    let reconnect_rx = state_rx.clone();
+    let reconnect_tx = command_tx.clone();
+    let reconnect_visibility = visibility.clone();
+    Router::new()
+        .route(
+            "/api/state",
+            get(move |client| state(state_rx, visibility, client)),
+        )
+        .route(
+            "/api/devices",
+            get(move |client| devices(devices_rx, devices_visibility, client)),
+        )
+        .route(
+            "/api/command",
+            post(move |client, body| {
+                command(command_tx, command_rx, command_visibility, client, body)
+            }),
+        )
+        .route(
+            "/api/reconnect",
+            post(move |client, body| {
+                reconnect(
+                    reconnect_tx,
+                    reconnect_rx,
+                    reconnect_visibility,
+                    client,
+                    body,
+                )
+            }),
+        )
+}
+
+/// The state, with just the groups and devices the client can see.
+async fn state(
+    state_rx: watch::Receiver<State>,
+    visibility: Arc<Visibility>,
+    client: Option<Extension<Client>>,
+) -> impl IntoResponse {
+    let source = Client::source(client);
+    let state = state_rx.borrow();
+    let mut value = serde_json::to_value(&*state).unwrap();
+    value["groups"] = serde_json::to_value(visibility.groups(&source, &state.groups)).unwrap();
+    value["devices"] = visible_devices(&state, &visibility, &source);
+    // A snapshot is stale as soon as it's sent, so it's never worth caching
+    ([(header::CACHE_CONTROL, "no-store")], Json(value))
+}
+
+async fn devices(
+    state_rx: watch::Receiver<State>,
+    visibility: Arc<Visibility>,
+    client: Option<Extension<Client>>,
+) -> Json<Value> {
+    let source = Client::source(client);
+    Json(visible_devices(&state_rx.borrow(), &visibility, &source))
+}
+
+fn visible_devices(state: &State, visibility: &Visibility, source: &str) -> Value {
+    let devices = state
+        .devices
+        .iter()
+        .filter(|(id, _)| visibility.shows(source, id))
+        .map(|(id, status)| (id.clone(), serde_json::to_value(status).unwrap()));
+    Value::Object(devices.collect())
+}
+
+async fn command(
+    command_tx: mpsc::UnboundedSender<Operation>,
+    state_rx: watch::Receiver<State>,
+    visibility: Arc<Visibility>,
+    client: Option<Extension<Client>>,
+    Json(mut request): Json<CommandRequest>,
+) -> Response {
+    let source = Client::source(client);
+    let resolved = resolve_devices(
+        &state_rx.borrow(),
+        &visibility,
+        &source,
+        &mut request.devices,
+    );
+    if let Err(message) = resolved {
+        return (StatusCode::NOT_FOUND, message).into_response();
+    }
+    send(&command_tx, Operation::Command(request), &source)
+}
+
+async fn reconnect(
+    command_tx: mpsc::UnboundedSender<Operation>,
+    state_rx: watch::Receiver<State>,
+    visibility: Arc<Visibility>,
+    client: Option<Extension<Client>>,
+    Json(mut request): Json<ReconnectRequest>,
+) -> Response {
+    let source = Client::source(client);
+    let resolved = resolve_devices(
+        &state_rx.borrow(),
+        &visibility,
+        &source,
+        &mut request.devices,
+    );
+    if let Err(message) = resolved {
+        return (StatusCode::NOT_FOUND, message).into_response();
+    }
+    send(&command_tx, Operation::Reconnect(request), &source)
+}
+
+/// Expands `tag:` entries to the devices the source can see with that tag, failing with the
+/// devices and tags that don't match any. Hidden devices are reported as unknown, so the
+/// response doesn't give away that they exist.
+fn resolve_devices(
+    state: &State,
+    visibility: &Visibility,
+    source: &str,
+    devices: &mut Vec<String>,
+) -> Result<(), String> {
+    let tags: IndexMap<String, Vec<String>> = state
+        .devices
+        .iter()
+        .filter(|(id, _)| visibility.shows(source, id))
+        .sorted_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(id, status)| (id.clone(), status.tags.clone()))
+        .collect();
+    let unknown = devices
+        .iter()
+        .filter(|d| match d.strip_prefix(TAG_PREFIX) {
+            Some(tag) => !tags.values().flatten().any(|t| t == tag),
+            None => !tags.contains_key(*d),
+        })
+        .join(", ");
+    if !unknown.is_empty() {
+        return Err(format!("unknown devices: {}", unknown));
+    }
+    expand_tags(devices, &tags);
+    Ok(())
+}
+
+fn send(
+    command_tx: &mpsc::UnboundedSender<Operation>,
+    operation: Operation,
+    source: &str,
+) -> Response {
+    let operation = operation.sent_by(&Origin::new(source));
+    match command_tx.send(operation) {
+        Ok(_) => StatusCode::ACCEPTED.into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "shutting down").into_response(),
+    }
+}
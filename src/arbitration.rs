@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{ArbitrationConfig, ArbitrationPolicy},
+    device::{Command, Velocity},
+    Origin,
+};
+
+/// A client that's moving a device.
+struct Claim {
+    client: String,
+    priority: i32,
+    command: Command,
+    time: Instant,
+}
+
+/// Decides what a device does when several clients send it commands at once. A client has a
+/// claim on a device from when it starts moving it until it stops, or until it hasn't sent a
+/// command for `holdMs`, e.g. because its tab was closed mid-move.
+pub struct Arbiter {
+    policy: ArbitrationPolicy,
+    priorities: HashMap<String, i32>,
+    hold: Duration,
+    claims: HashMap<String, Vec<Claim>>,
+}
+
+impl Arbiter {
+    pub fn new(config: &ArbitrationConfig) -> Arbiter {
+        Arbiter {
+            policy: config.policy,
+            priorities: config.priorities.clone().into_iter().collect(),
+            hold: Duration::from_millis(config.hold_ms),
+            claims: HashMap::new(),
+        }
+    }
+
+    /// The command to send the device, or the client that's keeping the sender from moving it.
+    pub fn arbitrate(
+        &mut self,
+        device: &str,
+        origin: &Origin,
+        command: Command,
+        now: Instant,
+    ) -> Result<Command, String> {
+        let priority = self.priorities.get(&origin.source).copied().unwrap_or(0);
+        let claims = self.claims.entry(device.to_owned()).or_default();
+        claims.retain(|c| now.duration_since(c.time) < self.hold);
+        if self.policy == ArbitrationPolicy::Priority {
+            let outranked = claims
+                .iter()
+                .find(|c| c.client != origin.client && c.priority > priority);
+            if let Some(claim) = outranked {
+                return Err(claim.client.clone());
+            }
+        }
+        claims.retain(|c| c.client != origin.client);
+        if is_moving(&command) {
+            claims.push(Claim {
+                client: origin.client.clone(),
+                priority,
+                command,
+                time: now,
+            });
+        }
+        match self.policy {
+            ArbitrationPolicy::Sum => Ok(Command {
+                autofocus: command.autofocus,
+                record: command.record,
+                shutter: command.shutter,
+                focus_point: command.focus_point,
+                ..sum(claims)
+            }),
+            _ => Ok(command),
+        }
+    }
+
+    /// The client whose commands the device is following. Devices moved by several clients at
+    /// once under the `sum` policy have no single owner.
+    pub fn owner(&self, device: &str) -> Option<&str> {
+        let claims = self.claims.get(device)?;
+        let claim = match self.policy {
+            ArbitrationPolicy::LastWriter => claims.last(),
+            ArbitrationPolicy::Priority => claims.iter().max_by_key(|c| c.priority),
+            ArbitrationPolicy::Sum if claims.len() == 1 => claims.first(),
+            ArbitrationPolicy::Sum => None,
+        };
+        claim.map(|c| c.client.as_str())
+    }
+
+    /// Drops the claims that have run out, returning the devices that had any.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let mut expired = vec![];
+        for (device, claims) in self.claims.iter_mut() {
+            let before = claims.len();
+            claims.retain(|c| now.duration_since(c.time) < self.hold);
+            if claims.len() != before {
+                expired.push(device.clone());
+            }
+        }
+        self.claims.retain(|_, claims| !claims.is_empty());
+        expired
+    }
+
+    /// What the device should do now that the claims on it have changed, for policies where
+    /// that depends on more than the latest command.
+    pub fn combined(&self, device: &str) -> Option<Command> {
+        match self.policy {
+            ArbitrationPolicy::Sum => {
+                Some(self.claims.get(device).map(|c| sum(c)).unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn remove(&mut self, device: &str) {
+        self.claims.remove(device);
+    }
+}
+
+fn is_moving(command: &Command) -> bool {
+    let axes = Command {
+        autofocus: false,
+        record: None,
+        shutter: false,
+        focus_point: None,
+        ..*command
+    };
+    axes != Command::default()
+}
+
+fn sum(claims: &[Claim]) -> Command {
+    let axis = |f: fn(&Command) -> Velocity| {
+        Velocity::new(claims.iter().map(|c| f(&c.command).get()).sum())
+    };
+    Command {
+        pan: axis(|c| c.pan),
+        tilt: axis(|c| c.tilt),
+        roll: axis(|c| c.roll),
+        zoom: axis(|c| c.zoom),
+        focus: axis(|c| c.focus),
+        iris: axis(|c| c.iris),
+        autofocus: false,
+        record: None,
+        shutter: false,
+        focus_point: None,
+    }
+}
+
+#[cfg(test)]
+fn arbiter(json: serde_json::Value) -> Arbiter {
+    Arbiter::new(&serde_json::from_value(json).unwrap())
+}
+
+#[cfg(test)]
+fn pan(value: f64) -> Command {
+    Command {
+        pan: Velocity::new(value),
+        ..Command::default()
+    }
+}
+
+#[test]
+fn test_last_writer() {
+    let mut arbiter = arbiter(serde_json::json!({}));
+    let a = Origin::new("osc");
+    let b = Origin::new("web");
+    let now = Instant::now();
+
+    assert_eq!(arbiter.arbitrate("cam1", &a, pan(0.5), now), Ok(pan(0.5)));
+    assert_eq!(arbiter.owner("cam1"), Some("osc"));
+    assert_eq!(arbiter.arbitrate("cam1", &b, pan(-0.5), now), Ok(pan(-0.5)));
+    assert_eq!(arbiter.owner("cam1"), Some("web"));
+
+    // Stopping gives the device back to whoever else is still moving it
+    assert_eq!(arbiter.arbitrate("cam1", &b, pan(0.0), now), Ok(pan(0.0)));
+    assert_eq!(arbiter.owner("cam1"), Some("osc"));
+    assert_eq!(arbiter.combined("cam1"), None);
+}
+
+#[test]
+fn test_priority() {
+    let mut arbiter = arbiter(serde_json::json!({
+        "policy": "priority",
+        "priorities": {"token:director": 10},
+        "holdMs": 500,
+    }));
+    let director = Origin::new("token:director");
+    let osc = Origin::new("osc");
+    let now = Instant::now();
+
+    assert_eq!(arbiter.arbitrate("cam1", &osc, pan(0.2), now), Ok(pan(0.2)));
+    assert_eq!(
+        arbiter.arbitrate("cam1", &director, pan(0.5), now),
+        Ok(pan(0.5))
+    );
+    assert_eq!(arbiter.owner("cam1"), Some("token:director"));
+    assert_eq!(
+        arbiter.arbitrate("cam1", &osc, pan(-0.5), now),
+        Err("token:director".to_owned())
+    );
+    assert_eq!(
+        arbiter.arbitrate("cam2", &osc, pan(-0.5), now),
+        Ok(pan(-0.5))
+    );
+
+    // The claim lapses once the director stops sending commands
+    let later = now + Duration::from_millis(600);
+    let mut expired = arbiter.expire(later);
+    expired.sort();
+    assert_eq!(expired, vec!["cam1", "cam2"]);
+    assert_eq!(arbiter.owner("cam1"), None);
+    assert_eq!(
+        arbiter.arbitrate("cam1", &osc, pan(-0.5), later),
+        Ok(pan(-0.5))
+    );
+}
+
+#[test]
+fn test_sum() {
+    let mut arbiter = arbiter(serde_json::json!({"policy": "sum"}));
+    let a = Origin::new("osc");
+    let b = Origin::new("web");
+    let now = Instant::now();
+
+    assert_eq!(arbiter.arbitrate("cam1", &a, pan(0.75), now), Ok(pan(0.75)));
+    assert_eq!(arbiter.owner("cam1"), Some("osc"));
+    assert_eq!(arbiter.arbitrate("cam1", &b, pan(0.5), now), Ok(pan(1.0)));
+    assert_eq!(arbiter.owner("cam1"), None);
+    assert_eq!(arbiter.arbitrate("cam1", &a, pan(0.0), now), Ok(pan(0.5)));
+    assert_eq!(arbiter.combined("cam1"), Some(pan(0.5)));
+
+    arbiter.expire(now + Duration::from_secs(1));
+    assert_eq!(arbiter.combined("cam1"), Some(Command::default()));
+}
@@ -0,0 +1,138 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension,
+};
+use axum_extra::headers::{
+    authorization::{Basic, Bearer},
+    Authorization, HeaderMapExt as _,
+};
+use tracing::info;
+
+use crate::config::AuthConfig;
+
+/// Who a request came from, as named in access rules: `token:<name>` for named tokens,
+/// `user:<name>` for basic authentication, and `web` for anyone else.
+#[derive(Clone, Debug)]
+pub struct Client(pub String);
+
+impl Client {
+    /// The source name for a request, which only has a `Client` when authentication is on.
+    pub fn source(client: Option<Extension<Client>>) -> String {
+        client.map_or_else(|| "web".to_owned(), |Extension(Client(name))| name)
+    }
+}
+
+/// Rejects requests that don't present one of the configured credentials, either as a bearer
+/// token, with basic authentication, or as a `token` query parameter (which is the only option
+/// for browser websockets).
+pub async fn require(
+    State(auth): State<Arc<AuthConfig>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(client) = authorize(&auth, &request) {
+        request.extensions_mut().insert(client);
+        return next.run(request).await;
+    }
+    info!(
+        "Rejected unauthenticated request for {}",
+        request.uri().path()
+    );
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"webptz\"")],
+    )
+        .into_response()
+}
+
+fn authorize(auth: &AuthConfig, request: &Request) -> Option<Client> {
+    let headers = request.headers();
+    if let Some(Authorization(bearer)) = headers.typed_get::<Authorization<Bearer>>() {
+        return check_token(auth, bearer.token());
+    }
+    if let Some(Authorization(basic)) = headers.typed_get::<Authorization<Basic>>() {
+        return auth
+            .users
+            .get(basic.username())
+            .filter(|password| constant_time_eq(password, basic.password()))
+            .map(|_| Client(format!("user:{}", basic.username())));
+    }
+    Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(query)| query.get("token").cloned())
+        .and_then(|token| check_token(auth, &token))
+}
+
+pub fn check_token(auth: &AuthConfig, token: &str) -> Option<Client> {
+    // Every token is checked so that timing doesn't reveal which one nearly matched
+    let tokens = auth
+        .tokens
+        .iter()
+        .map(|t| (None, t))
+        .chain(auth.named_tokens.iter().map(|(name, t)| (Some(name), t)));
+    tokens.fold(None, |client, (name, t)| match constant_time_eq(t, token) {
+        true => Some(match name {
+            Some(name) => Client(format!("token:{}", name)),
+            None => Client("web".to_owned()),
+        }),
+        false => client,
+    })
+}
+
+/// Compares secrets without bailing out at the first difference, so response times don't leak
+/// how much of a guess was right.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+#[test]
+fn test_authorize() {
+    let auth = AuthConfig {
+        tokens: vec!["s3cret".to_owned().into()],
+        named_tokens: [("lighting".to_owned(), "l1ghts".to_owned().into())].into(),
+        users: [("director".to_owned(), "hunter2".to_owned().into())].into(),
+    };
+    let client = |uri: &str, authorization: Option<&str>| {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(value) = authorization {
+            builder = builder.header(header::AUTHORIZATION, value);
+        }
+        let request = builder.body(axum::body::Body::empty()).unwrap();
+        authorize(&auth, &request).map(|Client(name)| name)
+    };
+    assert_eq!(
+        client("/control?token=s3cret", None).as_deref(),
+        Some("web")
+    );
+    assert_eq!(
+        client("/api/state", Some("Bearer s3cret")).as_deref(),
+        Some("web")
+    );
+    assert_eq!(
+        client("/control?token=l1ghts", None).as_deref(),
+        Some("token:lighting")
+    );
+    // director:hunter2
+    assert_eq!(
+        client("/api/state", Some("Basic ZGlyZWN0b3I6aHVudGVyMg==")).as_deref(),
+        Some("user:director")
+    );
+
+    assert_eq!(client("/control", None), None);
+    assert_eq!(client("/control?token=s3cre", None), None);
+    assert_eq!(client("/api/state", Some("Bearer nope")), None);
+    // director:hunter3
+    assert_eq!(
+        client("/api/state", Some("Basic ZGlyZWN0b3I6aHVudGVyMw==")),
+        None
+    );
+}
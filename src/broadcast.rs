@@ -0,0 +1,316 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use itertools::Itertools as _;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::sync::watch;
+
+use crate::{access::Visibility, config::Group, State};
+
+/// Parts of the state that clients can subscribe to separately, so that special-purpose
+/// clients (a battery dashboard, a tally bridge) aren't sent everything.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Topic {
+    /// Groups, default controls, and each device's connection status.
+    State,
+    /// Each device's `telemetry`.
+    Telemetry,
+    /// Each device's `thumbnail` URL.
+    Preview,
+}
+
+impl Topic {
+    const ALL: [Topic; 3] = [Topic::State, Topic::Telemetry, Topic::Preview];
+
+    fn of_device_field(field: &str) -> Topic {
+        match field {
+            "telemetry" => Topic::Telemetry,
+            "thumbnail" => Topic::Preview,
+            _ => Topic::State,
+        }
+    }
+}
+
+/// The topics a client wants, from a comma-separated list like `state,telemetry`. Every topic
+/// if there's no list.
+pub fn parse_topics(list: Option<&str>) -> Result<HashSet<Topic>, String> {
+    let Some(list) = list else {
+        return Ok(HashSet::from(Topic::ALL));
+    };
+    list.split(',')
+        .map(|t| {
+            serde_json::from_value(Value::String(t.trim().to_owned()))
+                .map_err(|_| format!("unknown topic {}", t))
+        })
+        .collect()
+}
+
+/// What a client is sent: the topics it subscribed to, about the devices its source can see.
+pub struct View {
+    pub topics: HashSet<Topic>,
+    pub source: String,
+}
+
+/// A serialized state, split up so that clients can be sent just the devices that changed, and
+/// just the topics they subscribed to. Parts are kept as the inside of a JSON object so they can
+/// be put together for each client without serializing again.
+pub struct Snapshot {
+    instance: String,
+    /// Kept as they are, since they're serialized differently for clients that can't see every
+    /// device.
+    groups: Vec<Group>,
+    /// Everything except `instance`, `groups`, and `devices`.
+    rest: String,
+    devices: HashMap<String, HashMap<Topic, String>>,
+    visibility: Arc<Visibility>,
+}
+
+impl Snapshot {
+    fn new(state: &State, visibility: Arc<Visibility>) -> Snapshot {
+        let fields = |value: Value| match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        };
+        let mut state_fields = fields(serde_json::to_value(state).unwrap());
+        state_fields.remove("devices");
+        state_fields.remove("groups");
+        let instance = state_fields.remove("instance").unwrap();
+        Snapshot {
+            instance: members([("instance".to_owned(), instance)]),
+            groups: state.groups.clone(),
+            rest: members(state_fields),
+            devices: state
+                .devices
+                .iter()
+                .map(|(id, status)| {
+                    let mut topics: HashMap<Topic, Map<String, Value>> = HashMap::new();
+                    for (field, value) in fields(serde_json::to_value(status).unwrap()) {
+                        topics
+                            .entry(Topic::of_device_field(&field))
+                            .or_default()
+                            .insert(field, value);
+                    }
+                    let topics = topics.into_iter().map(|(t, f)| (t, members(f))).collect();
+                    (id.clone(), topics)
+                })
+                .collect(),
+            visibility,
+        }
+    }
+
+    /// The IDs of the devices the view can see.
+    fn visible_devices<'a>(&'a self, view: &'a View) -> impl Iterator<Item = &'a String> {
+        self.devices
+            .keys()
+            .filter(|id| self.visibility.shows(&view.source, id))
+    }
+
+    /// A device's status, with just the fields in the view's topics.
+    fn device(&self, id: &str, view: &View) -> String {
+        let parts = Topic::ALL
+            .iter()
+            .filter(|t| view.topics.contains(t))
+            .filter_map(|t| self.devices[id].get(t));
+        format!("{{{}}}", parts.filter(|p| !p.is_empty()).join(","))
+    }
+
+    /// The whole state (or as much of it as the client subscribed to and can see), as sent to
+    /// new clients.
+    pub fn full(&self, view: &View) -> String {
+        let devices = self
+            .visible_devices(view)
+            .map(|id| {
+                format!(
+                    "{}:{}",
+                    serde_json::to_string(id).unwrap(),
+                    self.device(id, view)
+                )
+            })
+            .join(",");
+        let mut parts = vec![
+            self.instance.clone(),
+            format!("\"devices\":{{{}}}", devices),
+        ];
+        if view.topics.contains(&Topic::State) {
+            let groups = self.visibility.groups(&view.source, &self.groups);
+            parts.push(format!(
+                "\"groups\":{}",
+                serde_json::to_string(&groups).unwrap()
+            ));
+            parts.push(self.rest.clone());
+        }
+        format!("{{{}}}", parts.join(","))
+    }
+
+    /// The message that brings a client that was sent `previous` up to date: a `devicesPatch`
+    /// with the devices that changed if that's all that did, or otherwise the whole state.
+    /// Returns `None` if nothing the client subscribed to changed.
+    pub fn update_from(&self, previous: &Snapshot, view: &View) -> Option<String> {
+        let same_devices = self.devices.len() == previous.devices.len()
+            && self
+                .devices
+                .keys()
+                .all(|id| previous.devices.contains_key(id));
+        let rest_changed = view.topics.contains(&Topic::State)
+            && (self.rest != previous.rest || self.groups != previous.groups);
+        if self.instance != previous.instance || rest_changed || !same_devices {
+            return Some(self.full(view));
+        }
+        let changed = self
+            .visible_devices(view)
+            .map(|id| (id, self.device(id, view)))
+            .filter(|(id, status)| previous.device(id, view) != *status)
+            .map(|(id, status)| format!("{}:{}", serde_json::to_string(id).unwrap(), status))
+            .join(",");
+        (!changed.is_empty()).then(|| format!("{{\"devicesPatch\":{{{}}}}}", changed))
+    }
+}
+
+/// Serializes the members of a JSON object, without the surrounding braces.
+fn members(fields: impl IntoIterator<Item = (String, Value)>) -> String {
+    let object = Value::Object(fields.into_iter().collect());
+    let json = serde_json::to_string(&object).unwrap();
+    json[1..json.len() - 1].to_owned()
+}
+
+/// Serializes the state once per change and shares the result with every client.
+pub fn serialize_states(
+    mut state_rx: watch::Receiver<State>,
+    visibility: Arc<Visibility>,
+) -> watch::Receiver<Arc<Snapshot>> {
+    let snapshot = Snapshot::new(&state_rx.borrow_and_update(), visibility.clone());
+    let (snapshot_tx, snapshot_rx) = watch::channel(Arc::new(snapshot));
+    tokio::spawn(async move {
+        while state_rx.changed().await.is_ok() {
+            let snapshot = Snapshot::new(&state_rx.borrow_and_update(), visibility.clone());
+            let snapshot = Arc::new(snapshot);
+            if snapshot_tx.send(snapshot).is_err() {
+                break;
+            }
+        }
+    });
+    snapshot_rx
+}
+
+#[test]
+fn test_snapshot_update() {
+    use crate::DeviceStatus;
+
+    let status = |id: &str, connected| DeviceStatus {
+        id: id.to_owned(),
+        name: id.to_owned(),
+        connected,
+        reconnecting: false,
+        repairable: false,
+        wakeable: false,
+        telemetry: None,
+        guidance: None,
+        tags: vec![],
+        thumbnail: None,
+        preview: None,
+        calibrating: false,
+        last_error: None,
+        trim: None,
+        lens: None,
+        position: None,
+        owner: None,
+        locked_by: None,
+    };
+    let state = |devices: Vec<DeviceStatus>| State {
+        instance: "a".to_owned(),
+        groups: vec![],
+        devices: devices.into_iter().map(|d| (d.id.clone(), d)).collect(),
+        default_controls: None,
+    };
+    let rules = serde_json::from_value(serde_json::json!({ "token:op1": ["cam1"] })).unwrap();
+    let visibility = Arc::new(Visibility::new(&rules, &[]));
+    let view = |topics: Option<&str>, source: &str| View {
+        topics: parse_topics(topics).unwrap(),
+        source: source.to_owned(),
+    };
+    let all = view(None, "web");
+    let initial = Snapshot::new(
+        &state(vec![status("cam1", true), status("cam2", true)]),
+        visibility.clone(),
+    );
+    let full: serde_json::Value = serde_json::from_str(&initial.full(&all)).unwrap();
+    assert_eq!(
+        full,
+        serde_json::to_value(state(vec![status("cam1", true), status("cam2", true)])).unwrap()
+    );
+
+    let unchanged = Snapshot::new(
+        &state(vec![status("cam1", true), status("cam2", true)]),
+        visibility.clone(),
+    );
+    assert_eq!(unchanged.update_from(&initial, &all), None);
+
+    let dropped = Snapshot::new(
+        &state(vec![status("cam1", true), status("cam2", false)]),
+        visibility.clone(),
+    );
+    let patch: serde_json::Value =
+        serde_json::from_str(&dropped.update_from(&initial, &all).unwrap()).unwrap();
+    assert_eq!(
+        patch,
+        serde_json::json!({ "devicesPatch": { "cam2": {
+            "id": "cam2",
+            "name": "cam2",
+            "connected": false,
+            "reconnecting": false,
+            "repairable": false,
+            "wakeable": false,
+        } } })
+    );
+
+    let removed = Snapshot::new(&state(vec![status("cam1", true)]), visibility.clone());
+    assert_eq!(
+        removed.update_from(&initial, &all),
+        Some(removed.full(&all))
+    );
+
+    // Clients that only want telemetry don't hear about connections
+    let telemetry = view(Some("telemetry"), "web");
+    assert_eq!(dropped.update_from(&initial, &telemetry), None);
+    let mut reporting = status("cam2", false);
+    reporting.telemetry = Some(
+        serde_json::json!({ "battery": 80 })
+            .as_object()
+            .unwrap()
+            .clone(),
+    );
+    let reported = Snapshot::new(
+        &state(vec![status("cam1", true), reporting]),
+        visibility.clone(),
+    );
+    assert_eq!(
+        reported.update_from(&dropped, &telemetry).as_deref(),
+        Some(r#"{"devicesPatch":{"cam2":{"telemetry":{"battery":80}}}}"#)
+    );
+    let full: serde_json::Value = serde_json::from_str(&reported.full(&telemetry)).unwrap();
+    assert_eq!(
+        full,
+        serde_json::json!({
+            "instance": "a",
+            "devices": { "cam1": {}, "cam2": { "telemetry": { "battery": 80 } } },
+        })
+    );
+    assert!(parse_topics(Some("state,nope")).is_err());
+
+    // Clients that can only see some devices don't hear about the others
+    let op1 = view(None, "token:op1");
+    assert_eq!(dropped.update_from(&initial, &op1), None);
+    let full: serde_json::Value = serde_json::from_str(&initial.full(&op1)).unwrap();
+    assert_eq!(
+        full["devices"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .collect::<Vec<_>>(),
+        vec!["cam1"]
+    );
+}
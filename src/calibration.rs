@@ -0,0 +1,124 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+struct Lock {
+    client: String,
+    expires: Instant,
+}
+
+/// Devices locked to a single client for calibration or protocol debugging. While a device is
+/// locked, operations from anyone else (including presets and other background tasks) skip it,
+/// and the lock lapses on its own if the client doesn't renew it.
+#[derive(Default)]
+pub struct Calibrations {
+    locks: HashMap<String, Lock>,
+}
+
+impl Calibrations {
+    /// Locks the device to the client, or extends the client's existing lock.
+    pub fn start(
+        &mut self,
+        device: &str,
+        client: &str,
+        timeout: Duration,
+        now: Instant,
+    ) -> Result<(), String> {
+        if let Some(lock) = self.locks.get(device) {
+            if lock.client != client && lock.expires > now {
+                return Err(format!("{} is being calibrated by {}", device, lock.client));
+            }
+        }
+        let lock = Lock {
+            client: client.to_owned(),
+            expires: now + timeout,
+        };
+        self.locks.insert(device.to_owned(), lock);
+        Ok(())
+    }
+
+    /// Whether an operation from the client (or from webptz itself, without one) may touch the
+    /// device.
+    pub fn allows(&self, device: &str, client: Option<&str>) -> bool {
+        self.locks
+            .get(device)
+            .is_none_or(|lock| Some(lock.client.as_str()) == client)
+    }
+
+    pub fn is_calibrating(&self, device: &str) -> bool {
+        self.locks.contains_key(device)
+    }
+
+    pub fn end(&mut self, device: &str) -> bool {
+        self.locks.remove(device).is_some()
+    }
+
+    /// Passes every device the client is calibrating to another client, keeping when the locks
+    /// run out. Returns the devices that changed hands.
+    pub fn hand_off(&mut self, from: &str, to: &str) -> Vec<String> {
+        let mut devices = vec![];
+        for (device, lock) in self.locks.iter_mut().filter(|(_, l)| l.client == from) {
+            lock.client = to.to_owned();
+            devices.push(device.clone());
+        }
+        devices.sort();
+        devices
+    }
+
+    /// Releases the locks that have run out, returning their devices.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let expired: Vec<String> = self
+            .locks
+            .iter()
+            .filter(|(_, lock)| lock.expires <= now)
+            .map(|(device, _)| device.clone())
+            .collect();
+        for device in expired.iter() {
+            self.locks.remove(device);
+        }
+        expired
+    }
+}
+
+#[test]
+fn test_calibrations() {
+    let mut calibrations = Calibrations::default();
+    let now = Instant::now();
+    let timeout = Duration::from_secs(60);
+    calibrations.start("cam1", "wizard", timeout, now).unwrap();
+
+    assert!(calibrations.allows("cam1", Some("wizard")));
+    assert!(!calibrations.allows("cam1", Some("joystick")));
+    assert!(!calibrations.allows("cam1", None));
+    assert!(calibrations.allows("cam2", Some("joystick")));
+    assert!(calibrations
+        .start("cam1", "joystick", timeout, now + Duration::from_secs(30))
+        .is_err());
+
+    // Renewing pushes the expiry back
+    calibrations
+        .start("cam1", "wizard", timeout, now + Duration::from_secs(30))
+        .unwrap();
+    assert!(calibrations.expire(now + timeout).is_empty());
+    assert_eq!(
+        calibrations.expire(now + Duration::from_secs(90)),
+        vec!["cam1"]
+    );
+    assert!(calibrations.allows("cam1", None));
+
+    // Locks that ran out can be taken over before they're expired
+    calibrations.start("cam1", "wizard", timeout, now).unwrap();
+    calibrations
+        .start("cam1", "joystick", timeout, now + timeout)
+        .unwrap();
+    assert!(calibrations.end("cam1"));
+    assert!(!calibrations.is_calibrating("cam1"));
+
+    // Handing off moves the lock without extending it
+    calibrations.start("cam1", "wizard", timeout, now).unwrap();
+    assert_eq!(calibrations.hand_off("wizard", "debugger"), vec!["cam1"]);
+    assert!(calibrations.allows("cam1", Some("debugger")));
+    assert!(!calibrations.allows("cam1", Some("wizard")));
+    assert_eq!(calibrations.expire(now + timeout), vec!["cam1"]);
+}
@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+use crate::{config::PushInCalibration, device::Command, CommandRequest, Operation};
+
+const STEP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Zooms a single device for `duration` while following its focus calibration curve, feeding the
+/// interleaved zoom/focus commands back through the command loop.
+pub async fn push_in(
+    device: String,
+    zoom: f64,
+    duration: Duration,
+    calibration: Option<PushInCalibration>,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) {
+    let start = Instant::now();
+    let mut interval = tokio::time::interval(STEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let elapsed = start.elapsed();
+        let done = elapsed >= duration;
+        let command = if done {
+            Command::default()
+        } else {
+            let progress = elapsed.as_secs_f64() / duration.as_secs_f64();
+            let focus = calibration
+                .as_ref()
+                .map(|c| interpolate(&c.focus_curve, progress) * zoom.signum())
+                .unwrap_or(0.0);
+            Command {
+                zoom: zoom.into(),
+                focus: focus.into(),
+                ..Default::default()
+            }
+        };
+        let request = CommandRequest {
+            devices: vec![device.clone()],
+            command,
+        };
+        if command_tx.send(Operation::Command(request)).is_err() || done {
+            return;
+        }
+    }
+}
+
+/// Linearly interpolates a curve of `[x, y]` points sorted by `x`, holding the end values outside
+/// of the covered range.
+fn interpolate(curve: &[[f64; 2]], x: f64) -> f64 {
+    let Some(first) = curve.first() else {
+        return 0.0;
+    };
+    if x <= first[0] {
+        return first[1];
+    }
+    for pair in curve.windows(2) {
+        let ([x0, y0], [x1, y1]) = (pair[0], pair[1]);
+        if x <= x1 {
+            if x1 == x0 {
+                return y1;
+            }
+            return y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+        }
+    }
+    curve.last().unwrap()[1]
+}
+
+#[test]
+fn test_interpolate() {
+    let curve = [[0.0, 0.1], [0.5, 0.3], [1.0, 0.0]];
+    assert_eq!(interpolate(&curve, -1.0), 0.1);
+    assert_eq!(interpolate(&curve, 0.25), 0.2);
+    assert_eq!(interpolate(&curve, 0.5), 0.3);
+    assert_eq!(interpolate(&curve, 0.75), 0.15);
+    assert_eq!(interpolate(&curve, 2.0), 0.0);
+    assert_eq!(interpolate(&[], 0.5), 0.0);
+}
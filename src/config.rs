@@ -1,26 +1,488 @@
 use indexmap::IndexMap;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, env, error::Error};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use crate::{
+    device::{AngleDeg, Command, Position, Velocity},
+    timecode::Timecode,
+};
 
 #[derive(Deserialize, Serialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Addresses to serve the UI and control API on, each bound separately so that one
+    /// interface going down doesn't affect the others. Defaults to every interface.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub listen: Vec<IpAddr>,
     pub groups: Vec<Group>,
     pub devices: IndexMap<String, DeviceConfig>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub default_controls: Option<Vec<Mappings>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bluetooth: Option<BluetoothConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadband: Option<DeadbandConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchdog: Option<WatchdogConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arbitration: Option<ArbitrationConfig>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub health_checks: IndexMap<String, HealthCheckConfig>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub push_in: IndexMap<String, PushInCalibration>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub mounting: IndexMap<String, Mounting>,
+    /// Set with `trim` requests, and saved here so it survives restarts.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub trim: IndexMap<String, Trim>,
+    /// Locations or other labels for each device, so requests can address them with `tag:<name>`.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub tags: IndexMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<InputBinding>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_actions: Vec<InputAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timecode: Option<TimecodeConfig>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub panels: IndexMap<String, PanelConfig>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub hid: IndexMap<String, HidConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub osc: Option<OscConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_protocol: Option<LineProtocolConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthConfig>,
+    /// Command sources allowed to control each device or group. Devices without a rule can be
+    /// controlled from anywhere.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub access: IndexMap<String, Vec<String>>,
+    /// Groups and devices each web client source is shown. Sources without an entry see
+    /// everything.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub visibility: IndexMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Encrypted file holding the values of `secret:<name>` references, `secrets.age` by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnails: Option<ThumbnailConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mqtt: Option<MqttConfig>,
+    /// Saved positions, by device ID and then slot number.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub presets: IndexMap<String, IndexMap<u32, Position>>,
+    /// Where the config was read from, which changes made at runtime are saved back to.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// Tuning for low-latency setups on small machines.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeConfig {
+    /// Defaults to one worker thread per CPU core.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub worker_threads: Option<usize>,
+    /// CPUs that worker threads are pinned to, round-robin. Only supported on Linux.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cpus: Vec<usize>,
+    /// Runs Bluetooth devices on a dedicated thread, away from the web server.
+    #[serde(default)]
+    pub bluetooth_thread: bool,
+    /// Process niceness, from -20 (highest priority) to 19. Raising the priority usually
+    /// requires root or `CAP_SYS_NICE`. Only supported on Linux.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nice: Option<i32>,
+}
+
+/// Serves the UI over HTTPS with a PEM certificate and PKCS#8 private key.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Generates a self-signed certificate if `cert` and `key` don't exist yet.
+    #[serde(default)]
+    pub self_signed: bool,
+}
+
+/// Periodically grabs preview images from devices with a live view.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailConfig {
+    #[serde(default = "default_thumbnail_interval")]
+    pub interval_secs: u64,
 }
 
+const fn default_thumbnail_interval() -> u64 {
+    10
+}
+
+/// A password or token, left out of `Debug` output so it doesn't end up in the config dump
+/// logged at startup.
+#[derive(Deserialize, Serialize, Default, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Redacted<T>(pub T);
+
+impl<T> std::fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl<T> std::ops::Deref for Redacted<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Redacted<T> {
+    fn from(value: T) -> Self {
+        Redacted(value)
+    }
+}
+
+/// Publishes device status to an MQTT broker and accepts requests from it.
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Prepended to every topic, e.g. `webptz/devices/cam1`.
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Redacted<String>>,
+}
+
+const fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "webptz".to_owned()
+}
+
+fn default_mqtt_client_id() -> String {
+    "webptz".to_owned()
+}
+
+/// Credentials required to control devices. Clients can present any one of them.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthConfig {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<Redacted<String>>,
+    /// Tokens by name, so that access rules can tell the clients using them apart.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub named_tokens: IndexMap<String, Redacted<String>>,
+    /// Mapping of username to password, checked with HTTP basic authentication.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub users: IndexMap<String, Redacted<String>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BluetoothConfig {
+    /// Command (program followed by arguments) run when the Bluetooth adapter disappears or
+    /// powers off, e.g. `["hciconfig", "hci0", "reset"]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_command: Option<Vec<String>>,
+    /// Signal strength (in dBm) below which a device's telemetry has `signalLow` set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rssi_warn_below: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct Group {
     pub name: String,
     pub devices: Vec<String>,
 }
 
+/// Polls a device's JSON status endpoint. `fields` maps telemetry names to JSON pointers (e.g.
+/// `"/system/temperature"`) into the response.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheckConfig {
+    pub url: String,
+    #[serde(default = "default_health_interval")]
+    pub interval_secs: u64,
+    pub fields: IndexMap<String, String>,
+}
+
+const fn default_health_interval() -> u64 {
+    30
+}
+
+/// Binds a control from a server-side input source to an axis, shaping the value through
+/// `stages` (applied in order) and routing it to `groups`. Browser gamepads are still configured
+/// through `defaultControls`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InputBinding {
+    pub source: String,
+    pub control: String,
+    pub axis: InputAxis,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<ShapingStage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ramp: Option<Ramp>,
+    pub groups: Vec<String>,
+}
+
+/// Makes a button act like a zoom rocker: while it's held, the value builds from `start` up to
+/// `max` over `attackMs`, instead of jumping straight to full speed. The ramped value goes
+/// through the binding's stages as usual, so e.g. `invert` makes a button that zooms out.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Ramp {
+    pub attack_ms: u64,
+    #[serde(default)]
+    pub start: f64,
+    #[serde(default = "default_ramp_max")]
+    pub max: f64,
+}
+
+const fn default_ramp_max() -> f64 {
+    1.0
+}
+
+/// Sends a request when an input control is pressed (crosses 0.5), e.g. to bind buttons to
+/// operations other than movement.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InputAction {
+    pub source: String,
+    pub control: String,
+    /// A request in the same format the websocket accepts.
+    pub request: serde_json::Value,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum InputAxis {
+    Pan,
+    Tilt,
+    Roll,
+    Zoom,
+    Focus,
+    Iris,
+    Autofocus,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum ShapingStage {
+    /// Zeroes values below `threshold` and rescales the rest to cover the full range.
+    Deadzone {
+        threshold: f64,
+    },
+    /// Raises the magnitude to `exponent`, making small values easier to hit.
+    Curve {
+        exponent: f64,
+    },
+    Scale {
+        factor: f64,
+    },
+    Invert,
+}
+
+/// A Skaarhoj panel in raw panel mode. Its controls are reported as input events, with the
+/// panel's ID as the `source` and `HWC#<number>` as the `control`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PanelConfig {
+    /// Host and port of the panel, e.g. `192.168.0.30:9923`.
+    pub address: String,
+    /// How far a single encoder detent moves the control's value.
+    #[serde(default = "default_encoder_step")]
+    pub encoder_step: f64,
+}
+
+const fn default_encoder_step() -> f64 {
+    0.1
+}
+
+/// A HID button box (X-keys, macro pads, ...). Every bit of its input reports is treated as a
+/// key, reported as the control `key<index>` with the device's ID as the `source`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HidConfig {
+    /// Raw HID device node, e.g. `/dev/hidraw0`.
+    pub path: PathBuf,
+}
+
+/// Listens for OSC messages over UDP. Addresses like `/webptz/<device>/pan` and
+/// `/webptz/group/<name>/zoom` move devices directly, and anything else is reported as an input
+/// event with `osc` as the `source` and the address as the `control`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OscConfig {
+    #[serde(default = "default_osc_port")]
+    pub port: u16,
+    #[serde(default = "default_osc_prefix")]
+    pub prefix: String,
+}
+
+const fn default_osc_port() -> u16 {
+    9000
+}
+
+fn default_osc_prefix() -> String {
+    "/webptz".to_owned()
+}
+
+/// Accepts newline-delimited text commands over TCP, for Companion and hardware controllers.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LineProtocolConfig {
+    pub port: u16,
+}
+
+/// Triggers requests at exact MIDI timecode positions, e.g. to line moves up with a playback
+/// track.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimecodeConfig {
+    /// Raw MIDI device that MIDI timecode arrives on, e.g. `/dev/snd/midiC1D0`.
+    pub midi_device: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cues: Vec<TimecodeCue>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimecodeCue {
+    pub at: Timecode,
+    /// A request in the same format the websocket accepts.
+    pub request: serde_json::Value,
+}
+
+/// Focus speed to apply while zooming in, as `[progress, focus]` points where progress runs from
+/// 0 to 1 over the course of the move.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PushInCalibration {
+    pub focus_curve: Vec<[f64; 2]>,
+}
+
+/// Stops a moving device when no command has been received for it within `timeout_ms`, for
+/// backends that would otherwise keep moving after a client goes away mid-move.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchdogConfig {
+    #[serde(default = "default_watchdog_timeout")]
+    pub timeout_ms: u64,
+}
+
+const fn default_watchdog_timeout() -> u64 {
+    500
+}
+
+/// How commands from several clients moving the same device at once are combined.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ArbitrationConfig {
+    #[serde(default)]
+    pub policy: ArbitrationPolicy,
+    /// Priority of each command source, named as in access rules. Sources without one have
+    /// priority 0.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub priorities: IndexMap<String, i32>,
+    /// How long a client keeps moving a device after its last command, as far as other clients
+    /// are concerned.
+    #[serde(default = "default_arbitration_hold")]
+    pub hold_ms: u64,
+}
+
+impl Default for ArbitrationConfig {
+    fn default() -> Self {
+        ArbitrationConfig {
+            policy: ArbitrationPolicy::default(),
+            priorities: IndexMap::new(),
+            hold_ms: default_arbitration_hold(),
+        }
+    }
+}
+
+const fn default_arbitration_hold() -> u64 {
+    500
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ArbitrationPolicy {
+    /// Devices follow whichever client sent the latest command.
+    #[default]
+    LastWriter,
+    /// Clients can't move a device while a client with a higher priority is moving it.
+    Priority,
+    /// Commands from every client moving a device are added together.
+    Sum,
+}
+
+/// Commands whose axes all differ from the last command sent to a device by less than `epsilon`
+/// are dropped. `backends` overrides the threshold per device type (e.g. `{"serial": 0.05}`).
+/// Devices that need commands repeated aren't deadbanded unless `backends` says so.
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadbandConfig {
+    pub epsilon: f64,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub backends: HashMap<String, f64>,
+}
+
+impl DeadbandConfig {
+    pub fn epsilon_for(&self, device: &DeviceConfig) -> f64 {
+        self.backends
+            .get(device.type_name())
+            .copied()
+            .unwrap_or(match device.needs_repeats() {
+                true => 0.0,
+                false => self.epsilon,
+            })
+    }
+}
+
+#[test]
+fn test_epsilon_for() {
+    let deadband = DeadbandConfig {
+        epsilon: 0.02,
+        backends: HashMap::new(),
+    };
+    let lanc: DeviceConfig = serde_json::from_str(r#"{"type": "lanc", "port": "COM3"}"#).unwrap();
+    let dummy: DeviceConfig = serde_json::from_str(r#"{"type": "dummy", "name": "d"}"#).unwrap();
+    assert_eq!(deadband.epsilon_for(&lanc), 0.0);
+    assert_eq!(deadband.epsilon_for(&dummy), 0.02);
+    let deadband = DeadbandConfig {
+        backends: HashMap::from([("lanc".to_owned(), 0.1)]),
+        ..deadband
+    };
+    assert_eq!(deadband.epsilon_for(&lanc), 0.1);
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -30,15 +492,285 @@ pub enum DeviceConfig {
     Crane(CraneConfig),
     Lumix(LumixConfig),
     Lanc(LancConfig),
+    Serial(SerialConfig),
+    Modbus(ModbusConfig),
+    Camcorder(CamcorderConfig),
+    Fcb(FcbConfig),
+    Onvif(OnvifConfig),
+    Pelco(PelcoConfig),
+    Aw(AwConfig),
+    CanonCcapi(CanonCcapiConfig),
+    CanonUsb(CanonUsbConfig),
+    Sony(SonyConfig),
+    Uvc(UvcConfig),
+    Feiyu(FeiyuConfig),
+    Script(ScriptConfig),
+    GenericBle(GenericBleConfig),
+}
+
+impl DeviceConfig {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DeviceConfig::Dummy(_) => "dummy",
+            DeviceConfig::Ronin(_) => "ronin",
+            DeviceConfig::Crane(_) => "crane",
+            DeviceConfig::Lumix(_) => "lumix",
+            DeviceConfig::Lanc(_) => "lanc",
+            DeviceConfig::Serial(_) => "serial",
+            DeviceConfig::Modbus(_) => "modbus",
+            DeviceConfig::Camcorder(_) => "camcorder",
+            DeviceConfig::Fcb(_) => "fcb",
+            DeviceConfig::Onvif(_) => "onvif",
+            DeviceConfig::Pelco(_) => "pelco",
+            DeviceConfig::Aw(_) => "aw",
+            DeviceConfig::CanonCcapi(_) => "canonCcapi",
+            DeviceConfig::CanonUsb(_) => "canonUsb",
+            DeviceConfig::Sony(_) => "sony",
+            DeviceConfig::Uvc(_) => "uvc",
+            DeviceConfig::Feiyu(_) => "feiyu",
+            DeviceConfig::Script(_) => "script",
+            DeviceConfig::GenericBle(_) => "genericBle",
+        }
+    }
+
+    /// The capabilities the device is limited to, if its config lists them.
+    pub fn capabilities(&self) -> Option<&[Capability]> {
+        let capabilities = match self {
+            DeviceConfig::Dummy(c) => &c.capabilities,
+            DeviceConfig::Ronin(c) => &c.capabilities,
+            DeviceConfig::Crane(c) => &c.capabilities,
+            DeviceConfig::Lumix(c) => &c.capabilities,
+            DeviceConfig::Lanc(c) => &c.capabilities,
+            DeviceConfig::Serial(c) => &c.capabilities,
+            DeviceConfig::Modbus(c) => &c.capabilities,
+            DeviceConfig::Camcorder(c) => &c.capabilities,
+            DeviceConfig::Fcb(c) => &c.capabilities,
+            DeviceConfig::Onvif(c) => &c.capabilities,
+            DeviceConfig::Pelco(c) => &c.capabilities,
+            DeviceConfig::Aw(c) => &c.capabilities,
+            DeviceConfig::CanonCcapi(c) => &c.capabilities,
+            DeviceConfig::CanonUsb(c) => &c.capabilities,
+            DeviceConfig::Sony(c) => &c.capabilities,
+            DeviceConfig::Uvc(c) => &c.capabilities,
+            DeviceConfig::Feiyu(c) => &c.capabilities,
+            DeviceConfig::Script(c) => &c.capabilities,
+            DeviceConfig::GenericBle(c) => &c.capabilities,
+        };
+        capabilities.as_deref()
+    }
+
+    /// Whether the device only keeps moving for as long as commands keep coming, so that
+    /// dropping a repeated command would stop it.
+    pub fn needs_repeats(&self) -> bool {
+        matches!(self, DeviceConfig::Lanc(_))
+    }
+
+    /// The cargo feature the device's backend is built with, along with whether this build
+    /// includes it. Backends without dependencies of their own are always built.
+    pub fn feature(&self) -> Option<(&'static str, bool)> {
+        match self {
+            DeviceConfig::Dummy(_) | DeviceConfig::Modbus(_) => None,
+            DeviceConfig::Ronin(_)
+            | DeviceConfig::Crane(_)
+            | DeviceConfig::Feiyu(_)
+            | DeviceConfig::GenericBle(_) => Some(("bluetooth", cfg!(feature = "bluetooth"))),
+            DeviceConfig::Lumix(_) => Some(("lumix", cfg!(feature = "lumix"))),
+            DeviceConfig::Lanc(c) if c.transport == LancTransport::Gpio => {
+                Some(("gpio", cfg!(all(feature = "gpio", target_os = "linux"))))
+            }
+            DeviceConfig::Lanc(_) => Some(("lanc", cfg!(feature = "lanc"))),
+            DeviceConfig::Serial(_) | DeviceConfig::Fcb(_) | DeviceConfig::Pelco(_) => {
+                Some(("serial", cfg!(feature = "serial")))
+            }
+            DeviceConfig::Camcorder(_) | DeviceConfig::Aw(_) => {
+                Some(("panasonic", cfg!(feature = "panasonic")))
+            }
+            DeviceConfig::Onvif(_) => Some(("onvif", cfg!(feature = "onvif"))),
+            DeviceConfig::CanonCcapi(_) | DeviceConfig::CanonUsb(_) => {
+                Some(("canon", cfg!(feature = "canon")))
+            }
+            DeviceConfig::Sony(_) => Some(("sony", cfg!(feature = "sony"))),
+            DeviceConfig::Uvc(_) => Some(("uvc", cfg!(feature = "uvc"))),
+            DeviceConfig::Script(_) => Some(("script", cfg!(feature = "script"))),
+        }
+    }
+}
+
+#[test]
+fn test_lanc_transport_feature() {
+    let device: DeviceConfig =
+        serde_json::from_str(r#"{"type": "lanc", "transport": "gpio", "inputPin": 4}"#).unwrap();
+    assert_eq!(
+        device.feature(),
+        Some(("gpio", cfg!(all(feature = "gpio", target_os = "linux"))))
+    );
+    let device: DeviceConfig = serde_json::from_str(r#"{"type": "lanc", "port": "COM3"}"#).unwrap();
+    assert_eq!(device.feature(), Some(("lanc", cfg!(feature = "lanc"))));
+}
+
+/// How a gimbal is rigged, as seen from behind the camera.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Mounting {
+    #[default]
+    Upright,
+    /// Hanging upside down, e.g. from a truss or slider.
+    Underslung,
+    /// On its side, with the base to the left of the camera.
+    SidewaysLeft,
+    /// On its side, with the base to the right of the camera.
+    SidewaysRight,
+}
+
+impl Mounting {
+    /// Maps a command for the shot (pan right, tilt up) onto the gimbal's own axes, which are
+    /// reversed or swapped depending on how it's rigged. Roll is unaffected, since the gimbal
+    /// is only ever rotated around the camera's forward axis.
+    pub fn apply(self, command: Command) -> Command {
+        let (pan, tilt) = match self {
+            Mounting::Upright => (command.pan, command.tilt),
+            Mounting::Underslung => (-command.pan, -command.tilt),
+            Mounting::SidewaysLeft => (command.tilt, -command.pan),
+            Mounting::SidewaysRight => (-command.tilt, command.pan),
+        };
+        Command {
+            pan,
+            tilt,
+            ..command
+        }
+    }
+
+    /// The mounting that undoes this one's mapping.
+    pub fn inverse(self) -> Mounting {
+        match self {
+            Mounting::SidewaysLeft => Mounting::SidewaysRight,
+            Mounting::SidewaysRight => Mounting::SidewaysLeft,
+            m => m,
+        }
+    }
+}
+
+#[test]
+fn test_mounting() {
+    let command = Command {
+        pan: crate::device::Velocity::new(0.5),
+        tilt: crate::device::Velocity::new(0.25),
+        roll: crate::device::Velocity::new(0.1),
+        ..Default::default()
+    };
+    let axes = |mounting: Mounting| {
+        let c = mounting.apply(command);
+        (c.pan.get(), c.tilt.get(), c.roll.get())
+    };
+    assert_eq!(axes(Mounting::Upright), (0.5, 0.25, 0.1));
+    assert_eq!(axes(Mounting::Underslung), (-0.5, -0.25, 0.1));
+    assert_eq!(axes(Mounting::SidewaysLeft), (0.25, -0.5, 0.1));
+    assert_eq!(axes(Mounting::SidewaysRight), (-0.25, 0.5, 0.1));
+    for mounting in [Mounting::Underslung, Mounting::SidewaysLeft] {
+        assert_eq!(mounting.inverse().apply(mounting.apply(command)), command);
+    }
+}
+
+/// Small corrections for a device, in its own axes (after `mounting`), for horizons that drift
+/// or presets that are slightly off without having to save them all again.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Trim {
+    /// Added to the speeds (-1 to 1) of every command sent to the device.
+    #[serde(default, skip_serializing_if = "TrimAxes::is_zero")]
+    pub velocity: TrimAxes,
+    /// Degrees added to saved positions when recalling or guiding to them.
+    #[serde(default, skip_serializing_if = "TrimAxes::is_zero")]
+    pub position: TrimAxes,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+pub struct TrimAxes {
+    #[serde(default)]
+    pub pan: f64,
+    #[serde(default)]
+    pub tilt: f64,
+    #[serde(default)]
+    pub roll: f64,
+}
+
+impl TrimAxes {
+    fn is_zero(&self) -> bool {
+        *self == TrimAxes::default()
+    }
+}
+
+impl Trim {
+    pub fn is_zero(&self) -> bool {
+        self.velocity.is_zero() && self.position.is_zero()
+    }
+
+    pub fn apply(&self, command: Command) -> Command {
+        let biased = |v: Velocity, bias: f64| Velocity::new(v.get() + bias);
+        Command {
+            pan: biased(command.pan, self.velocity.pan),
+            tilt: biased(command.tilt, self.velocity.tilt),
+            roll: biased(command.roll, self.velocity.roll),
+            ..command
+        }
+    }
+
+    pub fn offset(&self, position: Position) -> Position {
+        let offset =
+            |a: Option<AngleDeg>, degrees: f64| a.map(|a| AngleDeg::new(a.get() + degrees));
+        Position {
+            pan: offset(position.pan, self.position.pan),
+            tilt: offset(position.tilt, self.position.tilt),
+            roll: offset(position.roll, self.position.roll),
+            ..position
+        }
+    }
+}
+
+#[test]
+fn test_trim() {
+    let trim: Trim = serde_json::from_value(serde_json::json!({
+        "velocity": { "roll": -0.25 },
+        "position": { "pan": 2.5, "tilt": -1 },
+    }))
+    .unwrap();
+    let command = trim.apply(Command {
+        pan: Velocity::new(0.5),
+        roll: Velocity::new(-1.0),
+        ..Default::default()
+    });
+    assert_eq!(
+        (command.pan.get(), command.tilt.get(), command.roll.get()),
+        (0.5, 0.0, -1.0)
+    );
+    let position = trim.offset(Position {
+        pan: Some(AngleDeg::new(179.0)),
+        tilt: Some(AngleDeg::new(10.0)),
+        ..Default::default()
+    });
+    assert_eq!(position.pan, Some(AngleDeg::new(-178.5)));
+    assert_eq!(position.tilt, Some(AngleDeg::new(9.0)));
+    assert_eq!(position.roll, None);
+    assert!(!trim.is_zero());
+    assert!(Trim::default().is_zero());
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum Capability {
+    /// Pan and tilt.
     Ptr,
+    /// Kept apart from pan and tilt, since gimbals are often run with roll locked, and a bumped
+    /// stick shouldn't tip the horizon.
+    Roll,
     Zoom,
     Focus,
     Autofocus,
+    /// Starting and stopping recording.
+    Record,
+    Iris,
+    /// Taking still photos.
+    Shutter,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -59,10 +791,28 @@ pub enum RoninOption {
     ReverseZoom,
 }
 
+/// DJI gimbals differ in how they take motion commands.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum RoninModel {
+    /// RSC 2, RS 3, and other gimbals that take DUML motion commands.
+    #[default]
+    Rsc2,
+    /// Takes R SDK speed commands.
+    Rs2,
+    /// Takes R SDK speed commands.
+    Rs3Mini,
+    /// Osmo Mobile phone gimbals, which take the same DUML frames as the RSC 2 but have no focus
+    /// motor to zoom with.
+    OsmoMobile,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RoninConfig {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<RoninModel>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capabilities: Option<Vec<Capability>>,
     pub options: Option<Vec<RoninOption>>,
@@ -84,6 +834,144 @@ pub struct CraneConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capabilities: Option<Vec<Capability>>,
     pub options: Option<Vec<CraneOption>>,
+    /// How often to read back the gimbal's angles and battery level.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub poll_interval_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Clone)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::enum_variant_names)]
+pub enum FeiyuOption {
+    ReversePan,
+    ReverseTilt,
+    ReverseRoll,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FeiyuConfig {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+    pub options: Option<Vec<FeiyuOption>>,
+}
+
+/// Bluetooth devices whose packets are described entirely in config, for gimbals that just take
+/// scaled values at fixed offsets.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GenericBleConfig {
+    pub name: String,
+    /// UUID of the characteristic that packets are written to.
+    pub characteristic: String,
+    /// Waits for the device to acknowledge each write.
+    #[serde(default)]
+    pub with_response: bool,
+    pub packets: Vec<BlePacket>,
+    /// Appended to the end of every packet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<BleChecksum>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlePacket {
+    pub parts: Vec<BlePart>,
+    /// Only sent when autofocus is triggered, rather than with every command.
+    #[serde(default)]
+    pub on_autofocus: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BlePart {
+    /// Fixed bytes, in hex.
+    Bytes {
+        bytes: String,
+    },
+    Value(BleField),
+}
+
+/// A value written as `sign(value) * |value|^exponent * scale + offset`, rounded and clamped to
+/// the encoding's range.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BleField {
+    pub value: BleValue,
+    pub encoding: BleEncoding,
+    #[serde(default = "default_ble_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    #[serde(default = "default_ble_exponent")]
+    pub exponent: f64,
+}
+
+const fn default_ble_scale() -> f64 {
+    1.0
+}
+
+const fn default_ble_exponent() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum BleValue {
+    Pan,
+    Tilt,
+    Roll,
+    Zoom,
+    Focus,
+    /// 1 while autofocus is triggered, otherwise 0.
+    Autofocus,
+    /// A counter that goes up by one with every packet sent.
+    Sequence,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BleEncoding {
+    U8,
+    I8,
+    U16le,
+    U16be,
+    I16le,
+    I16be,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BleChecksum {
+    /// The CRC-16 used by Zhiyun Crane gimbals.
+    Crane,
+    /// The CRC-16 at the end of DJI DUML packets.
+    Duml,
+    /// The low byte of the sum of every byte after the first `skip`.
+    #[serde(rename_all = "camelCase")]
+    Sum8 {
+        #[serde(default)]
+        skip: usize,
+    },
+    /// Any other CRC-16 of every byte after the first `skip`.
+    #[serde(rename_all = "camelCase")]
+    Crc16 {
+        poly: u16,
+        #[serde(default)]
+        init: u16,
+        /// Reflects both the input bytes and the result.
+        #[serde(default)]
+        reflect: bool,
+        #[serde(default)]
+        xor_out: u16,
+        #[serde(default)]
+        skip: usize,
+        #[serde(default)]
+        big_endian: bool,
+    },
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -91,21 +979,373 @@ pub struct CraneConfig {
 pub struct LumixConfig {
     pub address: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub password: Option<String>,
+    pub password: Option<Redacted<String>>,
+    /// MAC address used to wake the camera with Wake-on-LAN.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_monitor: Option<StorageMonitorConfig>,
+    /// The zoom magnitudes above which each successive zoom speed step is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom_speeds: Option<Vec<f64>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capabilities: Option<Vec<Capability>>,
 }
 
+#[test]
+fn test_device_config_debug_redacts_passwords() {
+    let devices: IndexMap<String, DeviceConfig> = serde_json::from_str(
+        r#"{
+            "lumix": {"type": "lumix", "address": "10.0.0.2", "password": "hunter2"},
+            "camcorder": {"type": "camcorder", "address": "10.0.0.3", "password": "hunter2"},
+            "aw": {"type": "aw", "address": "10.0.0.4", "password": "hunter2"},
+            "canon": {"type": "canonCcapi", "address": "10.0.0.5", "password": "hunter2"},
+            "onvif": {"type": "onvif", "address": "http://10.0.0.6", "password": "hunter2"}
+        }"#,
+    )
+    .unwrap();
+    let dump = format!("{:?}", devices);
+    assert!(dump.contains("10.0.0.6"));
+    assert!(!dump.contains("hunter2"));
+
+    let auth: AuthConfig = serde_json::from_str(
+        r#"{"tokens": ["hunter2"], "namedTokens": {"lighting": "hunter2"}, "users": {"director": "hunter2"}}"#,
+    )
+    .unwrap();
+    let dump = format!("{:?}", auth);
+    assert!(dump.contains("director"));
+    assert!(!dump.contains("hunter2"));
+}
+
+/// Periodically reads the remaining card space. `bitrateMbps` is used to estimate the remaining
+/// recording time.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageMonitorConfig {
+    #[serde(default = "default_storage_interval")]
+    pub interval_secs: u64,
+    #[serde(default = "default_storage_warning")]
+    pub warn_below_mb: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitrate_mbps: Option<f64>,
+}
+
+const fn default_storage_interval() -> u64 {
+    60
+}
+
+const fn default_storage_warning() -> u64 {
+    4096
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct LancConfig {
+    /// Serial port of the Arduino bridge.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub port: String,
+    #[serde(default)]
+    pub transport: LancTransport,
+    /// GPIO pin (BCM numbering) the LANC line is read on, for the `gpio` transport.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_pin: Option<u8>,
+    /// GPIO pin that pulls the LANC line low, for the `gpio` transport.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_pin: Option<u8>,
+    /// How long to keep sending the last zoom or focus command after a stop, to ease out of moves.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fade_out_ms: Option<u64>,
+    /// Named LANC codes in hex (e.g. `"powerOff": "185E"`), for functions that aren't modeled.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub custom_commands: IndexMap<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub capabilities: Option<Vec<Capability>>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LancTransport {
+    /// Through an Arduino bridge on a serial port.
+    #[default]
+    Serial,
+    /// Bit-banged on a Raspberry Pi's GPIO pins.
+    Gpio,
+}
+
+/// Panasonic camcorders (AG-CX/HC-X series) controlled through their IP remote interface.
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
+pub struct CamcorderConfig {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Redacted<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Panasonic AW-series PTZ cameras (AW-UE/AW-HE) controlled through their HTTP interface.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AwConfig {
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Redacted<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Canon EOS cameras controlled through the Camera Connect API, which has to be enabled on the
+/// camera first.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonCcapiConfig {
+    /// Host of the camera, with the port if it isn't the default `8080`.
+    pub address: String,
+    /// Set if the camera's API has authentication turned on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Redacted<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Canon cameras plugged in over USB, controlled with the EOS remote protocol (Linux only).
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonUsbConfig {
+    /// usbfs node of the camera, e.g. `/dev/bus/usb/001/004`. The first Canon device plugged in
+    /// is used if it's left out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Sony cameras with the Camera Remote API, usually joined over the camera's own Wi-Fi network.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SonyConfig {
+    /// Host and port of the camera's API, `192.168.122.1:8080` on most cameras' own networks.
+    #[serde(default = "default_sony_address")]
+    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+fn default_sony_address() -> String {
+    "192.168.122.1:8080".to_owned()
+}
+
+/// Generic USB Video Class cameras with zoom and focus controls (Linux only).
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UvcConfig {
+    /// Video device of the camera, e.g. `/dev/video0`.
+    pub device: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Devices driven by a user-provided Rhai script, for hardware without a built-in backend.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptConfig {
+    pub path: PathBuf,
+    /// Passed to the script as `this.settings`, e.g. the address of the device.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub settings: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Sony FCB block cameras controlled with VISCA over TTL serial.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FcbConfig {
+    pub port: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    /// VISCA address of the camera, from 1 to 7.
+    #[serde(default = "default_visca_address")]
+    pub address: u8,
+    /// Combines digital zoom with optical zoom, so zooming continues past the optical range.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digital_zoom: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stabilizer: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+const fn default_visca_address() -> u8 {
+    1
+}
+
+/// ONVIF PTZ cameras, controlled through their PTZ service.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OnvifConfig {
+    /// URL of the device service, e.g. `http://192.168.0.90/onvif/device_service`. If omitted,
+    /// the camera is found with WS-Discovery.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// When discovering, only cameras with a scope containing this text are used, e.g.
+    /// `name/Stage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<Redacted<String>>,
+    /// Token of the media profile to move. Defaults to the first profile with a PTZ
+    /// configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+/// Analog PTZ heads controlled with Pelco-D or Pelco-P over an RS-485 adapter.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PelcoConfig {
+    pub port: String,
+    #[serde(default = "default_pelco_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default)]
+    pub protocol: PelcoProtocol,
+    /// The camera's address as set on the head, from 1.
+    #[serde(default = "default_pelco_address")]
+    pub address: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PelcoProtocol {
+    #[default]
+    #[serde(rename = "pelcoD")]
+    D,
+    #[serde(rename = "pelcoP")]
+    P,
+}
+
+const fn default_pelco_baud_rate() -> u32 {
+    2400
+}
+
+const fn default_pelco_address() -> u8 {
+    1
+}
+
+/// A serial-controlled device driven entirely by command templates. In each template, `{value}`
+/// is replaced with the axis value multiplied by `range`, `{abs}` with its absolute value, and
+/// `{sign}` with `+` or `-`.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialConfig {
+    pub port: String,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    #[serde(default = "default_line_ending")]
+    pub line_ending: String,
+    #[serde(default = "default_range")]
+    pub range: f64,
+    /// Number of decimal places values are formatted with.
+    #[serde(default)]
+    pub precision: usize,
+    pub commands: SerialCommands,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SerialCommands {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focus: Option<String>,
+    /// Sent once each time autofocus is triggered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autofocus: Option<String>,
+}
+
+const fn default_baud_rate() -> u32 {
+    9600
+}
+
+fn default_line_ending() -> String {
+    "\r\n".to_owned()
+}
+
+const fn default_range() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModbusConfig {
+    /// Host and port of the Modbus TCP server, e.g. `192.168.0.50:502`.
+    pub address: String,
+    #[serde(default = "default_unit_id")]
+    pub unit_id: u8,
+    pub registers: ModbusRegisters,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModbusRegisters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan: Option<ModbusRegister>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<ModbusRegister>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<ModbusRegister>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<ModbusRegister>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focus: Option<ModbusRegister>,
+    /// Set to `scale + offset` while autofocus is held, and `offset` otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autofocus: Option<ModbusRegister>,
+}
+
+/// A holding register that an axis value (from -1 to 1) is written to as
+/// `value * scale + offset`. Negative results are written as two's complement.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ModbusRegister {
+    pub address: u16,
+    #[serde(default = "default_register_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+const fn default_unit_id() -> u8 {
+    1
+}
+
+const fn default_register_scale() -> f64 {
+    1000.0
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Mappings {
     #[serde(skip_serializing_if = "empty_or_none")]
     pub pan_l: Option<Vec<PadInput>>,
@@ -151,7 +1391,7 @@ impl Mappings {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PadInput {
     pub pad_index: usize,
@@ -163,7 +1403,7 @@ pub struct PadInput {
     pub modifiers: Option<Vec<UnmodifiedPadInput>>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct UnmodifiedPadInput {
     pub pad_index: usize,
@@ -173,12 +1413,25 @@ pub struct UnmodifiedPadInput {
     pub multiplier: f32,
 }
 
+#[cfg_attr(
+    not(any(
+        feature = "bluetooth",
+        feature = "lumix",
+        feature = "lanc",
+        feature = "panasonic"
+    )),
+    allow(dead_code)
+)]
 pub fn all_capabilities() -> HashSet<Capability> {
     HashSet::from([
         Capability::Ptr,
+        Capability::Roll,
         Capability::Zoom,
         Capability::Focus,
         Capability::Autofocus,
+        Capability::Record,
+        Capability::Iris,
+        Capability::Shutter,
     ])
 }
 
@@ -186,28 +1439,79 @@ const fn default_port() -> u16 {
     8000
 }
 
-pub async fn load_config() -> Result<Config, Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    let config_path = match args.get(1) {
-        Some(path) => path,
-        None => {
-            println!("No config path provided, defaulting to config.json");
-            "config.json"
+impl Config {
+    pub fn listen_addresses(&self) -> Vec<SocketAddr> {
+        match self.listen.is_empty() {
+            true => vec![SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), self.port)],
+            false => self
+                .listen
+                .iter()
+                .map(|&ip| SocketAddr::new(ip, self.port))
+                .collect(),
         }
-    };
-    let content = tokio::fs::read_to_string(config_path).await?;
-    let config: Config = serde_json::from_str(&content)?;
+    }
+}
+
+pub fn read_config(path: &str) -> Result<Config, Box<dyn Error>> {
+    // Read synchronously, since the config determines how the async runtime is set up
+    let content = std::fs::read_to_string(path)?;
+    let config = parse_config(&content)?;
+    Ok(Config {
+        path: Some(path.into()),
+        ..config
+    })
+}
+
+pub fn parse_config(content: &str) -> Result<Config, Box<dyn Error>> {
+    let config: Config = serde_json::from_str(content)?;
     check_duplicate_group_names(&config)?;
     detect_undefined_devices(&config)?;
+    detect_undefined_input_groups(&config)?;
+    detect_undefined_access_targets(&config)?;
+    detect_unbuilt_devices(&config)?;
     Ok(config)
 }
 
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_parse_config_arbitrary_input(content in "\\PC*") {
+        let _ = parse_config(&content);
+    }
+
+    #[test]
+    fn test_parse_config_consistency(
+        groups in proptest::collection::vec(
+            ("[a-c]", proptest::collection::vec("dev[0-4]", 0..4)),
+            0..4,
+        ),
+        device_ids in proptest::collection::vec("dev[0-4]", 0..5),
+    ) {
+        let json = serde_json::json!({
+            "groups": groups
+                .iter()
+                .map(|(name, devices)| serde_json::json!({ "name": name, "devices": devices }))
+                .collect::<Vec<_>>(),
+            "devices": device_ids
+                .iter()
+                .map(|id| (id.clone(), serde_json::json!({ "type": "dummy", "name": id })))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        if let Ok(config) = parse_config(&json.to_string()) {
+            let names: HashSet<&String> = config.groups.iter().map(|g| &g.name).collect();
+            proptest::prop_assert_eq!(names.len(), config.groups.len());
+            for id in config.groups.iter().flat_map(|g| g.devices.iter()) {
+                proptest::prop_assert!(config.devices.contains_key(id));
+            }
+        }
+    }
+}
+
 pub async fn save_config(config: &Config) -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
-    let config_path = match args.get(1) {
-        Some(path) => path,
-        None => "config.json",
-    };
+    let config_path = config
+        .path
+        .as_ref()
+        .ok_or("the config wasn't read from a file, so changes can't be saved")?;
     let content = serde_json::to_string_pretty(config)?;
     tokio::fs::write(config_path, content).await?;
     Ok(())
@@ -240,7 +1544,7 @@ fn test_check_duplicate_group_names() {
             },
         ],
         devices: IndexMap::new(),
-        default_controls: None,
+        ..Default::default()
     };
     assert!(check_duplicate_group_names(&config).is_err());
 }
@@ -252,6 +1556,15 @@ fn detect_undefined_devices(config: &Config) -> Result<(), Box<dyn Error>> {
         .iter()
         .flat_map(|g| g.devices.iter())
         .collect();
+    let used_ids: HashSet<&String> = used_ids
+        .into_iter()
+        .chain(config.health_checks.keys())
+        .chain(config.push_in.keys())
+        .chain(config.presets.keys())
+        .chain(config.mounting.keys())
+        .chain(config.trim.keys())
+        .chain(config.tags.keys())
+        .collect();
     let undefined_ids: Vec<&str> = used_ids
         .difference(&device_ids)
         .map(|&x| x.as_str())
@@ -292,11 +1605,60 @@ fn test_detect_undefined_devices() {
                 }),
             ),
         ]),
-        default_controls: None,
+        ..Default::default()
     };
     assert!(detect_undefined_devices(&config).is_err());
 }
 
+fn detect_undefined_input_groups(config: &Config) -> Result<(), Box<dyn Error>> {
+    let undefined: Vec<&str> = config
+        .inputs
+        .iter()
+        .flat_map(|i| i.groups.iter())
+        .filter(|name| !config.groups.iter().any(|g| &g.name == *name))
+        .map(|x| x.as_str())
+        .unique()
+        .collect();
+    if !undefined.is_empty() {
+        return Err(format!("input groups not defined: {}", undefined.join(", ")).into());
+    }
+    Ok(())
+}
+
+fn detect_undefined_access_targets(config: &Config) -> Result<(), Box<dyn Error>> {
+    let undefined: Vec<&str> = config
+        .access
+        .keys()
+        .filter(|&target| {
+            !config.devices.contains_key(target) && !config.groups.iter().any(|g| &g.name == target)
+        })
+        .map(|x| x.as_str())
+        .collect();
+    if !undefined.is_empty() {
+        return Err(format!(
+            "access rules for undefined devices or groups: {}",
+            undefined.join(", ")
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn detect_unbuilt_devices(config: &Config) -> Result<(), Box<dyn Error>> {
+    for (id, device) in config.devices.iter() {
+        if let Some((feature, false)) = device.feature() {
+            return Err(format!(
+                "{} is a {} device, but this build of webptz doesn't include the `{}` feature",
+                id,
+                device.type_name(),
+                feature
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
 fn empty_or_none<T>(val: &Option<Vec<T>>) -> bool {
     val.as_ref().is_none_or(|v| v.is_empty())
 }
@@ -0,0 +1,149 @@
+use std::error::Error;
+
+use futures::{SinkExt as _, StreamExt as _};
+use serde_json::{json, Value};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const DEFAULT_URL: &str = "ws://localhost:8000/control";
+const USAGE: &str = "usage: webptz ctl [--url <ws-url>] <action>
+
+actions:
+  command <devices> [pan=<v>] [tilt=<v>] [roll=<v>] [zoom=<v>] [focus=<v>] [autofocus]
+  stop <devices>
+  stop-all
+  disconnect <devices>
+  reconnect <devices>
+  repair <devices>
+  wake <devices>
+  push-in <devices> <zoom> <duration-ms>
+  preset save <device> <slot>
+  preset recall <device> <slot> [duration-ms]
+  preset guide <device> [slot]
+
+<devices> is a comma-separated list of device IDs, or tag:<tag> for every device with a tag";
+
+/// Entry point for `webptz ctl`, which sends one-off requests to a running instance.
+pub async fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (url, args) = match args {
+        [flag, url, rest @ ..] if flag == "--url" => (url.as_str(), rest),
+        _ => (DEFAULT_URL, args),
+    };
+    let (mut socket, _) = connect_async(url).await?;
+
+    // The server sends its state as soon as the socket opens
+    let state = loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => break serde_json::from_str::<Value>(&text)?,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err("connection closed before receiving state".into()),
+        }
+    };
+
+    for request in build_requests(args, &state)? {
+        println!("Sending {}", request);
+        socket.send(Message::Text(request.to_string())).await?;
+    }
+    socket.close(None).await?;
+    Ok(())
+}
+
+fn build_requests(args: &[String], state: &Value) -> Result<Vec<Value>, Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let requests = match args[..] {
+        ["command", devices, ref axes @ ..] => vec![command_request(devices, axes)?],
+        ["stop", devices] => vec![command_request(devices, &[])?],
+        ["stop-all"] => {
+            let devices = state["devices"]
+                .as_object()
+                .map(|d| d.keys().cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            vec![command_request(&devices.join(","), &[])?]
+        }
+        [action @ ("disconnect" | "reconnect" | "repair" | "wake"), devices] => {
+            vec![json!({ action: { "devices": split_devices(devices) } })]
+        }
+        ["push-in", devices, zoom, duration_ms] => vec![json!({
+            "pushIn": {
+                "devices": split_devices(devices),
+                "zoom": zoom.parse::<f64>()?,
+                "durationMs": duration_ms.parse::<u64>()?,
+            }
+        })],
+        ["preset", "save", device, slot] => vec![json!({
+            "savePreset": { "device": device, "slot": slot.parse::<u32>()? }
+        })],
+        ["preset", "recall", device, slot, ref duration_ms @ ..] if duration_ms.len() <= 1 => {
+            let mut request = json!({ "device": device, "slot": slot.parse::<u32>()? });
+            if let [duration_ms] = duration_ms {
+                request["durationMs"] = json!(duration_ms.parse::<u64>()?);
+            }
+            vec![json!({ "recallPreset": request })]
+        }
+        ["preset", "guide", device, ref slot @ ..] if slot.len() <= 1 => {
+            let slot = slot.first().map(|s| s.parse::<u32>()).transpose()?;
+            vec![json!({ "guidePreset": { "device": device, "slot": slot } })]
+        }
+        _ => return Err(USAGE.into()),
+    };
+    Ok(requests)
+}
+
+fn split_devices(devices: &str) -> Vec<&str> {
+    devices.split(',').filter(|d| !d.is_empty()).collect()
+}
+
+fn command_request(devices: &str, axes: &[&str]) -> Result<Value, Box<dyn Error>> {
+    let mut command = json!({
+        "devices": split_devices(devices),
+        "pan": 0.0,
+        "tilt": 0.0,
+        "roll": 0.0,
+        "zoom": 0.0,
+        "focus": 0.0,
+        "autofocus": false,
+    });
+    for axis in axes {
+        match axis.split_once('=') {
+            None if *axis == "autofocus" => command["autofocus"] = json!(true),
+            Some((name @ ("pan" | "tilt" | "roll" | "zoom" | "focus"), value)) => {
+                command[name] = json!(value.parse::<f64>()?)
+            }
+            _ => return Err(format!("invalid axis value `{}`\n\n{}", axis, USAGE).into()),
+        }
+    }
+    Ok(json!({ "command": command }))
+}
+
+#[test]
+fn test_build_requests() {
+    let args = |s: &str| s.split(' ').map(str::to_owned).collect::<Vec<_>>();
+    let state = json!({ "devices": { "cam1": {}, "cam2": {} } });
+
+    let requests = build_requests(&args("command cam1,cam2 pan=0.5 autofocus"), &state).unwrap();
+    assert_eq!(requests[0]["command"]["devices"], json!(["cam1", "cam2"]));
+    assert_eq!(requests[0]["command"]["pan"], json!(0.5));
+    assert_eq!(requests[0]["command"]["autofocus"], json!(true));
+
+    let requests = build_requests(&args("stop-all"), &state).unwrap();
+    assert_eq!(requests[0]["command"]["devices"], json!(["cam1", "cam2"]));
+    assert_eq!(requests[0]["command"]["zoom"], json!(0.0));
+
+    let requests = build_requests(&args("reconnect cam2"), &state).unwrap();
+    assert_eq!(requests[0], json!({ "reconnect": { "devices": ["cam2"] } }));
+
+    let requests = build_requests(&args("preset recall cam1 3 1500"), &state).unwrap();
+    assert_eq!(
+        requests[0],
+        json!({ "recallPreset": { "device": "cam1", "slot": 3, "durationMs": 1500 } })
+    );
+
+    let requests = build_requests(&args("preset guide cam1"), &state).unwrap();
+    assert_eq!(
+        requests[0],
+        json!({ "guidePreset": { "device": "cam1", "slot": null } })
+    );
+
+    assert!(build_requests(&args("command cam1 yaw=1"), &state).is_err());
+    assert!(build_requests(&args("bogus"), &state).is_err());
+}
@@ -1,39 +1,712 @@
-use std::error::Error;
+use std::{error::Error, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use serde::Deserialize;
+#[cfg(feature = "bluetooth")]
+use btleplug::{api::Manager as _, platform::Manager};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::sync::watch;
 
+#[cfg(feature = "panasonic")]
+pub mod aw;
+#[cfg(feature = "bluetooth")]
+pub mod ble;
+#[cfg(feature = "panasonic")]
+pub mod camcorder;
+#[cfg(feature = "canon")]
+pub mod canon_ccapi;
+#[cfg(feature = "canon")]
+pub mod canon_usb;
+#[cfg(feature = "bluetooth")]
 pub mod crane;
+#[cfg(feature = "bluetooth")]
+mod duml;
 pub mod dummy;
+#[cfg(feature = "serial")]
+pub mod fcb;
+#[cfg(feature = "bluetooth")]
+pub mod feiyu;
+#[cfg(feature = "bluetooth")]
+pub mod generic_ble;
+#[cfg(feature = "lanc")]
 pub mod lanc;
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+mod lanc_gpio;
+#[cfg(feature = "lumix")]
 pub mod lumix;
+pub mod modbus;
+#[cfg(feature = "onvif")]
+pub mod onvif;
+#[cfg(feature = "serial")]
+pub mod pelco;
+#[cfg(feature = "bluetooth")]
 pub mod ronin;
+#[cfg(feature = "bluetooth")]
+mod rsdk;
+#[cfg(feature = "script")]
+pub mod script;
+#[cfg(feature = "serial")]
+pub mod serial;
+#[cfg(feature = "sony")]
+pub mod sony;
+#[cfg(feature = "uvc")]
+pub mod uvc;
+#[cfg(feature = "lumix")]
+mod wol;
 
-#[derive(Deserialize, Debug, Copy, Clone)]
+#[cfg(feature = "bluetooth")]
+pub use btleplug::platform::Adapter;
+
+/// Stands in for the Bluetooth adapter in builds without the `bluetooth` feature, so that
+/// everything that passes one around doesn't need to know.
+#[cfg(not(feature = "bluetooth"))]
+#[derive(Clone, Debug)]
+pub struct Adapter;
+
+#[cfg_attr(not(feature = "bluetooth"), allow(dead_code))]
+pub enum AdapterEvent {
+    Lost,
+    Restored(Adapter),
+}
+
+/// The span that everything done with a device is logged in, so that backends don't need to say
+/// which device they're logging about.
+pub fn span(id: &str) -> tracing::Span {
+    tracing::info_span!("device", id)
+}
+
+/// The first Bluetooth adapter, which Bluetooth devices connect through.
+#[cfg(feature = "bluetooth")]
+pub async fn bluetooth_adapter() -> Result<Adapter, Box<dyn Error>> {
+    use btleplug::api::Central as _;
+
+    let manager = Manager::new().await?;
+    let adapters = manager.adapters().await?;
+    let central = adapters
+        .into_iter()
+        .next()
+        .ok_or("no bluetooth adapter found")?;
+    tracing::info!("Using adapter: {}", central.adapter_info().await?);
+    Ok(central)
+}
+
+#[cfg(not(feature = "bluetooth"))]
+pub async fn bluetooth_adapter() -> Result<Adapter, Box<dyn Error>> {
+    Ok(Adapter)
+}
+
+/// A normalized speed, from -1 (full speed one way) to 1 (full speed the other way). Values are
+/// clamped to that range on the way in, so out-of-range client input can't overflow the packet
+/// encoders.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+#[serde(from = "f64", into = "f64")]
+pub struct Velocity(f64);
+
+impl Velocity {
+    pub const STOP: Velocity = Velocity(0.0);
+
+    pub fn new(value: f64) -> Velocity {
+        match value.is_nan() {
+            true => Velocity::STOP,
+            false => Velocity(value.clamp(-1.0, 1.0)),
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    pub fn is_stopped(self) -> bool {
+        self.0 == 0.0
+    }
+}
+
+impl From<f64> for Velocity {
+    fn from(value: f64) -> Self {
+        Velocity::new(value)
+    }
+}
+
+impl From<Velocity> for f64 {
+    fn from(value: Velocity) -> Self {
+        value.0
+    }
+}
+
+impl std::ops::Neg for Velocity {
+    type Output = Velocity;
+
+    fn neg(self) -> Velocity {
+        Velocity(-self.0)
+    }
+}
+
+/// An angle in degrees, wrapped to [-180, 180).
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+#[serde(from = "f64", into = "f64")]
+pub struct AngleDeg(f64);
+
+impl AngleDeg {
+    pub fn new(degrees: f64) -> AngleDeg {
+        AngleDeg((degrees + 180.0).rem_euclid(360.0) - 180.0)
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// The shortest signed rotation from `other` to `self`.
+    pub fn minus(self, other: AngleDeg) -> f64 {
+        AngleDeg::new(self.0 - other.0).0
+    }
+}
+
+impl From<f64> for AngleDeg {
+    fn from(degrees: f64) -> Self {
+        AngleDeg::new(degrees)
+    }
+}
+
+impl From<AngleDeg> for f64 {
+    fn from(value: AngleDeg) -> Self {
+        value.0
+    }
+}
+
+/// How far a zoom lens is extended, from 0 (wide) to 1 (tele).
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq, PartialOrd)]
+#[serde(from = "f64", into = "f64")]
+pub struct ZoomRatio(f64);
+
+impl ZoomRatio {
+    pub fn new(value: f64) -> ZoomRatio {
+        match value.is_nan() {
+            true => ZoomRatio(0.0),
+            false => ZoomRatio(value.clamp(0.0, 1.0)),
+        }
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl From<f64> for ZoomRatio {
+    fn from(value: f64) -> Self {
+        ZoomRatio::new(value)
+    }
+}
+
+impl From<ZoomRatio> for f64 {
+    fn from(value: ZoomRatio) -> Self {
+        value.0
+    }
+}
+
+#[test]
+fn test_axis_values() {
+    let velocity: Velocity = serde_json::from_str("-3.5").unwrap();
+    assert_eq!(velocity.get(), -1.0);
+    assert_eq!(Velocity::new(f64::NAN), Velocity::STOP);
+    assert_eq!(
+        serde_json::to_string(&-Velocity::new(0.25)).unwrap(),
+        "-0.25"
+    );
+
+    let angle: AngleDeg = serde_json::from_str("190").unwrap();
+    assert_eq!(angle.get(), -170.0);
+    assert_eq!(AngleDeg::new(-170.0).minus(AngleDeg::new(170.0)), 20.0);
+
+    let zoom: ZoomRatio = serde_json::from_str("1.5").unwrap();
+    assert_eq!(zoom.get(), 1.0);
+}
+
+#[derive(Deserialize, Debug, Copy, Clone, Default, PartialEq)]
 pub struct Command {
-    pub pan: f64,
-    pub tilt: f64,
-    pub roll: f64,
-    pub zoom: f64,
-    pub focus: f64,
+    pub pan: Velocity,
+    pub tilt: Velocity,
+    pub roll: Velocity,
+    pub zoom: Velocity,
+    pub focus: Velocity,
+    /// Opens (positive) or closes (negative) the iris.
+    #[serde(default)]
+    pub iris: Velocity,
     pub autofocus: bool,
+    /// Starts (`true`) or stops (`false`) recording, for cameras that can. Left out to leave
+    /// recording as it is.
+    #[serde(default)]
+    pub record: Option<bool>,
+    /// Takes a still photo.
+    #[serde(default)]
+    pub shutter: bool,
+    /// Moves the AF area to a point in the frame and focuses there, e.g. where the preview was
+    /// tapped.
+    #[serde(default, rename = "focusPoint")]
+    pub focus_point: Option<FocusPoint>,
+}
+
+/// A point in the frame, from 0 to 1 across from the left and down from the top.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct FocusPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Command {
+    /// Whether sending `next` after `self` would be redundant: every axis moved by less than
+    /// `epsilon`, no axis started or stopped moving, and no one-shot action was requested.
+    pub fn within_deadband(&self, next: &Command, epsilon: f64) -> bool {
+        if epsilon <= 0.0
+            || next.autofocus
+            || next.record.is_some()
+            || next.shutter
+            || next.focus_point.is_some()
+        {
+            return false;
+        }
+        let axes = [
+            (self.pan, next.pan),
+            (self.tilt, next.tilt),
+            (self.roll, next.roll),
+            (self.zoom, next.zoom),
+            (self.focus, next.focus),
+            (self.iris, next.iris),
+        ];
+        axes.iter()
+            .all(|&(a, b)| a.is_stopped() == b.is_stopped() && (a.get() - b.get()).abs() < epsilon)
+    }
+}
+
+#[test]
+fn test_within_deadband() {
+    let base = Command {
+        pan: Velocity::new(0.5),
+        tilt: Velocity::new(-0.25),
+        roll: Velocity::new(0.0),
+        zoom: Velocity::new(0.0),
+        focus: Velocity::new(0.1),
+        iris: Velocity::new(0.0),
+        autofocus: false,
+        record: None,
+        shutter: false,
+        focus_point: None,
+    };
+    let nudged = Command {
+        pan: Velocity::new(0.51),
+        focus: Velocity::new(0.099),
+        ..base
+    };
+    assert!(base.within_deadband(&nudged, 0.02));
+    assert!(!base.within_deadband(&nudged, 0.0));
+    assert!(!base.within_deadband(
+        &Command {
+            pan: Velocity::new(0.6),
+            ..base
+        },
+        0.02
+    ));
+    assert!(!base.within_deadband(
+        &Command {
+            zoom: Velocity::new(0.001),
+            ..base
+        },
+        0.02
+    ));
+    assert!(!base.within_deadband(
+        &Command {
+            focus: Velocity::new(0.0),
+            ..base
+        },
+        0.5
+    ));
+    assert!(!base.within_deadband(
+        &Command {
+            autofocus: true,
+            ..base
+        },
+        0.02
+    ));
+    assert!(!base.within_deadband(
+        &Command {
+            record: Some(true),
+            ..base
+        },
+        0.02
+    ));
+    assert!(!base.within_deadband(
+        &Command {
+            shutter: true,
+            ..base
+        },
+        0.02
+    ));
+    assert!(!base.within_deadband(
+        &Command {
+            focus_point: Some(FocusPoint { x: 0.5, y: 0.5 }),
+            ..base
+        },
+        0.02
+    ));
+}
+
+/// A camera's live view, as a stream of JPEG frames that are `None` while nothing is coming in.
+/// Cameras only stream while someone is subscribed.
+#[derive(Clone)]
+pub struct LiveView(Arc<watch::Sender<Option<Arc<[u8]>>>>);
+
+impl LiveView {
+    pub fn new() -> LiveView {
+        LiveView(Arc::new(watch::channel(None).0))
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Option<Arc<[u8]>>> {
+        self.0.subscribe()
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.0.receiver_count() > 0
+    }
+
+    pub fn send(&self, frame: Arc<[u8]>) {
+        self.0.send_replace(Some(frame));
+    }
+
+    /// Tells viewers that frames have stopped coming in.
+    pub fn stall(&self) {
+        self.0.send_if_modified(|frame| frame.take().is_some());
+    }
+}
+
+impl Default for LiveView {
+    fn default() -> Self {
+        LiveView::new()
+    }
+}
+
+/// Where a device is pointing, as far as it can tell. Values increase in the direction positive
+/// commands move them.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq)]
+pub struct Position {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pan: Option<AngleDeg>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<AngleDeg>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roll: Option<AngleDeg>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<ZoomRatio>,
+}
+
+/// An exposure setting, in the same units on every camera so settings read from one can be sent
+/// to another: ISO as is, shutter speed in seconds, and aperture as an F-number.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Property {
+    Iso,
+    ShutterSpeed,
+    Aperture,
+}
+
+/// An absolute position for a gimbal to move to, in the device's own axes. Axes that are left
+/// out stay where they are.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MoveTo {
+    pub pan: Option<AngleDeg>,
+    pub tilt: Option<AngleDeg>,
+    pub roll: Option<AngleDeg>,
+    /// How long the move should take, for devices that can be told.
+    pub duration: Duration,
+}
+
+/// What went wrong with a device, in enough detail for clients to tell e.g. a device that just
+/// isn't connected from one that answered with something unexpected.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum DeviceError {
+    #[error("not connected")]
+    NotConnected,
+    /// The device didn't answer in time.
+    #[error("{0}")]
+    Timeout(String),
+    /// The link to the device failed: a socket, serial port, or Bluetooth error.
+    #[error("{0}")]
+    Transport(String),
+    /// The device answered, but with an error or something that couldn't be understood.
+    #[error("{0}")]
+    Protocol(String),
+    /// The device can't do what was asked.
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+impl DeviceError {
+    /// The variant's name, as sent to clients.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DeviceError::NotConnected => "notConnected",
+            DeviceError::Timeout(_) => "timeout",
+            DeviceError::Transport(_) => "transport",
+            DeviceError::Protocol(_) => "protocol",
+            DeviceError::Unsupported(_) => "unsupported",
+        }
+    }
+
+    /// For errors from parsing what a device sent, which don't have a conversion of their own.
+    #[cfg_attr(
+        not(any(feature = "lumix", feature = "canon", feature = "onvif")),
+        allow(dead_code)
+    )]
+    pub fn protocol(error: impl std::fmt::Display) -> DeviceError {
+        DeviceError::Protocol(error.to_string())
+    }
+}
+
+// Errors described by backends themselves are mostly about what the device said
+impl From<String> for DeviceError {
+    fn from(message: String) -> Self {
+        DeviceError::Protocol(message)
+    }
+}
+
+impl From<&str> for DeviceError {
+    fn from(message: &str) -> Self {
+        DeviceError::Protocol(message.to_owned())
+    }
+}
+
+impl From<std::io::Error> for DeviceError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::TimedOut => DeviceError::Timeout(error.to_string()),
+            std::io::ErrorKind::NotConnected => DeviceError::NotConnected,
+            _ => DeviceError::Transport(error.to_string()),
+        }
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for DeviceError {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        DeviceError::Timeout("timed out".to_owned())
+    }
+}
+
+impl From<serde_json::Error> for DeviceError {
+    fn from(error: serde_json::Error) -> Self {
+        DeviceError::Protocol(error.to_string())
+    }
+}
+
+#[cfg(feature = "bluetooth")]
+impl From<btleplug::Error> for DeviceError {
+    fn from(error: btleplug::Error) -> Self {
+        match error {
+            btleplug::Error::NotConnected => DeviceError::NotConnected,
+            btleplug::Error::TimedOut(_) => DeviceError::Timeout(error.to_string()),
+            btleplug::Error::NotSupported(_) => DeviceError::Unsupported(error.to_string()),
+            _ => DeviceError::Transport(error.to_string()),
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "lumix",
+    feature = "panasonic",
+    feature = "canon",
+    feature = "sony",
+    feature = "onvif",
+    feature = "health"
+))]
+impl From<reqwest::Error> for DeviceError {
+    fn from(error: reqwest::Error) -> Self {
+        match error.is_timeout() {
+            true => DeviceError::Timeout(error.to_string()),
+            false => DeviceError::Transport(error.to_string()),
+        }
+    }
+}
+
+#[cfg(any(feature = "script", feature = "lanc", feature = "serial"))]
+impl From<tokio_serial::Error> for DeviceError {
+    fn from(error: tokio_serial::Error) -> Self {
+        DeviceError::Transport(error.to_string())
+    }
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl From<rppal::gpio::Error> for DeviceError {
+    fn from(error: rppal::gpio::Error) -> Self {
+        DeviceError::Transport(error.to_string())
+    }
 }
 
 #[async_trait]
-pub trait Device: std::fmt::Display {
-    async fn send_command(&mut self, command: Command) -> Result<(), Box<dyn Error>>;
+pub trait Device: std::fmt::Display + Send {
+    async fn send_command(&mut self, command: Command) -> Result<(), DeviceError>;
+
+    /// Does any slow work needed before `send_command` (resuming connections, building
+    /// packets) so that synchronized sends to several devices go out as close together as
+    /// possible.
+    async fn prepare_command(&mut self, _command: Command) -> Result<(), DeviceError> {
+        Ok(())
+    }
+
+    /// Stops every axis ahead of a disconnect, waiting for the device to acknowledge it where
+    /// the protocol allows. Some gimbals carry on at their last velocity if the link drops
+    /// before the stop gets there.
+    async fn send_stop(&mut self) -> Result<(), DeviceError> {
+        self.send_command(Command::default()).await
+    }
 
-    async fn connect(&mut self) -> Result<(), Box<dyn Error>>;
+    async fn connect(&mut self) -> Result<(), DeviceError>;
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Error>>;
+    async fn disconnect(&mut self) -> Result<(), DeviceError>;
 
-    async fn reconnect(&mut self) -> Result<(), Box<dyn Error>>;
+    async fn reconnect(&mut self) -> Result<(), DeviceError>;
+
+    /// Forgets any cached pairing with the device and connects to it from scratch.
+    async fn repair(&mut self) -> Result<(), DeviceError> {
+        self.reconnect().await
+    }
+
+    fn supports_repair(&self) -> bool {
+        false
+    }
+
+    /// Powers the device up remotely (e.g. via Wake-on-LAN) and connects once it's reachable.
+    async fn wake(&mut self) -> Result<(), DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} doesn't support waking",
+            self
+        )))
+    }
+
+    fn supports_wake(&self) -> bool {
+        false
+    }
+
+    /// Called periodically while connected. Returned values are merged into the device's
+    /// telemetry.
+    async fn poll_telemetry(&mut self) -> Result<Map<String, Value>, DeviceError> {
+        Ok(Map::new())
+    }
+
+    /// Live values the backend already has on hand, such as what the device last reported on
+    /// its own. Read whenever the device's status is refreshed, so it mustn't wait on the
+    /// device, and merged over the values from `poll_telemetry`.
+    fn telemetry(&self) -> Map<String, Value> {
+        Map::new()
+    }
+
+    /// Grabs a small JPEG of what the camera currently sees, for devices with a live view.
+    async fn thumbnail(&mut self) -> Result<Vec<u8>, DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} doesn't have a live view",
+            self
+        )))
+    }
+
+    fn supports_thumbnails(&self) -> bool {
+        false
+    }
+
+    /// The camera's live view, for devices that can stream one.
+    fn live_view(&self) -> Option<LiveView> {
+        None
+    }
+
+    /// Stores the current position in one of the device's own preset memories.
+    async fn save_preset(&mut self, _slot: u32) -> Result<(), DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} doesn't store presets",
+            self
+        )))
+    }
+
+    /// Moves to a position stored with `save_preset`, at the device's own preset speed.
+    async fn recall_preset(&mut self, _slot: u32) -> Result<(), DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} doesn't store presets",
+            self
+        )))
+    }
+
+    /// Moves straight to an absolute position, for gimbals that take angles as well as speeds.
+    async fn move_to(&mut self, _target: MoveTo) -> Result<(), DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} can't move to a position",
+            self
+        )))
+    }
+
+    /// Changes an exposure setting, for cameras that can be set remotely.
+    async fn set_property(&mut self, property: Property, _value: f64) -> Result<(), DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} can't set {:?}",
+            self, property
+        )))
+    }
+
+    /// Sends one of the commands configured for the device by name, for functions that aren't
+    /// otherwise modeled.
+    async fn custom_command(&mut self, name: &str) -> Result<(), DeviceError> {
+        Err(DeviceError::Unsupported(format!(
+            "{} has no custom command {}",
+            self, name
+        )))
+    }
+
+    /// Whether presets are stored on the device rather than from its position feedback.
+    fn supports_presets(&self) -> bool {
+        false
+    }
+
+    /// The last position reported by the device, for devices with position feedback.
+    fn position(&self) -> Option<Position> {
+        None
+    }
+
+    /// The lens that's attached, for cameras that can tell.
+    fn lens(&self) -> Option<String> {
+        None
+    }
+
+    fn uses_bluetooth(&self) -> bool {
+        false
+    }
+
+    /// Switches a Bluetooth device over to a newly enumerated adapter.
+    fn set_adapter(&mut self, _adapter: &Adapter) {}
 
     fn is_connected(&self) -> bool;
 
+    /// Actively checks whether the connection is still alive, for devices whose links can drop
+    /// without `is_connected` noticing.
+    async fn check_connection(&mut self) -> bool {
+        self.is_connected()
+    }
+
     fn name(&self) -> String {
         format!("{}", self)
     }
 
     fn id(&self) -> String;
 }
+
+/// Parses a golden fixture file into `(inputs, expected)` pairs. Each non-empty line that doesn't
+/// start with `#` is a whitespace-separated list of inputs followed by the expected output.
+#[cfg(test)]
+#[cfg_attr(
+    not(any(feature = "bluetooth", feature = "lumix", feature = "lanc")),
+    allow(dead_code)
+)]
+fn golden_cases(fixture: &str) -> Vec<(Vec<&str>, &str)> {
+    fixture
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let mut fields: Vec<&str> = l.split_whitespace().collect();
+            let expected = fields.pop().unwrap();
+            (fields, expected)
+        })
+        .collect()
+}
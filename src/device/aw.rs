@@ -0,0 +1,201 @@
+use std::{collections::HashSet, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use super::{
+    camcorder::{check_response, speed},
+    DeviceError,
+};
+use crate::config::{all_capabilities, AwConfig, Capability};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// The cameras ignore commands sent less than this long after the previous one.
+const COMMAND_INTERVAL: Duration = Duration::from_millis(130);
+const PRESET_COUNT: u32 = 100;
+
+/// Panasonic AW-UE/AW-HE PTZ cameras, driven through their HTTP CGI commands.
+pub struct Aw {
+    id: String,
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    capabilities: HashSet<Capability>,
+    client: Option<Client>,
+    last_sent: Option<Instant>,
+    pan_tilt_speed: (u8, u8),
+    zoom_speed: u8,
+    focus_speed: u8,
+    autofocus: bool,
+}
+
+impl std::fmt::Display for Aw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AW[{}]", self.address)
+    }
+}
+
+impl Aw {
+    /// Sends a command to the `aw_ptz` (head and lens) or `aw_cam` (camera) endpoint and returns
+    /// the camera's response.
+    async fn send(&mut self, endpoint: &str, cmd: &str) -> Result<String, DeviceError> {
+        let Some(client) = &self.client else {
+            return Err(DeviceError::NotConnected);
+        };
+        if let Some(last_sent) = self.last_sent {
+            tokio::time::sleep_until(last_sent + COMMAND_INTERVAL).await;
+        }
+        let mut request = client
+            .get(format!("http://{}/cgi-bin/{}", self.address, endpoint))
+            .query(&[("cmd", cmd), ("res", "1")])
+            .timeout(TIMEOUT);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        self.last_sent = Some(Instant::now());
+        let response = request.send().await?.error_for_status()?.text().await?;
+        check_response(&response)?;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl super::Device for Aw {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        self.client = Some(Client::new());
+        self.last_sent = None;
+        match self.send("aw_cam", "QID").await {
+            Ok(model) => info!("Connected to {}", model.trim()),
+            Err(e) => {
+                self.client = None;
+                return Err(e);
+            }
+        }
+        self.pan_tilt_speed = (speed(0.0), speed(0.0));
+        self.zoom_speed = speed(0.0);
+        self.focus_speed = speed(0.0);
+        self.autofocus = false;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.client.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    async fn save_preset(&mut self, slot: u32) -> Result<(), DeviceError> {
+        let cmd = format!("#M{:02}", preset_number(slot)?);
+        self.send("aw_ptz", &cmd).await?;
+        Ok(())
+    }
+
+    async fn recall_preset(&mut self, slot: u32) -> Result<(), DeviceError> {
+        let cmd = format!("#R{:02}", preset_number(slot)?);
+        self.send("aw_ptz", &cmd).await?;
+        Ok(())
+    }
+
+    fn supports_presets(&self) -> bool {
+        true
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.client.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+
+        let pan_tilt_speed = (speed(command.pan.get()), speed(command.tilt.get()));
+        if self.capabilities.contains(&Capability::Ptr) && pan_tilt_speed != self.pan_tilt_speed {
+            let (pan, tilt) = pan_tilt_speed;
+            self.send("aw_ptz", &format!("#PTS{:02}{:02}", pan, tilt))
+                .await?;
+            self.pan_tilt_speed = pan_tilt_speed;
+        }
+
+        let zoom_speed = speed(command.zoom.get());
+        if self.capabilities.contains(&Capability::Zoom) && zoom_speed != self.zoom_speed {
+            self.send("aw_ptz", &format!("#Z{:02}", zoom_speed)).await?;
+            self.zoom_speed = zoom_speed;
+        }
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus && !self.autofocus {
+            // One-push autofocus
+            self.send("aw_cam", "OSE:69:1").await?;
+        }
+        self.autofocus = autofocus;
+
+        let focus_speed = speed(command.focus.get());
+        if self.capabilities.contains(&Capability::Focus)
+            && !autofocus
+            && focus_speed != self.focus_speed
+        {
+            self.send("aw_ptz", &format!("#F{:02}", focus_speed))
+                .await?;
+            self.focus_speed = focus_speed;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a preset slot, numbered from 1 as on the camera's menus, into the protocol's preset
+/// number, which starts at 0.
+fn preset_number(slot: u32) -> Result<u32, DeviceError> {
+    match slot {
+        1..=PRESET_COUNT => Ok(slot - 1),
+        _ => Err(DeviceError::Unsupported(format!(
+            "presets are numbered 1 to {}",
+            PRESET_COUNT
+        ))),
+    }
+}
+
+pub fn create(id: &str, config: &AwConfig) -> Aw {
+    Aw {
+        id: id.to_owned(),
+        address: config.address.clone(),
+        username: config.username.clone(),
+        password: config.password.as_deref().cloned(),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(all_capabilities),
+        client: None,
+        last_sent: None,
+        pan_tilt_speed: (speed(0.0), speed(0.0)),
+        zoom_speed: speed(0.0),
+        focus_speed: speed(0.0),
+        autofocus: false,
+    }
+}
+
+#[test]
+fn test_preset_number() {
+    assert_eq!(preset_number(1).unwrap(), 0);
+    assert_eq!(preset_number(100).unwrap(), 99);
+    assert!(preset_number(0).is_err());
+    assert!(preset_number(101).is_err());
+}
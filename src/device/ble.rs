@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use btleplug::{
+    api::{
+        bleuuid::uuid_from_u16, Central as _, CentralEvent, CentralState, Manager as _,
+        Peripheral as _, ScanFilter,
+    },
+    platform::{Adapter, Manager, Peripheral},
+};
+use futures::StreamExt as _;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::{AdapterEvent, DeviceError};
+
+const ADAPTER_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const ADAPTER_RETRY_MAX: Duration = Duration::from_secs(30);
+/// The Battery Level characteristic of the standard Battery Service.
+const BATTERY_LEVEL_UUID: uuid::Uuid = uuid_from_u16(0x2a19);
+
+/// The signal strength the adapter last saw, and the battery level for peripherals with the
+/// standard Battery Service, as `rssi` and `battery` telemetry.
+pub async fn link_telemetry(peripheral: &Peripheral) -> Result<Map<String, Value>, DeviceError> {
+    let mut values = Map::new();
+    if let Some(rssi) = peripheral.properties().await?.and_then(|p| p.rssi) {
+        values.insert("rssi".to_owned(), rssi.into());
+    }
+    let battery = peripheral
+        .characteristics()
+        .into_iter()
+        .find(|c| c.uuid == BATTERY_LEVEL_UUID);
+    if let Some(characteristic) = battery {
+        if let Some(&level) = peripheral.read(&characteristic).await?.first() {
+            values.insert("battery".to_owned(), level.into());
+        }
+    }
+    Ok(values)
+}
+
+/// Scans for the peripheral advertising `name`, for up to five seconds.
+pub async fn find_peripheral(adapter: &Adapter, name: &str) -> Result<Peripheral, DeviceError> {
+    adapter.start_scan(ScanFilter::default()).await?;
+
+    for _ in 0..10 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let peripherals = adapter.peripherals().await?;
+        for p in peripherals {
+            if p.properties()
+                .await?
+                .and_then(|p| p.local_name)
+                .map(|n| n == name)
+                .unwrap_or(false)
+            {
+                adapter.stop_scan().await?;
+                return Ok(p);
+            }
+        }
+    }
+
+    adapter.stop_scan().await?;
+    Err(DeviceError::Transport(format!(
+        "unable to find peripheral {}",
+        name
+    )))
+}
+
+/// Disconnects from `name` and forgets its bond, so it can be connected to again from scratch.
+pub async fn repair(
+    adapter: &Adapter,
+    name: &str,
+    connected: Option<&Peripheral>,
+) -> Result<(), DeviceError> {
+    info!("Re-pairing");
+    if let Some(peripheral) = connected {
+        if let Err(e) = peripheral.disconnect().await {
+            warn!("Error disconnecting: {}", e);
+        }
+    }
+    match find_peripheral(adapter, name).await {
+        Ok(p) => forget_peripheral(&p).await,
+        Err(e) => {
+            info!("No known peripheral to forget: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Drops the operating system's bond with a peripheral so the next scan and service discovery
+/// starts from scratch. Stale bonds are a common reason a gimbal refuses to reconnect.
+pub async fn forget_peripheral(peripheral: &Peripheral) -> Result<(), DeviceError> {
+    if peripheral.is_connected().await.unwrap_or(false) {
+        peripheral.disconnect().await?;
+    }
+    remove_bond(&peripheral.address().to_string()).await
+}
+
+#[cfg(target_os = "linux")]
+async fn remove_bond(address: &str) -> Result<(), DeviceError> {
+    let output = tokio::process::Command::new("bluetoothctl")
+        .args(["remove", address])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(DeviceError::Transport(format!(
+            "bluetoothctl failed to remove {}: {}",
+            address,
+            String::from_utf8_lossy(&output.stdout).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn remove_bond(address: &str) -> Result<(), DeviceError> {
+    info!(
+        "Removing the bond for {} isn't supported on this platform, only rescanning",
+        address
+    );
+    Ok(())
+}
+
+/// Watches the Bluetooth adapter and reports when it disappears or powers off, then keeps
+/// re-enumerating adapters (after running the optional reset command) until one comes back.
+pub async fn monitor_adapter(
+    mut adapter: Adapter,
+    reset_command: Option<Vec<String>>,
+    events: mpsc::UnboundedSender<AdapterEvent>,
+) {
+    let manager = match Manager::new().await {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("Can't monitor the Bluetooth adapter: {}", e);
+            return;
+        }
+    };
+    loop {
+        wait_for_adapter_loss(&adapter).await;
+        warn!("Bluetooth adapter lost");
+        if events.send(AdapterEvent::Lost).is_err() {
+            return;
+        }
+        if let Some(command) = &reset_command {
+            run_reset_command(command).await;
+        }
+        adapter = wait_for_adapter(&manager).await;
+        info!("Bluetooth adapter restored");
+        if events
+            .send(AdapterEvent::Restored(adapter.clone()))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+async fn wait_for_adapter_loss(adapter: &Adapter) {
+    let mut events = adapter.events().await.ok();
+    let mut poll = tokio::time::interval(ADAPTER_POLL_INTERVAL);
+    loop {
+        match &mut events {
+            Some(stream) => tokio::select! {
+                event = stream.next() => match event {
+                    Some(CentralEvent::StateUpdate(CentralState::PoweredOff)) | None => return,
+                    _ => continue,
+                },
+                _ = poll.tick() => (),
+            },
+            None => {
+                poll.tick().await;
+            }
+        }
+        match adapter.adapter_state().await {
+            Ok(CentralState::PoweredOff) | Err(_) => return,
+            Ok(_) => (),
+        }
+    }
+}
+
+async fn wait_for_adapter(manager: &Manager) -> Adapter {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        tokio::time::sleep(delay).await;
+        for adapter in manager.adapters().await.unwrap_or_default() {
+            if let Ok(CentralState::PoweredOn) = adapter.adapter_state().await {
+                return adapter;
+            }
+        }
+        delay = (delay * 2).min(ADAPTER_RETRY_MAX);
+    }
+}
+
+async fn run_reset_command(command: &[String]) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+    info!("Running Bluetooth reset command {:?}", command);
+    match tokio::process::Command::new(program)
+        .args(args)
+        .status()
+        .await
+    {
+        Ok(status) if status.success() => (),
+        Ok(status) => info!("Bluetooth reset command exited with {}", status),
+        Err(e) => warn!("Failed to run Bluetooth reset command: {}", e),
+    }
+}
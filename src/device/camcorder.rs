@@ -0,0 +1,171 @@
+use std::{collections::HashSet, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use tracing::{debug, info};
+
+use super::DeviceError;
+use crate::config::{all_capabilities, CamcorderConfig, Capability};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Panasonic AG-CX/HC-X camcorders, driven through the same HTTP commands as Panasonic's IP
+/// remote controllers.
+pub struct Camcorder {
+    id: String,
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    capabilities: HashSet<Capability>,
+    client: Option<Client>,
+    zoom_speed: u8,
+    focus_speed: u8,
+    autofocus: bool,
+}
+
+impl std::fmt::Display for Camcorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Camcorder[{}]", self.address)
+    }
+}
+
+impl Camcorder {
+    /// Sends a command to the `aw_ptz` (lens) or `aw_cam` (camera) endpoint and returns the
+    /// camera's response.
+    async fn send(&self, endpoint: &str, cmd: &str) -> Result<String, DeviceError> {
+        let Some(client) = &self.client else {
+            return Err(DeviceError::NotConnected);
+        };
+        let mut request = client
+            .get(format!("http://{}/cgi-bin/{}", self.address, endpoint))
+            .query(&[("cmd", cmd), ("res", "1")])
+            .timeout(TIMEOUT);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+        let response = request.send().await?.error_for_status()?.text().await?;
+        check_response(&response)?;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl super::Device for Camcorder {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        self.client = Some(Client::new());
+        match self.send("aw_cam", "QID").await {
+            Ok(model) => info!("Connected to {}", model.trim()),
+            Err(e) => {
+                self.client = None;
+                return Err(e);
+            }
+        }
+        self.zoom_speed = speed(0.0);
+        self.focus_speed = speed(0.0);
+        self.autofocus = false;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.client.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.client.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+
+        let zoom_speed = speed(command.zoom.get());
+        if self.capabilities.contains(&Capability::Zoom) && zoom_speed != self.zoom_speed {
+            self.send("aw_ptz", &format!("#Z{:02}", zoom_speed)).await?;
+            self.zoom_speed = zoom_speed;
+        }
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus && !self.autofocus {
+            // One-push autofocus
+            self.send("aw_cam", "OSE:69:1").await?;
+        }
+        self.autofocus = autofocus;
+
+        let focus_speed = speed(command.focus.get());
+        if self.capabilities.contains(&Capability::Focus)
+            && !autofocus
+            && focus_speed != self.focus_speed
+        {
+            self.send("aw_ptz", &format!("#F{:02}", focus_speed))
+                .await?;
+            self.focus_speed = focus_speed;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a value from -1 to 1 into the protocol's speed range, where 50 is stopped, 01 is
+/// full speed wide/near and 99 is full speed tele/far.
+pub(super) fn speed(value: f64) -> u8 {
+    (50.0 + value.clamp(-1.0, 1.0) * 49.0).round() as u8
+}
+
+/// Errors are reported in the response body rather than through the status code.
+pub(super) fn check_response(response: &str) -> Result<(), DeviceError> {
+    let response = response.trim();
+    if response.starts_with("er") || response.starts_with("rER") {
+        return Err(format!("camera rejected command: {}", response).into());
+    }
+    Ok(())
+}
+
+pub fn create(id: &str, config: &CamcorderConfig) -> Camcorder {
+    Camcorder {
+        id: id.to_owned(),
+        address: config.address.clone(),
+        username: config.username.clone(),
+        password: config.password.as_deref().cloned(),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                all_capabilities()
+                    .into_iter()
+                    .filter(|c| *c != Capability::Ptr && *c != Capability::Roll)
+                    .collect()
+            }),
+        client: None,
+        zoom_speed: speed(0.0),
+        focus_speed: speed(0.0),
+        autofocus: false,
+    }
+}
+
+#[test]
+fn test_speed() {
+    assert_eq!(speed(0.0), 50);
+    assert_eq!(speed(1.0), 99);
+    assert_eq!(speed(-1.0), 1);
+    assert_eq!(speed(0.5), 75);
+    assert!(check_response("gz50").is_ok());
+    assert!(check_response("er2:OSE:69").is_err());
+}
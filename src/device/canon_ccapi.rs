@@ -0,0 +1,407 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use digest_auth::{AuthContext, HttpMethod, WwwAuthenticateHeader};
+use reqwest::{header, Client, Method, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug, info, warn, Instrument as _};
+
+use super::DeviceError;
+use crate::config::{CanonCcapiConfig, Capability};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+const DRIVE_INTERVAL: Duration = Duration::from_millis(200);
+/// Fraction of the zoom range covered per second at full speed.
+const ZOOM_RATE: f64 = 0.5;
+
+/// Canon EOS cameras controlled through the Camera Connect API. Zoom needs a power zoom adapter
+/// (PZ-E1/PZ-E2) or a power zoom lens.
+pub struct CanonCcapi {
+    id: String,
+    address: String,
+    credentials: Option<(String, String)>,
+    capabilities: HashSet<Capability>,
+    connection: Option<Connection>,
+    autofocus: bool,
+}
+
+struct Connection {
+    api: Arc<Api>,
+    /// Whether live view has been started for thumbnails.
+    live_view: bool,
+    /// Zoom and focus speeds for the drive task.
+    drive: watch::Sender<(f64, f64)>,
+    drive_task: JoinHandle<()>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.drive_task.abort();
+    }
+}
+
+struct Api {
+    client: Client,
+    base: String,
+    credentials: Option<(String, String)>,
+    /// The last digest challenge, reused so that each request doesn't have to be rejected first.
+    challenge: Mutex<Option<WwwAuthenticateHeader>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ZoomState {
+    value: i64,
+    ability: ZoomAbility,
+}
+
+#[derive(Debug, Deserialize)]
+struct ZoomAbility {
+    min: i64,
+    max: i64,
+}
+
+impl Api {
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Value, DeviceError> {
+        let response = self.send(method, path, body).await?;
+        let status = response.status();
+        let text = response.text().await?;
+        let value: Value = match text.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_str(&text)?,
+        };
+        if !status.is_success() {
+            let message = value["message"].as_str().unwrap_or(status.as_str());
+            return Err(format!("camera rejected {}: {}", path, message).into());
+        }
+        Ok(value)
+    }
+
+    /// Fetches a binary resource, such as a live view frame.
+    async fn get_bytes(&self, path: &str) -> Result<Vec<u8>, DeviceError> {
+        let response = self.send(Method::GET, path, None).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("camera rejected {}: {}", path, status.as_str()).into());
+        }
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Sends a request, answering the camera's digest challenge if it asks for one.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<Response, DeviceError> {
+        let url = format!("{}{}", self.base, path);
+        let body = body.map(|b| b.to_string());
+        let mut retried = false;
+        loop {
+            let mut request = self.client.request(method.clone(), &url).timeout(TIMEOUT);
+            if let Some(body) = &body {
+                request = request
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(body.clone());
+            }
+            if let Some(authorization) = self.authorization(&method, path, body.as_deref())? {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let response = request.send().await?;
+            if response.status() == StatusCode::UNAUTHORIZED
+                && self.credentials.is_some()
+                && !retried
+            {
+                let challenge = response
+                    .headers()
+                    .get(header::WWW_AUTHENTICATE)
+                    .ok_or("camera requires authentication but didn't say how")?
+                    .to_str()
+                    .map_err(DeviceError::protocol)?;
+                let challenge = digest_auth::parse(challenge).map_err(DeviceError::protocol)?;
+                *self.challenge.lock().unwrap() = Some(challenge);
+                retried = true;
+                continue;
+            }
+            return Ok(response);
+        }
+    }
+
+    fn authorization(
+        &self,
+        method: &Method,
+        path: &str,
+        body: Option<&str>,
+    ) -> Result<Option<String>, DeviceError> {
+        let Some((username, password)) = &self.credentials else {
+            return Ok(None);
+        };
+        let mut challenge = self.challenge.lock().unwrap();
+        let Some(challenge) = challenge.as_mut() else {
+            return Ok(None);
+        };
+        let context = AuthContext::new_with_method(
+            username.as_str(),
+            password.as_str(),
+            path,
+            body.map(str::as_bytes),
+            HttpMethod(method.as_str().to_owned().into()),
+        );
+        let authorization = challenge.respond(&context).map_err(DeviceError::protocol)?;
+        Ok(Some(authorization.to_header_string()))
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value, DeviceError> {
+        self.request(Method::POST, path, Some(body)).await
+    }
+}
+
+impl std::fmt::Display for CanonCcapi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Canon[{}]", self.address)
+    }
+}
+
+#[async_trait]
+impl super::Device for CanonCcapi {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let address = match self.address.contains(':') {
+            true => self.address.clone(),
+            false => format!("{}:8080", self.address),
+        };
+        let api = Arc::new(Api {
+            client: Client::new(),
+            base: format!("http://{}", address),
+            credentials: self.credentials.clone(),
+            challenge: Mutex::new(None),
+        });
+        let info = api
+            .request(Method::GET, "/ccapi/ver100/deviceinformation", None)
+            .await?;
+        info!(
+            "Connected to {}",
+            info["productname"].as_str().unwrap_or("unknown camera")
+        );
+
+        let zoom = match self.capabilities.contains(&Capability::Zoom) {
+            false => None,
+            true => match api
+                .request(Method::GET, "/ccapi/ver100/shooting/control/zoom", None)
+                .await
+                .and_then(|v| Ok(serde_json::from_value::<ZoomState>(v)?))
+            {
+                Ok(zoom) => Some(zoom),
+                Err(e) => {
+                    warn!("Zoom not available: {}", e);
+                    None
+                }
+            },
+        };
+        let (drive, drive_rx) = watch::channel((0.0, 0.0));
+        let drive_task = tokio::spawn(drive_lens(api.clone(), zoom, drive_rx).in_current_span());
+        self.connection = Some(Connection {
+            api,
+            live_view: false,
+            drive,
+            drive_task,
+        });
+        self.autofocus = false;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.connection.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn thumbnail(&mut self) -> Result<Vec<u8>, DeviceError> {
+        let Some(connection) = &mut self.connection else {
+            return Err(DeviceError::NotConnected);
+        };
+        if !connection.live_view {
+            // Keep the camera's own screen on, since whoever's operating it still needs it
+            connection
+                .api
+                .post(
+                    "/ccapi/ver100/shooting/liveview",
+                    json!({ "liveviewsize": "small", "cameradisplay": "on" }),
+                )
+                .await?;
+            connection.live_view = true;
+        }
+        connection
+            .api
+            .get_bytes("/ccapi/ver100/shooting/liveview/flip")
+            .await
+    }
+
+    fn supports_thumbnails(&self) -> bool {
+        true
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        let Some(connection) = &self.connection else {
+            debug!("Not connected");
+            return Ok(());
+        };
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus != self.autofocus {
+            let action = if autofocus { "half_press" } else { "release" };
+            connection
+                .api
+                .post(
+                    "/ccapi/ver100/shooting/control/shutterbutton/manual",
+                    json!({ "action": action, "af": true }),
+                )
+                .await?;
+            self.autofocus = autofocus;
+        }
+
+        if self.capabilities.contains(&Capability::Shutter) && command.shutter {
+            // Focus stays where the operator left it, rather than hunting before every shot
+            info!("Taking a photo");
+            connection
+                .api
+                .post(
+                    "/ccapi/ver100/shooting/control/shutterbutton",
+                    json!({ "af": false }),
+                )
+                .await?;
+        }
+
+        let zoom = match self.capabilities.contains(&Capability::Zoom) {
+            true => command.zoom.get(),
+            false => 0.0,
+        };
+        let focus = match self.capabilities.contains(&Capability::Focus) && !autofocus {
+            true => command.focus.get(),
+            false => 0.0,
+        };
+        connection.drive.send_replace((zoom, focus));
+        Ok(())
+    }
+}
+
+/// Moves the lens while the zoom or focus speed isn't 0. The API only takes zoom positions and
+/// single focus steps, so zooming moves the target position along at the current speed, and
+/// focusing repeats steps sized by the speed.
+async fn drive_lens(
+    api: Arc<Api>,
+    mut zoom: Option<ZoomState>,
+    mut speeds: watch::Receiver<(f64, f64)>,
+) {
+    let mut target = zoom.as_ref().map(|z| z.value as f64);
+    loop {
+        let (zoom_speed, focus_speed) = *speeds.borrow_and_update();
+        if zoom_speed == 0.0 && focus_speed == 0.0 {
+            if speeds.changed().await.is_err() {
+                return;
+            }
+            continue;
+        }
+        if let (Some(zoom), Some(target)) = (&mut zoom, &mut target) {
+            let range = (zoom.ability.max - zoom.ability.min) as f64;
+            *target = (*target + zoom_speed * range * ZOOM_RATE * DRIVE_INTERVAL.as_secs_f64())
+                .clamp(zoom.ability.min as f64, zoom.ability.max as f64);
+            let value = target.round() as i64;
+            if value != zoom.value {
+                match api
+                    .post(
+                        "/ccapi/ver100/shooting/control/zoom",
+                        json!({ "value": value }),
+                    )
+                    .await
+                {
+                    Ok(_) => zoom.value = value,
+                    Err(e) => warn!("Error zooming: {}", e),
+                }
+            }
+        }
+        if let Some(step) = focus_step(focus_speed) {
+            if let Err(e) = api
+                .post(
+                    "/ccapi/ver100/shooting/control/drivefocus",
+                    json!({ "value": step }),
+                )
+                .await
+            {
+                warn!("Error focusing: {}", e);
+            }
+        }
+        tokio::time::sleep(DRIVE_INTERVAL).await;
+    }
+}
+
+/// The focus step for a speed from -1 (near) to 1 (far), from the smallest step (`near1`) to the
+/// largest (`near3`).
+fn focus_step(speed: f64) -> Option<String> {
+    if speed == 0.0 {
+        return None;
+    }
+    let direction = if speed > 0.0 { "far" } else { "near" };
+    let size = ((speed.abs() * 3.0).ceil() as u8).clamp(1, 3);
+    Some(format!("{}{}", direction, size))
+}
+
+pub fn create(id: &str, config: &CanonCcapiConfig) -> CanonCcapi {
+    CanonCcapi {
+        id: id.to_owned(),
+        address: config.address.clone(),
+        credentials: config
+            .username
+            .clone()
+            .map(|u| (u, config.password.as_deref().cloned().unwrap_or_default())),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                HashSet::from([
+                    Capability::Zoom,
+                    Capability::Focus,
+                    Capability::Autofocus,
+                    Capability::Shutter,
+                ])
+            }),
+        connection: None,
+        autofocus: false,
+    }
+}
+
+#[test]
+fn test_focus_step() {
+    assert_eq!(focus_step(0.0), None);
+    assert_eq!(focus_step(0.1).as_deref(), Some("far1"));
+    assert_eq!(focus_step(-0.5).as_deref(), Some("near2"));
+    assert_eq!(focus_step(-1.0).as_deref(), Some("near3"));
+}
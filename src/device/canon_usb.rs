@@ -0,0 +1,615 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read as _},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug, info, warn, Instrument as _};
+
+use super::DeviceError;
+use crate::config::{CanonUsbConfig, Capability};
+
+const CANON_VENDOR_ID: &str = "04a9";
+const DRIVE_INTERVAL: Duration = Duration::from_millis(200);
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(2);
+/// Room for the largest reply this backend asks for, which are all small.
+const READ_SIZE: usize = 16 * 1024;
+
+// PTP container types
+const CONTAINER_COMMAND: u16 = 1;
+const CONTAINER_DATA: u16 = 2;
+const CONTAINER_RESPONSE: u16 = 3;
+const CONTAINER_HEADER_LEN: usize = 12;
+
+const RESPONSE_OK: u16 = 0x2001;
+const RESPONSE_SESSION_ALREADY_OPEN: u16 = 0x201e;
+
+const OPEN_SESSION: u16 = 0x1002;
+const CLOSE_SESSION: u16 = 0x1003;
+// Canon's EOS extensions, as used by EOS Utility
+const SET_DEVICE_PROP_VALUE: u16 = 0x9110;
+const SET_REMOTE_MODE: u16 = 0x9114;
+const SET_EVENT_MODE: u16 = 0x9115;
+const DO_AF: u16 = 0x9154;
+const DRIVE_LENS: u16 = 0x9155;
+const AF_CANCEL: u16 = 0x9160;
+
+/// Where the live view goes. Driving the lens only works while it's sent to the computer.
+const PROP_EVF_OUTPUT_DEVICE: u32 = 0xd1b0;
+const EVF_OUTPUT_CAMERA: u32 = 1;
+const EVF_OUTPUT_PC: u32 = 2;
+const PROP_EVF_RECORD_STATUS: u32 = 0xd1b8;
+const RECORD_START: u32 = 4;
+const RECORD_STOP: u32 = 0;
+
+/// Still image class, which is what the camera's PTP interface reports.
+const INTERFACE_CLASS_IMAGE: u8 = 6;
+
+/// Canon cameras controlled over USB with the EOS remote protocol, the PTP extensions that
+/// Canon's EOS Utility uses. The protocol has no zoom, so only focus, autofocus, and recording
+/// can be controlled.
+pub struct CanonUsb {
+    id: String,
+    /// usbfs node of the camera, or `None` to use the first Canon device found.
+    device: Option<String>,
+    capabilities: HashSet<Capability>,
+    connection: Option<Connection>,
+    autofocus: bool,
+}
+
+struct Connection {
+    path: String,
+    session: Arc<Mutex<Session>>,
+    focus: watch::Sender<f64>,
+    focus_task: JoinHandle<()>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.focus_task.abort();
+    }
+}
+
+/// An open PTP session. Transfers block, so they're run with `spawn_blocking`.
+struct Session {
+    file: File,
+    endpoints: Endpoints,
+    transaction_id: u32,
+}
+
+#[derive(Debug, PartialEq)]
+struct Endpoints {
+    interface: u8,
+    bulk_in: u8,
+    bulk_out: u8,
+}
+
+impl Session {
+    /// Runs a transaction, with `data` sent after the command if given, and returns whatever
+    /// data the camera sent back.
+    fn transaction(
+        &mut self,
+        code: u16,
+        params: &[u32],
+        data: Option<&[u8]>,
+    ) -> Result<Vec<u8>, DeviceError> {
+        match self.exchange(code, params, data)? {
+            (RESPONSE_OK, received) => Ok(received),
+            (response, _) => Err(format!("camera responded with {:#06x}", response).into()),
+        }
+    }
+
+    /// Runs a transaction and returns the response code along with the data received.
+    fn exchange(
+        &mut self,
+        code: u16,
+        params: &[u32],
+        data: Option<&[u8]>,
+    ) -> Result<(u16, Vec<u8>), DeviceError> {
+        let transaction_id = self.transaction_id;
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let payload = params
+            .iter()
+            .flat_map(|p| p.to_le_bytes())
+            .collect::<Vec<_>>();
+        let mut command = container(CONTAINER_COMMAND, code, transaction_id, &payload);
+        debug!(packet = %hex::encode(&command), "Sending");
+        bulk(&self.file, self.endpoints.bulk_out, &mut command)?;
+        if let Some(data) = data {
+            let mut data = container(CONTAINER_DATA, code, transaction_id, data);
+            bulk(&self.file, self.endpoints.bulk_out, &mut data)?;
+        }
+
+        let mut received = vec![];
+        loop {
+            let mut buffer = vec![0; READ_SIZE];
+            let len = bulk(&self.file, self.endpoints.bulk_in, &mut buffer)?;
+            buffer.truncate(len);
+            // Short transfers that end on a packet boundary are followed by an empty one
+            if buffer.is_empty() {
+                continue;
+            }
+            debug!(packet = %hex::encode(&buffer), "Received");
+            let Some((kind, response_code, container_len)) = parse_header(&buffer) else {
+                return Err(format!("invalid container {}", hex::encode(&buffer)).into());
+            };
+            // The rest of a container longer than one transfer follows in the next ones
+            while buffer.len() < container_len {
+                let mut more = vec![0; READ_SIZE];
+                let len = bulk(&self.file, self.endpoints.bulk_in, &mut more)?;
+                buffer.extend_from_slice(&more[..len]);
+            }
+            match kind {
+                CONTAINER_DATA => received.extend_from_slice(&buffer[CONTAINER_HEADER_LEN..]),
+                CONTAINER_RESPONSE => return Ok((response_code, received)),
+                _ => (),
+            }
+        }
+    }
+
+    fn set_property(&mut self, property: u32, value: u32) -> Result<(), DeviceError> {
+        self.transaction(
+            SET_DEVICE_PROP_VALUE,
+            &[],
+            Some(&property_value(property, value)),
+        )?;
+        Ok(())
+    }
+}
+
+/// Runs `f` on the session off the async runtime.
+async fn with_session<T: Send + 'static>(
+    session: &Arc<Mutex<Session>>,
+    f: impl FnOnce(&mut Session) -> Result<T, DeviceError> + Send + 'static,
+) -> Result<T, DeviceError> {
+    let session = session.clone();
+    tokio::task::spawn_blocking(move || f(&mut session.lock().unwrap()))
+        .await
+        .map_err(|e| DeviceError::Transport(e.to_string()))?
+}
+
+impl std::fmt::Display for CanonUsb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CanonUSB[{}]",
+            self.device.as_deref().unwrap_or("first found")
+        )
+    }
+}
+
+#[async_trait]
+impl super::Device for CanonUsb {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let path = match &self.device {
+            Some(device) => device.clone(),
+            None => find_camera()?,
+        };
+        debug!("Opening {}", path);
+        let mut file = File::options().read(true).write(true).open(&path)?;
+        // Reading a usbfs node gives the device's descriptors
+        let mut descriptors = vec![];
+        file.read_to_end(&mut descriptors)?;
+        let endpoints = find_endpoints(&descriptors).ok_or_else(|| {
+            DeviceError::Unsupported(format!("{} doesn't have a PTP interface", path))
+        })?;
+        debug!("Using {:?}", endpoints);
+        claim_interface(&file, endpoints.interface)?;
+        let live_view = self.capabilities.contains(&Capability::Focus);
+        let session = tokio::task::spawn_blocking(move || {
+            let mut session = Session {
+                file,
+                endpoints,
+                transaction_id: 0,
+            };
+            match session.exchange(OPEN_SESSION, &[1], None)?.0 {
+                RESPONSE_OK => (),
+                // Left open by whatever had the camera before
+                RESPONSE_SESSION_ALREADY_OPEN => debug!("Session was already open"),
+                response => return Err(format!("couldn't open session: {:#06x}", response).into()),
+            }
+            session.transaction(SET_REMOTE_MODE, &[1], None)?;
+            session.transaction(SET_EVENT_MODE, &[1], None)?;
+            if live_view {
+                session.set_property(PROP_EVF_OUTPUT_DEVICE, EVF_OUTPUT_PC)?;
+            }
+            Ok::<_, DeviceError>(session)
+        })
+        .await
+        .map_err(|e| DeviceError::Transport(e.to_string()))??;
+        let session = Arc::new(Mutex::new(session));
+
+        let (focus, focus_rx) = watch::channel(0.0);
+        let focus_task = tokio::spawn(drive_focus(session.clone(), focus_rx).in_current_span());
+        self.connection = Some(Connection {
+            path,
+            session,
+            focus,
+            focus_task,
+        });
+        self.autofocus = false;
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        let Some(connection) = self.connection.take() else {
+            debug!("Already disconnected");
+            return Ok(());
+        };
+        connection.focus_task.abort();
+        // Hand the live view back to the camera's own screen
+        let closed = with_session(&connection.session, |session| {
+            session.set_property(PROP_EVF_OUTPUT_DEVICE, EVF_OUTPUT_CAMERA)?;
+            session.transaction(CLOSE_SESSION, &[], None)
+        })
+        .await;
+        if let Err(e) = closed {
+            warn!("Error closing session: {}", e);
+        }
+        info!("Disconnected");
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn check_connection(&mut self) -> bool {
+        // The node goes away when the cable is pulled
+        self.connection
+            .as_ref()
+            .is_some_and(|c| Path::new(&c.path).exists())
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        let Some(connection) = &self.connection else {
+            debug!("Not connected");
+            return Ok(());
+        };
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus != self.autofocus {
+            // Cancelling holds the focus where it landed
+            let code = if autofocus { DO_AF } else { AF_CANCEL };
+            with_session(&connection.session, move |s| s.transaction(code, &[], None)).await?;
+            self.autofocus = autofocus;
+        }
+
+        if let Some(start) = command
+            .record
+            .filter(|_| self.capabilities.contains(&Capability::Record))
+        {
+            info!("{} recording", if start { "Starting" } else { "Stopping" });
+            let status = if start { RECORD_START } else { RECORD_STOP };
+            with_session(&connection.session, move |s| {
+                s.set_property(PROP_EVF_RECORD_STATUS, status)
+            })
+            .await?;
+        }
+
+        let focus = match self.capabilities.contains(&Capability::Focus) && !autofocus {
+            true => command.focus.get(),
+            false => 0.0,
+        };
+        connection.focus.send_replace(focus);
+        Ok(())
+    }
+}
+
+/// Repeats focus steps sized by the speed while it isn't 0, since the camera only moves the
+/// focus by a set step each time it's asked.
+async fn drive_focus(session: Arc<Mutex<Session>>, mut speed: watch::Receiver<f64>) {
+    loop {
+        let focus_speed = *speed.borrow_and_update();
+        let Some(step) = focus_step(focus_speed) else {
+            if speed.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+        if let Err(e) =
+            with_session(&session, move |s| s.transaction(DRIVE_LENS, &[step], None)).await
+        {
+            warn!("Error focusing: {}", e);
+        }
+        tokio::time::sleep(DRIVE_INTERVAL).await;
+    }
+}
+
+/// The `DriveLens` step for a speed from -1 (near) to 1 (far): 1 to 3 for the smallest to
+/// largest step, with the top bit set to go toward infinity.
+fn focus_step(speed: f64) -> Option<u32> {
+    if speed == 0.0 {
+        return None;
+    }
+    let size = ((speed.abs() * 3.0).ceil() as u32).clamp(1, 3);
+    Some(match speed > 0.0 {
+        true => 0x8000 | size,
+        false => size,
+    })
+}
+
+/// A PTP container: its length, type, and operation or response code, the transaction it
+/// belongs to, then the payload.
+fn container(kind: u16, code: u16, transaction_id: u32, payload: &[u8]) -> Vec<u8> {
+    let len = (CONTAINER_HEADER_LEN + payload.len()) as u32;
+    let mut bytes = Vec::with_capacity(len as usize);
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(&kind.to_le_bytes());
+    bytes.extend_from_slice(&code.to_le_bytes());
+    bytes.extend_from_slice(&transaction_id.to_le_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+/// The type, code, and full length of the container `bytes` starts with.
+fn parse_header(bytes: &[u8]) -> Option<(u16, u16, usize)> {
+    let header = bytes.get(..CONTAINER_HEADER_LEN)?;
+    let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    if len < CONTAINER_HEADER_LEN {
+        return None;
+    }
+    let kind = u16::from_le_bytes([header[4], header[5]]);
+    let code = u16::from_le_bytes([header[6], header[7]]);
+    Some((kind, code, len))
+}
+
+/// The data for `SetDevicePropValueEx`, which carries its own length ahead of the property and
+/// value.
+fn property_value(property: u32, value: u32) -> Vec<u8> {
+    [12, property, value]
+        .iter()
+        .flat_map(|v: &u32| v.to_le_bytes())
+        .collect()
+}
+
+/// Finds the bulk endpoints of the first still image interface in a device's descriptors.
+fn find_endpoints(descriptors: &[u8]) -> Option<Endpoints> {
+    const INTERFACE: u8 = 4;
+    const ENDPOINT: u8 = 5;
+    const TRANSFER_BULK: u8 = 2;
+
+    let mut interface = None;
+    let (mut bulk_in, mut bulk_out) = (None, None);
+    let mut rest = descriptors;
+    while let [len, kind, ..] = *rest {
+        let len = len as usize;
+        if len < 2 || len > rest.len() {
+            break;
+        }
+        let descriptor = &rest[..len];
+        rest = &rest[len..];
+        match kind {
+            INTERFACE if len >= 9 => {
+                if interface.is_some() {
+                    break;
+                }
+                if descriptor[5] == INTERFACE_CLASS_IMAGE {
+                    interface = Some(descriptor[2]);
+                }
+            }
+            ENDPOINT if len >= 7 && interface.is_some() => {
+                let address = descriptor[2];
+                if descriptor[3] & 0x03 != TRANSFER_BULK {
+                    continue;
+                }
+                match address & 0x80 != 0 {
+                    true => bulk_in = bulk_in.or(Some(address)),
+                    false => bulk_out = bulk_out.or(Some(address)),
+                }
+            }
+            _ => (),
+        }
+    }
+    Some(Endpoints {
+        interface: interface?,
+        bulk_in: bulk_in?,
+        bulk_out: bulk_out?,
+    })
+}
+
+/// The usbfs node of the first Canon device plugged in.
+fn find_camera() -> Result<String, DeviceError> {
+    let read = |dir: &Path, name: &str| {
+        fs::read_to_string(dir.join(name))
+            .ok()
+            .map(|s| s.trim().to_owned())
+    };
+    let entries = fs::read_dir("/sys/bus/usb/devices")
+        .map_err(|e| DeviceError::Unsupported(format!("can't list USB devices: {}", e)))?;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if read(&dir, "idVendor").as_deref() != Some(CANON_VENDOR_ID) {
+            continue;
+        }
+        let (Some(bus), Some(device)) = (read(&dir, "busnum"), read(&dir, "devnum")) else {
+            continue;
+        };
+        let (Ok(bus), Ok(device)) = (bus.parse::<u16>(), device.parse::<u16>()) else {
+            continue;
+        };
+        return Ok(format!("/dev/bus/usb/{:03}/{:03}", bus, device));
+    }
+    Err(DeviceError::Transport(
+        "no Canon camera plugged in".to_owned(),
+    ))
+}
+
+#[cfg(target_os = "linux")]
+mod usbfs {
+    // struct usbdevfs_bulktransfer
+    #[repr(C)]
+    pub struct BulkTransfer {
+        pub endpoint: u32,
+        pub len: u32,
+        pub timeout_ms: u32,
+        pub data: *mut libc::c_void,
+    }
+
+    pub const USBDEVFS_CLAIMINTERFACE: u32 = 0x8004550f;
+    // _IOWR('U', 2, struct usbdevfs_bulktransfer), whose size depends on the pointer's
+    pub const USBDEVFS_BULK: u32 =
+        0xc0005502 | ((std::mem::size_of::<BulkTransfer>() as u32) << 16);
+}
+
+#[cfg(target_os = "linux")]
+fn claim_interface(file: &File, interface: u8) -> io::Result<()> {
+    use std::os::fd::AsRawFd as _;
+
+    let mut interface = interface as libc::c_uint;
+    // SAFETY: the request matches the value passed, which outlives the call
+    let result = unsafe {
+        libc::ioctl(
+            file.as_raw_fd(),
+            usbfs::USBDEVFS_CLAIMINTERFACE as _,
+            &mut interface,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Runs a bulk transfer on `endpoint`, sending `data` or filling it depending on the endpoint's
+/// direction, and returns how many bytes were transferred.
+#[cfg(target_os = "linux")]
+fn bulk(file: &File, endpoint: u8, data: &mut [u8]) -> io::Result<usize> {
+    use std::os::fd::AsRawFd as _;
+
+    let mut transfer = usbfs::BulkTransfer {
+        endpoint: endpoint as u32,
+        len: data.len() as u32,
+        timeout_ms: TRANSFER_TIMEOUT.as_millis() as u32,
+        data: data.as_mut_ptr().cast(),
+    };
+    // SAFETY: the request matches the struct passed, which outlives the call, as does the buffer
+    // it points to
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), usbfs::USBDEVFS_BULK as _, &mut transfer) };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn claim_interface(_file: &File, _interface: u8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Canon USB control is only supported on Linux",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bulk(_file: &File, _endpoint: u8, _data: &mut [u8]) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Canon USB control is only supported on Linux",
+    ))
+}
+
+pub fn create(id: &str, config: &CanonUsbConfig) -> CanonUsb {
+    CanonUsb {
+        id: id.to_owned(),
+        device: config.device.clone(),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                HashSet::from([Capability::Focus, Capability::Autofocus, Capability::Record])
+            }),
+        connection: None,
+        autofocus: false,
+    }
+}
+
+#[test]
+fn test_containers() {
+    assert_eq!(
+        hex::encode(container(
+            CONTAINER_COMMAND,
+            OPEN_SESSION,
+            0,
+            &1u32.to_le_bytes()
+        )),
+        "10000000010002100000000001000000"
+    );
+    let data = container(
+        CONTAINER_DATA,
+        SET_DEVICE_PROP_VALUE,
+        7,
+        &property_value(PROP_EVF_RECORD_STATUS, RECORD_START),
+    );
+    assert_eq!(
+        hex::encode(&data),
+        "1800000002001091070000000c000000b8d1000004000000"
+    );
+    assert_eq!(
+        parse_header(&data),
+        Some((CONTAINER_DATA, SET_DEVICE_PROP_VALUE, 24))
+    );
+    assert_eq!(
+        parse_header(&hex::decode("0c0000000300012001000000").unwrap()),
+        Some((CONTAINER_RESPONSE, RESPONSE_OK, 12))
+    );
+    assert_eq!(parse_header(&[0x0c, 0, 0, 0]), None);
+    assert_eq!(
+        parse_header(&hex::decode("040000000300012001000000").unwrap()),
+        None
+    );
+}
+
+#[test]
+fn test_find_endpoints() {
+    let descriptors = [
+        // Device, then its configuration
+        "1201000200000040a9042732020001020301",
+        "0902270001010080fa",
+        // A vendor interface first, then the PTP one with bulk in and out and an interrupt
+        // endpoint
+        "0904000001ff000000",
+        "07058302000200",
+        "090401000306010100",
+        "07058102000200",
+        "07050202000200",
+        "0705840308000a",
+    ]
+    .concat();
+    let descriptors = &hex::decode(descriptors).unwrap();
+    assert_eq!(
+        find_endpoints(descriptors),
+        Some(Endpoints {
+            interface: 1,
+            bulk_in: 0x81,
+            bulk_out: 0x02,
+        })
+    );
+    assert_eq!(find_endpoints(&descriptors[..52]), None);
+}
+
+#[test]
+fn test_focus_step() {
+    assert_eq!(focus_step(0.0), None);
+    assert_eq!(focus_step(0.1), Some(0x8001));
+    assert_eq!(focus_step(-0.5), Some(0x0002));
+    assert_eq!(focus_step(-1.0), Some(0x0003));
+}
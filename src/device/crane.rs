@@ -1,19 +1,24 @@
 use async_trait::async_trait;
 use btleplug::{
-    api::{Central as _, Characteristic, Peripheral as _, ScanFilter, WriteType},
+    api::{Characteristic, Peripheral as _, WriteType},
     platform::{Adapter, Peripheral},
 };
-use futures::TryFutureExt as _;
+use futures::{StreamExt as _, TryFutureExt as _};
 use itertools::Itertools;
+use serde_json::{Map, Value};
 use std::{
     collections::HashSet,
-    error::Error,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
-use tokio::{sync::watch, time::timeout};
+use tokio::{sync::watch, task::JoinHandle, time::timeout};
+use tracing::{debug, info, warn, Instrument as _};
 use uuid::uuid;
 
+use super::DeviceError;
 use crate::config::{all_capabilities, Capability, CraneConfig, CraneOption};
 
 const COMMAND_UUID: uuid::Uuid = uuid!("d44bc439-abfd-45a2-b575-925416129600");
@@ -27,9 +32,14 @@ const CUSTOM_ALG: crc::Algorithm<u16> = crc::Algorithm {
     check: 0x0000,
     residue: 0x0000,
 };
-const CRC: crc::Crc<u16> = crc::Crc::<u16>::new(&CUSTOM_ALG);
+pub const CRC: crc::Crc<u16> = crc::Crc::<u16>::new(&CUSTOM_ALG);
 const PTR_BASE: u16 = 2048;
 const PTR_MIN: u16 = 2;
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Registers holding the current tilt, roll, and pan angles, in tenths of a degree.
+const ANGLE_REGISTERS: [u16; 3] = [0x1031, 0x1032, 0x1033];
+/// Register holding the battery level, in percent.
+const BATTERY_REGISTER: u16 = 0x1008;
 
 pub fn add_checksum(b: &[u8]) -> Vec<u8> {
     let checksum = CRC.checksum(b).to_le_bytes();
@@ -94,52 +104,197 @@ fn create_pan_packet(seq_num: u8, pan: f64) -> Vec<u8> {
     add_checksum(&concat)
 }
 
-fn get_seq(next_seq: &watch::Sender<u8>) -> u8 {
-    let mut seq = 0;
-    next_seq.send_modify(|s| {
-        seq = *s;
-        *s = s.wrapping_add(1);
-    });
-    seq
+/// Builds an absolute angle command for one axis (`0x01` tilt, `0x02` roll, `0x03` pan), in
+/// tenths of a degree. The gimbal moves there at its own speed.
+fn create_angle_packet(seq_num: u8, axis: u8, angle: f64) -> Vec<u8> {
+    let prefix = vec![0x24, 0x3c, 0x08, 0x00, 0x18, 0x12];
+    let midfix = vec![0x01, 0x20 | axis, 0x10];
+
+    let seq_bytes = vec![seq_num];
+    let angle_bytes = ((angle * 10.0).round() as i16).to_le_bytes().to_vec();
+
+    let concat = [prefix, seq_bytes, midfix, angle_bytes].concat();
+    add_checksum(&concat)
+}
+
+/// Builds a request to read a register, which the gimbal answers with a notification.
+fn create_read_packet(seq_num: u8, register: u16) -> Vec<u8> {
+    let prefix = vec![0x24, 0x3c, 0x06, 0x00, 0x18, 0x11];
+    let midfix = vec![0x01];
+
+    let seq_bytes = vec![seq_num];
+    let register_bytes = register.to_le_bytes().to_vec();
+
+    let concat = [prefix, seq_bytes, midfix, register_bytes].concat();
+    add_checksum(&concat)
+}
+
+/// The register and value from the gimbal's answer to a read request.
+fn parse_read_reply(packet: &[u8]) -> Option<(u16, i16)> {
+    if packet.len() != 14 || packet[..6] != [0x24, 0x3c, 0x08, 0x00, 0x18, 0x11] {
+        return None;
+    }
+    if add_checksum(&packet[..12]) != packet {
+        warn!(packet = %hex::encode(packet), "Dropping packet with bad checksum");
+        return None;
+    }
+    let register = u16::from_le_bytes([packet[8], packet[9]]);
+    let value = i16::from_le_bytes([packet[10], packet[11]]);
+    Some((register, value))
+}
+
+/// What the gimbal last said about itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Status {
+    tilt: Option<f64>,
+    roll: Option<f64>,
+    pan: Option<f64>,
+    battery: Option<i16>,
+}
+
+impl Status {
+    fn update(&mut self, register: u16, value: i16) {
+        let angle = Some(value as f64 / 10.0);
+        match register {
+            r if r == ANGLE_REGISTERS[0] => self.tilt = angle,
+            r if r == ANGLE_REGISTERS[1] => self.roll = angle,
+            r if r == ANGLE_REGISTERS[2] => self.pan = angle,
+            BATTERY_REGISTER => self.battery = Some(value),
+            _ => (),
+        }
+    }
+}
+
+/// Sequence numbers for outgoing packets, shared by everything that sends them. The same counter
+/// is handed to each new connection, so numbers keep increasing across reconnects instead of
+/// restarting at 0.
+#[derive(Clone, Default)]
+struct Sequence(Arc<AtomicU8>);
+
+impl Sequence {
+    /// Reserves `N` consecutive numbers, wrapping around after 255, so that a batch of packets
+    /// isn't interleaved with numbers taken by other senders.
+    fn reserve<const N: usize>(&self) -> [u8; N] {
+        let first = self.0.fetch_add(N as u8, Ordering::Relaxed);
+        std::array::from_fn(|i| first.wrapping_add(i as u8))
+    }
+}
+
+#[test]
+fn test_sequence_wraparound() {
+    let seq = Sequence::default();
+    let shared = seq.clone();
+    assert_eq!(seq.reserve(), [0, 1, 2]);
+    assert_eq!(shared.reserve(), [3]);
+
+    seq.0.store(254, Ordering::Relaxed);
+    assert_eq!(seq.reserve(), [254, 255, 0]);
+    assert_eq!(shared.reserve(), [1, 2]);
 }
 
 pub struct Crane {
     id: String,
     name: String,
-    next_seq: watch::Sender<u8>,
+    seq: Sequence,
     adapter: Adapter,
     connection: Option<Connection>,
     capabilities: HashSet<Capability>,
     options: HashSet<CraneOption>,
+    prepared: Option<(super::Command, Vec<Vec<u8>>)>,
+    poll_interval: Duration,
 }
 
 struct Connection {
     peripheral: Peripheral,
     characteristic: Arc<Mutex<Characteristic>>,
+    seq: Sequence,
+    status: watch::Receiver<Status>,
+    status_tasks: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for task in self.status_tasks.iter() {
+            task.abort();
+        }
+    }
 }
 
 impl Connection {
-    pub async fn try_resume_connection(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+    pub async fn try_resume_connection(&mut self, name: &str) -> Result<(), DeviceError> {
         if self.peripheral.is_connected().await? {
             return Ok(());
         }
-        println!("{}: Lost connection, reconnecting...", name);
+        warn!("Lost connection, reconnecting...");
         let timer = Instant::now();
         self.peripheral.disconnect().await?;
 
         timeout(Duration::from_millis(200), self.peripheral.connect())
-            .map_err(|_| -> Box<dyn Error> {
-                format!("{}: timed out while trying to reconnect", name).into()
+            .map_err(|_| {
+                DeviceError::Timeout(format!("{}: timed out while trying to reconnect", name))
             })
             .await??;
 
         let command_characteristic = get_characteristic(&self.peripheral, COMMAND_UUID).await?;
         *self.characteristic.lock().unwrap() = command_characteristic;
-        println!("{}: Reconnected in {:?}", name, timer.elapsed());
+        info!("Reconnected in {:?}", timer.elapsed());
         Ok(())
     }
 }
 
+impl Crane {
+    /// The pan, tilt, and roll to send, with the axes the gimbal isn't allowed to move zeroed.
+    fn ptr_axes(&self, command: super::Command) -> (f64, f64, f64) {
+        let axis = |capability, option, value: f64| {
+            if !self.capabilities.contains(&capability) {
+                0.0
+            } else if self.options.contains(&option) {
+                -value
+            } else {
+                value
+            }
+        };
+        (
+            axis(Capability::Ptr, CraneOption::ReversePan, command.pan.get()),
+            axis(
+                Capability::Ptr,
+                CraneOption::ReverseTilt,
+                command.tilt.get(),
+            ),
+            axis(
+                Capability::Roll,
+                CraneOption::ReverseRoll,
+                command.roll.get(),
+            ),
+        )
+    }
+
+    /// The angles to move to, with the axes the gimbal isn't allowed to move left out.
+    fn target_angles(&self, target: super::MoveTo) -> [(u8, Option<f64>); 3] {
+        let axis = |capability, option, angle: Option<super::AngleDeg>| {
+            let angle = angle.filter(|_| self.capabilities.contains(&capability))?;
+            match self.options.contains(&option) {
+                true => Some(-angle.get()),
+                false => Some(angle.get()),
+            }
+        };
+        [
+            (
+                0x01,
+                axis(Capability::Ptr, CraneOption::ReverseTilt, target.tilt),
+            ),
+            (
+                0x02,
+                axis(Capability::Roll, CraneOption::ReverseRoll, target.roll),
+            ),
+            (
+                0x03,
+                axis(Capability::Ptr, CraneOption::ReversePan, target.pan),
+            ),
+        ]
+    }
+}
+
 impl std::fmt::Display for Crane {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Crane[{}]", self.name)
@@ -152,90 +307,212 @@ impl super::Device for Crane {
         self.id.clone()
     }
 
-    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-        let name = format!("{}", self);
-        println!("{}: Connecting", name);
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
 
-        let peripheral = find_peripheral(&self.adapter, &self.name).await?;
+        let peripheral = super::ble::find_peripheral(&self.adapter, &self.name).await?;
         peripheral.connect().await?;
         let cmd_characteristic = Arc::new(Mutex::new(
             get_characteristic(&peripheral, COMMAND_UUID).await?,
         ));
 
+        let (status_tx, status_rx) = watch::channel(Status::default());
+        let subscribed = {
+            let characteristic = cmd_characteristic.lock().unwrap().clone();
+            peripheral.subscribe(&characteristic).await
+        };
+        let status_tasks = match subscribed {
+            Ok(()) => vec![
+                create_event_task(peripheral.clone(), status_tx),
+                create_poll_task(
+                    peripheral.clone(),
+                    cmd_characteristic.clone(),
+                    self.seq.clone(),
+                    self.poll_interval,
+                ),
+            ],
+            Err(e) => {
+                warn!("Can't read back angles or battery level: {}", e);
+                vec![]
+            }
+        };
+
         self.connection = Some(Connection {
             peripheral,
             characteristic: cmd_characteristic,
+            seq: self.seq.clone(),
+            status: status_rx,
+            status_tasks,
         });
-        println!("{}: Connected", self);
+        info!("Connected");
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
         match &self.connection {
             None => {
-                println!("{}: Already disconnected", self);
+                debug!("Already disconnected");
             }
             Some(c) => {
-                println!("{}: Disconnecting", self);
+                info!("Disconnecting");
                 c.peripheral.disconnect().await?;
                 self.connection = None;
-                println!("{}: Disconnected", self);
+                info!("Disconnected");
             }
         }
         Ok(())
     }
 
-    async fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
         self.disconnect().await?;
         self.connect().await?;
         Ok(())
     }
 
+    async fn repair(&mut self) -> Result<(), DeviceError> {
+        let connection = self.connection.take();
+        let connected = connection.as_ref().map(|c| &c.peripheral);
+        super::ble::repair(&self.adapter, &self.name, connected).await?;
+        drop(connection);
+        self.connect().await
+    }
+
+    fn supports_repair(&self) -> bool {
+        true
+    }
+
+    /// Crane gimbals can't be told how long to take, so they move at their own speed.
+    async fn move_to(&mut self, target: super::MoveTo) -> Result<(), DeviceError> {
+        let name = format!("{}", self);
+        let angles = self.target_angles(target);
+        let Some(c) = &mut self.connection else {
+            return Err(DeviceError::NotConnected);
+        };
+        c.try_resume_connection(&name).await?;
+        let packets = angles
+            .into_iter()
+            .filter_map(|(axis, angle)| {
+                let angle = angle?;
+                let [seq] = c.seq.reserve();
+                Some(create_angle_packet(seq, axis, angle))
+            })
+            .collect::<Vec<_>>();
+        debug!(
+            packets = %packets.iter().map(hex::encode).join(" "),
+            "Moving to {:?}", target
+        );
+        let cmd_characteristic = c.characteristic.lock().unwrap().clone();
+        for packet in packets {
+            c.peripheral
+                .write(&cmd_characteristic, &packet, WriteType::WithoutResponse)
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> Option<super::Position> {
+        let status = *self.connection.as_ref()?.status.borrow();
+        if status.tilt.is_none() && status.roll.is_none() && status.pan.is_none() {
+            return None;
+        }
+        // Reversed axes are reported the other way around too, so that positions still
+        // increase in the direction positive commands move them
+        let angle = |option, value: Option<f64>| {
+            value.map(|v| {
+                super::AngleDeg::new(match self.options.contains(&option) {
+                    true => -v,
+                    false => v,
+                })
+            })
+        };
+        Some(super::Position {
+            pan: angle(CraneOption::ReversePan, status.pan),
+            tilt: angle(CraneOption::ReverseTilt, status.tilt),
+            roll: angle(CraneOption::ReverseRoll, status.roll),
+            zoom: None,
+        })
+    }
+
+    async fn poll_telemetry(&mut self) -> Result<Map<String, Value>, DeviceError> {
+        match &self.connection {
+            None => Ok(Map::new()),
+            Some(c) => super::ble::link_telemetry(&c.peripheral).await,
+        }
+    }
+
+    fn telemetry(&self) -> Map<String, Value> {
+        let mut values = Map::new();
+        if let Some(battery) = self
+            .connection
+            .as_ref()
+            .and_then(|c| c.status.borrow().battery)
+        {
+            values.insert("battery".to_owned(), battery.into());
+        }
+        values
+    }
+
+    fn uses_bluetooth(&self) -> bool {
+        true
+    }
+
+    fn set_adapter(&mut self, adapter: &Adapter) {
+        self.adapter = adapter.clone();
+    }
+
     fn is_connected(&self) -> bool {
         self.connection.is_some()
     }
 
-    async fn send_command(&mut self, command: super::Command) -> Result<(), Box<dyn Error>> {
+    async fn check_connection(&mut self) -> bool {
+        let name = format!("{}", self);
+        match &mut self.connection {
+            None => false,
+            Some(c) => c.try_resume_connection(&name).await.is_ok(),
+        }
+    }
+
+    async fn prepare_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        let name = format!("{}", self);
+        let (pan, tilt, roll) = self.ptr_axes(command);
+        if pan == 0.0 && tilt == 0.0 && roll == 0.0 {
+            return Ok(());
+        }
+        if let Some(c) = &mut self.connection {
+            c.try_resume_connection(&name).await?;
+            let packets = create_packets(&c.seq, pan, tilt, roll);
+            self.prepared = Some((command, packets));
+        }
+        Ok(())
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
         let name = format!("{}", self);
-        println!("{}: Received command {:?}", name, command);
+        debug!("Received command {:?}", command);
+        let (pan, tilt, roll) = self.ptr_axes(command);
+        let prepared = self
+            .prepared
+            .take()
+            .filter(|(prepared, _)| *prepared == command)
+            .map(|(_, packets)| packets);
         match &mut self.connection {
             None => {
-                println!("{}: Not connected", name);
+                debug!("Not connected");
             }
             Some(ref mut c) => {
-                let pan = if self.options.contains(&CraneOption::ReversePan) {
-                    -command.pan
-                } else {
-                    command.pan
-                };
-                let tilt = if self.options.contains(&CraneOption::ReverseTilt) {
-                    -command.tilt
-                } else {
-                    command.tilt
-                };
-                let roll = if self.options.contains(&CraneOption::ReverseRoll) {
-                    -command.roll
-                } else {
-                    command.roll
-                };
-
-                let send_ptr = self.capabilities.contains(&Capability::Ptr)
-                    && (pan != 0.0 || tilt != 0.0 || roll != 0.0);
+                let send_ptr = pan != 0.0 || tilt != 0.0 || roll != 0.0;
                 if !send_ptr {
                     return Ok(());
                 }
 
-                c.try_resume_connection(&name).await?;
-
-                let packets = vec![
-                    create_tilt_packet(get_seq(&self.next_seq), tilt),
-                    create_roll_packet(get_seq(&self.next_seq), roll),
-                    create_pan_packet(get_seq(&self.next_seq), pan),
-                ];
-                print!(
-                    "{}: Sending PTR commands {}",
-                    name,
-                    packets.iter().map(hex::encode).join(" ")
+                if prepared.is_none() {
+                    c.try_resume_connection(&name).await?;
+                }
+
+                let packets = prepared.unwrap_or_else(|| create_packets(&c.seq, pan, tilt, roll));
+                debug!(
+                    packets = %packets.iter().map(hex::encode).join(" "),
+                    "Sending PTR commands"
                 );
                 let cmd_characteristic = c.characteristic.lock().unwrap().clone();
                 for packet in packets {
@@ -244,40 +521,72 @@ impl super::Device for Crane {
                         .await
                         .unwrap();
                 }
-                println!(" ...sent");
             }
         }
         Ok(())
     }
 }
 
-async fn find_peripheral(adapter: &Adapter, name: &str) -> Result<Peripheral, Box<dyn Error>> {
-    adapter.start_scan(ScanFilter::default()).await?;
-
-    for _ in 0..10 {
-        tokio::time::sleep(Duration::from_millis(500)).await;
-        let peripherals = adapter.peripherals().await?;
-        for p in peripherals {
-            if p.properties()
-                .await?
-                .and_then(|p| p.local_name)
-                .map(|n| n == name)
-                .unwrap_or(false)
-            {
-                adapter.stop_scan().await?;
-                return Ok(p);
+fn create_packets(seq: &Sequence, pan: f64, tilt: f64, roll: f64) -> Vec<Vec<u8>> {
+    let [tilt_seq, roll_seq, pan_seq] = seq.reserve();
+    vec![
+        create_tilt_packet(tilt_seq, tilt),
+        create_roll_packet(roll_seq, roll),
+        create_pan_packet(pan_seq, pan),
+    ]
+}
+
+/// Keeps `status` up to date with the gimbal's answers to read requests.
+fn create_event_task(peripheral: Peripheral, status_tx: watch::Sender<Status>) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            let Ok(mut stream) = peripheral.notifications().await else {
+                return;
+            };
+            while let Some(notification) = stream.next().await {
+                if let Some((register, value)) = parse_read_reply(&notification.value) {
+                    status_tx.send_modify(|s| s.update(register, value));
+                }
             }
         }
-    }
+        .in_current_span(),
+    )
+}
 
-    adapter.stop_scan().await?;
-    Err(format!("unable to find peripheral {}", name).into())
+/// Asks the gimbal for its angles and battery level every `interval`.
+fn create_poll_task(
+    peripheral: Peripheral,
+    characteristic: Arc<Mutex<Characteristic>>,
+    seq: Sequence,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                let seqs: [u8; 4] = seq.reserve();
+                let registers = ANGLE_REGISTERS.into_iter().chain([BATTERY_REGISTER]);
+                let characteristic = characteristic.lock().unwrap().clone();
+                for (seq, register) in seqs.into_iter().zip(registers) {
+                    let packet = create_read_packet(seq, register);
+                    let written = peripheral
+                        .write(&characteristic, &packet, WriteType::WithoutResponse)
+                        .await;
+                    if let Err(e) = written {
+                        debug!("Error asking for status: {}", e);
+                    }
+                }
+            }
+        }
+        .in_current_span(),
+    )
 }
 
 async fn get_characteristic(
     peripheral: &Peripheral,
     uuid: uuid::Uuid,
-) -> Result<Characteristic, Box<dyn Error>> {
+) -> Result<Characteristic, DeviceError> {
     peripheral.discover_services().await?;
     match peripheral.characteristics().iter().find(|c| c.uuid == uuid) {
         None => Err("characteristic not found".into()),
@@ -286,11 +595,10 @@ async fn get_characteristic(
 }
 
 pub fn create(id: &str, adapter: Adapter, config: &CraneConfig) -> Crane {
-    let (next_seq, _) = watch::channel(0);
     Crane {
         id: id.to_owned(),
         name: config.name.to_owned(),
-        next_seq,
+        seq: Sequence::default(),
         adapter,
         connection: None,
         capabilities: config
@@ -303,6 +611,11 @@ pub fn create(id: &str, adapter: Adapter, config: &CraneConfig) -> Crane {
             .clone()
             .map(HashSet::from_iter)
             .unwrap_or_default(),
+        prepared: None,
+        poll_interval: config
+            .poll_interval_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_POLL_INTERVAL),
     }
 }
 
@@ -345,3 +658,44 @@ fn test_checksum() {
         assert_eq!(hex::encode(with_checksum), v,);
     }
 }
+
+#[test]
+fn test_packet_golden() {
+    for (inputs, expected) in super::golden_cases(include_str!("fixtures/crane_ptr.txt")) {
+        let [axis, seq, value] = inputs[..] else {
+            panic!("malformed fixture line: {:?}", inputs);
+        };
+        let (seq, value) = (seq.parse().unwrap(), value.parse().unwrap());
+        let packet = match axis {
+            "tilt" => create_tilt_packet(seq, value),
+            "roll" => create_roll_packet(seq, value),
+            "pan" => create_pan_packet(seq, value),
+            _ => panic!("unknown axis {}", axis),
+        };
+        assert_eq!(hex::encode(packet), expected, "inputs: {:?}", inputs);
+    }
+}
+
+#[test]
+fn test_angle_packet() {
+    let packet = create_angle_packet(0x28, 0x03, -45.5);
+    // Pan, at -45.5 degrees
+    assert_eq!(hex::encode(&packet[..12]), "243c080018122801231039fe");
+    assert_eq!(packet, add_checksum(&packet[..12]));
+}
+
+#[test]
+fn test_read_reply() {
+    let request = create_read_packet(0x28, 0x1033);
+    assert_eq!(hex::encode(&request[..10]), "243c0600181128013310");
+
+    let reply = add_checksum(&hex::decode("243c0800181128013310c5fe").unwrap());
+    assert_eq!(parse_read_reply(&reply), Some((0x1033, -315)));
+    let mut status = Status::default();
+    status.update(0x1033, -315);
+    assert_eq!(status.pan, Some(-31.5));
+
+    let mut corrupted = reply.clone();
+    corrupted[11] ^= 0x01;
+    assert_eq!(parse_read_reply(&corrupted), None);
+}
@@ -0,0 +1,169 @@
+//! DJI's DUML packet format, used by Ronin gimbals over Bluetooth.
+//!
+//! Each packet starts with `0x55`, a 10-bit total length and a version, and a CRC-8 of those,
+//! followed by the sender and receiver, a sequence number, the command type, set and ID, the
+//! payload, and a CRC-16 of everything before it.
+
+use tracing::warn;
+
+use super::DeviceError;
+
+const START: u8 = 0x55;
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 4;
+/// Everything besides the payload: the header, addresses, sequence number, command, and CRC-16.
+const OVERHEAD: usize = 13;
+const MAX_LEN: usize = 0x3ff;
+const HEADER_ALG: crc::Algorithm<u8> = crc::Algorithm {
+    width: 8,
+    poly: 0x31,
+    init: 0xee,
+    refin: true,
+    refout: true,
+    xorout: 0x00,
+    check: 0x00,
+    residue: 0x00,
+};
+const HEADER_CRC: crc::Crc<u8> = crc::Crc::<u8>::new(&HEADER_ALG);
+const PACKET_ALG: crc::Algorithm<u16> = crc::Algorithm {
+    width: 16,
+    poly: 0x1021,
+    init: 0x496c,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+    check: 0x7109,
+    residue: 0x0000,
+};
+pub const PACKET_CRC: crc::Crc<u16> = crc::Crc::<u16>::new(&PACKET_ALG);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub sender: u8,
+    pub receiver: u8,
+    pub seq: u16,
+    pub cmd_type: u8,
+    pub cmd_set: u8,
+    pub cmd_id: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.payload.len() + OVERHEAD;
+        assert!(len <= MAX_LEN, "payload too long for one packet");
+        let mut packet = vec![START, len as u8, (VERSION << 2) | (len >> 8) as u8];
+        packet.push(HEADER_CRC.checksum(&packet));
+        packet.extend([self.sender, self.receiver]);
+        packet.extend(self.seq.to_le_bytes());
+        packet.extend([self.cmd_type, self.cmd_set, self.cmd_id]);
+        packet.extend(&self.payload);
+        add_checksum(&packet)
+    }
+
+    fn decode(packet: &[u8]) -> Result<Packet, DeviceError> {
+        let (body, checksum) = packet.split_at(packet.len() - 2);
+        if PACKET_CRC.checksum(body).to_le_bytes() != checksum {
+            return Err("bad packet checksum".into());
+        }
+        Ok(Packet {
+            sender: body[4],
+            receiver: body[5],
+            seq: u16::from_le_bytes([body[6], body[7]]),
+            cmd_type: body[8],
+            cmd_set: body[9],
+            cmd_id: body[10],
+            payload: body[11..].to_vec(),
+        })
+    }
+}
+
+pub fn add_checksum(b: &[u8]) -> Vec<u8> {
+    let checksum = PACKET_CRC.checksum(b).to_le_bytes();
+    [b, &checksum].concat()
+}
+
+/// The length of the packet starting at the beginning of `header`, if it's a valid header.
+fn packet_len(header: &[u8]) -> Option<usize> {
+    if header[0] != START || HEADER_CRC.checksum(&header[..3]) != header[3] {
+        return None;
+    }
+    let len = header[1] as usize | ((header[2] & 0x03) as usize) << 8;
+    (len >= OVERHEAD).then_some(len)
+}
+
+/// Joins packets back together from notifications, which can split a packet across several
+/// notifications or hold several packets at once.
+#[derive(Default)]
+pub struct Reassembler {
+    buffer: Vec<u8>,
+}
+
+impl Reassembler {
+    /// Adds a notification's bytes and returns the packets they completed. Bytes that can't be
+    /// part of a valid packet are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Packet> {
+        self.buffer.extend_from_slice(bytes);
+        let mut packets = vec![];
+        while self.buffer.len() >= HEADER_LEN {
+            let Some(len) = packet_len(&self.buffer) else {
+                let skip = self.buffer[1..]
+                    .iter()
+                    .position(|&b| b == START)
+                    .map_or(self.buffer.len(), |i| i + 1);
+                self.buffer.drain(..skip);
+                continue;
+            };
+            if self.buffer.len() < len {
+                break;
+            }
+            let packet: Vec<u8> = self.buffer.drain(..len).collect();
+            match Packet::decode(&packet) {
+                Ok(p) => packets.push(p),
+                Err(e) => warn!(packet = %hex::encode(&packet), "Dropping packet: {}", e),
+            }
+        }
+        packets
+    }
+}
+
+#[test]
+fn test_checksum() {
+    let bytes = vec![
+        0x55, 0x11, 0x04, 0x92, 0x02, 0xdf, 0x20, 0x02, 0x00, 0x04, 0x2f, 0x0b, 0x00, 0x01, 0xc5,
+    ];
+    let with_checksum = add_checksum(&bytes);
+    assert_eq!(
+        hex::encode(with_checksum),
+        "5511049202df200200042f0b0001c5f5a7"
+    );
+}
+
+#[test]
+fn test_reassembly() {
+    let zoom = "5511049202df200200042f0b0001c5f5a7";
+    let packet = Packet::decode(&hex::decode(zoom).unwrap()).unwrap();
+    assert_eq!(hex::encode(packet.encode()), zoom);
+
+    let long = Packet {
+        payload: vec![0xaa; 300],
+        ..packet.clone()
+    };
+    let long_bytes = long.encode();
+    assert_eq!(packet_len(&long_bytes), Some(313));
+
+    let stream = [
+        &[0x00, 0x55, 0x01][..],
+        &packet.encode(),
+        &long_bytes,
+        &packet.encode(),
+    ]
+    .concat();
+    let mut reassembler = Reassembler::default();
+    let mut received = vec![];
+    for chunk in stream.chunks(20) {
+        received.extend(reassembler.push(chunk));
+    }
+    assert_eq!(received, vec![packet.clone(), long, packet]);
+    assert!(reassembler.buffer.is_empty());
+}
@@ -1,6 +1,7 @@
-use std::error::Error;
-
 use async_trait::async_trait;
+use tracing::{debug, info};
+
+use super::DeviceError;
 
 pub struct Dummy {
     id: String,
@@ -16,24 +17,24 @@ impl std::fmt::Display for Dummy {
 
 #[async_trait]
 impl super::Device for Dummy {
-    async fn send_command(&mut self, command: super::Command) -> Result<(), Box<dyn Error>> {
-        println!("{}: Received command {:?}", self, command);
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
         Ok(())
     }
 
-    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn connect(&mut self) -> Result<(), DeviceError> {
         self.connected = true;
-        println!("{}: Connected", self);
+        info!("Connected");
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
         self.connected = false;
-        println!("{}: Disconnecting", self);
+        info!("Disconnecting");
         Ok(())
     }
 
-    async fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
         self.disconnect().await?;
         self.connect().await?;
         Ok(())
@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _, WriteHalf},
+    task::JoinHandle,
+};
+use tokio_serial::{SerialPortBuilderExt as _, SerialStream};
+use tracing::{debug, info, warn, Instrument as _};
+
+use super::DeviceError;
+use crate::config::{Capability, FcbConfig};
+
+/// Sony FCB block cameras, controlled with VISCA over TTL serial.
+pub struct Fcb {
+    id: String,
+    port: String,
+    baud_rate: u32,
+    address: u8,
+    digital_zoom: Option<bool>,
+    stabilizer: Option<bool>,
+    capabilities: HashSet<Capability>,
+    connection: Option<Connection>,
+    zoom_speed: i8,
+    focus_speed: i8,
+    autofocus: bool,
+}
+
+struct Connection {
+    writer: WriteHalf<SerialStream>,
+    reader_task: JoinHandle<()>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+impl std::fmt::Display for Fcb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FCB[{}]", self.port)
+    }
+}
+
+impl Fcb {
+    async fn send(&mut self, packet: Vec<u8>) -> Result<(), DeviceError> {
+        let Some(connection) = &mut self.connection else {
+            return Err(DeviceError::NotConnected);
+        };
+        debug!(packet = %hex::encode(&packet), "Sending");
+        connection.writer.write_all(&packet).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl super::Device for Fcb {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        if !(1..=7).contains(&self.address) {
+            return Err(format!("invalid VISCA address {}", self.address).into());
+        }
+        let stream = tokio_serial::new(&self.port, self.baud_rate)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .open_native_async()?;
+        let (mut reader, writer) = tokio::io::split(stream);
+        let reader_task = tokio::spawn(
+            async move {
+                let mut buf = [0u8; 64];
+                let mut reply = vec![];
+                while let Ok(n) = reader.read(&mut buf).await {
+                    if n == 0 {
+                        break;
+                    }
+                    for byte in &buf[..n] {
+                        reply.push(*byte);
+                        if *byte == 0xff {
+                            if let Some(error) = reply_error(&reply) {
+                                warn!("Camera reported {}", error);
+                            }
+                            reply.clear();
+                        }
+                    }
+                }
+            }
+            .in_current_span(),
+        );
+        self.connection = Some(Connection {
+            writer,
+            reader_task,
+        });
+        self.zoom_speed = 0;
+        self.focus_speed = 0;
+        self.autofocus = false;
+
+        if let Some(enabled) = self.digital_zoom {
+            self.send(packet(
+                self.address,
+                &[0x04, 0x36, if enabled { 0x00 } else { 0x01 }],
+            ))
+            .await?;
+            self.send(packet(
+                self.address,
+                &[0x04, 0x06, if enabled { 0x02 } else { 0x03 }],
+            ))
+            .await?;
+        }
+        if let Some(enabled) = self.stabilizer {
+            self.send(packet(
+                self.address,
+                &[0x04, 0x34, if enabled { 0x02 } else { 0x03 }],
+            ))
+            .await?;
+        }
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.connection.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.connection.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+
+        let zoom_speed = speed(command.zoom.get());
+        if self.capabilities.contains(&Capability::Zoom) && zoom_speed != self.zoom_speed {
+            self.send(zoom_command(self.address, zoom_speed)).await?;
+            self.zoom_speed = zoom_speed;
+        }
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus && !self.autofocus {
+            self.send(packet(self.address, &[0x04, 0x18, 0x01])).await?;
+        }
+        self.autofocus = autofocus;
+
+        let focus_speed = speed(command.focus.get());
+        if self.capabilities.contains(&Capability::Focus) && focus_speed != self.focus_speed {
+            self.send(focus_command(self.address, focus_speed)).await?;
+            self.focus_speed = focus_speed;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a command payload with the camera's address and the terminator.
+fn packet(address: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x80 | address, 0x01];
+    packet.extend(payload);
+    packet.push(0xff);
+    packet
+}
+
+/// Converts a value from -1 to 1 into a signed variable speed. VISCA speeds run from 0 (slowest)
+/// to 7, so 1 to 8 here are moving and 0 is stopped.
+fn speed(value: f64) -> i8 {
+    (value.clamp(-1.0, 1.0) * 8.0).round() as i8
+}
+
+/// Direction and speed byte shared by the variable zoom and focus commands: `2p` for tele/far,
+/// `3p` for wide/near, and `00` to stop.
+fn drive(speed: i8) -> u8 {
+    match speed {
+        0 => 0x00,
+        x if x > 0 => 0x20 | (x - 1) as u8,
+        x => 0x30 | (-x - 1) as u8,
+    }
+}
+
+fn zoom_command(address: u8, speed: i8) -> Vec<u8> {
+    packet(address, &[0x04, 0x07, drive(speed)])
+}
+
+fn focus_command(address: u8, speed: i8) -> Vec<u8> {
+    packet(address, &[0x04, 0x08, drive(speed)])
+}
+
+/// Describes error replies (`90 6y ...`); ACK and completion replies return `None`.
+fn reply_error(reply: &[u8]) -> Option<&'static str> {
+    match reply {
+        [_, y, 0x01, 0xff] if y & 0xf0 == 0x60 => Some("message length error"),
+        [_, y, 0x02, 0xff] if y & 0xf0 == 0x60 => Some("syntax error"),
+        [_, y, 0x03, 0xff] if y & 0xf0 == 0x60 => Some("command buffer full"),
+        [_, y, 0x04, 0xff] if y & 0xf0 == 0x60 => Some("command cancelled"),
+        [_, y, 0x05, 0xff] if y & 0xf0 == 0x60 => Some("no socket"),
+        [_, y, 0x41, 0xff] if y & 0xf0 == 0x60 => Some("command not executable"),
+        [_, y, ..] if y & 0xf0 == 0x60 => Some("unknown error"),
+        _ => None,
+    }
+}
+
+pub fn create(id: &str, config: &FcbConfig) -> Fcb {
+    Fcb {
+        id: id.to_owned(),
+        port: config.port.clone(),
+        baud_rate: config.baud_rate,
+        address: config.address,
+        digital_zoom: config.digital_zoom,
+        stabilizer: config.stabilizer,
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                HashSet::from([Capability::Zoom, Capability::Focus, Capability::Autofocus])
+            }),
+        connection: None,
+        zoom_speed: 0,
+        focus_speed: 0,
+        autofocus: false,
+    }
+}
+
+#[test]
+fn test_visca_commands() {
+    assert_eq!(hex::encode(zoom_command(1, speed(1.0))), "8101040727ff");
+    assert_eq!(hex::encode(zoom_command(1, speed(-0.1))), "8101040730ff");
+    assert_eq!(hex::encode(zoom_command(2, speed(0.0))), "8201040700ff");
+    assert_eq!(hex::encode(focus_command(1, speed(0.5))), "8101040823ff");
+    assert_eq!(reply_error(&[0x90, 0x41, 0xff]), None);
+    assert_eq!(reply_error(&[0x90, 0x60, 0x02, 0xff]), Some("syntax error"));
+}
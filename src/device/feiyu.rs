@@ -0,0 +1,291 @@
+use async_trait::async_trait;
+use btleplug::{
+    api::{Characteristic, Peripheral as _, WriteType},
+    platform::{Adapter, Peripheral},
+};
+use futures::TryFutureExt as _;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+use uuid::uuid;
+
+use super::DeviceError;
+use crate::config::{Capability, FeiyuConfig, FeiyuOption};
+
+// The command layout hasn't been verified on hardware yet, so it's kept in one place
+const COMMAND_UUID: uuid::Uuid = uuid!("0000ffe1-0000-1000-8000-00805f9b34fb");
+const HEADER: [u8; 2] = [0xa5, 0x5a];
+const JOYSTICK: u8 = 0x01;
+const SPEED_MAX: f64 = 1000.0;
+
+/// Scales a value from -1 to 1 into a joystick speed, making it easier to hit smaller values.
+fn scale_ptr_value(val: f64) -> i16 {
+    (val.powi(3) * SPEED_MAX) as i16
+}
+
+/// Builds a joystick packet: the header, the length of the rest, the command, the sequence
+/// number, pan, tilt, and roll speeds, and the low byte of the sum of everything after the header.
+fn create_packet(seq_num: u8, pan: f64, tilt: f64, roll: f64) -> Vec<u8> {
+    let body = [
+        &[JOYSTICK, seq_num][..],
+        &scale_ptr_value(pan).to_le_bytes(),
+        &scale_ptr_value(tilt).to_le_bytes(),
+        &scale_ptr_value(roll).to_le_bytes(),
+    ]
+    .concat();
+    let len = body.len() as u8 + 1;
+    let checksum = body.iter().fold(len, |sum, b| sum.wrapping_add(*b));
+    [&HEADER[..], &[len], &body, &[checksum]].concat()
+}
+
+/// FeiyuTech gimbals (AK and Scorp series), driven through their Bluetooth joystick commands.
+pub struct Feiyu {
+    id: String,
+    name: String,
+    seq: u8,
+    adapter: Adapter,
+    connection: Option<Connection>,
+    capabilities: HashSet<Capability>,
+    options: HashSet<FeiyuOption>,
+    /// Whether the last command moved, so that one stop is sent after moving.
+    moving: bool,
+}
+
+struct Connection {
+    peripheral: Peripheral,
+    characteristic: Arc<Mutex<Characteristic>>,
+}
+
+impl Connection {
+    pub async fn try_resume_connection(&mut self, name: &str) -> Result<(), DeviceError> {
+        if self.peripheral.is_connected().await? {
+            return Ok(());
+        }
+        warn!("Lost connection, reconnecting...");
+        let timer = Instant::now();
+        self.peripheral.disconnect().await?;
+
+        timeout(Duration::from_millis(200), self.peripheral.connect())
+            .map_err(|_| {
+                DeviceError::Timeout(format!("{}: timed out while trying to reconnect", name))
+            })
+            .await??;
+
+        let command_characteristic = get_characteristic(&self.peripheral, COMMAND_UUID).await?;
+        *self.characteristic.lock().unwrap() = command_characteristic;
+        info!("Reconnected in {:?}", timer.elapsed());
+        Ok(())
+    }
+}
+
+impl Feiyu {
+    /// The pan, tilt, and roll to send, with the axes the gimbal isn't allowed to move zeroed.
+    fn ptr_axes(&self, command: super::Command) -> (f64, f64, f64) {
+        let axis = |capability, option, value: f64| {
+            if !self.capabilities.contains(&capability) {
+                0.0
+            } else if self.options.contains(&option) {
+                -value
+            } else {
+                value
+            }
+        };
+        (
+            axis(Capability::Ptr, FeiyuOption::ReversePan, command.pan.get()),
+            axis(
+                Capability::Ptr,
+                FeiyuOption::ReverseTilt,
+                command.tilt.get(),
+            ),
+            axis(
+                Capability::Roll,
+                FeiyuOption::ReverseRoll,
+                command.roll.get(),
+            ),
+        )
+    }
+}
+
+impl std::fmt::Display for Feiyu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Feiyu[{}]", self.name)
+    }
+}
+
+#[async_trait]
+impl super::Device for Feiyu {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+
+        let peripheral = super::ble::find_peripheral(&self.adapter, &self.name).await?;
+        peripheral.connect().await?;
+        let cmd_characteristic = Arc::new(Mutex::new(
+            get_characteristic(&peripheral, COMMAND_UUID).await?,
+        ));
+
+        self.connection = Some(Connection {
+            peripheral,
+            characteristic: cmd_characteristic,
+        });
+        self.moving = false;
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match &self.connection {
+            None => {
+                debug!("Already disconnected");
+            }
+            Some(c) => {
+                info!("Disconnecting");
+                c.peripheral.disconnect().await?;
+                self.connection = None;
+                info!("Disconnected");
+            }
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    async fn repair(&mut self) -> Result<(), DeviceError> {
+        let connection = self.connection.take();
+        let connected = connection.as_ref().map(|c| &c.peripheral);
+        super::ble::repair(&self.adapter, &self.name, connected).await?;
+        drop(connection);
+        self.connect().await
+    }
+
+    fn supports_repair(&self) -> bool {
+        true
+    }
+
+    fn uses_bluetooth(&self) -> bool {
+        true
+    }
+
+    fn set_adapter(&mut self, adapter: &Adapter) {
+        self.adapter = adapter.clone();
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn check_connection(&mut self) -> bool {
+        let name = format!("{}", self);
+        match &mut self.connection {
+            None => false,
+            Some(c) => c.try_resume_connection(&name).await.is_ok(),
+        }
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        let name = format!("{}", self);
+        debug!("Received command {:?}", command);
+        let (pan, tilt, roll) = self.ptr_axes(command);
+        let Some(c) = &mut self.connection else {
+            debug!("Not connected");
+            return Ok(());
+        };
+        let moving = pan != 0.0 || tilt != 0.0 || roll != 0.0;
+        // Unlike DJI and Zhiyun gimbals, these keep moving until told to stop
+        if !moving && !self.moving {
+            return Ok(());
+        }
+
+        c.try_resume_connection(&name).await?;
+        let packet = create_packet(self.seq, pan, tilt, roll);
+        self.seq = self.seq.wrapping_add(1);
+        debug!(packet = %hex::encode(&packet), "Sending PTR command");
+        let cmd_characteristic = c.characteristic.lock().unwrap().clone();
+        c.peripheral
+            .write(&cmd_characteristic, &packet, WriteType::WithoutResponse)
+            .await?;
+        self.moving = moving;
+        Ok(())
+    }
+
+    async fn send_stop(&mut self) -> Result<(), DeviceError> {
+        let Some(c) = &mut self.connection else {
+            return Ok(());
+        };
+        if !self.moving {
+            return Ok(());
+        }
+        let packet = create_packet(self.seq, 0.0, 0.0, 0.0);
+        self.seq = self.seq.wrapping_add(1);
+        debug!(packet = %hex::encode(&packet), "Sending PTR stop");
+        let cmd_characteristic = c.characteristic.lock().unwrap().clone();
+        // Asking for a response means the stop has arrived by the time the link is dropped
+        let acknowledged = c
+            .peripheral
+            .write(&cmd_characteristic, &packet, WriteType::WithResponse)
+            .await;
+        if let Err(e) = acknowledged {
+            info!("Stop wasn't acknowledged, resending: {}", e);
+            c.peripheral
+                .write(&cmd_characteristic, &packet, WriteType::WithoutResponse)
+                .await?;
+        }
+        self.moving = false;
+        Ok(())
+    }
+}
+
+async fn get_characteristic(
+    peripheral: &Peripheral,
+    uuid: uuid::Uuid,
+) -> Result<Characteristic, DeviceError> {
+    peripheral.discover_services().await?;
+    match peripheral.characteristics().iter().find(|c| c.uuid == uuid) {
+        None => Err("characteristic not found".into()),
+        Some(x) => Ok(x.to_owned()),
+    }
+}
+
+pub fn create(id: &str, adapter: Adapter, config: &FeiyuConfig) -> Feiyu {
+    Feiyu {
+        id: id.to_owned(),
+        name: config.name.to_owned(),
+        seq: 0,
+        adapter,
+        connection: None,
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| HashSet::from([Capability::Ptr, Capability::Roll])),
+        options: config
+            .options
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_default(),
+        moving: false,
+    }
+}
+
+#[test]
+fn test_packet() {
+    assert_eq!(
+        hex::encode(create_packet(3, 1.0, -0.5, 0.0)),
+        "a55a090103e80383ff00007a"
+    );
+    assert_eq!(
+        hex::encode(create_packet(4, 0.0, 0.0, 0.0)),
+        "a55a0901040000000000000e"
+    );
+}
@@ -0,0 +1,431 @@
+use async_trait::async_trait;
+use btleplug::{
+    api::{Characteristic, Peripheral as _, WriteType},
+    platform::{Adapter, Peripheral},
+};
+use futures::TryFutureExt as _;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::time::timeout;
+use tracing::{debug, info, warn};
+
+use super::DeviceError;
+use crate::config::{
+    all_capabilities, BleChecksum, BleEncoding, BleField, BlePacket, BlePart, BleValue, Capability,
+    GenericBleConfig,
+};
+
+/// Bluetooth devices driven by packets described in config, for gimbals that only need scaled
+/// values written at fixed offsets.
+pub struct GenericBle {
+    id: String,
+    name: String,
+    characteristic: Result<uuid::Uuid, uuid::Error>,
+    write_type: WriteType,
+    packets: Vec<BlePacket>,
+    checksum: Option<Checksum>,
+    seq: u8,
+    adapter: Adapter,
+    connection: Option<Connection>,
+    capabilities: HashSet<Capability>,
+}
+
+struct Connection {
+    peripheral: Peripheral,
+    characteristic: Arc<Mutex<Characteristic>>,
+}
+
+impl Connection {
+    pub async fn try_resume_connection(
+        &mut self,
+        name: &str,
+        uuid: uuid::Uuid,
+    ) -> Result<(), DeviceError> {
+        if self.peripheral.is_connected().await? {
+            return Ok(());
+        }
+        warn!("Lost connection, reconnecting...");
+        let timer = Instant::now();
+        self.peripheral.disconnect().await?;
+
+        timeout(Duration::from_millis(200), self.peripheral.connect())
+            .map_err(|_| {
+                DeviceError::Timeout(format!("{}: timed out while trying to reconnect", name))
+            })
+            .await??;
+
+        let command_characteristic = get_characteristic(&self.peripheral, uuid).await?;
+        *self.characteristic.lock().unwrap() = command_characteristic;
+        info!("Reconnected in {:?}", timer.elapsed());
+        Ok(())
+    }
+}
+
+enum Checksum {
+    Crc16 {
+        crc: Box<crc::Crc<u16>>,
+        skip: usize,
+        big_endian: bool,
+    },
+    Sum8 {
+        skip: usize,
+    },
+}
+
+impl Checksum {
+    fn new(config: &BleChecksum) -> Checksum {
+        match *config {
+            BleChecksum::Crane => Checksum::Crc16 {
+                crc: Box::new(super::crane::CRC),
+                skip: 0,
+                big_endian: false,
+            },
+            BleChecksum::Duml => Checksum::Crc16 {
+                crc: Box::new(super::duml::PACKET_CRC),
+                skip: 0,
+                big_endian: false,
+            },
+            BleChecksum::Sum8 { skip } => Checksum::Sum8 { skip },
+            BleChecksum::Crc16 {
+                poly,
+                init,
+                reflect,
+                xor_out,
+                skip,
+                big_endian,
+            } => {
+                // The crc crate only takes static algorithms, and there's one per device
+                let algorithm = Box::leak(Box::new(crc::Algorithm {
+                    width: 16,
+                    poly,
+                    init,
+                    refin: reflect,
+                    refout: reflect,
+                    xorout: xor_out,
+                    check: 0x0000,
+                    residue: 0x0000,
+                }));
+                Checksum::Crc16 {
+                    crc: Box::new(crc::Crc::<u16>::new(algorithm)),
+                    skip,
+                    big_endian,
+                }
+            }
+        }
+    }
+
+    fn append(&self, packet: &mut Vec<u8>) {
+        match self {
+            Checksum::Crc16 {
+                crc,
+                skip,
+                big_endian,
+            } => {
+                let checksum = crc.checksum(packet.get(*skip..).unwrap_or_default());
+                match big_endian {
+                    true => packet.extend(checksum.to_be_bytes()),
+                    false => packet.extend(checksum.to_le_bytes()),
+                }
+            }
+            Checksum::Sum8 { skip } => {
+                let body = packet.get(*skip..).unwrap_or_default();
+                let checksum = body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+                packet.push(checksum);
+            }
+        }
+    }
+}
+
+/// Scales a value from -1 to 1 as described by a field, clamped to what its encoding can hold.
+fn encode_field(field: &BleField, value: f64) -> Vec<u8> {
+    let curved = value.abs().powf(field.exponent).copysign(value);
+    let raw = (curved * field.scale + field.offset).round();
+    match field.encoding {
+        BleEncoding::U8 => vec![raw.clamp(0.0, u8::MAX as f64) as u8],
+        BleEncoding::I8 => (raw.clamp(i8::MIN as f64, i8::MAX as f64) as i8)
+            .to_le_bytes()
+            .to_vec(),
+        BleEncoding::U16le => (raw.clamp(0.0, u16::MAX as f64) as u16)
+            .to_le_bytes()
+            .to_vec(),
+        BleEncoding::U16be => (raw.clamp(0.0, u16::MAX as f64) as u16)
+            .to_be_bytes()
+            .to_vec(),
+        BleEncoding::I16le => (raw.clamp(i16::MIN as f64, i16::MAX as f64) as i16)
+            .to_le_bytes()
+            .to_vec(),
+        BleEncoding::I16be => (raw.clamp(i16::MIN as f64, i16::MAX as f64) as i16)
+            .to_be_bytes()
+            .to_vec(),
+    }
+}
+
+fn create_packet(
+    packet: &BlePacket,
+    checksum: Option<&Checksum>,
+    seq_num: u8,
+    command: super::Command,
+) -> Result<Vec<u8>, DeviceError> {
+    let mut bytes = vec![];
+    for part in packet.parts.iter() {
+        match part {
+            BlePart::Bytes { bytes: hex_bytes } => bytes.extend(
+                hex::decode(hex_bytes)
+                    .map_err(|e| format!("invalid bytes {}: {}", hex_bytes, e))?,
+            ),
+            BlePart::Value(field) => {
+                let value = match field.value {
+                    BleValue::Pan => command.pan.get(),
+                    BleValue::Tilt => command.tilt.get(),
+                    BleValue::Roll => command.roll.get(),
+                    BleValue::Zoom => command.zoom.get(),
+                    BleValue::Focus => command.focus.get(),
+                    BleValue::Autofocus => command.autofocus as u8 as f64,
+                    // Used as-is rather than scaled
+                    BleValue::Sequence => {
+                        bytes.push(seq_num);
+                        continue;
+                    }
+                };
+                bytes.extend(encode_field(field, value));
+            }
+        }
+    }
+    if let Some(checksum) = checksum {
+        checksum.append(&mut bytes);
+    }
+    Ok(bytes)
+}
+
+impl std::fmt::Display for GenericBle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GenericBle[{}]", self.name)
+    }
+}
+
+impl GenericBle {
+    fn characteristic_uuid(&self) -> Result<uuid::Uuid, DeviceError> {
+        self.characteristic
+            .clone()
+            .map_err(|e| format!("invalid characteristic UUID: {}", e).into())
+    }
+
+    /// Zeroes the axes the device isn't allowed to move.
+    fn filter_command(&self, command: super::Command) -> super::Command {
+        let ptr = self.capabilities.contains(&Capability::Ptr);
+        let axis = |enabled: bool, velocity: super::Velocity| match enabled {
+            true => velocity,
+            false => super::Velocity::STOP,
+        };
+        super::Command {
+            pan: axis(ptr, command.pan),
+            tilt: axis(ptr, command.tilt),
+            roll: axis(self.capabilities.contains(&Capability::Roll), command.roll),
+            zoom: axis(self.capabilities.contains(&Capability::Zoom), command.zoom),
+            focus: axis(
+                self.capabilities.contains(&Capability::Focus),
+                command.focus,
+            ),
+            iris: super::Velocity::STOP,
+            autofocus: self.capabilities.contains(&Capability::Autofocus) && command.autofocus,
+            record: None,
+            shutter: false,
+            focus_point: None,
+        }
+    }
+}
+
+#[async_trait]
+impl super::Device for GenericBle {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let uuid = self.characteristic_uuid()?;
+
+        let peripheral = super::ble::find_peripheral(&self.adapter, &self.name).await?;
+        peripheral.connect().await?;
+        let cmd_characteristic = Arc::new(Mutex::new(get_characteristic(&peripheral, uuid).await?));
+
+        self.connection = Some(Connection {
+            peripheral,
+            characteristic: cmd_characteristic,
+        });
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match &self.connection {
+            None => {
+                debug!("Already disconnected");
+            }
+            Some(c) => {
+                info!("Disconnecting");
+                c.peripheral.disconnect().await?;
+                self.connection = None;
+                info!("Disconnected");
+            }
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    async fn repair(&mut self) -> Result<(), DeviceError> {
+        let connection = self.connection.take();
+        let connected = connection.as_ref().map(|c| &c.peripheral);
+        super::ble::repair(&self.adapter, &self.name, connected).await?;
+        drop(connection);
+        self.connect().await
+    }
+
+    fn supports_repair(&self) -> bool {
+        true
+    }
+
+    fn uses_bluetooth(&self) -> bool {
+        true
+    }
+
+    fn set_adapter(&mut self, adapter: &Adapter) {
+        self.adapter = adapter.clone();
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn check_connection(&mut self) -> bool {
+        let name = format!("{}", self);
+        let Ok(uuid) = self.characteristic_uuid() else {
+            return false;
+        };
+        match &mut self.connection {
+            None => false,
+            Some(c) => c.try_resume_connection(&name, uuid).await.is_ok(),
+        }
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        let name = format!("{}", self);
+        debug!("Received command {:?}", command);
+        let command = self.filter_command(command);
+        let uuid = self.characteristic_uuid()?;
+        let Some(c) = &mut self.connection else {
+            debug!("Not connected");
+            return Ok(());
+        };
+
+        c.try_resume_connection(&name, uuid).await?;
+        let cmd_characteristic = c.characteristic.lock().unwrap().clone();
+        for packet in self.packets.iter() {
+            if packet.on_autofocus && !command.autofocus {
+                continue;
+            }
+            let bytes = create_packet(packet, self.checksum.as_ref(), self.seq, command)?;
+            self.seq = self.seq.wrapping_add(1);
+            debug!(packet = %hex::encode(&bytes), "Sending");
+            c.peripheral
+                .write(&cmd_characteristic, &bytes, self.write_type)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+async fn get_characteristic(
+    peripheral: &Peripheral,
+    uuid: uuid::Uuid,
+) -> Result<Characteristic, DeviceError> {
+    peripheral.discover_services().await?;
+    match peripheral.characteristics().iter().find(|c| c.uuid == uuid) {
+        None => Err("characteristic not found".into()),
+        Some(x) => Ok(x.to_owned()),
+    }
+}
+
+pub fn create(id: &str, adapter: Adapter, config: &GenericBleConfig) -> GenericBle {
+    GenericBle {
+        id: id.to_owned(),
+        name: config.name.to_owned(),
+        characteristic: uuid::Uuid::parse_str(&config.characteristic),
+        write_type: match config.with_response {
+            true => WriteType::WithResponse,
+            false => WriteType::WithoutResponse,
+        },
+        packets: config.packets.clone(),
+        checksum: config.checksum.as_ref().map(Checksum::new),
+        seq: 0,
+        adapter,
+        connection: None,
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(all_capabilities),
+    }
+}
+
+#[test]
+fn test_create_packet() {
+    // Zhiyun Crane's tilt packet, which should come out the same as the built-in backend's
+    let crane_tilt: BlePacket = serde_json::from_str(
+        r#"{"parts": [
+            {"bytes": "243c08001812"},
+            {"value": "sequence", "encoding": "u8"},
+            {"bytes": "010110"},
+            {"value": "tilt", "encoding": "u16le", "scale": 2048, "offset": 2048, "exponent": 3}
+        ]}"#,
+    )
+    .unwrap();
+    let command = super::Command {
+        tilt: super::Velocity::new(0.5),
+        ..Default::default()
+    };
+    let checksum = Checksum::new(&BleChecksum::Crane);
+    assert_eq!(
+        create_packet(&crane_tilt, Some(&checksum), 7, command).unwrap(),
+        super::crane::add_checksum(&hex::decode("243c08001812070101100009").unwrap())
+    );
+
+    // A FeiyuTech-style packet with a byte sum after the header
+    let feiyu: BlePacket = serde_json::from_str(
+        r#"{"parts": [
+            {"bytes": "a55a0901"},
+            {"value": "sequence", "encoding": "u8"},
+            {"value": "pan", "encoding": "i16le", "scale": 1000, "exponent": 3},
+            {"value": "tilt", "encoding": "i16le", "scale": 1000, "exponent": 3},
+            {"value": "roll", "encoding": "i16le", "scale": 1000, "exponent": 3}
+        ]}"#,
+    )
+    .unwrap();
+    let command = super::Command {
+        pan: super::Velocity::new(1.0),
+        tilt: super::Velocity::new(-0.5),
+        ..Default::default()
+    };
+    let checksum = Checksum::new(&BleChecksum::Sum8 { skip: 2 });
+    assert_eq!(
+        hex::encode(create_packet(&feiyu, Some(&checksum), 3, command).unwrap()),
+        "a55a090103e80383ff00007a"
+    );
+
+    let clamped = BleField {
+        value: BleValue::Zoom,
+        encoding: BleEncoding::I8,
+        scale: 1000.0,
+        offset: 0.0,
+        exponent: 1.0,
+    };
+    assert_eq!(encode_field(&clamped, -1.0), vec![0x80]);
+}
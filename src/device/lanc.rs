@@ -1,47 +1,105 @@
 use std::{
     collections::HashSet,
+    io,
     time::{Duration, Instant},
     vec,
 };
 
 use async_trait::async_trait;
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    sync::mpsc::UnboundedSender,
+    sync::{mpsc, watch},
     task::JoinHandle,
 };
-use tokio_serial::SerialPortBuilderExt as _;
+use tokio_serial::{SerialPortBuilderExt as _, SerialStream};
+use tracing::{debug, info, warn, Instrument as _};
 
-use crate::config::{self, all_capabilities, Capability};
+use super::DeviceError;
+use crate::config::{self, all_capabilities, Capability, LancTransport};
 
 // Other potentially useful commands:
 // 2835: Zoom Tele slow
 // 2837: Zoom Wide slow
 // 2845: Focus Far
 // 2847: Focus Near
-// 2853: Iris Close
-// 2855: Iris Open
 
+/// Toggles recording, since LANC has no separate start and stop.
+const RECORD: LancCommand = *b"1833\n";
 const INTERVAL: Duration = Duration::from_millis(200);
+/// How long a button is held, and then released before the next press. The camera only acts on a
+/// command it has seen for several frames in a row.
+const PRESS: Duration = Duration::from_millis(200);
+/// How long the camera gets to report a record press before its status is trusted again.
+const STATUS_SETTLE: Duration = Duration::from_secs(1);
+/// Byte 4 of a frame while the camera is recording.
+const STATUS_RECORDING: u8 = 0x04;
+/// Guide code (upper half of byte 5) for frames whose byte 6 holds the zoom position.
+const GUIDE_ZOOM_POSITION: u8 = 0x2;
+/// GPIO pins for the `gpio` transport, unless configured otherwise.
+const DEFAULT_INPUT_PIN: u8 = 17;
+const DEFAULT_OUTPUT_PIN: u8 = 27;
 
 pub struct Lanc {
     id: String,
     port: String,
+    transport: LancTransport,
+    input_pin: u8,
+    output_pin: u8,
     connection: Option<Connection>,
     capabilities: HashSet<Capability>,
+    fade_out: Duration,
+    /// Whether the camera is recording, as far as we know. LANC can only toggle it, so this
+    /// assumes the camera wasn't recording when we connected, unless the camera reports it.
+    recording: bool,
+    last_record_press: Option<Instant>,
+    custom_commands: IndexMap<String, String>,
 }
 
 type LancCommand = [u8; 5];
 
+/// What the camera reports about itself, in the last four bytes of each LANC frame. Stays `None`
+/// with bridges that don't pass frames on, or cameras that don't report it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Status {
+    recording: Option<bool>,
+    /// From `0` at the wide end to `1` at the tele end.
+    zoom: Option<f64>,
+}
+
+impl Status {
+    /// Takes in a frame relayed by the bridge. Returns whether anything changed.
+    fn update(&mut self, frame: [u8; 8]) -> bool {
+        let previous = *self;
+        self.recording = Some(frame[4] == STATUS_RECORDING);
+        if frame[5] >> 4 == GUIDE_ZOOM_POSITION {
+            self.zoom = Some(frame[6] as f64 / u8::MAX as f64);
+        }
+        *self != previous
+    }
+}
+
 struct Connection {
-    communication_channel: UnboundedSender<[LancCommand; 2]>,
+    /// The latest commands, or `None` once stopped. Only the latest matters, so a new command
+    /// replaces one that hasn't been sent yet and cuts short the one being sent.
+    communication_channel: watch::Sender<Option<[LancCommand; 2]>>,
+    /// Button presses, such as record. Unlike moves, a press that's dropped or cut short can
+    /// leave the camera in a different state than we think, so each one is sent in full.
+    presses: mpsc::UnboundedSender<LancCommand>,
+    status: watch::Receiver<Status>,
     #[allow(unused)]
     communication_thread: JoinHandle<()>,
 }
 
 impl std::fmt::Display for Lanc {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "LANC[{}]", self.port)
+        match self.transport {
+            LancTransport::Serial => write!(f, "LANC[{}]", self.port),
+            LancTransport::Gpio => {
+                write!(f, "LANC[GPIO{}/{}]", self.input_pin, self.output_pin)
+            }
+        }
     }
 }
 
@@ -51,79 +109,65 @@ impl super::Device for Lanc {
         self.id.clone()
     }
 
-    async fn connect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let name = format!("{}", self);
-        println!("{}: Connecting", name);
-        let mut stream = tokio_serial::new(&self.port, 115200)
-            .data_bits(tokio_serial::DataBits::Eight)
-            .parity(tokio_serial::Parity::None)
-            .stop_bits(tokio_serial::StopBits::One)
-            .open_native_async()?;
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<[LancCommand; 2]>();
-        let communication_thread = tokio::spawn(async move {
-            while let Some(data) = rx.recv().await {
-                println!(
-                    "{}: Writing commands {:?} {:?}",
-                    name,
-                    std::str::from_utf8(&data[0]).unwrap(),
-                    std::str::from_utf8(&data[1]).unwrap(),
-                );
-                let mut buf = [0; 32];
-                let mut counter = 0;
-                let timer = Instant::now();
-                while timer.elapsed() < INTERVAL * 9 / 10 {
-                    loop {
-                        let read = match stream.read(&mut buf).await {
-                            Ok(read) => read,
-                            Err(e) => {
-                                eprintln!("{}: Failed to read from stream: {}", name, e);
-                                break;
-                            }
-                        };
-                        // Signal from the Arduino that it has just finished sending a LANC command
-                        if read > 0 && buf[read - 1] == 0xA {
-                            break;
-                        }
-                    }
-
-                    if let Err(e) = stream.write_all(&data[counter % 2]).await {
-                        eprintln!("{}: Failed to write to stream: {}", name, e);
-                    }
-                    counter += 1;
-                }
-                println!(
-                    "{}: Wrote {} commands over {:?}",
-                    name,
-                    counter,
-                    timer.elapsed(),
-                );
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let link = match self.transport {
+            LancTransport::Serial => Link::Serial {
+                stream: tokio_serial::new(&self.port, 115200)
+                    .data_bits(tokio_serial::DataBits::Eight)
+                    .parity(tokio_serial::Parity::None)
+                    .stop_bits(tokio_serial::StopBits::One)
+                    .open_native_async()?,
+                pending: vec![],
+            },
+            #[cfg(all(feature = "gpio", target_os = "linux"))]
+            LancTransport::Gpio => Link::Gpio(super::lanc_gpio::Link::open(
+                self.input_pin,
+                self.output_pin,
+            )?),
+            #[cfg(not(all(feature = "gpio", target_os = "linux")))]
+            LancTransport::Gpio => {
+                return Err(DeviceError::Unsupported(
+                    "this build of webptz can't drive LANC over GPIO".to_owned(),
+                ))
             }
-            println!("{}: Communication channel closed", name);
-        });
+        };
+        let (tx, rx) = watch::channel(None);
+        let (presses, presses_rx) = mpsc::unbounded_channel();
+        let (status_tx, status) = watch::channel(Status::default());
+        self.recording = false;
+        self.last_record_press = None;
+        let bridge = Bridge {
+            link,
+            status: status_tx,
+        };
+        let communication_thread =
+            tokio::spawn(communicate(bridge, rx, presses_rx, self.fade_out).in_current_span());
         self.connection = Some(Connection {
             communication_channel: tx,
+            presses,
+            status,
             communication_thread,
         });
-        println!("{}: Connected", self);
+        info!("Connected");
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let name = format!("{}", self);
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
         match &mut self.connection {
             None => {
-                println!("{}: Already disconnected", name);
+                debug!("Already disconnected");
             }
             Some(ref mut _c) => {
-                println!("{}: Disconnecting", name);
+                info!("Disconnecting");
                 self.connection = None;
-                println!("{}: Disconnected", name);
+                info!("Disconnected");
             }
         }
         Ok(())
     }
 
-    async fn reconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
         self.disconnect().await?;
         self.connect().await?;
         Ok(())
@@ -133,84 +177,372 @@ impl super::Device for Lanc {
         self.connection.is_some()
     }
 
-    async fn send_command(
-        &mut self,
-        command: super::Command,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let name = format!("{}", self);
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
         if self.connection.is_none() {
-            println!("{}: Not connected", name);
+            debug!("Not connected");
             return Ok(());
         }
         let connection = self.connection.as_mut().unwrap();
 
-        println!("{}: Received command {:?}", name, command);
-        let mut commands: Vec<LancCommand> = vec![];
-
-        if self.capabilities.contains(&Capability::Zoom) && command.zoom != 0.0 {
-            commands.push(match command.zoom {
-                x if x >= 0.8 => *b"280E\n",
-                x if x >= 0.7 => *b"280C\n",
-                x if x >= 0.6 => *b"280A\n",
-                x if x >= 0.5 => *b"2808\n",
-                x if x >= 0.4 => *b"2806\n",
-                x if x >= 0.3 => *b"2804\n",
-                x if x >= 0.2 => *b"2802\n",
-                x if x >= 0.0 => *b"2800\n",
-                x if x <= -0.8 => *b"281E\n",
-                x if x <= -0.7 => *b"281C\n",
-                x if x <= -0.6 => *b"281A\n",
-                x if x <= -0.5 => *b"2818\n",
-                x if x <= -0.4 => *b"2816\n",
-                x if x <= -0.3 => *b"2814\n",
-                x if x <= -0.2 => *b"2812\n",
-                x if x <= -0.0 => *b"2810\n",
-                _ => *b"0000\n",
-            });
+        debug!("Received command {:?}", command);
+        // The camera's own report wins, once it's had time to show our last press
+        let settled = self
+            .last_record_press
+            .is_none_or(|t| t.elapsed() > STATUS_SETTLE);
+        if let (true, Some(recording)) = (settled, connection.status.borrow().recording) {
+            self.recording = recording;
         }
-
-        if self.capabilities.contains(&Capability::Autofocus) && command.autofocus {
-            commands.push(*b"2843\n");
-        } else if self.capabilities.contains(&Capability::Focus) && command.focus != 0.0 {
-            commands.push(match command.focus {
-                x if x >= 0.80 => *b"28EB\n",
-                x if x >= 0.65 => *b"28E9\n",
-                x if x >= 0.50 => *b"28E7\n",
-                x if x >= 0.35 => *b"28E5\n",
-                x if x >= 0.20 => *b"28E3\n",
-                x if x >= 0.00 => *b"28E1\n",
-                x if x <= -0.80 => *b"28FB\n",
-                x if x <= -0.65 => *b"28F9\n",
-                x if x <= -0.50 => *b"28F7\n",
-                x if x <= -0.35 => *b"28F5\n",
-                x if x <= -0.20 => *b"28F3\n",
-                x if x <= -0.00 => *b"28F1\n",
-                _ => *b"0000\n",
-            });
+        let mut commands = build_commands(&self.capabilities, command);
+        let record = command
+            .record
+            .filter(|r| *r != self.recording && self.capabilities.contains(&Capability::Record));
+        if let Some(recording) = record {
+            info!(
+                "{} recording",
+                if recording { "Starting" } else { "Stopping" }
+            );
+            self.recording = recording;
+            self.last_record_press = Some(Instant::now());
+            // Goes out before the commands, which were meant to follow it
+            let _ = connection.presses.send(RECORD);
         }
 
-        if !commands.is_empty() {
-            // We're always sending two commands just for convenience reasons
-            if commands.len() == 1 {
-                commands.push(*commands.first().unwrap());
+        let commands = match commands.is_empty() {
+            true => None,
+            false => {
+                // We're always sending two commands just for convenience reasons
+                if commands.len() == 1 {
+                    commands.push(*commands.first().unwrap());
+                }
+                Some(commands.try_into().unwrap())
             }
-            let arr: [LancCommand; 2] = commands.try_into().unwrap();
-            connection.communication_channel.send(arr)?;
+        };
+        connection.communication_channel.send_replace(commands);
+
+        Ok(())
+    }
+
+    async fn custom_command(&mut self, name: &str) -> Result<(), DeviceError> {
+        let Some(code) = self.custom_commands.get(name) else {
+            return Err(DeviceError::Unsupported(format!(
+                "{} has no custom command {}",
+                self, name
+            )));
+        };
+        let command =
+            parse_code(code).ok_or_else(|| format!("invalid LANC code for {}: {}", name, code))?;
+        let Some(connection) = &self.connection else {
+            return Err(DeviceError::NotConnected);
+        };
+        info!("Sending {} ({})", name, code);
+        let _ = connection.presses.send(command);
+        Ok(())
+    }
+
+    fn telemetry(&self) -> Map<String, Value> {
+        let mut values = Map::new();
+        let Some(c) = &self.connection else {
+            return values;
+        };
+        let status = *c.status.borrow();
+        if let Some(recording) = status.recording {
+            values.insert("recording".to_owned(), recording.into());
+        }
+        if let Some(zoom) = status.zoom {
+            values.insert("zoomPosition".to_owned(), zoom.into());
         }
+        values
+    }
+}
+
+/// Whatever puts commands on the LANC line, one per frame.
+struct Bridge {
+    link: Link,
+    status: watch::Sender<Status>,
+}
+
+enum Link {
+    /// An Arduino on a serial port. It ends a line each time it has sent a LANC command, and
+    /// bridges that pass on what the camera reports put the whole frame ahead of it, as 16 hex
+    /// digits.
+    Serial {
+        stream: SerialStream,
+        /// The start of a line that hasn't been finished yet.
+        pending: Vec<u8>,
+    },
+    #[cfg(all(feature = "gpio", target_os = "linux"))]
+    Gpio(super::lanc_gpio::Link),
+}
 
+impl Bridge {
+    /// Waits for the end of the next frame, taking in what the camera reported in it.
+    async fn read_frame(&mut self) -> io::Result<()> {
+        let frame = match &mut self.link {
+            Link::Serial { stream, pending } => read_line(stream, pending).await?,
+            #[cfg(all(feature = "gpio", target_os = "linux"))]
+            Link::Gpio(gpio) => Some(gpio.read_frame().await?),
+        };
+        if let Some(frame) = frame {
+            self.status.send_if_modified(|s| s.update(frame));
+        }
         Ok(())
     }
+
+    async fn write(&mut self, command: &LancCommand) -> io::Result<()> {
+        match &mut self.link {
+            Link::Serial { stream, .. } => stream.write_all(command).await,
+            #[cfg(all(feature = "gpio", target_os = "linux"))]
+            Link::Gpio(gpio) => {
+                let bytes = hex::decode(&command[..4])
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                gpio.write([bytes[0], bytes[1]])
+            }
+        }
+    }
+
+    /// Alternates between the two commands for `duration`, or until `interrupted`.
+    async fn write_commands(
+        &mut self,
+        data: [LancCommand; 2],
+        duration: Duration,
+        interrupted: impl Fn() -> bool,
+    ) {
+        debug!(
+            "Writing commands {:?} {:?}",
+            std::str::from_utf8(&data[0]).unwrap(),
+            std::str::from_utf8(&data[1]).unwrap()
+        );
+        let mut counter = 0;
+        let timer = Instant::now();
+        while timer.elapsed() < duration && !interrupted() {
+            // Written right after the bridge has sent the last one
+            if let Err(e) = self.read_frame().await {
+                warn!("Failed to read from stream: {}", e);
+            }
+            if let Err(e) = self.write(&data[counter % 2]).await {
+                warn!("Failed to write to stream: {}", e);
+            }
+            counter += 1;
+        }
+        debug!("Wrote {} commands over {:?}", counter, timer.elapsed());
+    }
+}
+
+/// Waits for the Arduino to finish a line, returning the frame on it, if any.
+async fn read_line(
+    stream: &mut SerialStream,
+    pending: &mut Vec<u8>,
+) -> io::Result<Option<[u8; 8]>> {
+    let mut buf = [0; 32];
+    while !pending.contains(&b'\n') {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        pending.extend_from_slice(&buf[..read]);
+    }
+    let end = pending.iter().position(|b| *b == b'\n').unwrap();
+    let line: Vec<u8> = pending.drain(..=end).collect();
+    Ok(parse_frame(&line))
+}
+
+/// Reads a frame relayed by the bridge, or `None` for a bare end of command.
+fn parse_frame(line: &[u8]) -> Option<[u8; 8]> {
+    hex::decode(line.trim_ascii()).ok()?.try_into().ok()
+}
+
+#[test]
+fn test_status() {
+    assert_eq!(parse_frame(b"\n"), None);
+    assert_eq!(parse_frame(b"183300000400"), None);
+    let mut status = Status::default();
+    let recording = parse_frame(b"1833000004000000\r\n").unwrap();
+    assert!(status.update(recording));
+    assert_eq!(status.recording, Some(true));
+    assert!(!status.update(recording));
+    let zoomed = parse_frame(b"28000000022080ff\n").unwrap();
+    assert!(status.update(zoomed));
+    assert_eq!(status.recording, Some(false));
+    assert_eq!(status.zoom, Some(128.0 / 255.0));
+}
+
+/// Writes each new set of commands repeatedly for one interval, since the camera only acts on
+/// commands for as long as they keep coming. After a stop, the last commands are kept up for the
+/// fade out time so that moves don't end abruptly. Presses interrupt moves and go out on their
+/// own.
+async fn communicate(
+    mut bridge: Bridge,
+    mut rx: watch::Receiver<Option<[LancCommand; 2]>>,
+    mut presses: mpsc::UnboundedReceiver<LancCommand>,
+    fade_out: Duration,
+) {
+    let mut last: Option<[LancCommand; 2]> = None;
+    loop {
+        tokio::select! {
+            biased;
+            Some(press) = presses.recv() => {
+                bridge.write_commands([press, press], PRESS, || false).await;
+                tokio::time::sleep(PRESS).await;
+                // Picks the current moves back up, rather than fading out the ones before
+                last = None;
+                rx.mark_changed();
+                continue;
+            }
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+            }
+            // Keeps the status current while there's nothing to send
+            read = bridge.read_frame() => {
+                if let Err(e) = read {
+                    warn!("Failed to read from stream: {}", e);
+                    tokio::time::sleep(INTERVAL).await;
+                }
+                continue;
+            }
+        }
+        let next = *rx.borrow_and_update();
+        let (data, duration) = match (next, last) {
+            (Some(data), _) => (data, INTERVAL * 9 / 10),
+            (None, Some(data)) if !fade_out.is_zero() => (data, fade_out),
+            (None, _) => continue,
+        };
+        last = next;
+        bridge
+            .write_commands(data, duration, || rx.has_changed().unwrap_or(true))
+            .await;
+    }
+    info!("Communication channel closed");
+}
+
+/// Turns a hex LANC code such as `"18 33"` into the command the bridge expects.
+fn parse_code(code: &str) -> Option<LancCommand> {
+    let hex: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut command = *b"0000\n";
+    command[..4].copy_from_slice(hex.as_bytes());
+    Some(command)
+}
+
+#[test]
+fn test_parse_code() {
+    assert_eq!(parse_code("185E"), Some(*b"185E\n"));
+    assert_eq!(parse_code("18 5e"), Some(*b"185E\n"));
+    assert_eq!(parse_code("185"), None);
+    assert_eq!(parse_code("18 5G"), None);
+}
+
+fn build_commands(capabilities: &HashSet<Capability>, command: super::Command) -> Vec<LancCommand> {
+    let mut commands: Vec<LancCommand> = vec![];
+
+    if capabilities.contains(&Capability::Zoom) && command.zoom.get() != 0.0 {
+        commands.push(match command.zoom.get() {
+            x if x >= 0.8 => *b"280E\n",
+            x if x >= 0.7 => *b"280C\n",
+            x if x >= 0.6 => *b"280A\n",
+            x if x >= 0.5 => *b"2808\n",
+            x if x >= 0.4 => *b"2806\n",
+            x if x >= 0.3 => *b"2804\n",
+            x if x >= 0.2 => *b"2802\n",
+            x if x >= 0.0 => *b"2800\n",
+            x if x <= -0.8 => *b"281E\n",
+            x if x <= -0.7 => *b"281C\n",
+            x if x <= -0.6 => *b"281A\n",
+            x if x <= -0.5 => *b"2818\n",
+            x if x <= -0.4 => *b"2816\n",
+            x if x <= -0.3 => *b"2814\n",
+            x if x <= -0.2 => *b"2812\n",
+            x if x <= -0.0 => *b"2810\n",
+            _ => *b"0000\n",
+        });
+    }
+
+    if capabilities.contains(&Capability::Autofocus) && command.autofocus {
+        commands.push(*b"2843\n");
+    } else if capabilities.contains(&Capability::Focus) && command.focus.get() != 0.0 {
+        commands.push(match command.focus.get() {
+            x if x >= 0.80 => *b"28EB\n",
+            x if x >= 0.65 => *b"28E9\n",
+            x if x >= 0.50 => *b"28E7\n",
+            x if x >= 0.35 => *b"28E5\n",
+            x if x >= 0.20 => *b"28E3\n",
+            x if x >= 0.00 => *b"28E1\n",
+            x if x <= -0.80 => *b"28FB\n",
+            x if x <= -0.65 => *b"28F9\n",
+            x if x <= -0.50 => *b"28F7\n",
+            x if x <= -0.35 => *b"28F5\n",
+            x if x <= -0.20 => *b"28F3\n",
+            x if x <= -0.00 => *b"28F1\n",
+            _ => *b"0000\n",
+        });
+    }
+
+    // The bridge alternates between two commands, so the iris waits while zoom and focus move
+    let iris = command.iris.get();
+    if capabilities.contains(&Capability::Iris) && iris != 0.0 && commands.len() < 2 {
+        commands.push(match iris > 0.0 {
+            true => *b"2855\n",
+            false => *b"2853\n",
+        });
+    }
+
+    commands
 }
 
 pub fn create(id: &str, config: &config::LancConfig) -> Lanc {
     Lanc {
         id: id.to_string(),
         port: config.port.to_string(),
+        transport: config.transport,
+        input_pin: config.input_pin.unwrap_or(DEFAULT_INPUT_PIN),
+        output_pin: config.output_pin.unwrap_or(DEFAULT_OUTPUT_PIN),
         connection: None,
         capabilities: config
             .capabilities
             .clone()
             .map(HashSet::from_iter)
             .unwrap_or_else(all_capabilities),
+        fade_out: Duration::from_millis(config.fade_out_ms.unwrap_or(0)),
+        recording: false,
+        last_record_press: None,
+        custom_commands: config.custom_commands.clone(),
+    }
+}
+
+#[test]
+fn test_build_commands_golden() {
+    for (inputs, expected) in super::golden_cases(include_str!("fixtures/lanc_commands.txt")) {
+        let [zoom, focus, iris, autofocus] = inputs[..] else {
+            panic!("malformed fixture line: {:?}", inputs);
+        };
+        let command = super::Command {
+            pan: super::Velocity::STOP,
+            tilt: super::Velocity::STOP,
+            roll: super::Velocity::STOP,
+            zoom: super::Velocity::new(zoom.parse().unwrap()),
+            focus: super::Velocity::new(focus.parse().unwrap()),
+            iris: super::Velocity::new(iris.parse().unwrap()),
+            autofocus: autofocus.parse().unwrap(),
+            record: None,
+            shutter: false,
+            focus_point: None,
+        };
+        let commands = build_commands(&all_capabilities(), command)
+            .iter()
+            .map(|c| std::str::from_utf8(&c[..4]).unwrap().to_owned())
+            .collect::<Vec<_>>();
+        let actual = if commands.is_empty() {
+            "-".to_owned()
+        } else {
+            commands.join(",")
+        };
+        assert_eq!(actual, expected, "inputs: {:?}", inputs);
     }
 }
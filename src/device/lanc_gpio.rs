@@ -0,0 +1,171 @@
+//! LANC straight from a Raspberry Pi's GPIO pins, in place of the Arduino bridge. The LANC line is
+//! read on one pin, through a divider down to 3.3V, and pulled low through a transistor driven by
+//! another pin.
+
+use std::{
+    io,
+    sync::mpsc::{self as std_mpsc, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use rppal::gpio::{Gpio, InputPin, OutputPin};
+use tokio::sync::watch;
+use tracing::{debug, info};
+
+use super::DeviceError;
+
+/// LANC runs at 9600 baud.
+const BIT: Duration = Duration::from_micros(104);
+/// How long the line rests high between frames. Bytes within a frame are only separated by their
+/// stop bits, which are much shorter.
+const FRAME_GAP: Duration = Duration::from_millis(5);
+/// How long to wait for the next byte of a frame before giving up on the frame.
+const BYTE_TIMEOUT: Duration = Duration::from_millis(2);
+/// How long to wait for a frame at all. Frames come every 20ms or so while a camera is attached.
+const FRAME_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub struct Link {
+    commands: std_mpsc::Sender<[u8; 2]>,
+    frames: watch::Receiver<[u8; 8]>,
+}
+
+impl Link {
+    /// Takes over the pins (by BCM number) and starts following the camera's frames.
+    pub fn open(input_pin: u8, output_pin: u8) -> Result<Link, DeviceError> {
+        let gpio = Gpio::new()?;
+        let input = gpio.get(input_pin)?.into_input();
+        let output = gpio.get(output_pin)?.into_output_low();
+        let (commands, commands_rx) = std_mpsc::channel();
+        let (frames_tx, frames) = watch::channel([0; 8]);
+        let span = tracing::Span::current();
+        // The bit timing is far too tight for the async runtime, so the line gets its own thread
+        thread::Builder::new()
+            .name("lanc-gpio".to_owned())
+            .spawn(move || span.in_scope(|| run(input, output, commands_rx, frames_tx)))?;
+        Ok(Link { commands, frames })
+    }
+
+    /// Waits for the camera's next frame.
+    pub async fn read_frame(&mut self) -> io::Result<[u8; 8]> {
+        self.frames
+            .changed()
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(*self.frames.borrow_and_update())
+    }
+
+    /// Sends a command in the next frame.
+    pub fn write(&self, command: [u8; 2]) -> io::Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+/// Sends each command in one frame, and passes on every frame, until the `Link` is dropped.
+fn run(
+    input: InputPin,
+    mut output: OutputPin,
+    commands: std_mpsc::Receiver<[u8; 2]>,
+    frames: watch::Sender<[u8; 8]>,
+) {
+    info!("Following LANC line");
+    while !frames.is_closed() {
+        let command = match commands.try_recv() {
+            Ok(command) => Some(command),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => break,
+        };
+        let frame = wait_for_gap(&input).then(|| transfer_frame(&input, &mut output, command));
+        match frame.flatten() {
+            Some(frame) => {
+                frames.send_replace(frame);
+            }
+            None => {
+                debug!("No LANC frame");
+                // Nothing's attached, so there's no need to keep a core busy
+                thread::sleep(FRAME_TIMEOUT);
+            }
+        }
+    }
+    output.set_low();
+    info!("LANC line closed");
+}
+
+/// Waits until the line has rested high for long enough to be between frames.
+fn wait_for_gap(input: &InputPin) -> bool {
+    let deadline = Instant::now() + FRAME_TIMEOUT;
+    let mut high_since = Instant::now();
+    while Instant::now() < deadline {
+        if input.is_low() {
+            high_since = Instant::now();
+        } else if high_since.elapsed() >= FRAME_GAP {
+            return true;
+        }
+    }
+    false
+}
+
+/// Reads a whole frame, writing `command` over its first two bytes. Each byte starts with the
+/// camera pulling the line low for a bit, followed by its bits least significant first, where a
+/// low line is a 1.
+fn transfer_frame(
+    input: &InputPin,
+    output: &mut OutputPin,
+    command: Option<[u8; 2]>,
+) -> Option<[u8; 8]> {
+    let mut frame = [0; 8];
+    for (i, byte) in frame.iter_mut().enumerate() {
+        let timeout = if i == 0 { FRAME_TIMEOUT } else { BYTE_TIMEOUT };
+        let start = wait_for(|| input.is_low(), timeout)?;
+        let written = command.filter(|_| i < 2).map(|c| c[i]);
+        for bit in 0..8 {
+            let bit_start = start + BIT * (bit + 1);
+            match written {
+                Some(value) => {
+                    spin_until(bit_start);
+                    match value >> bit & 1 == 1 {
+                        true => output.set_high(),
+                        false => output.set_low(),
+                    }
+                }
+                None => {
+                    // Sampled in the middle of the bit, where it's settled
+                    spin_until(bit_start + BIT / 2);
+                    if input.is_low() {
+                        *byte |= 1 << bit;
+                    }
+                }
+            }
+        }
+        spin_until(start + BIT * 9);
+        output.set_low();
+        if let Some(value) = written {
+            *byte = value;
+        }
+        wait_for(|| input.is_high(), BYTE_TIMEOUT)?;
+    }
+    Some(frame)
+}
+
+/// Spins until `condition` holds, returning when it did, or `None` after `timeout`.
+fn wait_for(condition: impl Fn() -> bool, timeout: Duration) -> Option<Instant> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let now = Instant::now();
+        if condition() {
+            return Some(now);
+        }
+        if now >= deadline {
+            return None;
+        }
+    }
+}
+
+/// Sleeping can overshoot by far more than a bit, so waits are spun instead.
+fn spin_until(deadline: Instant) {
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
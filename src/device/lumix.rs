@@ -1,41 +1,94 @@
-use std::{collections::HashSet, error::Error, fmt::Display, time::Duration};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use futures::TryFutureExt;
+use quick_xml::{events::Event, Reader};
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
 use tokio::{
     io::{self, AsyncReadExt as _, AsyncWriteExt as _},
-    net::{tcp::OwnedWriteHalf, TcpStream},
+    net::{tcp::OwnedWriteHalf, TcpStream, UdpSocket},
+    task::JoinHandle,
     time::timeout,
 };
+use tracing::{debug, info, warn, Instrument as _};
 
-use crate::config::{self, all_capabilities, Capability};
+use super::DeviceError;
+use crate::{
+    config::{self, all_capabilities, Capability, StorageMonitorConfig},
+    thumbnails::extract_jpeg,
+};
 
 const APP_UUID: &str = "52D5842E-90C6-4846-9665-C238229D22E9";
 const APP_NAME: &str = "LUMIXTether";
 const READ_TIMEOUT_MS: u64 = 200;
+const SSDP_ADDRESS: (Ipv4Addr, u16) = (Ipv4Addr::new(239, 255, 255, 250), 1900);
+/// Lumix cameras announce themselves as media servers, with the PTP service alongside.
+const SSDP_SEARCH_TARGET: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+/// The standard PTP/IP port, for cameras that don't say which one they use.
+const DEFAULT_PTP_PORT: u16 = 15740;
+const PTPIP_DATA: u32 = 0x0a;
+const PTPIP_END_DATA: u32 = 0x0c;
+const PTPIP_OPERATION_RESPONSE: u32 = 0x07;
+const PTP_RESPONSE_OK: u16 = 0x2001;
+const WAKE_ATTEMPTS: u32 = 5;
+const WAKE_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+/// Focal length up to which focus moves at full speed. Longer lenses have a shallower depth of
+/// field, so their focus speed is scaled down in proportion.
+const FOCUS_REFERENCE_MM: f64 = 50.0;
+const ISO_PROPERTY: u32 = 0x02000020;
+/// Auto ISO, which has no single value to report.
+const ISO_AUTO: u32 = 0xffffffff;
+const SHUTTER_SPEED_PROPERTY: u32 = 0x02000030;
+/// Set on shutter speeds of a second or longer, which are in milliseconds rather than fractions.
+const SHUTTER_SPEED_LONG: u32 = 0x80000000;
+const APERTURE_PROPERTY: u32 = 0x02000040;
+/// Where the AF area is, as x and y in thousandths of the frame from the top left.
+const AF_AREA_POSITION_PROPERTY: u32 = 0x02000072;
+/// F-numbers in third stops, times ten as the camera reports them.
+const APERTURES: [u16; 28] = [
+    10, 11, 12, 14, 16, 18, 20, 22, 25, 28, 32, 35, 40, 45, 50, 56, 63, 71, 80, 90, 100, 110, 130,
+    140, 160, 180, 200, 220,
+];
+/// How often the iris steps by a third of a stop at full speed.
+const IRIS_STEP_INTERVAL: Duration = Duration::from_millis(250);
+/// How long the camera can go without answering anything before it's pinged, since the session
+/// quietly goes away when the camera sleeps or the network drops.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the live view stream is asked for again, since the camera stops sending frames
+/// after a while unless it is.
+const STREAM_KEEPALIVE: Duration = Duration::from_secs(5);
+/// How often to check whether anyone has started watching the live view.
+const STREAM_IDLE_CHECK: Duration = Duration::from_secs(1);
+/// The focal length the lens is zoomed to, in mm.
+const FOCAL_LENGTH_PROPERTY: u32 = 0x0d800011;
 
 trait WriteExt {
-    async fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>>;
+    async fn write_data(&mut self, data: &[u8]) -> Result<(), DeviceError>;
 
-    async fn write_and_read_resp(&mut self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>>;
+    async fn write_and_read_resp(&mut self, data: &[u8]) -> Result<Vec<u8>, DeviceError>;
 }
 
 impl WriteExt for TcpStream {
-    async fn write_data(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    async fn write_data(&mut self, data: &[u8]) -> Result<(), DeviceError> {
         self.write_all(data).await?;
         Ok(())
     }
 
-    async fn write_and_read_resp(&mut self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    async fn write_and_read_resp(&mut self, data: &[u8]) -> Result<Vec<u8>, DeviceError> {
         let mut buffer: [u8; 1024] = [0; 1024];
         self.write_data(data).await?;
         let len = timeout(
             Duration::from_millis(READ_TIMEOUT_MS),
             self.read(&mut buffer),
         )
-        .map_err(|_| -> Box<dyn Error> { "timed out waiting for response".into() })
+        .map_err(|_| DeviceError::Timeout("timed out waiting for response".to_owned()))
         .await??;
         let rec_buf = &buffer[..len];
         Ok(rec_buf.to_vec())
@@ -89,6 +142,51 @@ impl CommandPacket {
         }
     }
 
+    const fn get_device_info(transaction_id: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x1001,
+            transaction_id,
+            param1: 0x00000000,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn get_storage_ids(transaction_id: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x1004,
+            transaction_id,
+            param1: 0x00000000,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn get_storage_info(transaction_id: u32, storage_id: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x1005,
+            transaction_id,
+            param1: storage_id,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
     pub const fn start_zoom(transaction_id: u32) -> CommandPacket {
         CommandPacket {
             length: 0x26,
@@ -134,6 +232,96 @@ impl CommandPacket {
         }
     }
 
+    const fn movie_rec(transaction_id: u32, start: bool) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x940c,
+            transaction_id,
+            param1: if start { 0x07000011 } else { 0x07000012 },
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn initiate_capture(transaction_id: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x9404,
+            transaction_id,
+            param1: 0x03000011,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn get_property(transaction_id: u32, property: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x9402,
+            transaction_id,
+            param1: property,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn set_property(transaction_id: u32, property: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x02,
+            opcode: 0x9403,
+            transaction_id,
+            param1: property,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn start_live_view(transaction_id: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x9412,
+            transaction_id,
+            param1: 0x0d000010,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
+    const fn get_live_view_image(transaction_id: u32) -> CommandPacket {
+        CommandPacket {
+            length: 0x26,
+            packet_type: 0x06,
+            phase_info: 0x01,
+            opcode: 0x9706,
+            transaction_id,
+            param1: 0x00000000,
+            param2: 0x00000000,
+            param3: 0x00000000,
+            param4: 0x00000000,
+            param5: 0x00000000,
+        }
+    }
+
     const fn adjust_focus(transaction_id: u32) -> CommandPacket {
         CommandPacket {
             length: 0x26,
@@ -154,6 +342,9 @@ enum DataPacket {
     ZoomStart(ZoomStartDataPacket),
     ZoomStop(ZoomStopDataPacket),
     FocusAdjust(FocusAdjustDataPacket),
+    Property(PropertyDataPacket),
+    FocusPoint(FocusPointDataPacket),
+    PropertyU32(PropertyU32DataPacket),
 }
 
 #[derive(Debug, Serialize)]
@@ -176,11 +367,32 @@ enum ZoomDirection {
     Tele = 0x01,
 }
 
-#[derive(PartialEq, Copy, Clone)]
-enum ZoomSpeed {
-    Off = 0x00,
-    Low = 0x01,
-    High = 0x02,
+/// A zoom speed step, from 1 for the slowest up to as many as the lens supports.
+#[derive(PartialEq, Copy, Clone, Debug)]
+struct ZoomSpeed(u16);
+
+impl ZoomSpeed {
+    const OFF: ZoomSpeed = ZoomSpeed(0);
+}
+
+/// The magnitudes above which each zoom speed step is used, matching the lens's low and high
+/// speeds.
+const DEFAULT_ZOOM_SPEEDS: [f64; 2] = [0.0, 0.75];
+
+/// Picks the step for a zoom command: one faster for each of `thresholds` its magnitude is above.
+fn zoom_speed(x: f64, thresholds: &[f64]) -> ZoomSpeed {
+    ZoomSpeed(thresholds.iter().filter(|&&t| x.abs() > t).count() as u16)
+}
+
+#[test]
+fn test_zoom_speed() {
+    assert_eq!(zoom_speed(0.0, &DEFAULT_ZOOM_SPEEDS), ZoomSpeed::OFF);
+    assert_eq!(zoom_speed(0.5, &DEFAULT_ZOOM_SPEEDS), ZoomSpeed(1));
+    assert_eq!(zoom_speed(-0.8, &DEFAULT_ZOOM_SPEEDS), ZoomSpeed(2));
+    let fine = [0.0, 0.2, 0.4, 0.6, 0.8];
+    assert_eq!(zoom_speed(0.1, &fine), ZoomSpeed(1));
+    assert_eq!(zoom_speed(-0.5, &fine), ZoomSpeed(3));
+    assert_eq!(zoom_speed(1.0, &fine), ZoomSpeed(5));
 }
 
 impl Display for ZoomStartDataPacket {
@@ -220,7 +432,7 @@ impl ZoomStartDataPacket {
             param1,
             unknown2: 0x04,
             dir: dir as u16,
-            speed: speed as u16,
+            speed: speed.0,
         }
     }
 }
@@ -327,36 +539,231 @@ impl FocusAdjustDataPacket {
     }
 }
 
+/// A new value for a 16-bit property, e.g. the aperture.
+#[derive(Debug, Serialize)]
+struct PropertyDataPacket {
+    length: u32,
+    packet_type: u32,
+    transaction_id: u32,
+    data_length: u64,
+    unknown1: u64,
+    transaction_id2: u32,
+    param1: u32,
+    value_length: u32,
+    value: u16,
+}
+
+impl Display for PropertyDataPacket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let data_hex = hex::encode(bincode::serialize(&self).unwrap());
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {}",
+            &data_hex[0..8],
+            &data_hex[8..16],
+            &data_hex[16..24],
+            &data_hex[24..40],
+            &data_hex[40..56],
+            &data_hex[56..64],
+            &data_hex[64..72],
+            &data_hex[72..80],
+            &data_hex[80..],
+        )
+    }
+}
+
+impl PropertyDataPacket {
+    const fn create(transaction_id: u32, param1: u32, value: u16) -> Self {
+        PropertyDataPacket {
+            length: 0x14,
+            packet_type: 0x09,
+            transaction_id,
+            data_length: 0x0a,
+            unknown1: 0x0000000C_00000016,
+            transaction_id2: transaction_id,
+            param1,
+            value_length: 0x02,
+            value,
+        }
+    }
+}
+
+/// A new value for a 32-bit property, e.g. the ISO.
+#[derive(Debug, Serialize)]
+struct PropertyU32DataPacket {
+    length: u32,
+    packet_type: u32,
+    transaction_id: u32,
+    data_length: u64,
+    unknown1: u64,
+    transaction_id2: u32,
+    param1: u32,
+    value_length: u32,
+    value: u32,
+}
+
+impl Display for PropertyU32DataPacket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let data_hex = hex::encode(bincode::serialize(&self).unwrap());
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {}",
+            &data_hex[0..8],
+            &data_hex[8..16],
+            &data_hex[16..24],
+            &data_hex[24..40],
+            &data_hex[40..56],
+            &data_hex[56..64],
+            &data_hex[64..72],
+            &data_hex[72..80],
+            &data_hex[80..],
+        )
+    }
+}
+
+impl PropertyU32DataPacket {
+    const fn create(transaction_id: u32, param1: u32, value: u32) -> Self {
+        PropertyU32DataPacket {
+            length: 0x14,
+            packet_type: 0x09,
+            transaction_id,
+            data_length: 0x0c,
+            unknown1: 0x0000000C_00000018,
+            transaction_id2: transaction_id,
+            param1,
+            value_length: 0x04,
+            value,
+        }
+    }
+}
+
+/// A new position for the AF area.
+#[derive(Debug, Serialize)]
+struct FocusPointDataPacket {
+    length: u32,
+    packet_type: u32,
+    transaction_id: u32,
+    data_length: u64,
+    unknown1: u64,
+    transaction_id2: u32,
+    param1: u32,
+    value_length: u32,
+    x: u16,
+    y: u16,
+}
+
+impl Display for FocusPointDataPacket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let data_hex = hex::encode(bincode::serialize(&self).unwrap());
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {} {}",
+            &data_hex[0..8],
+            &data_hex[8..16],
+            &data_hex[16..24],
+            &data_hex[24..40],
+            &data_hex[40..56],
+            &data_hex[56..64],
+            &data_hex[64..72],
+            &data_hex[72..80],
+            &data_hex[80..84],
+            &data_hex[84..],
+        )
+    }
+}
+
+impl FocusPointDataPacket {
+    fn create(transaction_id: u32, param1: u32, point: super::FocusPoint) -> Self {
+        let thousandths = |v: f64| (v.clamp(0.0, 1.0) * 1000.0).round() as u16;
+        FocusPointDataPacket {
+            length: 0x14,
+            packet_type: 0x09,
+            transaction_id,
+            data_length: 0x0c,
+            unknown1: 0x0000000C_00000018,
+            transaction_id2: transaction_id,
+            param1,
+            value_length: 0x04,
+            x: thousandths(point.x),
+            y: thousandths(point.y),
+        }
+    }
+}
+
 pub struct Lumix {
     id: String,
     name: String,
     address: String,
     password: Option<String>,
+    mac: Option<String>,
+    storage_monitor: Option<StorageMonitorConfig>,
+    zoom_speeds: Vec<f64>,
+    last_storage_poll: Option<Instant>,
     connection: Option<Connection>,
     capabilities: HashSet<Capability>,
+    lens: Option<Lens>,
+    /// Kept across connections, so that viewers carry on once the camera reconnects.
+    live_view: super::LiveView,
 }
 
 struct Connection {
     socket: TcpStream,
     event_socket: OwnedWriteHalf,
     event_task: tokio::task::JoinHandle<()>,
+    stream_task: JoinHandle<()>,
     curr_transaction_id: u32,
     curr_dir: ZoomDirection,
     curr_speed: ZoomSpeed,
+    live_view: bool,
+    /// The aperture last read or set, as an F-number times ten.
+    aperture: Option<u16>,
+    last_iris_step: Option<Instant>,
+    /// The focal length last read, in mm, or `None` if the body doesn't report it.
+    focal_length: Option<u16>,
+    /// The ISO and shutter speed last read or set, as the camera encodes them.
+    iso: Option<u32>,
+    shutter_speed: Option<u32>,
+    last_reply: Instant,
+    /// Whether the last transaction failed, which might mean the session is gone.
+    failed: bool,
 }
 
 impl Connection {
-    async fn transaction(&mut self, name: &str, cmd: CommandPacket) -> Result<(), Box<dyn Error>> {
-        println!("{}: Sending ({}) {}", name, cmd.transaction_id, cmd);
+    /// Notes how a transaction went, for `check_session`.
+    fn note<T>(&mut self, result: Result<T, DeviceError>) -> Result<T, DeviceError> {
+        self.failed = result.is_err();
+        if !self.failed {
+            self.last_reply = Instant::now();
+        }
+        result
+    }
+
+    /// Pings the camera if the last transaction failed or it's been quiet for a while. Errors
+    /// mean the session has to be opened again.
+    async fn check_session(&mut self, name: &str) -> Result<(), DeviceError> {
+        if self.event_task.is_finished() {
+            return Err(format!("{}: event channel closed", name).into());
+        }
+        if !self.failed && self.last_reply.elapsed() < KEEPALIVE_INTERVAL {
+            return Ok(());
+        }
+        let ping_cmd = CommandPacket::get_device_info(self.curr_transaction_id);
+        self.transaction_with_response(name, ping_cmd).await?;
+        Ok(())
+    }
+
+    async fn transaction(&mut self, name: &str, cmd: CommandPacket) -> Result<(), DeviceError> {
+        debug!(transaction = cmd.transaction_id, "Sending {}", cmd);
         self.curr_transaction_id += 1;
         let resp = self
             .socket
             .write_and_read_resp(&bincode::serialize(&cmd).unwrap())
-            .map_err(|e| -> Box<dyn Error> {
+            .map_err(|e| -> DeviceError {
                 format!("{}: error sending command: {}", name, e).into()
             })
-            .await?;
-        println!("{}: Received {}", name, hex::encode(resp));
+            .await;
+        let resp = self.note(resp)?;
+        debug!(packet = %hex::encode(resp), "Received");
         Ok(())
     }
 
@@ -365,45 +772,207 @@ impl Connection {
         name: &str,
         cmd: CommandPacket,
         data: DataPacket,
-    ) -> Result<(), Box<dyn Error>> {
-        println!("{}: Sending ({}) {}", name, cmd.transaction_id, cmd);
+    ) -> Result<(), DeviceError> {
+        debug!(transaction = cmd.transaction_id, "Sending {}", cmd);
         self.curr_transaction_id += 1;
-        self.socket
+        let sent = self
+            .socket
             .write_data(&bincode::serialize(&cmd).unwrap())
-            .map_err(|e| -> Box<dyn Error> {
+            .map_err(|e| -> DeviceError {
                 format!("{}: error sending command: {}", name, e).into()
             })
-            .await?;
+            .await;
+        self.note(sent)?;
         let serialized_data = match data {
             DataPacket::ZoomStart(data) => {
-                println!("{}: Sending ({}) {}", name, data.transaction_id, data);
+                debug!(transaction = data.transaction_id, "Sending {}", data);
                 bincode::serialize(&data).unwrap()
             }
             DataPacket::ZoomStop(data) => {
-                println!("{}: Sending ({}) {}", name, data.transaction_id, data);
+                debug!(transaction = data.transaction_id, "Sending {}", data);
                 bincode::serialize(&data).unwrap()
             }
             DataPacket::FocusAdjust(data) => {
-                println!("{}: Sending ({}) {}", name, data.transaction_id, data);
+                debug!(transaction = data.transaction_id, "Sending {}", data);
+                bincode::serialize(&data).unwrap()
+            }
+            DataPacket::Property(data) => {
+                debug!(transaction = data.transaction_id, "Sending {}", data);
+                bincode::serialize(&data).unwrap()
+            }
+            DataPacket::FocusPoint(data) => {
+                debug!(transaction = data.transaction_id, "Sending {}", data);
+                bincode::serialize(&data).unwrap()
+            }
+            DataPacket::PropertyU32(data) => {
+                debug!(transaction = data.transaction_id, "Sending {}", data);
                 bincode::serialize(&data).unwrap()
             }
         };
         let resp = self
             .socket
             .write_and_read_resp(&serialized_data)
-            .map_err(|e| -> Box<dyn Error> {
+            .map_err(|e| -> DeviceError {
                 format!("{}: error sending command: {}", name, e).into()
             })
-            .await?;
-        println!("{}: Received {}", name, hex::encode(resp));
+            .await;
+        let resp = self.note(resp)?;
+        debug!(packet = %hex::encode(resp), "Received");
         Ok(())
     }
 
-    async fn handle_autofocus(
+    /// Runs a transaction with a data-in phase and returns the received data.
+    async fn transaction_with_response(
+        &mut self,
+        name: &str,
+        cmd: CommandPacket,
+    ) -> Result<Vec<u8>, DeviceError> {
+        let result = self.receive_transaction(name, cmd).await;
+        self.note(result)
+    }
+
+    async fn receive_transaction(
+        &mut self,
+        name: &str,
+        cmd: CommandPacket,
+    ) -> Result<Vec<u8>, DeviceError> {
+        debug!(transaction = cmd.transaction_id, "Sending {}", cmd);
+        self.curr_transaction_id += 1;
+        self.socket
+            .write_data(&bincode::serialize(&cmd).unwrap())
+            .map_err(|e| -> DeviceError {
+                format!("{}: error sending command: {}", name, e).into()
+            })
+            .await?;
+        let mut data = vec![];
+        loop {
+            let (packet_type, payload) = timeout(
+                Duration::from_millis(READ_TIMEOUT_MS),
+                read_packet(&mut self.socket),
+            )
+            .map_err(|_| DeviceError::Timeout("timed out waiting for response".to_owned()))
+            .await??;
+            match packet_type {
+                // Data payloads are prefixed with the transaction ID
+                PTPIP_DATA | PTPIP_END_DATA if payload.len() >= 4 => {
+                    data.extend_from_slice(&payload[4..])
+                }
+                PTPIP_OPERATION_RESPONSE if payload.len() >= 2 => {
+                    let code = u16::from_le_bytes([payload[0], payload[1]]);
+                    if code != PTP_RESPONSE_OK {
+                        return Err(format!("{}: camera responded with {:#06x}", name, code).into());
+                    }
+                    return Ok(data);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    async fn read_storage(&mut self, name: &str) -> Result<Vec<(u32, StorageInfo)>, DeviceError> {
+        let ids_cmd = CommandPacket::get_storage_ids(self.curr_transaction_id);
+        let ids = parse_storage_ids(&self.transaction_with_response(name, ids_cmd).await?);
+        let mut storage = vec![];
+        for id in ids {
+            let info_cmd = CommandPacket::get_storage_info(self.curr_transaction_id, id);
+            let data = self.transaction_with_response(name, info_cmd).await?;
+            match parse_storage_info(&data) {
+                Some(info) => storage.push((id, info)),
+                None => warn!(packet = %hex::encode(data), "Invalid storage info"),
+            }
+        }
+        Ok(storage)
+    }
+
+    /// Grabs a live view frame, which comes with a header of overlay info ahead of the JPEG.
+    async fn read_live_view(&mut self, name: &str) -> Result<Vec<u8>, DeviceError> {
+        if !self.live_view {
+            let start_cmd = CommandPacket::start_live_view(self.curr_transaction_id);
+            self.transaction(name, start_cmd).await?;
+            self.live_view = true;
+        }
+        let image_cmd = CommandPacket::get_live_view_image(self.curr_transaction_id);
+        let data = self.transaction_with_response(name, image_cmd).await?;
+        match extract_jpeg(&data) {
+            Some(jpeg) => Ok(jpeg.to_vec()),
+            None => Err(format!("{}: live view frame didn't contain a JPEG", name).into()),
+        }
+    }
+
+    async fn read_property(&mut self, name: &str, property: u32) -> Result<Vec<u8>, DeviceError> {
+        let get_cmd = CommandPacket::get_property(self.curr_transaction_id, property);
+        self.transaction_with_response(name, get_cmd).await
+    }
+
+    async fn write_property_u16(
+        &mut self,
+        name: &str,
+        property: u32,
+        value: u16,
+    ) -> Result<(), DeviceError> {
+        let set_cmd = CommandPacket::set_property(self.curr_transaction_id, property);
+        let set_data = PropertyDataPacket::create(self.curr_transaction_id, set_cmd.param1, value);
+        self.transaction_with_data(name, set_cmd, DataPacket::Property(set_data))
+            .await
+    }
+
+    async fn write_property_u32(
+        &mut self,
+        name: &str,
+        property: u32,
+        value: u32,
+    ) -> Result<(), DeviceError> {
+        let set_cmd = CommandPacket::set_property(self.curr_transaction_id, property);
+        let set_data =
+            PropertyU32DataPacket::create(self.curr_transaction_id, set_cmd.param1, value);
+        self.transaction_with_data(name, set_cmd, DataPacket::PropertyU32(set_data))
+            .await
+    }
+
+    /// Reads the ISO, shutter speed, and aperture. Settings the body doesn't report are left
+    /// out.
+    async fn read_exposure(&mut self, name: &str) {
+        self.iso = match self.read_property(name, ISO_PROPERTY).await {
+            Ok(data) => parse_property_u32(&data),
+            Err(e) => {
+                debug!("Couldn't read ISO: {}", e);
+                None
+            }
+        };
+        self.shutter_speed = match self.read_property(name, SHUTTER_SPEED_PROPERTY).await {
+            Ok(data) => parse_property_u32(&data),
+            Err(e) => {
+                debug!("Couldn't read shutter speed: {}", e);
+                None
+            }
+        };
+        self.aperture = match self.read_property(name, APERTURE_PROPERTY).await {
+            Ok(data) => parse_property_u16(&data),
+            Err(e) => {
+                debug!("Couldn't read aperture: {}", e);
+                None
+            }
+        };
+    }
+
+    /// Reads the focal length the lens is zoomed to. Bodies that don't report it are left
+    /// without one rather than failing the command that asked.
+    async fn read_focal_length(&mut self, name: &str) {
+        let get_cmd = CommandPacket::get_property(self.curr_transaction_id, FOCAL_LENGTH_PROPERTY);
+        self.focal_length = match self.transaction_with_response(name, get_cmd).await {
+            Ok(data) => parse_property_u16(&data),
+            Err(e) => {
+                debug!("Couldn't read focal length: {}", e);
+                None
+            }
+        };
+    }
+
+    async fn handle_autofocus(
         &mut self,
         name: &str,
         command: super::Command,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), DeviceError> {
         if command.autofocus {
             let af_cmd = CommandPacket::one_shot_af(self.curr_transaction_id);
             self.transaction(name, af_cmd).await?;
@@ -411,12 +980,63 @@ impl Connection {
         Ok(())
     }
 
+    /// Moves the AF area to the point and focuses there, like tapping the camera's screen.
+    async fn handle_focus_point(
+        &mut self,
+        name: &str,
+        command: super::Command,
+    ) -> Result<(), DeviceError> {
+        let Some(point) = command.focus_point else {
+            return Ok(());
+        };
+        debug!("Focusing at {:?}", point);
+        let set_cmd =
+            CommandPacket::set_property(self.curr_transaction_id, AF_AREA_POSITION_PROPERTY);
+        let set_data =
+            FocusPointDataPacket::create(self.curr_transaction_id, set_cmd.param1, point);
+        self.transaction_with_data(name, set_cmd, DataPacket::FocusPoint(set_data))
+            .await?;
+        // Already focusing if the command asked for autofocus too
+        if !command.autofocus {
+            let af_cmd = CommandPacket::one_shot_af(self.curr_transaction_id);
+            self.transaction(name, af_cmd).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_record(
+        &mut self,
+        name: &str,
+        command: super::Command,
+    ) -> Result<(), DeviceError> {
+        if let Some(start) = command.record {
+            info!("{} recording", if start { "Starting" } else { "Stopping" });
+            let rec_cmd = CommandPacket::movie_rec(self.curr_transaction_id, start);
+            self.transaction(name, rec_cmd).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_shutter(
+        &mut self,
+        name: &str,
+        command: super::Command,
+    ) -> Result<(), DeviceError> {
+        if command.shutter {
+            info!("Taking a photo");
+            let capture_cmd = CommandPacket::initiate_capture(self.curr_transaction_id);
+            self.transaction(name, capture_cmd).await?;
+        }
+        Ok(())
+    }
+
     async fn handle_focus(
         &mut self,
         name: &str,
         command: super::Command,
-    ) -> Result<(), Box<dyn Error>> {
-        let speed = match command.focus {
+        scale: f64,
+    ) -> Result<(), DeviceError> {
+        let speed = match command.focus.get() * scale {
             x if x < -0.75 => FocusAdjustSpeed::NearFast,
             x if x < 0.0 => FocusAdjustSpeed::NearSlow,
             x if x > 0.75 => FocusAdjustSpeed::FarFast,
@@ -436,33 +1056,64 @@ impl Connection {
         Ok(())
     }
 
+    /// Steps the aperture a third of a stop at a time, more often the faster the iris is
+    /// moving.
+    async fn handle_iris(
+        &mut self,
+        name: &str,
+        command: super::Command,
+    ) -> Result<(), DeviceError> {
+        let speed = command.iris.get();
+        if speed == 0.0 {
+            return Ok(());
+        }
+        let interval = IRIS_STEP_INTERVAL.div_f64(speed.abs());
+        if self.last_iris_step.is_some_and(|t| t.elapsed() < interval) {
+            return Ok(());
+        }
+        let current = match self.aperture {
+            Some(aperture) => aperture,
+            None => {
+                let data = self.read_property(name, APERTURE_PROPERTY).await?;
+                parse_property_u16(&data)
+                    .ok_or_else(|| DeviceError::protocol("invalid aperture value"))?
+            }
+        };
+        self.aperture = Some(current);
+        self.last_iris_step = Some(Instant::now());
+        // Opening the iris means a smaller F-number
+        let Some(next) = step_aperture(current, speed < 0.0) else {
+            return Ok(());
+        };
+        debug!("Setting aperture to f/{}", next as f64 / 10.0);
+        self.write_property_u16(name, APERTURE_PROPERTY, next)
+            .await?;
+        self.aperture = Some(next);
+        Ok(())
+    }
+
     async fn handle_zoom(
         &mut self,
         name: &str,
         command: super::Command,
-    ) -> Result<(), Box<dyn Error>> {
-        let dir = match command.zoom {
+        zoom_speeds: &[f64],
+    ) -> Result<(), DeviceError> {
+        let dir = match command.zoom.get() {
             x if x < 0.0 => ZoomDirection::Wide,
             x if x > 0.0 => ZoomDirection::Tele,
             _ => self.curr_dir,
         };
-        let speed = match command.zoom {
-            x if x < -0.75 => ZoomSpeed::High,
-            x if x < 0.0 => ZoomSpeed::Low,
-            x if x > 0.75 => ZoomSpeed::High,
-            x if x > 0.0 => ZoomSpeed::Low,
-            _ => ZoomSpeed::Off,
-        };
+        let speed = zoom_speed(command.zoom.get(), zoom_speeds);
         if (dir == self.curr_dir) && (speed == self.curr_speed) {
             return Ok(());
         }
-        if self.curr_speed != ZoomSpeed::Off {
+        if self.curr_speed != ZoomSpeed::OFF {
             let stop_cmd = CommandPacket::stop_zoom(self.curr_transaction_id);
             let stop_data = ZoomStopDataPacket::create(self.curr_transaction_id, stop_cmd.param1);
             self.transaction_with_data(name, stop_cmd, DataPacket::ZoomStop(stop_data))
                 .await?;
         }
-        if speed != ZoomSpeed::Off {
+        if speed != ZoomSpeed::OFF {
             let start_cmd = CommandPacket::start_zoom(self.curr_transaction_id);
             let start_data =
                 ZoomStartDataPacket::create(self.curr_transaction_id, start_cmd.param1, dir, speed);
@@ -487,23 +1138,13 @@ impl super::Device for Lumix {
         self.id.clone()
     }
 
-    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-        println!("{}: Connecting", self);
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
 
-        let info_resp = Client::new()
-            .get(format!(
-                "http://{}:60606/PTPRemote/Server0/ddd",
-                &self.address
-            ))
-            .timeout(Duration::from_secs(5))
-            .send()
-            .await?
-            .text()
-            .await?;
-        let camera_info: CameraInfo = quick_xml::de::from_str(&info_resp)?;
-        let name = camera_info.device.friendly_name.clone();
-        // TODO: Get port from camera (requires being able to parse namespaced tags)
-        let port: u16 = 15740;
+        let description = read_description(&self.address, Duration::from_secs(5)).await?;
+        let name = description.friendly_name;
+        let port = description.ptp_port.unwrap_or(DEFAULT_PTP_PORT);
+        debug!("Using PTP/IP port {}", port);
 
         let acc_resp = reqwest::get(format!(
             "http://{}/cam.cgi?mode=accctrl&type=req_acc_a&value={}&value2={}{}",
@@ -543,25 +1184,28 @@ impl super::Device for Lumix {
 
         let (mut r, w) = event_socket.into_split();
 
-        let event_task_name = name.clone();
-        let event_task = tokio::spawn(async move {
-            let mut buffer: [u8; 1024] = [0; 1024];
-            loop {
-                let _len = match r.read(&mut buffer).await {
-                    Ok(len) => len,
-                    Err(e) => {
-                        println!("{}: Error reading event: {}", event_task_name, e);
-                        continue;
-                    }
-                };
-                // let rec_buf = &buffer[..len];
-                // println!(
-                //     "{}: Received event {}",
-                //     event_task_name,
-                //     hex::encode(rec_buf)
-                // );
+        let event_task = tokio::spawn(
+            async move {
+                let mut buffer: [u8; 1024] = [0; 1024];
+                loop {
+                    // Ends the task when the camera goes away, which `check_session` notices
+                    let _len = match r.read(&mut buffer).await {
+                        Ok(0) => {
+                            info!("Event channel closed");
+                            return;
+                        }
+                        Ok(len) => len,
+                        Err(e) => {
+                            warn!("Error reading event: {}", e);
+                            return;
+                        }
+                    };
+                    // let rec_buf = &buffer[..len];
+                    // debug!(packet = %hex::encode(rec_buf), "Received event");
+                }
             }
-        });
+            .in_current_span(),
+        );
 
         let open_session_cmd = CommandPacket::open_session(0);
         socket
@@ -569,65 +1213,297 @@ impl super::Device for Lumix {
             .await?;
 
         self.name = name;
+        self.lens = read_lens(&self.address).await;
+        match &self.lens {
+            Some(lens) => info!("Lens: {}", lens),
+            None => debug!("Couldn't read lens info"),
+        }
         self.connection = Some(Connection {
             socket,
             event_socket: w,
             event_task,
             curr_transaction_id: 1,
             curr_dir: ZoomDirection::Wide,
-            curr_speed: ZoomSpeed::Off,
+            curr_speed: ZoomSpeed::OFF,
+            live_view: false,
+            aperture: None,
+            last_iris_step: None,
+            focal_length: None,
+            iso: None,
+            shutter_speed: None,
+            last_reply: Instant::now(),
+            failed: false,
+            stream_task: tokio::spawn(
+                stream_live_view(self.address.clone(), self.live_view.clone()).in_current_span(),
+            ),
         });
-        println!("{}: Connected", self);
+        let name = self.name();
+        if let (Some(c), Some(lens)) = (&mut self.connection, &self.lens) {
+            if !lens.is_prime() {
+                c.read_focal_length(&name).await;
+            }
+        }
+        info!("Connected");
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
-        let name = self.name();
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
         match &mut self.connection {
             None => {
-                println!("{}: Already disconnected", name);
+                debug!("Already disconnected");
             }
             Some(ref mut c) => {
-                println!("{}: Disconnecting", name);
+                info!("Disconnecting");
                 c.event_task.abort();
-                c.event_socket.shutdown().await?;
-                c.socket.shutdown().await?;
+                c.stream_task.abort();
+                self.live_view.stall();
+                // Sockets to a camera that has gone away can fail to shut down cleanly, and
+                // are closed when dropped anyway
+                if let Err(e) = c.event_socket.shutdown().await {
+                    debug!("Error closing event socket: {}", e);
+                }
+                if let Err(e) = c.socket.shutdown().await {
+                    debug!("Error closing socket: {}", e);
+                }
                 self.connection = None;
-                println!("{}: Disconnected", name);
+                self.lens = None;
+                info!("Disconnected");
             }
         }
         Ok(())
     }
 
-    async fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
         self.disconnect().await?;
         self.connect().await?;
         Ok(())
     }
 
+    async fn wake(&mut self) -> Result<(), DeviceError> {
+        let Some(mac) = self.mac.clone() else {
+            return Err(DeviceError::Unsupported(format!(
+                "{}: no MAC address configured",
+                self
+            )));
+        };
+        if self.connection.is_some() {
+            debug!("Already connected");
+            return Ok(());
+        }
+        for attempt in 1..=WAKE_ATTEMPTS {
+            debug!("Sending Wake-on-LAN packet to {}", mac);
+            super::wol::send_magic_packet(&mac).await?;
+            tokio::time::sleep(WAKE_RETRY_INTERVAL).await;
+            match self.connect().await {
+                Ok(()) => return Ok(()),
+                Err(e) => info!("Not reachable yet ({}/{}): {}", attempt, WAKE_ATTEMPTS, e),
+            }
+        }
+        Err(DeviceError::Timeout(format!("{}: didn't wake up", self)))
+    }
+
+    fn supports_wake(&self) -> bool {
+        self.mac.is_some()
+    }
+
+    async fn poll_telemetry(&mut self) -> Result<Map<String, Value>, DeviceError> {
+        let name = self.name();
+        let Some(c) = &mut self.connection else {
+            return Ok(Map::new());
+        };
+        // Picks up zooms and exposure changes made on the camera itself
+        if self.lens.as_ref().is_some_and(|l| !l.is_prime()) {
+            c.read_focal_length(&name).await;
+        }
+        c.read_exposure(&name).await;
+        let Some(monitor) = &self.storage_monitor else {
+            return Ok(Map::new());
+        };
+        let interval = Duration::from_secs(monitor.interval_secs);
+        if self
+            .last_storage_poll
+            .is_some_and(|t| t.elapsed() < interval)
+        {
+            return Ok(Map::new());
+        }
+        self.last_storage_poll = Some(Instant::now());
+
+        let storage = c.read_storage(&name).await?;
+        // Also warns when no card is inserted at all
+        let low = storage
+            .iter()
+            .all(|(_, info)| info.free_bytes < monitor.warn_below_mb * 1024 * 1024);
+        if low {
+            warn!("Storage is running low");
+        }
+        let storage_values: Vec<Value> = storage
+            .iter()
+            .map(|(id, info)| {
+                let mut v = json!({
+                    "storageId": format!("{:#010x}", id),
+                    "capacityBytes": info.max_capacity,
+                    "freeBytes": info.free_bytes,
+                    "freeImages": info.free_images,
+                });
+                if let Some(bitrate) = monitor.bitrate_mbps {
+                    v["recordingMinutes"] = json!(info.recording_minutes(bitrate));
+                }
+                v
+            })
+            .collect();
+        Ok(Map::from_iter([
+            ("storage".to_owned(), Value::Array(storage_values)),
+            ("storageLow".to_owned(), Value::Bool(low)),
+        ]))
+    }
+
+    async fn thumbnail(&mut self) -> Result<Vec<u8>, DeviceError> {
+        let name = self.name();
+        match &mut self.connection {
+            None => Err(DeviceError::NotConnected),
+            Some(c) => c.read_live_view(&name).await,
+        }
+    }
+
+    fn supports_thumbnails(&self) -> bool {
+        true
+    }
+
+    fn live_view(&self) -> Option<super::LiveView> {
+        Some(self.live_view.clone())
+    }
+
     fn is_connected(&self) -> bool {
         self.connection.is_some()
     }
 
-    async fn send_command(&mut self, command: super::Command) -> Result<(), Box<dyn Error>> {
+    async fn check_connection(&mut self) -> bool {
+        let name = self.name();
+        let Some(c) = &mut self.connection else {
+            return false;
+        };
+        match c.check_session(&name).await {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Session lost: {}", e);
+                false
+            }
+        }
+    }
+
+    fn lens(&self) -> Option<String> {
+        self.lens.as_ref().map(Lens::to_string)
+    }
+
+    fn position(&self) -> Option<super::Position> {
+        let focal_length = self.connection.as_ref()?.focal_length?;
+        Some(super::Position {
+            zoom: Some(self.lens.as_ref()?.zoom_ratio(focal_length as f64)?),
+            ..Default::default()
+        })
+    }
+
+    fn telemetry(&self) -> Map<String, Value> {
+        let mut values = Map::new();
+        let Some(c) = &self.connection else {
+            return values;
+        };
+        if let Some(focal_length) = c.focal_length {
+            values.insert("focalLengthMm".to_owned(), focal_length.into());
+        }
+        let mut exposure = Map::new();
+        if let Some(iso) = c.iso.filter(|iso| *iso != ISO_AUTO) {
+            exposure.insert("iso".to_owned(), iso.into());
+        }
+        if let Some(seconds) = c.shutter_speed.and_then(decode_shutter_speed) {
+            exposure.insert("shutterSpeed".to_owned(), seconds.into());
+        }
+        if let Some(aperture) = c.aperture {
+            exposure.insert("aperture".to_owned(), (aperture as f64 / 10.0).into());
+        }
+        if !exposure.is_empty() {
+            values.insert("exposure".to_owned(), Value::Object(exposure));
+        }
+        values
+    }
+
+    async fn set_property(
+        &mut self,
+        property: super::Property,
+        value: f64,
+    ) -> Result<(), DeviceError> {
+        let name = self.name();
+        let Some(c) = &mut self.connection else {
+            return Err(DeviceError::NotConnected);
+        };
+        let invalid = || DeviceError::Unsupported(format!("{:?} can't be {}", property, value));
+        info!("Setting {:?} to {}", property, value);
+        match property {
+            super::Property::Iso => {
+                let iso = (value >= 1.0 && value < ISO_AUTO as f64)
+                    .then_some(value.round() as u32)
+                    .ok_or_else(invalid)?;
+                c.write_property_u32(&name, ISO_PROPERTY, iso).await?;
+                c.iso = Some(iso);
+            }
+            super::Property::ShutterSpeed => {
+                let speed = encode_shutter_speed(value).ok_or_else(invalid)?;
+                c.write_property_u32(&name, SHUTTER_SPEED_PROPERTY, speed)
+                    .await?;
+                c.shutter_speed = Some(speed);
+            }
+            super::Property::Aperture => {
+                let aperture = (value >= 0.1 && value <= u16::MAX as f64 / 10.0)
+                    .then_some((value * 10.0).round() as u16)
+                    .ok_or_else(invalid)?;
+                c.write_property_u16(&name, APERTURE_PROPERTY, aperture)
+                    .await?;
+                c.aperture = Some(aperture);
+            }
+        }
+        Ok(())
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
         let name = self.name();
         match &mut self.connection {
             None => {
-                println!("{}: Not connected", name);
+                debug!("Not connected");
             }
             Some(ref mut c) => {
-                println!("{}: Received command {:?}", name, command);
+                debug!("Received command {:?}", command);
 
                 if self.capabilities.contains(&Capability::Autofocus) {
+                    c.handle_focus_point(&name, command).await?;
                     c.handle_autofocus(&name, command).await?;
                 }
 
                 if self.capabilities.contains(&Capability::Focus) {
-                    c.handle_focus(&name, command).await?;
+                    let scale = self.lens.as_ref().map_or(1.0, Lens::focus_scale);
+                    c.handle_focus(&name, command, scale).await?;
+                }
+
+                if self.capabilities.contains(&Capability::Iris) {
+                    c.handle_iris(&name, command).await?;
+                }
+
+                if self.capabilities.contains(&Capability::Record) {
+                    c.handle_record(&name, command).await?;
+                }
+
+                if self.capabilities.contains(&Capability::Shutter) {
+                    c.handle_shutter(&name, command).await?;
                 }
 
-                if self.capabilities.contains(&Capability::Zoom) {
-                    c.handle_zoom(&name, command).await?;
+                // Primes can't zoom, and some bodies answer zoom commands with an error
+                let prime = self.lens.as_ref().is_some_and(Lens::is_prime);
+                if self.capabilities.contains(&Capability::Zoom) && !prime {
+                    let was_zooming = c.curr_speed != ZoomSpeed::OFF;
+                    c.handle_zoom(&name, command, &self.zoom_speeds).await?;
+                    // Keeps the position current while zooming, for steering to a zoom
+                    if was_zooming || c.curr_speed != ZoomSpeed::OFF {
+                        c.read_focal_length(&name).await;
+                    }
                 }
             }
         }
@@ -640,8 +1516,17 @@ pub fn create(id: &str, config: &config::LumixConfig) -> Lumix {
         id: id.to_owned(),
         name: config.address.to_owned(),
         address: config.address.to_owned(),
-        password: config.password.to_owned(),
+        password: config.password.as_deref().cloned(),
+        mac: config.mac.to_owned(),
+        storage_monitor: config.storage_monitor.clone(),
+        zoom_speeds: config
+            .zoom_speeds
+            .clone()
+            .unwrap_or_else(|| DEFAULT_ZOOM_SPEEDS.to_vec()),
+        last_storage_poll: None,
         connection: None,
+        lens: None,
+        live_view: super::LiveView::new(),
         capabilities: config
             .capabilities
             .clone()
@@ -650,18 +1535,430 @@ pub fn create(id: &str, config: &config::LumixConfig) -> Lumix {
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct DeviceInfo {
-    #[serde(rename = "friendlyName")]
+/// Streams the camera's live view to whoever is subscribed to `frames`. The camera sends each
+/// frame as a UDP packet to the port it's asked to, with a header ahead of the JPEG. Streaming
+/// is stopped again once the last viewer leaves, since it takes up a fair bit of the camera's
+/// bandwidth.
+async fn stream_live_view(address: String, frames: super::LiveView) {
+    let socket = match UdpSocket::bind(("0.0.0.0", 0)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("Couldn't open live view socket: {}", e);
+            return;
+        }
+    };
+    let port = socket.local_addr().map_or(0, |a| a.port());
+    let client = Client::new();
+    let stream_url =
+        |mode: &str| format!("http://{}/cam.cgi?mode={}&value={}", address, mode, port);
+    let mut buffer = vec![0; 65536];
+    let mut requested: Option<Instant> = None;
+    loop {
+        if !frames.is_watched() {
+            if requested.take().is_some() {
+                debug!("Stopping live view stream");
+                let _ = client.get(stream_url("stopstream")).send().await;
+                frames.stall();
+            }
+            tokio::time::sleep(STREAM_IDLE_CHECK).await;
+            continue;
+        }
+        if requested.is_none_or(|t| t.elapsed() >= STREAM_KEEPALIVE) {
+            if requested.is_none() {
+                debug!("Starting live view stream on port {}", port);
+            }
+            let started = client
+                .get(stream_url("startstream"))
+                .timeout(Duration::from_secs(2))
+                .send()
+                .await;
+            if let Err(e) = started {
+                warn!("Couldn't start live view stream: {}", e);
+                tokio::time::sleep(STREAM_KEEPALIVE).await;
+                continue;
+            }
+            requested = Some(Instant::now());
+        }
+        match timeout(STREAM_IDLE_CHECK, socket.recv(&mut buffer)).await {
+            Ok(Ok(len)) => {
+                if let Some(jpeg) = extract_jpeg(&buffer[..len]) {
+                    frames.send(jpeg.into());
+                }
+            }
+            Ok(Err(e)) => warn!("Error reading live view: {}", e),
+            Err(_) => frames.stall(),
+        }
+    }
+}
+
+async fn read_packet(stream: &mut TcpStream) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0; 8];
+    stream.read_exact(&mut header).await?;
+    let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let packet_type = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let mut payload = vec![0; length.saturating_sub(header.len())];
+    stream.read_exact(&mut payload).await?;
+    Ok((packet_type, payload))
+}
+
+struct StorageInfo {
+    max_capacity: u64,
+    free_bytes: u64,
+    free_images: u32,
+}
+
+impl StorageInfo {
+    fn recording_minutes(&self, bitrate_mbps: f64) -> u64 {
+        (self.free_bytes as f64 * 8.0 / (bitrate_mbps * 1_000_000.0) / 60.0) as u64
+    }
+}
+
+/// The value of a 16-bit property, which comes after the property code and the value's size.
+fn parse_property_u16(data: &[u8]) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(8..10)?.try_into().ok()?))
+}
+
+fn parse_property_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(8..12)?.try_into().ok()?))
+}
+
+/// Shutter speeds under a second are sent as the fraction's denominator times 1000, and longer
+/// ones in milliseconds with `SHUTTER_SPEED_LONG` set.
+fn encode_shutter_speed(seconds: f64) -> Option<u32> {
+    match seconds {
+        s if s.is_nan() || s <= 0.0 => None,
+        s if s < 1.0 => Some((1000.0 / s).round() as u32),
+        s if s * 1000.0 < SHUTTER_SPEED_LONG as f64 => {
+            Some(SHUTTER_SPEED_LONG | (s * 1000.0).round() as u32)
+        }
+        _ => None,
+    }
+}
+
+fn decode_shutter_speed(value: u32) -> Option<f64> {
+    match value & SHUTTER_SPEED_LONG {
+        0 if value > 0 => Some(1000.0 / value as f64),
+        0 => None,
+        _ => Some((value & !SHUTTER_SPEED_LONG) as f64 / 1000.0),
+    }
+}
+
+#[test]
+fn test_exposure() {
+    let data = hex::decode("20000002_04000000_20030000".replace("_", "")).unwrap();
+    assert_eq!(parse_property_u32(&data), Some(800));
+    assert_eq!(parse_property_u32(&data[..11]), None);
+
+    assert_eq!(encode_shutter_speed(1.0 / 50.0), Some(50_000));
+    assert_eq!(encode_shutter_speed(2.5), Some(0x800009c4));
+    assert_eq!(encode_shutter_speed(0.0), None);
+    assert_eq!(encode_shutter_speed(f64::NAN), None);
+    assert_eq!(decode_shutter_speed(50_000), Some(0.02));
+    assert_eq!(decode_shutter_speed(0x800009c4), Some(2.5));
+    assert_eq!(decode_shutter_speed(0), None);
+}
+
+/// The next third stop up (closing) or down (opening) from the aperture, or `None` at the end
+/// of the range. Apertures between third stops step to the nearest one in that direction.
+fn step_aperture(current: u16, close: bool) -> Option<u16> {
+    match close {
+        true => APERTURES.iter().find(|a| **a > current).copied(),
+        false => APERTURES.iter().rev().find(|a| **a < current).copied(),
+    }
+}
+
+#[test]
+fn test_aperture() {
+    let data = hex::decode("40000002_02000000_1c00".replace("_", "")).unwrap();
+    assert_eq!(parse_property_u16(&data), Some(28));
+    assert_eq!(parse_property_u16(&data[..9]), None);
+
+    assert_eq!(step_aperture(28, true), Some(32));
+    assert_eq!(step_aperture(28, false), Some(25));
+    // f/1.7 isn't a third stop
+    assert_eq!(step_aperture(17, false), Some(16));
+    assert_eq!(step_aperture(10, false), None);
+    assert_eq!(step_aperture(220, true), None);
+}
+
+fn parse_storage_ids(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .skip(1)
+        .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+        // The low word is zero for slots without a card
+        .filter(|id| id & 0xffff != 0)
+        .collect()
+}
+
+fn parse_storage_info(data: &[u8]) -> Option<StorageInfo> {
+    // StorageType, FilesystemType and AccessCapability (u16 each) precede the capacities
+    Some(StorageInfo {
+        max_capacity: u64::from_le_bytes(data.get(6..14)?.try_into().ok()?),
+        free_bytes: u64::from_le_bytes(data.get(14..22)?.try_into().ok()?),
+        free_images: u32::from_le_bytes(data.get(22..26)?.try_into().ok()?),
+    })
+}
+
+#[test]
+fn test_parse_storage() {
+    let ids = hex::decode("02000000_01000100_00000200".replace("_", "")).unwrap();
+    assert_eq!(parse_storage_ids(&ids), vec![0x00010001]);
+
+    let info = hex::decode(
+        "0400_0200_0000_00000000_10000000_00000040_00000000_e8030000_00_00".replace("_", ""),
+    )
+    .unwrap();
+    let info = parse_storage_info(&info).unwrap();
+    assert_eq!(info.max_capacity, 64 * 1024 * 1024 * 1024);
+    assert_eq!(info.free_bytes, 1024 * 1024 * 1024);
+    assert_eq!(info.free_images, 1000);
+    assert_eq!(info.recording_minutes(100.0), 1);
+    assert!(parse_storage_info(&[0; 10]).is_none());
+}
+
+/// The lens attached to the camera.
+#[derive(Debug, Clone, PartialEq)]
+struct Lens {
+    name: Option<String>,
+    /// Focal length range in mm, which is a single length for primes.
+    wide_mm: f64,
+    tele_mm: f64,
+}
+
+impl Lens {
+    fn is_prime(&self) -> bool {
+        self.wide_mm == self.tele_mm
+    }
+
+    /// Zooms are scaled by their wide end, since where they're zoomed to isn't known.
+    fn focus_scale(&self) -> f64 {
+        (FOCUS_REFERENCE_MM / self.wide_mm).min(1.0)
+    }
+
+    /// How far through its range a zoom lens is at a focal length.
+    fn zoom_ratio(&self, focal_mm: f64) -> Option<super::ZoomRatio> {
+        if self.is_prime() {
+            return None;
+        }
+        let ratio = (focal_mm - self.wide_mm) / (self.tele_mm - self.wide_mm);
+        Some(super::ZoomRatio::new(ratio))
+    }
+}
+
+impl Display for Lens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.name, self.is_prime()) {
+            (Some(name), _) => write!(f, "{}", name),
+            (None, true) => write!(f, "{}mm", self.wide_mm),
+            (None, false) => write!(f, "{}-{}mm", self.wide_mm, self.tele_mm),
+        }
+    }
+}
+
+/// Asks the camera which lens is attached. Bodies that don't answer are treated as having an
+/// unknown lens, which leaves zoom and focus as they are.
+async fn read_lens(address: &str) -> Option<Lens> {
+    let resp = Client::new()
+        .get(format!("http://{}/cam.cgi?mode=getinfo&type=lens", address))
+        .timeout(Duration::from_secs(2))
+        .send()
+        .await
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    parse_lens(&resp)
+}
+
+/// Parses the reply to `getinfo&type=lens`: `ok` followed by comma-separated fields, of which
+/// the fifth and sixth are the longest and shortest focal lengths in mm, and the eighth is the
+/// lens name on bodies that report one.
+fn parse_lens(resp: &str) -> Option<Lens> {
+    let fields: Vec<&str> = resp.trim().split(',').collect();
+    if fields.first() != Some(&"ok") {
+        return None;
+    }
+    let tele_mm: f64 = fields.get(5)?.parse().ok()?;
+    let wide_mm: f64 = fields.get(6)?.parse().ok()?;
+    if wide_mm <= 0.0 || tele_mm < wide_mm {
+        return None;
+    }
+    let name = fields
+        .get(8)
+        .filter(|f| !f.is_empty())
+        .map(|f| f.to_string());
+    Some(Lens {
+        name,
+        wide_mm,
+        tele_mm,
+    })
+}
+
+#[test]
+fn test_parse_lens() {
+    let zoom =
+        parse_lens("ok,3072/256,1024/256,2816/256,0,60,12,on,LUMIX G VARIO 12-60\r\n").unwrap();
+    assert_eq!(zoom.to_string(), "LUMIX G VARIO 12-60");
+    assert!(!zoom.is_prime());
+    assert_eq!(zoom.focus_scale(), 1.0);
+    let prime = parse_lens("ok,3584/256,512/256,3584/256,0,100,100,on\r\n").unwrap();
+    assert!(prime.is_prime());
+    assert_eq!(prime.to_string(), "100mm");
+    assert_eq!(prime.focus_scale(), 0.5);
+    assert_eq!(zoom.zoom_ratio(12.0), Some(super::ZoomRatio::new(0.0)));
+    assert_eq!(zoom.zoom_ratio(36.0), Some(super::ZoomRatio::new(0.5)));
+    assert_eq!(zoom.zoom_ratio(70.0), Some(super::ZoomRatio::new(1.0)));
+    assert_eq!(prime.zoom_ratio(100.0), None);
+    assert_eq!(parse_lens("err_non_support"), None);
+    assert_eq!(parse_lens("ok,1,2"), None);
+}
+
+/// Fetches the description of the camera's PTP service.
+async fn read_description(address: &str, timeout: Duration) -> Result<Description, DeviceError> {
+    let resp = Client::new()
+        .get(format!("http://{}:60606/PTPRemote/Server0/ddd", address))
+        .timeout(timeout)
+        .send()
+        .await?
+        .text()
+        .await?;
+    parse_description(&resp)
+}
+
+/// A camera that answered discovery.
+pub struct Discovered {
+    pub address: String,
+    pub name: String,
+}
+
+/// Looks for Lumix cameras on the local network, by asking UPnP media servers to announce
+/// themselves over SSDP and keeping the ones that also describe a PTP service.
+pub async fn discover(duration: Duration) -> Result<Vec<Discovered>, DeviceError> {
+    let search = format!(
+        "M-SEARCH * HTTP/1.1\r\n\
+         HOST: {}:{}\r\n\
+         MAN: \"ssdp:discover\"\r\n\
+         MX: {}\r\n\
+         ST: {}\r\n\r\n",
+        SSDP_ADDRESS.0,
+        SSDP_ADDRESS.1,
+        duration.as_secs().max(1),
+        SSDP_SEARCH_TARGET
+    );
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.send_to(search.as_bytes(), SSDP_ADDRESS).await?;
+
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut buffer = vec![0; 4096];
+    let mut addresses: Vec<IpAddr> = vec![];
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await
+    {
+        let (len, from) = received?;
+        let response = String::from_utf8_lossy(&buffer[..len]);
+        if is_search_response(&response) && !addresses.contains(&from.ip()) {
+            addresses.push(from.ip());
+        }
+    }
+
+    let mut cameras = vec![];
+    for address in addresses {
+        match read_description(&address.to_string(), Duration::from_secs(2)).await {
+            Ok(description) => cameras.push(Discovered {
+                address: address.to_string(),
+                name: description.friendly_name,
+            }),
+            Err(e) => debug!("{} isn't a Lumix camera: {}", address, e),
+        }
+    }
+    Ok(cameras)
+}
+
+/// Whether an SSDP packet is an answer to our search rather than some other announcement.
+fn is_search_response(response: &str) -> bool {
+    let mut lines = response.lines();
+    let ok = lines.next().is_some_and(|l| l.starts_with("HTTP/1.1 200"));
+    ok && lines.any(|l| {
+        l.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("ST") && value.trim() == SSDP_SEARCH_TARGET
+        })
+    })
+}
+
+#[test]
+fn test_is_search_response() {
+    let response = "HTTP/1.1 200 OK\r\n\
+                    CACHE-CONTROL: max-age=1800\r\n\
+                    LOCATION: http://192.168.54.1:60606/Server0/ddd\r\n\
+                    st: urn:schemas-upnp-org:device:MediaServer:1\r\n\r\n";
+    assert!(is_search_response(response));
+    let notify = "NOTIFY * HTTP/1.1\r\n\
+                  NT: urn:schemas-upnp-org:device:MediaServer:1\r\n\r\n";
+    assert!(!is_search_response(notify));
+    let other = "HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\n\r\n";
+    assert!(!is_search_response(other));
+}
+
+/// What the camera's device description says about it.
+#[derive(Debug, PartialEq)]
+struct Description {
     friendly_name: String,
-    // quick-xml + serde doesn't support namespaces
-    // #[serde(name="pana:X_PTPPortNo")]
-    // ptp_port_no: u16,
+    ptp_port: Option<u16>,
 }
 
-#[derive(Debug, Deserialize)]
-struct CameraInfo {
-    device: DeviceInfo,
+/// Parses the UPnP device description. The PTP/IP port is in a Panasonic-specific element
+/// (`pana:X_PTPPortNo`), whose prefix can vary, so elements are matched by their local name.
+fn parse_description(xml: &str) -> Result<Description, DeviceError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut open: Vec<String> = vec![];
+    let mut friendly_name = None;
+    let mut ptp_port = None;
+    loop {
+        match reader.read_event().map_err(DeviceError::protocol)? {
+            Event::Start(e) => {
+                open.push(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+            }
+            Event::End(_) => {
+                open.pop();
+            }
+            Event::Text(t) => {
+                let text = t.unescape().map_err(DeviceError::protocol)?;
+                match open.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                    [.., "device", "friendlyName"] => friendly_name = Some(text.into_owned()),
+                    [.., "X_PTPPortNo"] => ptp_port = text.trim().parse().ok(),
+                    _ => (),
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    Ok(Description {
+        friendly_name: friendly_name
+            .ok_or_else(|| DeviceError::protocol("device description has no friendlyName"))?,
+        ptp_port,
+    })
+}
+
+#[test]
+fn test_parse_description() {
+    let xml = r#"<?xml version="1.0"?>
+        <root xmlns="urn:schemas-upnp-org:device-1-0" xmlns:pana="urn:schemas-panasonic-com:pana">
+          <device>
+            <friendlyName>DC-GH5</friendlyName>
+            <pana:X_PTPPortNo>15741</pana:X_PTPPortNo>
+          </device>
+        </root>"#;
+    assert_eq!(
+        parse_description(xml).unwrap(),
+        Description {
+            friendly_name: "DC-GH5".to_owned(),
+            ptp_port: Some(15741),
+        }
+    );
+    let without_port = "<root><device><friendlyName>Simulated LUMIX</friendlyName></device></root>";
+    assert_eq!(parse_description(without_port).unwrap().ptp_port, None);
+    assert!(parse_description("<root><device></device></root>").is_err());
 }
 
 fn encode_str(s: &str) -> Vec<u8> {
@@ -692,3 +1989,90 @@ async fn create_socket(address: &str, port: u16) -> io::Result<TcpStream> {
     sock_ref.set_tcp_keepalive(&ka)?;
     Ok(stream)
 }
+
+#[test]
+fn test_packet_golden() {
+    for (inputs, expected) in super::golden_cases(include_str!("fixtures/lumix_packets.txt")) {
+        let transaction_id: u32 = inputs[1].parse().unwrap();
+        let zoom_direction = |s: &str| match s {
+            "wide" => ZoomDirection::Wide,
+            "tele" => ZoomDirection::Tele,
+            _ => panic!("unknown zoom direction {}", s),
+        };
+        let zoom_speed = |s: &str| match s {
+            "low" => ZoomSpeed(1),
+            "high" => ZoomSpeed(2),
+            _ => panic!("unknown zoom speed {}", s),
+        };
+        let focus_speed = |s: &str| match s {
+            "farFast" => FocusAdjustSpeed::FarFast,
+            "farSlow" => FocusAdjustSpeed::FarSlow,
+            "nearSlow" => FocusAdjustSpeed::NearSlow,
+            "nearFast" => FocusAdjustSpeed::NearFast,
+            _ => panic!("unknown focus speed {}", s),
+        };
+        let bytes = match inputs[0] {
+            "openSession" => bincode::serialize(&CommandPacket::open_session(transaction_id)),
+            "startZoom" => bincode::serialize(&CommandPacket::start_zoom(transaction_id)),
+            "stopZoom" => bincode::serialize(&CommandPacket::stop_zoom(transaction_id)),
+            "oneShotAf" => bincode::serialize(&CommandPacket::one_shot_af(transaction_id)),
+            "movieRec" => bincode::serialize(&CommandPacket::movie_rec(
+                transaction_id,
+                inputs[2].parse().unwrap(),
+            )),
+            "initiateCapture" => {
+                bincode::serialize(&CommandPacket::initiate_capture(transaction_id))
+            }
+            "adjustFocus" => bincode::serialize(&CommandPacket::adjust_focus(transaction_id)),
+            "getDeviceInfo" => bincode::serialize(&CommandPacket::get_device_info(transaction_id)),
+            "getProperty" => bincode::serialize(&CommandPacket::get_property(
+                transaction_id,
+                APERTURE_PROPERTY,
+            )),
+            "setProperty" => bincode::serialize(&CommandPacket::set_property(
+                transaction_id,
+                APERTURE_PROPERTY,
+            )),
+            "propertyData" => bincode::serialize(&PropertyDataPacket::create(
+                transaction_id,
+                APERTURE_PROPERTY,
+                inputs[2].parse().unwrap(),
+            )),
+            "zoomStartData" => bincode::serialize(&ZoomStartDataPacket::create(
+                transaction_id,
+                CommandPacket::start_zoom(transaction_id).param1,
+                zoom_direction(inputs[2]),
+                zoom_speed(inputs[3]),
+            )),
+            "focusPointData" => bincode::serialize(&FocusPointDataPacket::create(
+                transaction_id,
+                AF_AREA_POSITION_PROPERTY,
+                super::FocusPoint {
+                    x: inputs[2].parse().unwrap(),
+                    y: inputs[3].parse().unwrap(),
+                },
+            )),
+            "propertyU32Data" => bincode::serialize(&PropertyU32DataPacket::create(
+                transaction_id,
+                ISO_PROPERTY,
+                inputs[2].parse().unwrap(),
+            )),
+            "zoomStopData" => bincode::serialize(&ZoomStopDataPacket::create(
+                transaction_id,
+                CommandPacket::stop_zoom(transaction_id).param1,
+            )),
+            "focusAdjustData" => bincode::serialize(&FocusAdjustDataPacket::create(
+                transaction_id,
+                CommandPacket::adjust_focus(transaction_id).param1,
+                focus_speed(inputs[2]),
+            )),
+            x => panic!("unknown packet {}", x),
+        };
+        assert_eq!(
+            hex::encode(bytes.unwrap()),
+            expected,
+            "inputs: {:?}",
+            inputs
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::{collections::HashSet, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::TcpStream,
+    time::timeout,
+};
+use tracing::{debug, info};
+
+use super::DeviceError;
+use crate::config::{Capability, ModbusConfig, ModbusRegister, ModbusRegisters};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+const WRITE_SINGLE_REGISTER: u8 = 0x06;
+
+pub struct Modbus {
+    id: String,
+    address: String,
+    unit_id: u8,
+    registers: ModbusRegisters,
+    capabilities: HashSet<Capability>,
+    connection: Option<TcpStream>,
+    transaction_id: u16,
+    last_values: Vec<(u16, u16)>,
+}
+
+impl std::fmt::Display for Modbus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Modbus[{}]", self.address)
+    }
+}
+
+#[async_trait]
+impl super::Device for Modbus {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let stream = timeout(TIMEOUT, TcpStream::connect(&self.address)).await??;
+        stream.set_nodelay(true)?;
+        self.connection = Some(stream);
+        self.last_values.clear();
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.connection.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.connection.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+
+        let writes = register_values(&self.registers, &self.capabilities, &command)
+            .into_iter()
+            .filter(|w| !self.last_values.contains(w))
+            .collect::<Vec<_>>();
+        for (address, value) in writes {
+            self.transaction_id = self.transaction_id.wrapping_add(1);
+            let request = write_register_request(self.transaction_id, self.unit_id, address, value);
+            debug!("Writing {} to register {}", value, address);
+            let stream = self.connection.as_mut().unwrap();
+            let result = match timeout(TIMEOUT, write_register(stream, &request)).await {
+                Ok(result) => result,
+                Err(elapsed) => Err(elapsed.into()),
+            };
+            if let Err(e) = result {
+                // The connection is in an unknown state, so start over with a fresh one
+                self.connection = None;
+                return Err(e);
+            }
+            self.last_values.retain(|(a, _)| *a != address);
+            self.last_values.push((address, value));
+        }
+        Ok(())
+    }
+}
+
+async fn write_register(stream: &mut TcpStream, request: &[u8]) -> Result<(), DeviceError> {
+    stream.write_all(request).await?;
+    let mut header = [0u8; 7];
+    stream.read_exact(&mut header).await?;
+    let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let mut pdu = vec![0u8; length.saturating_sub(1)];
+    stream.read_exact(&mut pdu).await?;
+    check_response(&pdu)
+}
+
+fn write_register_request(transaction_id: u16, unit_id: u8, address: u16, value: u16) -> Vec<u8> {
+    let mut request = vec![];
+    request.extend(transaction_id.to_be_bytes());
+    // Protocol identifier, always 0 for Modbus
+    request.extend([0x00, 0x00]);
+    // Remaining length: unit identifier + function code + address + value
+    request.extend(6u16.to_be_bytes());
+    request.push(unit_id);
+    request.push(WRITE_SINGLE_REGISTER);
+    request.extend(address.to_be_bytes());
+    request.extend(value.to_be_bytes());
+    request
+}
+
+fn check_response(pdu: &[u8]) -> Result<(), DeviceError> {
+    match pdu {
+        [WRITE_SINGLE_REGISTER, ..] => Ok(()),
+        [function, code, ..] if function & 0x80 != 0 => {
+            Err(format!("Modbus exception {:#04x}", code).into())
+        }
+        _ => Err(format!("unexpected Modbus response {}", hex::encode(pdu)).into()),
+    }
+}
+
+/// Register writes for every configured axis.
+fn register_values(
+    registers: &ModbusRegisters,
+    capabilities: &HashSet<Capability>,
+    command: &super::Command,
+) -> Vec<(u16, u16)> {
+    let autofocus = if command.autofocus { 1.0 } else { 0.0 };
+    [
+        (Capability::Ptr, &registers.pan, command.pan.get()),
+        (Capability::Ptr, &registers.tilt, command.tilt.get()),
+        (Capability::Roll, &registers.roll, command.roll.get()),
+        (Capability::Zoom, &registers.zoom, command.zoom.get()),
+        (Capability::Focus, &registers.focus, command.focus.get()),
+        (Capability::Autofocus, &registers.autofocus, autofocus),
+    ]
+    .into_iter()
+    .filter(|(capability, _, _)| capabilities.contains(capability))
+    .filter_map(|(_, register, value)| register.as_ref().map(|r| (r.address, scale(r, value))))
+    .collect()
+}
+
+fn scale(register: &ModbusRegister, value: f64) -> u16 {
+    let raw = (value * register.scale + register.offset)
+        .round()
+        .clamp(i16::MIN as f64, u16::MAX as f64);
+    if raw < 0.0 {
+        raw as i16 as u16
+    } else {
+        raw as u16
+    }
+}
+
+/// Only capabilities that have a register are enabled.
+fn register_capabilities(registers: &ModbusRegisters) -> HashSet<Capability> {
+    let mut capabilities = HashSet::new();
+    if registers.pan.is_some() || registers.tilt.is_some() {
+        capabilities.insert(Capability::Ptr);
+    }
+    if registers.roll.is_some() {
+        capabilities.insert(Capability::Roll);
+    }
+    if registers.zoom.is_some() {
+        capabilities.insert(Capability::Zoom);
+    }
+    if registers.focus.is_some() {
+        capabilities.insert(Capability::Focus);
+    }
+    if registers.autofocus.is_some() {
+        capabilities.insert(Capability::Autofocus);
+    }
+    capabilities
+}
+
+pub fn create(id: &str, config: &ModbusConfig) -> Modbus {
+    let capabilities = match &config.capabilities {
+        Some(c) => HashSet::from_iter(c.iter().cloned()),
+        None => register_capabilities(&config.registers),
+    };
+    Modbus {
+        id: id.to_owned(),
+        address: config.address.clone(),
+        unit_id: config.unit_id,
+        registers: config.registers.clone(),
+        capabilities,
+        connection: None,
+        transaction_id: 0,
+        last_values: vec![],
+    }
+}
+
+#[test]
+fn test_modbus_framing() {
+    let register = |address| ModbusRegister {
+        address,
+        scale: 1000.0,
+        offset: 0.0,
+    };
+    let registers = ModbusRegisters {
+        pan: Some(register(100)),
+        tilt: Some(register(101)),
+        ..Default::default()
+    };
+    let command = super::Command {
+        pan: super::Velocity::new(0.5),
+        tilt: super::Velocity::new(-0.25),
+        ..Default::default()
+    };
+    let values = register_values(&registers, &register_capabilities(&registers), &command);
+    assert_eq!(values, vec![(100, 500), (101, (-250i16) as u16)]);
+
+    assert_eq!(
+        hex::encode(write_register_request(7, 1, 100, 500)),
+        "0007000000060106006401f4"
+    );
+    assert!(check_response(&[0x06, 0x00, 0x64, 0x01, 0xf4]).is_ok());
+    assert!(check_response(&[0x86, 0x02]).is_err());
+}
@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt as _;
+use tokio_serial::{SerialPortBuilderExt as _, SerialStream};
+use tracing::{debug, info};
+
+use super::DeviceError;
+use crate::config::{Capability, PelcoConfig, PelcoProtocol};
+
+const MAX_SPEED: f64 = 0x3f as f64;
+
+/// Analog PTZ heads controlled with Pelco-D or Pelco-P over an RS-485 adapter.
+pub struct Pelco {
+    id: String,
+    port: String,
+    baud_rate: u32,
+    protocol: PelcoProtocol,
+    address: u8,
+    capabilities: HashSet<Capability>,
+    stream: Option<SerialStream>,
+    /// The last motion and zoom speed sent, so unchanged commands aren't repeated.
+    motion: Motion,
+    zoom_speed: u8,
+}
+
+/// The command bytes and pan and tilt speeds of a motion command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Motion {
+    command1: u8,
+    command2: u8,
+    pan_speed: u8,
+    tilt_speed: u8,
+}
+
+impl std::fmt::Display for Pelco {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pelco[{}]", self.port)
+    }
+}
+
+impl Pelco {
+    async fn send(&mut self, message: [u8; 4]) -> Result<(), DeviceError> {
+        let frame = frame(self.protocol, self.address, message);
+        let Some(stream) = &mut self.stream else {
+            return Err(DeviceError::NotConnected);
+        };
+        debug!(packet = %hex::encode(&frame), "Sending");
+        stream.write_all(&frame).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl super::Device for Pelco {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        if self.address == 0 {
+            return Err("Pelco addresses start at 1".into());
+        }
+        let stream = tokio_serial::new(&self.port, self.baud_rate)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .open_native_async()?;
+        self.stream = Some(stream);
+        self.motion = Motion::default();
+        self.zoom_speed = 0;
+        self.send([0x00; 4]).await?;
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.stream.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.stream.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+
+        let zoom_speed = zoom_speed(command.zoom.get());
+        if self.capabilities.contains(&Capability::Zoom)
+            && !command.zoom.is_stopped()
+            && zoom_speed != self.zoom_speed
+        {
+            self.send([0x00, 0x25, 0x00, zoom_speed]).await?;
+            self.zoom_speed = zoom_speed;
+        }
+
+        let motion = motion(self.protocol, &self.capabilities, command);
+        if motion != self.motion {
+            self.send([
+                motion.command1,
+                motion.command2,
+                motion.pan_speed,
+                motion.tilt_speed,
+            ])
+            .await?;
+            self.motion = motion;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the motion command for every supported axis at once, since the protocol stops any
+/// axis that isn't included.
+fn motion(
+    protocol: PelcoProtocol,
+    capabilities: &HashSet<Capability>,
+    command: super::Command,
+) -> Motion {
+    let mut motion = Motion::default();
+    let mut set = |value: f64, positive: (u8, u8), negative: (u8, u8)| {
+        let (command1, command2) = match value {
+            x if x > 0.0 => positive,
+            x if x < 0.0 => negative,
+            _ => (0, 0),
+        };
+        motion.command1 |= command1;
+        motion.command2 |= command2;
+    };
+    if capabilities.contains(&Capability::Ptr) {
+        set(command.pan.get(), (0, 0x02), (0, 0x04));
+        set(command.tilt.get(), (0, 0x08), (0, 0x10));
+    }
+    if capabilities.contains(&Capability::Zoom) {
+        set(command.zoom.get(), (0, 0x20), (0, 0x40));
+    }
+    if capabilities.contains(&Capability::Focus) {
+        match protocol {
+            PelcoProtocol::D => set(command.focus.get(), (0, 0x80), (0x01, 0)),
+            PelcoProtocol::P => set(command.focus.get(), (0x01, 0), (0x02, 0)),
+        }
+    }
+    if capabilities.contains(&Capability::Ptr) {
+        motion.pan_speed = speed(command.pan.get());
+        motion.tilt_speed = speed(command.tilt.get());
+    }
+    motion
+}
+
+/// Converts a value from -1 to 1 into a pan or tilt speed from 0 to 0x3f. Any movement at all
+/// gets at least the slowest speed.
+fn speed(value: f64) -> u8 {
+    (value.abs().min(1.0) * MAX_SPEED).ceil() as u8
+}
+
+/// Converts a value from -1 to 1 into one of the four zoom speeds.
+fn zoom_speed(value: f64) -> u8 {
+    ((value.abs().min(1.0) * 4.0).ceil() as u8).clamp(1, 4) - 1
+}
+
+/// Wraps a message with the protocol's framing, address, and checksum. Pelco-P addresses are
+/// sent starting from 0.
+fn frame(protocol: PelcoProtocol, address: u8, message: [u8; 4]) -> Vec<u8> {
+    match protocol {
+        PelcoProtocol::D => {
+            let mut frame = vec![0xff, address];
+            frame.extend(message);
+            let checksum = frame[1..].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+            frame.push(checksum);
+            frame
+        }
+        PelcoProtocol::P => {
+            let mut frame = vec![0xa0, address - 1];
+            frame.extend(message);
+            frame.push(0xaf);
+            let checksum = frame.iter().fold(0u8, |sum, b| sum ^ b);
+            frame.push(checksum);
+            frame
+        }
+    }
+}
+
+pub fn create(id: &str, config: &PelcoConfig) -> Pelco {
+    Pelco {
+        id: id.to_owned(),
+        port: config.port.clone(),
+        baud_rate: config.baud_rate,
+        protocol: config.protocol,
+        address: config.address,
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                HashSet::from([Capability::Ptr, Capability::Zoom, Capability::Focus])
+            }),
+        stream: None,
+        motion: Motion::default(),
+        zoom_speed: 0,
+    }
+}
+
+#[test]
+fn test_pelco_frames() {
+    use super::{Command, Velocity};
+
+    let all = HashSet::from([Capability::Ptr, Capability::Zoom, Capability::Focus]);
+    let encode = |protocol, command| {
+        let m = motion(protocol, &all, command);
+        hex::encode(frame(
+            protocol,
+            1,
+            [m.command1, m.command2, m.pan_speed, m.tilt_speed],
+        ))
+    };
+    let stop = Command::default();
+    let pan_right = Command {
+        pan: Velocity::new(0.5),
+        ..Default::default()
+    };
+    let tilt_up_focus_near = Command {
+        tilt: Velocity::new(1.0),
+        focus: Velocity::new(-0.5),
+        ..Default::default()
+    };
+    assert_eq!(encode(PelcoProtocol::D, stop), "ff010000000001");
+    assert_eq!(encode(PelcoProtocol::D, pan_right), "ff010002200023");
+    assert_eq!(
+        encode(PelcoProtocol::D, tilt_up_focus_near),
+        "ff010108003f49"
+    );
+    assert_eq!(encode(PelcoProtocol::P, stop), "a00000000000af0f");
+    assert_eq!(encode(PelcoProtocol::P, pan_right), "a00000022000af2d");
+
+    assert_eq!(speed(0.001), 1);
+    assert_eq!(zoom_speed(0.1), 0);
+    assert_eq!(zoom_speed(-1.0), 3);
+}
@@ -1,48 +1,48 @@
 use async_trait::async_trait;
 use btleplug::{
-    api::{
-        bleuuid::uuid_from_u16, Central as _, Characteristic, Peripheral as _, ScanFilter,
-        WriteType,
-    },
+    api::{bleuuid::uuid_from_u16, Characteristic, Peripheral as _, WriteType},
     platform::{Adapter, Peripheral},
 };
 use futures::{StreamExt, TryFutureExt as _};
+use serde_json::{Map, Value};
 use std::{
     collections::HashSet,
-    error::Error,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 use tokio::{sync::watch, task::JoinHandle, time::timeout};
+use tracing::{debug, info, warn, Instrument as _};
 
-use crate::config::{all_capabilities, Capability, RoninConfig, RoninOption};
+use super::{
+    duml::{Packet, Reassembler},
+    rsdk::{self, Frame},
+    DeviceError,
+};
+use crate::config::{all_capabilities, Capability, RoninConfig, RoninModel, RoninOption};
 
 #[allow(unused)]
 pub const SERVICE_UUID: uuid::Uuid = uuid_from_u16(0xfff0);
 pub const COMMAND_UUID: uuid::Uuid = uuid_from_u16(0xfff5);
 pub const NOTIFICATION_UUID: uuid::Uuid = uuid_from_u16(0xfff4);
-const CUSTOM_ALG: crc::Algorithm<u16> = crc::Algorithm {
-    width: 16,
-    poly: 0x1021,
-    init: 0x496c,
-    refin: true,
-    refout: true,
-    xorout: 0x0000,
-    check: 0x7109,
-    residue: 0x0000,
-};
-const CRC: crc::Crc<u16> = crc::Crc::<u16>::new(&CUSTOM_ALG);
 const ZOOM_MIN: u16 = 0;
 const ZOOM_MAX: u16 = 4095;
 const ZOOM_ENDPOINT_TOLERANCE: u16 = 20;
 const ZOOM_SPEED_MIN: f64 = 3.0;
 const ZOOM_SPEED_MAX: f64 = 1000.0;
 const ZOOM_MIN_INITIAL_INCREMENT: i32 = 15;
-
-fn add_checksum(b: &[u8]) -> Vec<u8> {
-    let checksum = CRC.checksum(b).to_le_bytes();
-    [b, &checksum].concat()
-}
+/// Longest single Bluetooth write. Motion packets fit in one; longer packets are split into
+/// several writes, which the gimbal joins back together using the length in the header.
+const MAX_WRITE_LEN: usize = 32;
+const APP: u8 = 0x02;
+const GIMBAL: u8 = 0x04;
+const FOCUS_MOTOR: u8 = 0xdf;
+/// Full speed for R SDK speed commands, in tenths of a degree per second.
+const RSDK_SPEED_MAX: f64 = 1800.0;
+/// How often R SDK gimbals are asked for their attitude, which they don't send on their own.
+const ATTITUDE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Pan, tilt, and roll in degrees, as the gimbal reports them.
+type Attitude = (f64, f64, f64);
 
 // Expects a value in the range [-1024, 1024]
 fn encode_value(val: i16) -> Vec<u8> {
@@ -58,20 +58,250 @@ fn create_packet(seq_num: u16, pan: f64, tilt: f64, roll: f64) -> Vec<u8> {
     let tilt_int = scale_ptr_value(tilt);
     let roll_int = scale_ptr_value(roll);
 
-    let prefix = vec![0x55, 0x16, 0x04, 0xfc, 0x02, 0x04];
-    let midfix = vec![0x40, 0x04, 0x01];
-    let suffix = vec![0x00, 0x00, 0x02];
+    let payload = [
+        encode_value(tilt_int),
+        encode_value(roll_int),
+        encode_value(pan_int),
+        vec![0x00, 0x00, 0x02],
+    ]
+    .concat();
+    Packet {
+        sender: APP,
+        receiver: GIMBAL,
+        seq: seq_num,
+        cmd_type: 0x40,
+        cmd_set: 0x04,
+        cmd_id: 0x01,
+        payload,
+    }
+    .encode()
+}
+
+/// Builds a handheld speed control frame, for gimbals that take R SDK commands.
+fn create_speed_frame(seq_num: u16, pan: f64, tilt: f64, roll: f64) -> Vec<u8> {
+    let speed = |value: f64| ((value * value.abs() * RSDK_SPEED_MAX) as i16).to_le_bytes();
+    let payload = [
+        &speed(pan)[..],
+        &speed(roll),
+        &speed(tilt),
+        // Take control from the joystick
+        &[0x80],
+    ]
+    .concat();
+    Frame {
+        cmd_type: rsdk::NO_REPLY,
+        seq: seq_num,
+        cmd_set: 0x0e,
+        cmd_id: 0x0c,
+        payload,
+    }
+    .encode()
+}
+
+/// Builds a motion command in the format the model takes.
+fn create_motion_packet(
+    model: RoninModel,
+    seq_num: u16,
+    pan: f64,
+    tilt: f64,
+    roll: f64,
+) -> Vec<u8> {
+    match model {
+        RoninModel::Rsc2 | RoninModel::OsmoMobile => create_packet(seq_num, pan, tilt, roll),
+        RoninModel::Rs2 | RoninModel::Rs3Mini => create_speed_frame(seq_num, pan, tilt, roll),
+    }
+}
+
+/// Angles in tenths of a degree, with axes that aren't moving left at 0.
+fn encode_angle(angle: Option<f64>) -> [u8; 2] {
+    ((angle.unwrap_or(0.0) * 10.0).round() as i16).to_le_bytes()
+}
+
+/// Marks the move as absolute, and the axes that are left out as ones to leave alone.
+fn angle_flags(pan: Option<f64>, tilt: Option<f64>, roll: Option<f64>) -> u8 {
+    let mut flags = 0x01;
+    if pan.is_none() {
+        flags |= 0x02;
+    }
+    if roll.is_none() {
+        flags |= 0x04;
+    }
+    if tilt.is_none() {
+        flags |= 0x08;
+    }
+    flags
+}
+
+/// Move durations in tenths of a second, the longest being 25.5s.
+fn encode_duration(duration: Duration) -> u8 {
+    (duration.as_millis() / 100).clamp(1, u8::MAX as u128) as u8
+}
 
-    let seq_bytes = seq_num.to_le_bytes().to_vec();
-    let pan_bytes = encode_value(pan_int);
-    let tilt_bytes = encode_value(tilt_int);
-    let roll_bytes = encode_value(roll_int);
+/// Builds an absolute angle command, for gimbals that take DUML motion packets.
+fn create_angle_packet(
+    seq_num: u16,
+    pan: Option<f64>,
+    tilt: Option<f64>,
+    roll: Option<f64>,
+    duration: Duration,
+) -> Vec<u8> {
+    let payload = [
+        &encode_angle(tilt)[..],
+        &encode_angle(roll),
+        &encode_angle(pan),
+        &[angle_flags(pan, tilt, roll), encode_duration(duration)],
+    ]
+    .concat();
+    Packet {
+        sender: APP,
+        receiver: GIMBAL,
+        seq: seq_num,
+        cmd_type: 0x40,
+        cmd_set: 0x04,
+        cmd_id: 0x0a,
+        payload,
+    }
+    .encode()
+}
 
-    let concat = [
-        prefix, seq_bytes, midfix, tilt_bytes, roll_bytes, pan_bytes, suffix,
+/// Builds a handheld position control frame, for gimbals that take R SDK commands.
+fn create_position_frame(
+    seq_num: u16,
+    pan: Option<f64>,
+    tilt: Option<f64>,
+    roll: Option<f64>,
+    duration: Duration,
+) -> Vec<u8> {
+    let payload = [
+        &encode_angle(pan)[..],
+        &encode_angle(roll),
+        &encode_angle(tilt),
+        &[angle_flags(pan, tilt, roll), encode_duration(duration)],
     ]
     .concat();
-    add_checksum(&concat)
+    Frame {
+        cmd_type: rsdk::NO_REPLY,
+        seq: seq_num,
+        cmd_set: 0x0e,
+        cmd_id: 0x00,
+        payload,
+    }
+    .encode()
+}
+
+/// Builds an absolute move in the format the model takes.
+fn create_move_to_packet(
+    model: RoninModel,
+    seq_num: u16,
+    (pan, tilt, roll): (Option<f64>, Option<f64>, Option<f64>),
+    duration: Duration,
+) -> Vec<u8> {
+    match model {
+        RoninModel::Rsc2 | RoninModel::OsmoMobile => {
+            create_angle_packet(seq_num, pan, tilt, roll, duration)
+        }
+        RoninModel::Rs2 | RoninModel::Rs3Mini => {
+            create_position_frame(seq_num, pan, tilt, roll, duration)
+        }
+    }
+}
+
+fn uses_rsdk(model: RoninModel) -> bool {
+    match model {
+        RoninModel::Rsc2 | RoninModel::OsmoMobile => false,
+        RoninModel::Rs2 | RoninModel::Rs3Mini => true,
+    }
+}
+
+/// Reads an angle in tenths of a degree.
+fn decode_angle(bytes: &[u8], offset: usize) -> f64 {
+    i16::from_le_bytes([bytes[offset], bytes[offset + 1]]) as f64 / 10.0
+}
+
+/// The gimbal's attitude, from the parameter packets DUML gimbals push while connected.
+fn duml_attitude(packet: &Packet) -> Option<Attitude> {
+    if packet.sender != GIMBAL
+        || packet.cmd_set != 0x04
+        || packet.cmd_id != 0x05
+        || packet.payload.len() < 6
+    {
+        return None;
+    }
+    // Pitch, roll, then yaw
+    let p = &packet.payload;
+    Some((decode_angle(p, 4), decode_angle(p, 0), decode_angle(p, 2)))
+}
+
+/// Asks an R SDK gimbal for its attitude.
+fn create_attitude_query(seq_num: u16) -> Vec<u8> {
+    Frame {
+        cmd_type: rsdk::NEEDS_REPLY,
+        seq: seq_num,
+        cmd_set: 0x0e,
+        cmd_id: 0x02,
+        payload: vec![0x01],
+    }
+    .encode()
+}
+
+/// The gimbal's attitude, from a reply to `create_attitude_query`.
+fn rsdk_attitude(frame: &Frame) -> Option<Attitude> {
+    if frame.cmd_type & rsdk::REPLY == 0
+        || frame.cmd_set != 0x0e
+        || frame.cmd_id != 0x02
+        || frame.payload.len() < 8
+        || frame.payload[0] != 0x00
+    {
+        return None;
+    }
+    // A return code and the data type, then yaw, roll, and pitch
+    let p = &frame.payload;
+    Some((decode_angle(p, 2), decode_angle(p, 6), decode_angle(p, 4)))
+}
+
+fn default_capabilities(model: RoninModel) -> HashSet<Capability> {
+    match model {
+        RoninModel::Rsc2 => all_capabilities(),
+        // Zooming goes through a DJI focus motor, which only the Ronin DUML gimbals can drive
+        RoninModel::Rs2 | RoninModel::Rs3Mini | RoninModel::OsmoMobile => {
+            HashSet::from([Capability::Ptr, Capability::Roll])
+        }
+    }
+}
+
+fn create_zoom_packet(seq_num: u16, target: u16) -> Vec<u8> {
+    Packet {
+        sender: APP,
+        receiver: FOCUS_MOTOR,
+        seq: seq_num,
+        cmd_type: 0x00,
+        cmd_set: 0x04,
+        cmd_id: 0x2f,
+        payload: [&[0x01, 0x00, 0x02][..], &target.to_le_bytes()].concat(),
+    }
+    .encode()
+}
+
+/// Writes a packet, split into as many writes as it needs.
+async fn write_packet(
+    peripheral: &Peripheral,
+    characteristic: &Characteristic,
+    packet: &[u8],
+) -> Result<(), btleplug::Error> {
+    for segment in packet.chunks(MAX_WRITE_LEN) {
+        peripheral
+            .write(characteristic, segment, WriteType::WithoutResponse)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The focus motor's current position, from its status packets.
+fn zoom_level(packet: &Packet) -> Option<u16> {
+    if packet.sender != FOCUS_MOTOR || packet.receiver != APP || packet.payload.len() != 15 {
+        return None;
+    }
+    Some(u16::from_le_bytes([packet.payload[3], packet.payload[4]]))
 }
 
 fn scale_ptr_value(val: f64) -> i16 {
@@ -96,33 +326,46 @@ fn get_seq(next_seq: &watch::Sender<u16>) -> u16 {
 pub struct Ronin {
     id: String,
     name: String,
+    model: RoninModel,
     next_seq: watch::Sender<u16>,
     adapter: Adapter,
     connection: Option<Connection>,
     capabilities: HashSet<Capability>,
     options: HashSet<RoninOption>,
+    prepared: Option<(super::Command, Vec<u8>)>,
 }
 
 struct Connection {
     peripheral: Peripheral,
     characteristic: Arc<Mutex<Characteristic>>,
     _event_task: JoinHandle<()>,
-    _zoom_task: JoinHandle<Result<(), Box<dyn Error + Send + Sync>>>,
+    _zoom_task: JoinHandle<Result<(), DeviceError>>,
     zoom_speed: watch::Sender<f64>,
+    current_zoom: watch::Receiver<Option<u16>>,
+    attitude: watch::Receiver<Option<Attitude>>,
+    attitude_task: Option<JoinHandle<Result<(), DeviceError>>>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Some(task) = &self.attitude_task {
+            task.abort();
+        }
+    }
 }
 
 impl Connection {
-    pub async fn try_resume_connection(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+    pub async fn try_resume_connection(&mut self, name: &str) -> Result<(), DeviceError> {
         if self.peripheral.is_connected().await? {
             return Ok(());
         }
-        println!("{}: Lost connection, reconnecting...", name);
+        warn!("Lost connection, reconnecting...");
         let timer = Instant::now();
         self.peripheral.disconnect().await?;
 
         timeout(Duration::from_millis(200), self.peripheral.connect())
-            .map_err(|_| -> Box<dyn Error> {
-                format!("{}: timed out while trying to reconnect", name).into()
+            .map_err(|_| {
+                DeviceError::Timeout(format!("{}: timed out while trying to reconnect", name))
             })
             .await??;
 
@@ -133,11 +376,55 @@ impl Connection {
         self.peripheral
             .subscribe(&notification_characteristic)
             .await?;
-        println!("{}: Reconnected in {:?}", name, timer.elapsed());
+        info!("Reconnected in {:?}", timer.elapsed());
         Ok(())
     }
 }
 
+impl Ronin {
+    /// The pan, tilt, and roll to send, with the axes the gimbal isn't allowed to move zeroed.
+    fn ptr_axes(&self, command: super::Command) -> (f64, f64, f64) {
+        let axis = |capability, option, value: f64| {
+            if !self.capabilities.contains(&capability) {
+                0.0
+            } else if self.options.contains(&option) {
+                -value
+            } else {
+                value
+            }
+        };
+        (
+            axis(Capability::Ptr, RoninOption::ReversePan, command.pan.get()),
+            axis(
+                Capability::Ptr,
+                RoninOption::ReverseTilt,
+                command.tilt.get(),
+            ),
+            axis(
+                Capability::Roll,
+                RoninOption::ReverseRoll,
+                command.roll.get(),
+            ),
+        )
+    }
+
+    /// The angles to move to, with the axes the gimbal isn't allowed to move left out.
+    fn target_angles(&self, target: super::MoveTo) -> (Option<f64>, Option<f64>, Option<f64>) {
+        let axis = |capability, option, angle: Option<super::AngleDeg>| {
+            let angle = angle.filter(|_| self.capabilities.contains(&capability))?;
+            match self.options.contains(&option) {
+                true => Some(-angle.get()),
+                false => Some(angle.get()),
+            }
+        };
+        (
+            axis(Capability::Ptr, RoninOption::ReversePan, target.pan),
+            axis(Capability::Ptr, RoninOption::ReverseTilt, target.tilt),
+            axis(Capability::Roll, RoninOption::ReverseRoll, target.roll),
+        )
+    }
+}
+
 impl std::fmt::Display for Ronin {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Ronin[{}]", self.name)
@@ -150,116 +437,218 @@ impl super::Device for Ronin {
         self.id.clone()
     }
 
-    async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
-        let name = format!("{}", self);
-        println!("{}: Connecting", name);
-        let (current_zoom_tx, current_zoom_rx) = watch::channel::<u16>(0);
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let (current_zoom_tx, current_zoom_rx) = watch::channel::<Option<u16>>(None);
         let (zoom_speed_tx, zoom_speed_rx) = watch::channel::<f64>(0.0);
         let (zoom_movement_tx, zoom_movement_rx) = watch::channel::<Instant>(Instant::now());
+        let (attitude_tx, attitude_rx) = watch::channel::<Option<Attitude>>(None);
 
-        let peripheral = find_peripheral(&self.adapter, &self.name).await?;
+        let peripheral = super::ble::find_peripheral(&self.adapter, &self.name).await?;
         peripheral.connect().await?;
         let cmd_characteristic = Arc::new(Mutex::new(
             get_characteristic(&peripheral, COMMAND_UUID).await?,
         ));
         let notif_characteristic = get_characteristic(&peripheral, NOTIFICATION_UUID).await?;
         peripheral.subscribe(&notif_characteristic).await?;
-        let event_task = create_event_task(peripheral.clone(), current_zoom_tx, zoom_movement_tx);
+        let event_task = create_event_task(
+            peripheral.clone(),
+            self.model,
+            current_zoom_tx,
+            zoom_movement_tx,
+            attitude_tx,
+        );
 
         let zoom_task = create_zoom_task(
-            &name,
             peripheral.clone(),
             cmd_characteristic.clone(),
             self.next_seq.clone(),
-            current_zoom_rx,
+            current_zoom_rx.clone(),
             zoom_movement_rx,
             zoom_speed_rx,
         );
 
+        let attitude_task = uses_rsdk(self.model).then(|| {
+            create_attitude_task(
+                peripheral.clone(),
+                cmd_characteristic.clone(),
+                self.next_seq.clone(),
+            )
+        });
+
         self.connection = Some(Connection {
             peripheral,
             characteristic: cmd_characteristic,
             _event_task: event_task,
             _zoom_task: zoom_task,
             zoom_speed: zoom_speed_tx,
+            current_zoom: current_zoom_rx,
+            attitude: attitude_rx,
+            attitude_task,
         });
-        println!("{}: Connected", self);
+        info!("Connected");
         Ok(())
     }
 
-    async fn disconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
         match &self.connection {
             None => {
-                println!("{}: Already disconnected", self);
+                debug!("Already disconnected");
             }
             Some(c) => {
-                println!("{}: Disconnecting", self);
+                info!("Disconnecting");
                 c.peripheral.disconnect().await?;
                 self.connection = None;
-                println!("{}: Disconnected", self);
+                info!("Disconnected");
             }
         }
         Ok(())
     }
 
-    async fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
         self.disconnect().await?;
         self.connect().await?;
         Ok(())
     }
 
+    async fn repair(&mut self) -> Result<(), DeviceError> {
+        let connection = self.connection.take();
+        let connected = connection.as_ref().map(|c| &c.peripheral);
+        super::ble::repair(&self.adapter, &self.name, connected).await?;
+        drop(connection);
+        self.connect().await
+    }
+
+    fn supports_repair(&self) -> bool {
+        true
+    }
+
+    fn position(&self) -> Option<super::Position> {
+        let c = self.connection.as_ref()?;
+        let zoom = c.current_zoom.borrow().map(|level| {
+            let zoom = (level - ZOOM_MIN) as f64 / (ZOOM_MAX - ZOOM_MIN) as f64;
+            super::ZoomRatio::new(match self.options.contains(&RoninOption::ReverseZoom) {
+                true => 1.0 - zoom,
+                false => zoom,
+            })
+        });
+        let attitude = *c.attitude.borrow();
+        if zoom.is_none() && attitude.is_none() {
+            return None;
+        }
+        // Reversed axes are reported the other way around too, so that positions still
+        // increase in the direction positive commands move them
+        let angle = |option, value: f64| {
+            super::AngleDeg::new(match self.options.contains(&option) {
+                true => -value,
+                false => value,
+            })
+        };
+        Some(super::Position {
+            pan: attitude.map(|(pan, _, _)| angle(RoninOption::ReversePan, pan)),
+            tilt: attitude.map(|(_, tilt, _)| angle(RoninOption::ReverseTilt, tilt)),
+            roll: attitude.map(|(_, _, roll)| angle(RoninOption::ReverseRoll, roll)),
+            zoom,
+        })
+    }
+
+    async fn move_to(&mut self, target: super::MoveTo) -> Result<(), DeviceError> {
+        let name = format!("{}", self);
+        let angles = self.target_angles(target);
+        let Some(c) = &mut self.connection else {
+            return Err(DeviceError::NotConnected);
+        };
+        c.try_resume_connection(&name).await?;
+        let content =
+            create_move_to_packet(self.model, get_seq(&self.next_seq), angles, target.duration);
+        debug!(packet = %hex::encode(&content), "Moving to {:?}", target);
+        let cmd_characteristic = c.characteristic.lock().unwrap().clone();
+        write_packet(&c.peripheral, &cmd_characteristic, &content).await?;
+        Ok(())
+    }
+
+    async fn poll_telemetry(&mut self) -> Result<Map<String, Value>, DeviceError> {
+        match &self.connection {
+            None => Ok(Map::new()),
+            Some(c) => super::ble::link_telemetry(&c.peripheral).await,
+        }
+    }
+
+    fn uses_bluetooth(&self) -> bool {
+        true
+    }
+
+    fn set_adapter(&mut self, adapter: &Adapter) {
+        self.adapter = adapter.clone();
+    }
+
     fn is_connected(&self) -> bool {
         self.connection.is_some()
     }
 
-    async fn send_command(&mut self, command: super::Command) -> Result<(), Box<dyn Error>> {
+    async fn check_connection(&mut self) -> bool {
+        let name = format!("{}", self);
+        match &mut self.connection {
+            None => false,
+            Some(c) => c.try_resume_connection(&name).await.is_ok(),
+        }
+    }
+
+    async fn prepare_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
         let name = format!("{}", self);
-        println!("{}: Received command {:?}", name, command);
+        let (pan, tilt, roll) = self.ptr_axes(command);
+        if pan == 0.0 && tilt == 0.0 && roll == 0.0 {
+            return Ok(());
+        }
+        if let Some(c) = &mut self.connection {
+            c.try_resume_connection(&name).await?;
+            let content =
+                create_motion_packet(self.model, get_seq(&self.next_seq), pan, tilt, roll);
+            self.prepared = Some((command, content));
+        }
+        Ok(())
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        let name = format!("{}", self);
+        debug!("Received command {:?}", command);
+        let (pan, tilt, roll) = self.ptr_axes(command);
+        let prepared = self
+            .prepared
+            .take()
+            .filter(|(prepared, _)| *prepared == command)
+            .map(|(_, packets)| packets);
         match &mut self.connection {
             None => {
-                println!("{}: Not connected", name);
+                debug!("Not connected");
             }
             Some(ref mut c) => {
-                let pan = if self.options.contains(&RoninOption::ReversePan) {
-                    -command.pan
-                } else {
-                    command.pan
-                };
-                let tilt = if self.options.contains(&RoninOption::ReverseTilt) {
-                    -command.tilt
-                } else {
-                    command.tilt
-                };
-                let roll = if self.options.contains(&RoninOption::ReverseRoll) {
-                    -command.roll
-                } else {
-                    command.roll
-                };
                 let zoom = if self.options.contains(&RoninOption::ReverseZoom) {
-                    -command.zoom
+                    -command.zoom.get()
                 } else {
-                    command.zoom
+                    command.zoom.get()
                 };
 
-                let send_ptr = self.capabilities.contains(&Capability::Ptr)
-                    && (pan != 0.0 || tilt != 0.0 || roll != 0.0);
+                let send_ptr = pan != 0.0 || tilt != 0.0 || roll != 0.0;
                 let send_zoom = self.capabilities.contains(&Capability::Zoom)
                     && (zoom != 0.0 || *c.zoom_speed.borrow() != 0.0);
                 if !send_ptr && !send_zoom {
                     return Ok(());
                 }
 
-                c.try_resume_connection(&name).await?;
+                if prepared.is_none() {
+                    c.try_resume_connection(&name).await?;
+                }
 
                 if send_ptr {
-                    let content = create_packet(get_seq(&self.next_seq), pan, tilt, roll);
-                    print!("{}: Sending PTR command {}", name, hex::encode(&content));
+                    let content = prepared.unwrap_or_else(|| {
+                        create_motion_packet(self.model, get_seq(&self.next_seq), pan, tilt, roll)
+                    });
+                    debug!(packet = %hex::encode(&content), "Sending PTR command");
                     let cmd_characteristic = c.characteristic.lock().unwrap().clone();
-                    c.peripheral
-                        .write(&cmd_characteristic, &content, WriteType::WithoutResponse)
+                    write_packet(&c.peripheral, &cmd_characteristic, &content)
                         .await
                         .unwrap();
-                    println!(" ...sent");
                 }
 
                 if send_zoom {
@@ -271,33 +660,10 @@ impl super::Device for Ronin {
     }
 }
 
-async fn find_peripheral(adapter: &Adapter, name: &str) -> Result<Peripheral, Box<dyn Error>> {
-    adapter.start_scan(ScanFilter::default()).await?;
-
-    for _ in 0..10 {
-        tokio::time::sleep(Duration::from_millis(500)).await;
-        let peripherals = adapter.peripherals().await?;
-        for p in peripherals {
-            if p.properties()
-                .await?
-                .and_then(|p| p.local_name)
-                .map(|n| n == name)
-                .unwrap_or(false)
-            {
-                adapter.stop_scan().await?;
-                return Ok(p);
-            }
-        }
-    }
-
-    adapter.stop_scan().await?;
-    Err(format!("unable to find peripheral {}", name).into())
-}
-
 async fn get_characteristic(
     peripheral: &Peripheral,
     uuid: uuid::Uuid,
-) -> Result<Characteristic, Box<dyn Error>> {
+) -> Result<Characteristic, DeviceError> {
     peripheral.discover_services().await?;
     match peripheral.characteristics().iter().find(|c| c.uuid == uuid) {
         None => Err("characteristic not found".into()),
@@ -307,18 +673,31 @@ async fn get_characteristic(
 
 fn create_event_task(
     peripheral: Peripheral,
-    current_zoom_tx: watch::Sender<u16>,
+    model: RoninModel,
+    current_zoom_tx: watch::Sender<Option<u16>>,
     zoom_movement_tx: watch::Sender<Instant>,
+    attitude_tx: watch::Sender<Option<Attitude>>,
 ) -> tokio::task::JoinHandle<()> {
-    tokio::spawn(async move {
-        let mut stream = peripheral.notifications().await.unwrap();
-        let mut last_zoom: Option<u16> = None;
-        loop {
-            let val = stream.next().await;
-            if let Some(v) = val {
-                if v.value.len() > 6 && v.value[0..6] == [0x55, 0x1c, 0x04, 0x1b, 0xdf, 0x02] {
-                    let zoom_level = u16::from_le_bytes([v.value[14], v.value[15]]);
-                    current_zoom_tx.send_replace(zoom_level);
+    tokio::spawn(
+        async move {
+            let mut stream = peripheral.notifications().await.unwrap();
+            let mut last_zoom: Option<u16> = None;
+            let mut reassembler = Reassembler::default();
+            let mut rsdk_reassembler = rsdk::Reassembler::default();
+            while let Some(notification) = stream.next().await {
+                if uses_rsdk(model) {
+                    let frames = rsdk_reassembler.push(&notification.value);
+                    if let Some(attitude) = frames.iter().rev().find_map(rsdk_attitude) {
+                        attitude_tx.send_replace(Some(attitude));
+                    }
+                    continue;
+                }
+                let packets = reassembler.push(&notification.value);
+                if let Some(attitude) = packets.iter().rev().find_map(duml_attitude) {
+                    attitude_tx.send_replace(Some(attitude));
+                }
+                for zoom_level in packets.iter().filter_map(zoom_level) {
+                    current_zoom_tx.send_replace(Some(zoom_level));
                     if last_zoom.is_some_and(|z| z != zoom_level) {
                         zoom_movement_tx.send_replace(Instant::now());
                     }
@@ -326,20 +705,39 @@ fn create_event_task(
                 }
             }
         }
-    })
+        .in_current_span(),
+    )
+}
+
+/// Asks the gimbal for its attitude until the connection is dropped, for R SDK gimbals.
+fn create_attitude_task(
+    peripheral: Peripheral,
+    cmd_characteristic: Arc<Mutex<Characteristic>>,
+    next_seq: watch::Sender<u16>,
+) -> tokio::task::JoinHandle<Result<(), DeviceError>> {
+    tokio::spawn(
+        async move {
+            let mut interval = tokio::time::interval(ATTITUDE_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let content = create_attitude_query(get_seq(&next_seq));
+                let cmd_characteristic = cmd_characteristic.lock().unwrap().clone();
+                write_packet(&peripheral, &cmd_characteristic, &content).await?;
+            }
+        }
+        .inspect_err(move |e| info!("Ending attitude task: {:?}", e))
+        .in_current_span(),
+    )
 }
 
 fn create_zoom_task(
-    name: &str,
     peripheral: Peripheral,
     cmd_characteristic: Arc<Mutex<Characteristic>>,
     next_seq: watch::Sender<u16>,
-    current_zoom_rx: watch::Receiver<u16>,
+    current_zoom_rx: watch::Receiver<Option<u16>>,
     zoom_movement_rx: watch::Receiver<Instant>,
     mut zoom_speed_rx: watch::Receiver<f64>,
-) -> tokio::task::JoinHandle<Result<(), Box<dyn Error + Send + Sync>>> {
-    let name = name.to_owned();
-    let err_name = name.to_owned();
+) -> tokio::task::JoinHandle<Result<(), DeviceError>> {
     tokio::spawn(
         async move {
             loop {
@@ -351,15 +749,15 @@ fn create_zoom_task(
                 let mut target_zoom = -1;
                 while speed != 0.0 {
                     let increment = scale_zoom_value(speed);
-                    let curr_zoom = *current_zoom_rx.borrow();
+                    let curr_zoom = current_zoom_rx.borrow().unwrap_or(ZOOM_MIN);
                     if prev_speed == 0.0
                         || prev_speed.is_sign_positive() != speed.is_sign_positive()
                     {
                         let initial_increment =
                             increment.signum() * increment.abs().max(ZOOM_MIN_INITIAL_INCREMENT);
-                        println!(
-                            "{}: Starting zoom. Step: {}, Initial step: {}, Current zoom level: {}",
-                            name, increment, initial_increment, curr_zoom
+                        info!(
+                            "Starting zoom. Step: {}, Initial step: {}, Current zoom level: {}",
+                            increment, initial_increment, curr_zoom
                         );
                         target_zoom = curr_zoom as i32 + initial_increment;
                     } else if zoom_movement_rx.borrow().elapsed() < Duration::from_millis(200) {
@@ -373,42 +771,34 @@ fn create_zoom_task(
                     let at_max_endpoint = clamped_target_zoom >= curr_zoom
                         && curr_zoom > ZOOM_MAX - ZOOM_ENDPOINT_TOLERANCE;
                     if !(at_min_endpoint || at_max_endpoint) {
-                        let content = add_checksum(
-                            &[
-                                hex::decode("551204c702df").unwrap(),
-                                get_seq(&next_seq).to_le_bytes().to_vec(),
-                                hex::decode("00042f010002").unwrap(),
-                                clamped_target_zoom.to_le_bytes().to_vec(),
-                            ]
-                            .concat(),
-                        );
+                        let content = create_zoom_packet(get_seq(&next_seq), clamped_target_zoom);
 
                         let cmd_characteristic = cmd_characteristic.lock().unwrap().clone();
-                        peripheral
-                            .write(&cmd_characteristic, &content, WriteType::WithoutResponse)
-                            .await?;
+                        write_packet(&peripheral, &cmd_characteristic, &content).await?;
                     }
 
                     tokio::time::sleep(Duration::from_millis(50)).await;
                     prev_speed = speed;
                     speed = *zoom_speed_rx.borrow_and_update();
                 }
-                println!(
-                    "{}: Ending zoom. Current zoom level: {}",
-                    name,
-                    *current_zoom_rx.borrow(),
+                info!(
+                    "Ending zoom. Current zoom level: {}",
+                    current_zoom_rx.borrow().unwrap_or(ZOOM_MIN)
                 );
             }
         }
-        .inspect_err(move |e| println!("{}: Ending zoom task: {:?}", err_name, e)),
+        .inspect_err(move |e| info!("Ending zoom task: {:?}", e))
+        .in_current_span(),
     )
 }
 
 pub fn create(id: &str, adapter: Adapter, config: &RoninConfig) -> Ronin {
     let (next_seq, _) = watch::channel(0);
+    let model = config.model.unwrap_or_default();
     Ronin {
         id: id.to_owned(),
         name: config.name.to_owned(),
+        model,
         next_seq,
         adapter,
         connection: None,
@@ -416,23 +806,105 @@ pub fn create(id: &str, adapter: Adapter, config: &RoninConfig) -> Ronin {
             .capabilities
             .clone()
             .map(HashSet::from_iter)
-            .unwrap_or_else(all_capabilities),
+            .unwrap_or_else(|| default_capabilities(model)),
         options: config
             .options
             .clone()
             .map(HashSet::from_iter)
             .unwrap_or_default(),
+        prepared: None,
     }
 }
 
 #[test]
-fn test_checksum() {
-    let bytes = vec![
-        0x55, 0x11, 0x04, 0x92, 0x02, 0xdf, 0x20, 0x02, 0x00, 0x04, 0x2f, 0x0b, 0x00, 0x01, 0xc5,
-    ];
-    let with_checksum = add_checksum(&bytes);
+fn test_zoom_packet() {
+    let expected =
+        super::duml::add_checksum(&hex::decode("551204c702df200200042f010002c40b").unwrap());
+    assert_eq!(create_zoom_packet(0x0220, 0x0bc4), expected);
+}
+
+#[test]
+fn test_speed_frame() {
+    let frame = create_speed_frame(7, 1.0, -0.5, 0.0);
+    assert_eq!(frame[0], 0xaa);
+    assert_eq!(&frame[12..14], &[0x0e, 0x0c]);
+    // Yaw, roll, and pitch speeds, then the control flag
+    assert_eq!(hex::encode(&frame[14..21]), "080700003efe80");
+}
+
+#[test]
+fn test_angle_packets() {
+    let duration = Duration::from_secs(2);
+    let packet = create_angle_packet(7, Some(90.0), Some(-30.0), None, duration);
+    assert_eq!(&packet[8..11], &[0x40, 0x04, 0x0a]);
+    // Pitch, roll, and yaw in tenths of a degree, then the flags and duration
+    assert_eq!(hex::encode(&packet[11..19]), "d4fe000084030514");
+
+    let frame = create_position_frame(7, Some(90.0), Some(-30.0), None, duration);
+    assert_eq!(&frame[12..14], &[0x0e, 0x00]);
+    assert_eq!(hex::encode(&frame[14..22]), "84030000d4fe0514");
+}
+
+#[test]
+fn test_model_packets() {
+    let duml = create_motion_packet(RoninModel::OsmoMobile, 1, 1.0, 0.0, 0.0);
+    assert_eq!(duml, create_packet(1, 1.0, 0.0, 0.0));
+    assert_eq!(duml[0], 0x55);
+    let angle = (Some(90.0), None, None);
+    let duration = Duration::from_secs(1);
+    assert_eq!(
+        create_move_to_packet(RoninModel::OsmoMobile, 1, angle, duration)[0],
+        0x55
+    );
     assert_eq!(
-        hex::encode(with_checksum),
-        "5511049202df200200042f0b0001c5f5a7"
+        create_move_to_packet(RoninModel::Rs3Mini, 1, angle, duration)[0],
+        0xaa
     );
+    assert!(!default_capabilities(RoninModel::OsmoMobile).contains(&Capability::Zoom));
+}
+
+#[test]
+fn test_attitude() {
+    let packet = Packet {
+        sender: GIMBAL,
+        receiver: APP,
+        seq: 0,
+        cmd_type: 0x00,
+        cmd_set: 0x04,
+        cmd_id: 0x05,
+        payload: hex::decode("d4fe0a00840300").unwrap(),
+    };
+    assert_eq!(duml_attitude(&packet), Some((90.0, -30.0, 1.0)));
+
+    let frame = Frame {
+        cmd_type: rsdk::REPLY,
+        seq: 0,
+        cmd_set: 0x0e,
+        cmd_id: 0x02,
+        payload: hex::decode("000184030a00d4fe").unwrap(),
+    };
+    assert_eq!(rsdk_attitude(&frame), Some((90.0, -30.0, 1.0)));
+    assert_eq!(
+        rsdk_attitude(&Frame {
+            payload: hex::decode("010184030a00d4fe").unwrap(),
+            ..frame
+        }),
+        None
+    );
+}
+
+#[test]
+fn test_packet_golden() {
+    for (inputs, expected) in super::golden_cases(include_str!("fixtures/ronin_ptr.txt")) {
+        let [seq, pan, tilt, roll] = inputs[..] else {
+            panic!("malformed fixture line: {:?}", inputs);
+        };
+        let packet = create_packet(
+            seq.parse().unwrap(),
+            pan.parse().unwrap(),
+            tilt.parse().unwrap(),
+            roll.parse().unwrap(),
+        );
+        assert_eq!(hex::encode(packet), expected, "inputs: {:?}", inputs);
+    }
 }
@@ -0,0 +1,169 @@
+//! DJI's R SDK frame format, used by the RS 2 and RS 3 Mini in place of DUML.
+//!
+//! Each frame starts with `0xaa`, a 10-bit total length, the command type, an encryption flag,
+//! three reserved bytes, and a sequence number, followed by a CRC-16 of those, then the command set
+//! and ID, the payload, and a CRC-32 of everything before it.
+
+use tracing::warn;
+
+use super::DeviceError;
+
+const START: u8 = 0xaa;
+const HEADER_LEN: usize = 10;
+/// Everything besides the payload: the header, its CRC-16, the command, and the CRC-32.
+const OVERHEAD: usize = HEADER_LEN + 2 + 2 + 4;
+const MAX_LEN: usize = 0x3ff;
+const HEADER_ALG: crc::Algorithm<u16> = crc::Algorithm {
+    width: 16,
+    poly: 0x8005,
+    init: 0x3aa3,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+    check: 0x0000,
+    residue: 0x0000,
+};
+const HEADER_CRC: crc::Crc<u16> = crc::Crc::<u16>::new(&HEADER_ALG);
+const FRAME_ALG: crc::Algorithm<u32> = crc::Algorithm {
+    width: 32,
+    poly: 0x04c11db7,
+    init: 0x3aa3,
+    refin: true,
+    refout: true,
+    xorout: 0x00000000,
+    check: 0x00000000,
+    residue: 0x00000000,
+};
+const FRAME_CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&FRAME_ALG);
+
+/// A command that the gimbal shouldn't reply to.
+pub const NO_REPLY: u8 = 0x00;
+/// A command that the gimbal has to reply to.
+pub const NEEDS_REPLY: u8 = 0x03;
+/// Set in the command type of the gimbal's replies.
+pub const REPLY: u8 = 0x20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub cmd_type: u8,
+    pub seq: u16,
+    pub cmd_set: u8,
+    pub cmd_id: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.payload.len() + OVERHEAD;
+        assert!(len <= MAX_LEN, "payload too long for one frame");
+        let mut frame = vec![START];
+        frame.extend((len as u16).to_le_bytes());
+        frame.extend([self.cmd_type, 0x00, 0x00, 0x00, 0x00]);
+        frame.extend(self.seq.to_le_bytes());
+        frame.extend(HEADER_CRC.checksum(&frame).to_le_bytes());
+        frame.extend([self.cmd_set, self.cmd_id]);
+        frame.extend(&self.payload);
+        frame.extend(FRAME_CRC.checksum(&frame).to_le_bytes());
+        frame
+    }
+
+    fn decode(frame: &[u8]) -> Result<Frame, DeviceError> {
+        let (body, checksum) = frame.split_at(frame.len() - 4);
+        if FRAME_CRC.checksum(body).to_le_bytes() != checksum {
+            return Err("bad frame checksum".into());
+        }
+        Ok(Frame {
+            cmd_type: body[3],
+            seq: u16::from_le_bytes([body[8], body[9]]),
+            cmd_set: body[12],
+            cmd_id: body[13],
+            payload: body[14..].to_vec(),
+        })
+    }
+}
+
+/// The length of the frame starting at the beginning of `header`, if it's a valid header.
+fn frame_len(header: &[u8]) -> Option<usize> {
+    let crc = HEADER_CRC.checksum(&header[..HEADER_LEN]).to_le_bytes();
+    if header[0] != START || header[HEADER_LEN..HEADER_LEN + 2] != crc {
+        return None;
+    }
+    let len = u16::from_le_bytes([header[1], header[2]]) as usize & MAX_LEN;
+    (len >= OVERHEAD).then_some(len)
+}
+
+/// Joins frames back together from notifications, like DUML's reassembler.
+#[derive(Default)]
+pub struct Reassembler {
+    buffer: Vec<u8>,
+}
+
+impl Reassembler {
+    /// Adds a notification's bytes and returns the frames they completed. Bytes that can't be
+    /// part of a valid frame are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Frame> {
+        self.buffer.extend_from_slice(bytes);
+        let mut frames = vec![];
+        while self.buffer.len() >= HEADER_LEN + 2 {
+            let Some(len) = frame_len(&self.buffer) else {
+                let skip = self.buffer[1..]
+                    .iter()
+                    .position(|&b| b == START)
+                    .map_or(self.buffer.len(), |i| i + 1);
+                self.buffer.drain(..skip);
+                continue;
+            };
+            if self.buffer.len() < len {
+                break;
+            }
+            let frame: Vec<u8> = self.buffer.drain(..len).collect();
+            match Frame::decode(&frame) {
+                Ok(f) => frames.push(f),
+                Err(e) => warn!(frame = %hex::encode(&frame), "Dropping frame: {}", e),
+            }
+        }
+        frames
+    }
+}
+
+#[test]
+fn test_frame() {
+    let frame = Frame {
+        cmd_type: NO_REPLY,
+        seq: 0x1234,
+        cmd_set: 0x0e,
+        cmd_id: 0x0c,
+        payload: vec![0x01, 0x02, 0x03],
+    }
+    .encode();
+    assert_eq!(frame.len(), 21);
+    assert_eq!(&frame[..10], &[0xaa, 21, 0, 0, 0, 0, 0, 0, 0x34, 0x12]);
+    assert_eq!(
+        &frame[10..12],
+        &HEADER_CRC.checksum(&frame[..10]).to_le_bytes()
+    );
+    assert_eq!(&frame[12..17], &[0x0e, 0x0c, 0x01, 0x02, 0x03]);
+    assert_eq!(
+        &frame[17..],
+        &FRAME_CRC.checksum(&frame[..17]).to_le_bytes()
+    );
+}
+
+#[test]
+fn test_reassembly() {
+    let frame = Frame {
+        cmd_type: REPLY,
+        seq: 7,
+        cmd_set: 0x0e,
+        cmd_id: 0x02,
+        payload: vec![0x00; 8],
+    };
+    let stream = [&[0x00, 0xaa, 0x01][..], &frame.encode(), &frame.encode()].concat();
+    let mut reassembler = Reassembler::default();
+    let mut received = vec![];
+    for chunk in stream.chunks(20) {
+        received.extend(reassembler.push(chunk));
+    }
+    assert_eq!(received, vec![frame.clone(), frame]);
+    assert!(reassembler.buffer.is_empty());
+}
@@ -0,0 +1,485 @@
+use std::{
+    collections::HashSet,
+    io::Write as _,
+    net::TcpStream,
+    path::PathBuf,
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use btleplug::{
+    api::{Characteristic, Peripheral as _, WriteType},
+    platform::{Adapter, Peripheral},
+};
+use rhai::{Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::Value;
+use tokio::{runtime::Handle, sync::oneshot};
+use tracing::{debug, info, Span};
+
+use super::{DeviceError, Velocity};
+use crate::config::{all_capabilities, Capability, ScriptConfig};
+
+/// How long transport reads wait for data before giving up and returning what they have.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Devices driven by a Rhai script, so that users can add hardware without a built-in backend.
+/// The script defines `connect`, `send_command`, and optionally `disconnect` functions, and can
+/// keep state between them in `this`.
+///
+/// Scripts run on their own thread, since they block on I/O.
+pub struct Script {
+    id: String,
+    path: PathBuf,
+    settings: serde_json::Map<String, Value>,
+    capabilities: HashSet<Capability>,
+    adapter: Adapter,
+    runner: Option<mpsc::Sender<Call>>,
+}
+
+enum Hook {
+    Connect,
+    SendCommand(super::Command),
+    Disconnect,
+}
+
+struct Call {
+    hook: Hook,
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+impl std::fmt::Display for Script {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Script[{}]", self.path.display())
+    }
+}
+
+impl Script {
+    async fn call(&mut self, hook: Hook) -> Result<(), DeviceError> {
+        let Some(runner) = &self.runner else {
+            return Err(DeviceError::NotConnected);
+        };
+        let (reply, result) = oneshot::channel();
+        if runner.send(Call { hook, reply }).is_err() {
+            self.runner = None;
+            return Err(DeviceError::Transport("script stopped".to_owned()));
+        }
+        match result.await {
+            Ok(result) => Ok(result?),
+            Err(_) => {
+                self.runner = None;
+                Err(DeviceError::Transport("script stopped".to_owned()))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl super::Device for Script {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let span = Span::current();
+        let (tx, rx) = mpsc::channel();
+        let path = self.path.clone();
+        let settings = serde_json::to_string(&self.settings)?;
+        let adapter = self.adapter.clone();
+        let handle = Handle::current();
+        std::thread::Builder::new()
+            .name(format!("script-{}", self.id))
+            .spawn(move || run_script(span, path, settings, adapter, handle, rx))?;
+        self.runner = Some(tx);
+        if let Err(e) = self.call(Hook::Connect).await {
+            self.runner = None;
+            return Err(e);
+        }
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        if self.runner.is_none() {
+            debug!("Already disconnected");
+            return Ok(());
+        }
+        let result = self.call(Hook::Disconnect).await;
+        // Dropping the channel ends the script's thread
+        self.runner = None;
+        info!("Disconnected");
+        result
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.runner.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.runner.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+        let ptr = self.capabilities.contains(&Capability::Ptr);
+        let roll = self.capabilities.contains(&Capability::Roll);
+        let zoom = self.capabilities.contains(&Capability::Zoom);
+        let focus = self.capabilities.contains(&Capability::Focus);
+        let autofocus = self.capabilities.contains(&Capability::Autofocus);
+        let record = self.capabilities.contains(&Capability::Record);
+        let iris = self.capabilities.contains(&Capability::Iris);
+        let shutter = self.capabilities.contains(&Capability::Shutter);
+        let axis = |enabled: bool, velocity: Velocity| match enabled {
+            true => velocity,
+            false => Velocity::STOP,
+        };
+        let command = super::Command {
+            pan: axis(ptr, command.pan),
+            tilt: axis(ptr, command.tilt),
+            roll: axis(roll, command.roll),
+            zoom: axis(zoom, command.zoom),
+            focus: axis(focus, command.focus),
+            iris: axis(iris, command.iris),
+            autofocus: autofocus && command.autofocus,
+            record: command.record.filter(|_| record),
+            shutter: shutter && command.shutter,
+            focus_point: command.focus_point.filter(|_| autofocus),
+        };
+        self.call(Hook::SendCommand(command)).await
+    }
+}
+
+/// Compiles the script and runs hooks as they come in, until the device disconnects. Runs in
+/// the device's span, so that what the script prints is logged as coming from the device.
+fn run_script(
+    span: Span,
+    path: PathBuf,
+    settings: String,
+    adapter: Adapter,
+    handle: Handle,
+    calls: mpsc::Receiver<Call>,
+) {
+    let _span = span.enter();
+    let engine = create_engine(adapter, handle);
+    let ast = engine.compile_file(path);
+    let mut this = Dynamic::from_map(Map::new());
+    let mut scope = Scope::new();
+    for call in calls {
+        let result = match &ast {
+            Err(e) => Err(format!("couldn't load script: {}", e)),
+            Ok(ast) => run_hook(&engine, ast, &mut scope, &mut this, &settings, call.hook),
+        };
+        _ = call.reply.send(result);
+    }
+}
+
+fn run_hook(
+    engine: &Engine,
+    ast: &AST,
+    scope: &mut Scope,
+    this: &mut Dynamic,
+    settings: &str,
+    hook: Hook,
+) -> Result<(), String> {
+    let (function, args): (&str, Vec<Dynamic>) = match hook {
+        Hook::Connect => {
+            // Start from a clean slate, so nothing from a previous connection lingers
+            let settings = engine
+                .parse_json(settings, true)
+                .map_err(|e| e.to_string())?;
+            *this = Dynamic::from_map(Map::from_iter([("settings".into(), settings.into())]));
+            ("connect", vec![])
+        }
+        Hook::SendCommand(command) => ("send_command", vec![command_map(command).into()]),
+        Hook::Disconnect => {
+            if !ast.iter_functions().any(|f| f.name == "disconnect") {
+                return Ok(());
+            }
+            ("disconnect", vec![])
+        }
+    };
+    let options = CallFnOptions::new().bind_this_ptr(this);
+    engine
+        .call_fn_with_options::<Dynamic>(options, scope, ast, function, args)
+        .map(|_| ())
+        .map_err(|e| format!("{} failed: {}", function, e))
+}
+
+fn command_map(command: super::Command) -> Map {
+    Map::from_iter([
+        ("pan".into(), command.pan.get().into()),
+        ("tilt".into(), command.tilt.get().into()),
+        ("roll".into(), command.roll.get().into()),
+        ("zoom".into(), command.zoom.get().into()),
+        ("focus".into(), command.focus.get().into()),
+        ("iris".into(), command.iris.get().into()),
+        ("autofocus".into(), command.autofocus.into()),
+        (
+            "record".into(),
+            command.record.map_or(Dynamic::UNIT, Dynamic::from),
+        ),
+        ("shutter".into(), command.shutter.into()),
+        (
+            "focusPoint".into(),
+            command.focus_point.map_or(Dynamic::UNIT, |p| {
+                Dynamic::from_map(Map::from_iter([
+                    ("x".into(), p.x.into()),
+                    ("y".into(), p.y.into()),
+                ]))
+            }),
+        ),
+    ])
+}
+
+/// Sets up the functions available to scripts: transports, `sleep`, and hex conversion.
+fn create_engine(adapter: Adapter, handle: Handle) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.on_print(|s| info!("{}", s));
+    engine.on_debug(|s, _, _| debug!("{}", s));
+
+    engine.register_fn("sleep", |ms: i64| {
+        std::thread::sleep(Duration::from_millis(ms.max(0) as u64))
+    });
+    engine.register_fn("hex", |data: Blob| hex::encode(data));
+    engine.register_fn("from_hex", |s: &str| -> ScriptResult<Blob> {
+        hex::decode(s).map_err(|e| e.to_string().into())
+    });
+
+    engine
+        .register_type_with_name::<Tcp>("Tcp")
+        .register_fn("tcp_connect", Tcp::connect)
+        .register_fn("write", Tcp::write)
+        .register_fn("read", Tcp::read);
+    engine
+        .register_type_with_name::<Serial>("Serial")
+        .register_fn("serial_open", Serial::open)
+        .register_fn("write", Serial::write)
+        .register_fn("read", Serial::read);
+
+    let ble_handle = handle.clone();
+    engine
+        .register_type_with_name::<Ble>("Ble")
+        .register_fn("ble_connect", move |name: &str| {
+            Ble::connect(&adapter, &ble_handle, name)
+        })
+        .register_fn("write", Ble::write)
+        .register_fn("write_without_response", Ble::write_without_response)
+        .register_fn("read", Ble::read)
+        .register_fn("disconnect", Ble::disconnect);
+    engine
+}
+
+#[derive(Clone)]
+struct Tcp(Arc<Mutex<TcpStream>>);
+
+impl Tcp {
+    fn connect(address: &str) -> ScriptResult<Tcp> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("couldn't connect to {}: {}", address, e))?;
+        stream.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Ok(Tcp(Arc::new(Mutex::new(stream))))
+    }
+
+    fn write(&mut self, data: Blob) -> ScriptResult<()> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap()
+            .write_all(&data)
+            .map_err(|e| e.to_string())?)
+    }
+
+    fn read(&mut self, max: i64) -> ScriptResult<Blob> {
+        read_available(&mut *self.0.lock().unwrap(), max)
+    }
+}
+
+#[derive(Clone)]
+struct Serial(Arc<Mutex<Box<dyn tokio_serial::SerialPort>>>);
+
+impl Serial {
+    fn open(path: &str, baud_rate: i64) -> ScriptResult<Serial> {
+        let port = tokio_serial::new(path, baud_rate as u32)
+            .timeout(READ_TIMEOUT)
+            .open()
+            .map_err(|e| format!("couldn't open {}: {}", path, e))?;
+        Ok(Serial(Arc::new(Mutex::new(port))))
+    }
+
+    fn write(&mut self, data: Blob) -> ScriptResult<()> {
+        Ok(self
+            .0
+            .lock()
+            .unwrap()
+            .write_all(&data)
+            .map_err(|e| e.to_string())?)
+    }
+
+    fn read(&mut self, max: i64) -> ScriptResult<Blob> {
+        read_available(&mut *self.0.lock().unwrap(), max)
+    }
+}
+
+/// Reads up to `max` bytes, returning an empty blob if nothing arrives in time.
+fn read_available(reader: &mut impl std::io::Read, max: i64) -> ScriptResult<Blob> {
+    let mut buf = vec![0; max.max(0) as usize];
+    match reader.read(&mut buf) {
+        Ok(n) => {
+            buf.truncate(n);
+            Ok(buf)
+        }
+        Err(e)
+            if e.kind() == std::io::ErrorKind::TimedOut
+                || e.kind() == std::io::ErrorKind::WouldBlock =>
+        {
+            Ok(vec![])
+        }
+        Err(e) => Err(e.to_string().into()),
+    }
+}
+
+#[derive(Clone)]
+struct Ble {
+    handle: Handle,
+    peripheral: Peripheral,
+}
+
+impl Ble {
+    fn connect(adapter: &Adapter, handle: &Handle, name: &str) -> ScriptResult<Ble> {
+        let peripheral = handle
+            .block_on(async {
+                let peripheral = super::ble::find_peripheral(adapter, name).await?;
+                peripheral.connect().await?;
+                peripheral.discover_services().await?;
+                Ok::<_, DeviceError>(peripheral)
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(Ble {
+            handle: handle.clone(),
+            peripheral,
+        })
+    }
+
+    fn characteristic(&self, uuid: &str) -> ScriptResult<Characteristic> {
+        let uuid = uuid::Uuid::parse_str(uuid).map_err(|e| e.to_string())?;
+        let characteristic = self
+            .peripheral
+            .characteristics()
+            .into_iter()
+            .find(|c| c.uuid == uuid);
+        Ok(characteristic.ok_or_else(|| format!("characteristic {} not found", uuid))?)
+    }
+
+    fn write_with(&mut self, uuid: &str, data: Blob, write_type: WriteType) -> ScriptResult<()> {
+        let characteristic = self.characteristic(uuid)?;
+        Ok(self
+            .handle
+            .block_on(self.peripheral.write(&characteristic, &data, write_type))
+            .map_err(|e| e.to_string())?)
+    }
+
+    fn write(&mut self, uuid: &str, data: Blob) -> ScriptResult<()> {
+        self.write_with(uuid, data, WriteType::WithResponse)
+    }
+
+    fn write_without_response(&mut self, uuid: &str, data: Blob) -> ScriptResult<()> {
+        self.write_with(uuid, data, WriteType::WithoutResponse)
+    }
+
+    fn read(&mut self, uuid: &str) -> ScriptResult<Blob> {
+        let characteristic = self.characteristic(uuid)?;
+        Ok(self
+            .handle
+            .block_on(self.peripheral.read(&characteristic))
+            .map_err(|e| e.to_string())?)
+    }
+
+    fn disconnect(&mut self) -> ScriptResult<()> {
+        Ok(self
+            .handle
+            .block_on(self.peripheral.disconnect())
+            .map_err(|e| e.to_string())?)
+    }
+}
+
+pub fn create(id: &str, adapter: Adapter, config: &ScriptConfig) -> Script {
+    Script {
+        id: id.to_owned(),
+        path: config.path.clone(),
+        settings: config.settings.clone(),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| HashSet::from_iter(all_capabilities())),
+        adapter,
+        runner: None,
+    }
+}
+
+#[test]
+fn test_run_hook() {
+    let engine = Engine::new();
+    let ast = engine
+        .compile(
+            "fn connect() { this.sent = []; this.address = this.settings.address; }
+             fn send_command(command) { this.sent.push(command.pan); }",
+        )
+        .unwrap();
+    let mut scope = Scope::new();
+    let mut this = Dynamic::UNIT;
+    let settings = r#"{"address": "10.0.0.5:5000"}"#;
+    run_hook(
+        &engine,
+        &ast,
+        &mut scope,
+        &mut this,
+        settings,
+        Hook::Connect,
+    )
+    .unwrap();
+    let command = super::Command {
+        pan: Velocity::new(0.5),
+        ..Default::default()
+    };
+    run_hook(
+        &engine,
+        &ast,
+        &mut scope,
+        &mut this,
+        settings,
+        Hook::SendCommand(command),
+    )
+    .unwrap();
+    // Scripts don't have to define disconnect
+    run_hook(
+        &engine,
+        &ast,
+        &mut scope,
+        &mut this,
+        settings,
+        Hook::Disconnect,
+    )
+    .unwrap();
+
+    let state = this.cast::<Map>();
+    assert_eq!(
+        state["address"].clone().into_string().unwrap(),
+        "10.0.0.5:5000"
+    );
+    assert_eq!(
+        state["sent"].clone().into_array().unwrap()[0].as_float(),
+        Ok(0.5)
+    );
+}
@@ -0,0 +1,217 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt as _;
+use tokio_serial::{SerialPortBuilderExt as _, SerialStream};
+use tracing::{debug, info};
+
+use super::DeviceError;
+use crate::config::{Capability, SerialCommands, SerialConfig};
+
+pub struct Serial {
+    id: String,
+    port: String,
+    baud_rate: u32,
+    format: Format,
+    commands: SerialCommands,
+    capabilities: HashSet<Capability>,
+    connection: Option<SerialStream>,
+    last_command: super::Command,
+}
+
+struct Format {
+    line_ending: String,
+    range: f64,
+    precision: usize,
+}
+
+impl std::fmt::Display for Serial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Serial[{}]", self.port)
+    }
+}
+
+#[async_trait]
+impl super::Device for Serial {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let stream = tokio_serial::new(&self.port, self.baud_rate)
+            .data_bits(tokio_serial::DataBits::Eight)
+            .parity(tokio_serial::Parity::None)
+            .stop_bits(tokio_serial::StopBits::One)
+            .open_native_async()?;
+        self.connection = Some(stream);
+        self.last_command = super::Command::default();
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.connection.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        let lines = build_lines(
+            &self.commands,
+            &self.format,
+            &self.capabilities,
+            &self.last_command,
+            &command,
+        );
+        let Some(stream) = &mut self.connection else {
+            debug!("Not connected");
+            return Ok(());
+        };
+        for line in lines {
+            debug!("Writing {:?}", line);
+            stream.write_all(line.as_bytes()).await?;
+        }
+        self.last_command = command;
+        Ok(())
+    }
+}
+
+/// Renders a line for every axis that changed since the last command.
+fn build_lines(
+    commands: &SerialCommands,
+    format: &Format,
+    capabilities: &HashSet<Capability>,
+    last: &super::Command,
+    next: &super::Command,
+) -> Vec<String> {
+    let axes = [
+        (Capability::Ptr, &commands.pan, last.pan, next.pan),
+        (Capability::Ptr, &commands.tilt, last.tilt, next.tilt),
+        (Capability::Roll, &commands.roll, last.roll, next.roll),
+        (Capability::Zoom, &commands.zoom, last.zoom, next.zoom),
+        (Capability::Focus, &commands.focus, last.focus, next.focus),
+    ];
+    let mut lines = axes
+        .into_iter()
+        .filter(|(capability, _, last, next)| capabilities.contains(capability) && last != next)
+        .filter_map(|(_, template, _, next)| {
+            template.as_ref().map(|t| render(t, next.get(), format))
+        })
+        .collect::<Vec<_>>();
+    if capabilities.contains(&Capability::Autofocus) && next.autofocus && !last.autofocus {
+        if let Some(template) = &commands.autofocus {
+            lines.push(render(template, 0.0, format));
+        }
+    }
+    lines
+}
+
+fn render(template: &str, value: f64, format: &Format) -> String {
+    // Adding zero turns -0 into 0, which would otherwise be formatted with a sign
+    let scaled = value * format.range + 0.0;
+    let precision = format.precision;
+    let line = template
+        .replace("{value}", &format!("{:.*}", precision, scaled))
+        .replace("{abs}", &format!("{:.*}", precision, scaled.abs()))
+        .replace("{sign}", if scaled < 0.0 { "-" } else { "+" });
+    line + format.line_ending.as_str()
+}
+
+/// Only capabilities that have a command template are enabled.
+fn template_capabilities(commands: &SerialCommands) -> HashSet<Capability> {
+    let mut capabilities = HashSet::new();
+    if commands.pan.is_some() || commands.tilt.is_some() {
+        capabilities.insert(Capability::Ptr);
+    }
+    if commands.roll.is_some() {
+        capabilities.insert(Capability::Roll);
+    }
+    if commands.zoom.is_some() {
+        capabilities.insert(Capability::Zoom);
+    }
+    if commands.focus.is_some() {
+        capabilities.insert(Capability::Focus);
+    }
+    if commands.autofocus.is_some() {
+        capabilities.insert(Capability::Autofocus);
+    }
+    capabilities
+}
+
+pub fn create(id: &str, config: &SerialConfig) -> Serial {
+    let commands = config.commands.clone();
+    let capabilities = match &config.capabilities {
+        Some(c) => HashSet::from_iter(c.iter().cloned()),
+        None => template_capabilities(&commands),
+    };
+    Serial {
+        id: id.to_owned(),
+        port: config.port.clone(),
+        baud_rate: config.baud_rate,
+        format: Format {
+            line_ending: config.line_ending.clone(),
+            range: config.range,
+            precision: config.precision,
+        },
+        commands,
+        capabilities,
+        connection: None,
+        last_command: super::Command::default(),
+    }
+}
+
+#[test]
+fn test_build_lines() {
+    let commands = SerialCommands {
+        pan: Some("P{value}".to_owned()),
+        tilt: Some("T{sign}{abs}".to_owned()),
+        roll: Some("R{value}".to_owned()),
+        autofocus: Some("AF".to_owned()),
+        ..Default::default()
+    };
+    let format = Format {
+        line_ending: "\r".to_owned(),
+        range: 63.0,
+        precision: 0,
+    };
+    let capabilities = template_capabilities(&commands);
+    let last = super::Command::default();
+    let next = super::Command {
+        pan: super::Velocity::new(-0.5),
+        tilt: super::Velocity::new(-1.0),
+        roll: super::Velocity::new(0.5),
+        zoom: super::Velocity::new(1.0),
+        autofocus: true,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        build_lines(&commands, &format, &capabilities, &last, &next),
+        vec!["P-32\r", "T-63\r", "R32\r", "AF\r"]
+    );
+    assert_eq!(
+        build_lines(&commands, &format, &capabilities, &next, &next),
+        Vec::<String>::new()
+    );
+
+    // Roll stays put on devices configured without it
+    let locked = HashSet::from([Capability::Ptr, Capability::Autofocus]);
+    assert_eq!(
+        build_lines(&commands, &format, &locked, &last, &next),
+        vec!["P-32\r", "T-63\r", "AF\r"]
+    );
+}
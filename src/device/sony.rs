@@ -0,0 +1,213 @@
+use std::{collections::HashSet, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::{header, Client};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use super::DeviceError;
+use crate::config::{Capability, SonyConfig};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// Cameras that need `startRecMode` take a moment to make the shooting APIs available.
+const REC_MODE_DELAY: Duration = Duration::from_secs(1);
+
+/// Sony cameras controlled through the Camera Remote API over Wi-Fi. The API has no manual focus,
+/// and zooms at a single speed.
+pub struct Sony {
+    id: String,
+    address: String,
+    capabilities: HashSet<Capability>,
+    client: Option<Client>,
+    next_id: u64,
+    /// The direction currently zooming in, if any.
+    zoom: Option<&'static str>,
+    autofocus: bool,
+}
+
+impl std::fmt::Display for Sony {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sony[{}]", self.address)
+    }
+}
+
+impl Sony {
+    /// Calls a method on the camera service and returns its result.
+    async fn call(&mut self, method: &str, params: Value) -> Result<Value, DeviceError> {
+        let Some(client) = &self.client else {
+            return Err(DeviceError::NotConnected);
+        };
+        self.next_id += 1;
+        let request = json!({
+            "method": method,
+            "params": params,
+            "id": self.next_id,
+            "version": "1.0",
+        });
+        let response = client
+            .post(format!("http://{}/sony/camera", self.address))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(request.to_string())
+            .timeout(TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        check_response(method, serde_json::from_str(&response)?)
+    }
+}
+
+/// Unwraps a JSON-RPC response, whose errors are given as `[code, message]`.
+fn check_response(method: &str, mut response: Value) -> Result<Value, DeviceError> {
+    if let Some(error) = response.get("error") {
+        return Err(format!(
+            "camera rejected {}: {} ({})",
+            method,
+            error[1].as_str().unwrap_or("unknown error"),
+            error[0]
+        )
+        .into());
+    }
+    Ok(response["result"].take())
+}
+
+fn zoom_direction(speed: f64) -> Option<&'static str> {
+    match speed {
+        x if x > 0.0 => Some("in"),
+        x if x < 0.0 => Some("out"),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl super::Device for Sony {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        self.client = Some(Client::new());
+        let apis = match self.call("getAvailableApiList", json!([])).await {
+            Ok(apis) => apis,
+            Err(e) => {
+                self.client = None;
+                return Err(e);
+            }
+        };
+        let available = |name: &str| {
+            apis[0]
+                .as_array()
+                .is_some_and(|a| a.iter().any(|api| api == name))
+        };
+        // Older cameras only allow shooting once in remote shooting mode
+        if available("startRecMode") {
+            self.call("startRecMode", json!([])).await?;
+            tokio::time::sleep(REC_MODE_DELAY).await;
+        }
+        let info = self.call("getApplicationInfo", json!([])).await?;
+        info!(
+            "Connected to {} {}",
+            info[0].as_str().unwrap_or("Camera Remote API"),
+            info[1].as_str().unwrap_or("")
+        );
+        self.zoom = None;
+        self.autofocus = false;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.client.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        if self.client.is_none() {
+            debug!("Not connected");
+            return Ok(());
+        }
+
+        let zoom = match self.capabilities.contains(&Capability::Zoom) {
+            true => zoom_direction(command.zoom.get()),
+            false => None,
+        };
+        if zoom != self.zoom {
+            if let Some(previous) = self.zoom {
+                self.call("actZoom", json!([previous, "stop"])).await?;
+                self.zoom = None;
+            }
+            if let Some(direction) = zoom {
+                self.call("actZoom", json!([direction, "start"])).await?;
+                self.zoom = zoom;
+            }
+        }
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus != self.autofocus {
+            let method = match autofocus {
+                true => "actHalfPressShutter",
+                false => "cancelHalfPressShutter",
+            };
+            self.call(method, json!([])).await?;
+            self.autofocus = autofocus;
+        }
+
+        if self.capabilities.contains(&Capability::Shutter) && command.shutter {
+            info!("Taking a photo");
+            self.call("actTakePicture", json!([])).await?;
+        }
+        Ok(())
+    }
+}
+
+pub fn create(id: &str, config: &SonyConfig) -> Sony {
+    Sony {
+        id: id.to_owned(),
+        address: config.address.clone(),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                HashSet::from([Capability::Zoom, Capability::Autofocus, Capability::Shutter])
+            }),
+        client: None,
+        next_id: 0,
+        zoom: None,
+        autofocus: false,
+    }
+}
+
+#[test]
+fn test_check_response() {
+    let ok = json!({ "result": [["getVersions", "actZoom"]], "id": 1 });
+    assert_eq!(
+        check_response("getAvailableApiList", ok).unwrap()[0][1],
+        "actZoom"
+    );
+
+    let error = json!({ "error": [1, "Not Available Now"], "id": 2 });
+    assert_eq!(
+        check_response("actZoom", error).unwrap_err().to_string(),
+        "camera rejected actZoom: Not Available Now (1)"
+    );
+
+    assert_eq!(zoom_direction(0.3), Some("in"));
+    assert_eq!(zoom_direction(-1.0), Some("out"));
+    assert_eq!(zoom_direction(0.0), None);
+}
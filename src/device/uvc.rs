@@ -0,0 +1,273 @@
+use std::{collections::HashSet, fs::File, io, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug, info, warn, Instrument as _};
+
+use super::DeviceError;
+use crate::config::{Capability, UvcConfig};
+
+const FOCUS_INTERVAL: Duration = Duration::from_millis(100);
+
+// Camera class controls from linux/v4l2-controls.h
+const FOCUS_RELATIVE: u32 = 0x009a090b;
+const FOCUS_AUTO: u32 = 0x009a090c;
+const ZOOM_CONTINUOUS: u32 = 0x009a090f;
+
+/// Generic USB Video Class cameras, driven through the standard camera controls for zoom and
+/// focus. Only cameras that expose those controls in their webcam mode can be moved this way.
+pub struct Uvc {
+    id: String,
+    device: String,
+    capabilities: HashSet<Capability>,
+    connection: Option<Connection>,
+}
+
+struct Connection {
+    file: Arc<File>,
+    /// Range of the continuous zoom speed, if the camera has it.
+    zoom: Option<(i32, i32)>,
+    zoom_speed: i32,
+    autofocus: bool,
+    focus: watch::Sender<f64>,
+    focus_task: JoinHandle<()>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.focus_task.abort();
+    }
+}
+
+impl std::fmt::Display for Uvc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UVC[{}]", self.device)
+    }
+}
+
+#[async_trait]
+impl super::Device for Uvc {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    async fn connect(&mut self) -> Result<(), DeviceError> {
+        info!("Connecting");
+        let file = Arc::new(File::options().read(true).write(true).open(&self.device)?);
+        let zoom = match self.capabilities.contains(&Capability::Zoom) {
+            false => None,
+            true => match control_range(&file, ZOOM_CONTINUOUS) {
+                Ok(range) => Some(range),
+                Err(e) => {
+                    warn!("Continuous zoom not available: {}", e);
+                    None
+                }
+            },
+        };
+        let focus_step = match self.capabilities.contains(&Capability::Focus) {
+            false => None,
+            true => match control_range(&file, FOCUS_RELATIVE) {
+                Ok((_, max)) => Some(max),
+                Err(e) => {
+                    warn!("Relative focus not available: {}", e);
+                    None
+                }
+            },
+        };
+        let (focus, focus_rx) = watch::channel(0.0);
+        let focus_task =
+            tokio::spawn(drive_focus(file.clone(), focus_step, focus_rx).in_current_span());
+        self.connection = Some(Connection {
+            file,
+            zoom,
+            zoom_speed: 0,
+            autofocus: false,
+            focus,
+            focus_task,
+        });
+        info!("Connected");
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<(), DeviceError> {
+        match self.connection.take() {
+            None => debug!("Already disconnected"),
+            Some(_) => info!("Disconnected"),
+        }
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), DeviceError> {
+        self.disconnect().await?;
+        self.connect().await?;
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn send_command(&mut self, command: super::Command) -> Result<(), DeviceError> {
+        debug!("Received command {:?}", command);
+        let Some(connection) = &mut self.connection else {
+            debug!("Not connected");
+            return Ok(());
+        };
+
+        if let Some(range) = connection.zoom {
+            let zoom_speed = scale(command.zoom.get(), range);
+            if zoom_speed != connection.zoom_speed {
+                set_control(&connection.file, ZOOM_CONTINUOUS, zoom_speed)?;
+                connection.zoom_speed = zoom_speed;
+            }
+        }
+
+        let autofocus = self.capabilities.contains(&Capability::Autofocus) && command.autofocus;
+        if autofocus != connection.autofocus {
+            // Turning autofocus back off holds the focus where it landed
+            set_control(&connection.file, FOCUS_AUTO, autofocus as i32)?;
+            connection.autofocus = autofocus;
+        }
+
+        let focus = match autofocus {
+            true => 0.0,
+            false => command.focus.get(),
+        };
+        connection.focus.send_replace(focus);
+        Ok(())
+    }
+}
+
+/// Scales a speed from -1 to 1 onto a control's range, rounding away from 0 so that any movement
+/// at all moves the camera.
+fn scale(value: f64, (min, max): (i32, i32)) -> i32 {
+    let limit = match value > 0.0 {
+        true => max,
+        false => -min,
+    };
+    (value * limit as f64).abs().ceil().copysign(value) as i32
+}
+
+/// Repeats relative focus steps while the focus speed isn't 0, since the control moves the focus
+/// by a set amount each time it's written.
+async fn drive_focus(file: Arc<File>, max_step: Option<i32>, mut speed: watch::Receiver<f64>) {
+    let Some(max_step) = max_step else {
+        return;
+    };
+    loop {
+        let focus_speed = *speed.borrow_and_update();
+        if focus_speed == 0.0 {
+            if speed.changed().await.is_err() {
+                return;
+            }
+            continue;
+        }
+        let step = scale(focus_speed, (-max_step, max_step));
+        if let Err(e) = set_control(&file, FOCUS_RELATIVE, step) {
+            warn!("Error focusing: {}", e);
+        }
+        tokio::time::sleep(FOCUS_INTERVAL).await;
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod v4l2 {
+    // struct v4l2_control
+    #[repr(C)]
+    pub struct Control {
+        pub id: u32,
+        pub value: i32,
+    }
+
+    // struct v4l2_queryctrl
+    #[repr(C)]
+    pub struct QueryControl {
+        pub id: u32,
+        pub control_type: u32,
+        pub name: [u8; 32],
+        pub minimum: i32,
+        pub maximum: i32,
+        pub step: i32,
+        pub default_value: i32,
+        pub flags: u32,
+        pub reserved: [u32; 2],
+    }
+
+    pub const VIDIOC_S_CTRL: u32 = 0xc008561c;
+    pub const VIDIOC_QUERYCTRL: u32 = 0xc0445624;
+    pub const CTRL_FLAG_DISABLED: u32 = 0x0001;
+}
+
+#[cfg(target_os = "linux")]
+fn set_control(file: &File, id: u32, value: i32) -> io::Result<()> {
+    use std::os::fd::AsRawFd as _;
+
+    let mut control = v4l2::Control { id, value };
+    // SAFETY: the request matches the struct passed, which outlives the call
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), v4l2::VIDIOC_S_CTRL as _, &mut control) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn control_range(file: &File, id: u32) -> io::Result<(i32, i32)> {
+    use std::os::fd::AsRawFd as _;
+
+    // SAFETY: the struct is plain data, for which all zeroes is valid
+    let mut query: v4l2::QueryControl = unsafe { std::mem::zeroed() };
+    query.id = id;
+    // SAFETY: the request matches the struct passed, which outlives the call
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), v4l2::VIDIOC_QUERYCTRL as _, &mut query) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if query.flags & v4l2::CTRL_FLAG_DISABLED != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "control is disabled",
+        ));
+    }
+    Ok((query.minimum, query.maximum))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_control(_file: &File, _id: u32, _value: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "UVC controls are only supported on Linux",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn control_range(_file: &File, _id: u32) -> io::Result<(i32, i32)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "UVC controls are only supported on Linux",
+    ))
+}
+
+pub fn create(id: &str, config: &UvcConfig) -> Uvc {
+    Uvc {
+        id: id.to_owned(),
+        device: config.device.clone(),
+        capabilities: config
+            .capabilities
+            .clone()
+            .map(HashSet::from_iter)
+            .unwrap_or_else(|| {
+                HashSet::from([Capability::Zoom, Capability::Focus, Capability::Autofocus])
+            }),
+        connection: None,
+    }
+}
+
+#[test]
+fn test_scale() {
+    assert_eq!(scale(0.0, (-7, 7)), 0);
+    assert_eq!(scale(0.01, (-7, 7)), 1);
+    assert_eq!(scale(-1.0, (-7, 7)), -7);
+    assert_eq!(scale(0.5, (-1, 1)), 1);
+    assert_eq!(scale(-0.5, (-8, 4)), -4);
+}
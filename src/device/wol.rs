@@ -0,0 +1,40 @@
+use std::net::Ipv4Addr;
+
+use tokio::net::UdpSocket;
+
+use super::DeviceError;
+
+const WOL_PORT: u16 = 9;
+
+pub fn parse_mac(mac: &str) -> Result<[u8; 6], DeviceError> {
+    let bytes = hex::decode(mac.replace([':', '-'], "")).map_err(DeviceError::protocol)?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("invalid MAC address {}", mac).into())
+}
+
+fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    [[0xff; 6].as_slice(), &mac.repeat(16)].concat()
+}
+
+/// Broadcasts a Wake-on-LAN magic packet for the given MAC address.
+pub async fn send_magic_packet(mac: &str) -> Result<(), DeviceError> {
+    let packet = magic_packet(&parse_mac(mac)?);
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(&packet, (Ipv4Addr::BROADCAST, WOL_PORT))
+        .await?;
+    Ok(())
+}
+
+#[test]
+fn test_magic_packet() {
+    let mac = parse_mac("01:23:45:67:89:AB").unwrap();
+    assert_eq!(mac, parse_mac("01-23-45-67-89-ab").unwrap());
+    assert!(parse_mac("01:23:45").is_err());
+    let packet = magic_packet(&mac);
+    assert_eq!(packet.len(), 102);
+    assert_eq!(hex::encode(&packet[..12]), "ffffffffffff0123456789ab");
+    assert_eq!(hex::encode(&packet[96..]), "0123456789ab");
+}
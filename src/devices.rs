@@ -0,0 +1,280 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+use tokio::sync::mpsc;
+use tracing::{warn, Instrument as _};
+
+use crate::{
+    actor, config,
+    device::{self, Adapter, Device},
+    secrets, UpdateDevicesRequest,
+};
+#[cfg(test)]
+use crate::{parse_request, Request};
+
+/// Creates a single configured device, with its secrets resolved, for tools that use a device
+/// without starting the server.
+pub async fn create_configured_device(
+    config: &config::Config,
+    id: &str,
+) -> Result<Box<dyn Device>, Box<dyn Error>> {
+    let device_configs: IndexMap<String, config::DeviceConfig> = secrets::resolve(
+        &config.devices,
+        config
+            .secrets_file
+            .as_deref()
+            .unwrap_or(secrets::DEFAULT_PATH.as_ref()),
+    )?;
+    let device_config = device_configs
+        .get(id)
+        .ok_or_else(|| format!("no device named {}", id))?;
+    let central = device::bluetooth_adapter().await?;
+    Ok(create_device(id, device_config, &central))
+}
+
+/// Creates the backend for a configured device. Bluetooth devices find their peripheral through
+/// `central` once connected.
+#[cfg_attr(not(feature = "bluetooth"), allow(unused_variables))]
+pub fn create_device(
+    id: &str,
+    device_config: &config::DeviceConfig,
+    central: &Adapter,
+) -> Box<dyn Device> {
+    match device_config {
+        config::DeviceConfig::Dummy(dummy_config) => {
+            let dummy = device::dummy::create_with_id_and_name(id, &dummy_config.name);
+            Box::new(dummy)
+        }
+        #[cfg(feature = "bluetooth")]
+        config::DeviceConfig::Ronin(ronin_config) => {
+            let ronin = device::ronin::create(id, central.clone(), ronin_config);
+            Box::new(ronin)
+        }
+        #[cfg(feature = "bluetooth")]
+        config::DeviceConfig::Crane(crane_config) => {
+            let crane = device::crane::create(id, central.clone(), crane_config);
+            Box::new(crane)
+        }
+        #[cfg(feature = "bluetooth")]
+        config::DeviceConfig::Feiyu(feiyu_config) => {
+            let feiyu = device::feiyu::create(id, central.clone(), feiyu_config);
+            Box::new(feiyu)
+        }
+        #[cfg(feature = "bluetooth")]
+        config::DeviceConfig::GenericBle(generic_ble_config) => {
+            let generic_ble = device::generic_ble::create(id, central.clone(), generic_ble_config);
+            Box::new(generic_ble)
+        }
+        #[cfg(feature = "lumix")]
+        config::DeviceConfig::Lumix(lumix_config) => {
+            let lumix = device::lumix::create(id, lumix_config);
+            Box::new(lumix)
+        }
+        #[cfg(feature = "lanc")]
+        config::DeviceConfig::Lanc(lanc_config) => {
+            let lanc = device::lanc::create(id, lanc_config);
+            Box::new(lanc)
+        }
+        #[cfg(feature = "serial")]
+        config::DeviceConfig::Serial(serial_config) => {
+            let serial = device::serial::create(id, serial_config);
+            Box::new(serial)
+        }
+        config::DeviceConfig::Modbus(modbus_config) => {
+            let modbus = device::modbus::create(id, modbus_config);
+            Box::new(modbus)
+        }
+        #[cfg(feature = "panasonic")]
+        config::DeviceConfig::Camcorder(camcorder_config) => {
+            let camcorder = device::camcorder::create(id, camcorder_config);
+            Box::new(camcorder)
+        }
+        #[cfg(feature = "serial")]
+        config::DeviceConfig::Fcb(fcb_config) => {
+            let fcb = device::fcb::create(id, fcb_config);
+            Box::new(fcb)
+        }
+        #[cfg(feature = "onvif")]
+        config::DeviceConfig::Onvif(onvif_config) => {
+            let onvif = device::onvif::create(id, onvif_config);
+            Box::new(onvif)
+        }
+        #[cfg(feature = "panasonic")]
+        config::DeviceConfig::Aw(aw_config) => {
+            let aw = device::aw::create(id, aw_config);
+            Box::new(aw)
+        }
+        #[cfg(feature = "canon")]
+        config::DeviceConfig::CanonCcapi(canon_config) => {
+            let canon = device::canon_ccapi::create(id, canon_config);
+            Box::new(canon)
+        }
+        #[cfg(feature = "canon")]
+        config::DeviceConfig::CanonUsb(canon_config) => {
+            let canon = device::canon_usb::create(id, canon_config);
+            Box::new(canon)
+        }
+        #[cfg(feature = "sony")]
+        config::DeviceConfig::Sony(sony_config) => {
+            let sony = device::sony::create(id, sony_config);
+            Box::new(sony)
+        }
+        #[cfg(feature = "uvc")]
+        config::DeviceConfig::Uvc(uvc_config) => {
+            let uvc = device::uvc::create(id, uvc_config);
+            Box::new(uvc)
+        }
+        #[cfg(feature = "script")]
+        config::DeviceConfig::Script(script_config) => {
+            let script = device::script::create(id, central.clone(), script_config);
+            Box::new(script)
+        }
+        #[cfg(feature = "serial")]
+        config::DeviceConfig::Pelco(pelco_config) => {
+            let pelco = device::pelco::create(id, pelco_config);
+            Box::new(pelco)
+        }
+        // Configs for backends that aren't compiled in are rejected when they're loaded
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(
+            "{} isn't supported by this build",
+            device_config.type_name()
+        ),
+    }
+}
+
+/// Connects each device, returning the errors of those that couldn't be. They're left
+/// disconnected for the supervisor to retry, since a camera that's still off (or about to be
+/// woken) shouldn't keep the others from being controlled.
+pub async fn connect_devices(
+    devices: &mut [Box<dyn Device>],
+) -> HashMap<String, device::DeviceError> {
+    let mut errors = HashMap::new();
+    for device in devices.iter_mut() {
+        let id = device.id();
+        if let Err(e) = device.connect().instrument(device::span(&id)).await {
+            warn!(device = %id, "Error connecting: {}", e);
+            errors.insert(id, e);
+        }
+    }
+    errors
+}
+
+/// The devices that are in at least one group, which are the only ones that get connected.
+pub fn used_device_ids(config: &config::Config) -> Vec<String> {
+    config
+        .groups
+        .iter()
+        .flat_map(|g| g.devices.iter())
+        .unique()
+        .sorted()
+        .cloned()
+        .collect()
+}
+
+pub fn deadbands(config: &config::Config) -> HashMap<String, f64> {
+    config
+        .deadband
+        .iter()
+        .flat_map(|deadband| {
+            config
+                .devices
+                .iter()
+                .map(|(id, d)| (id.clone(), deadband.epsilon_for(d)))
+        })
+        .collect()
+}
+
+/// Devices whose capabilities leave out roll have it zeroed before the deadband sees the
+/// command, so that a bumped roll axis doesn't count as a change.
+pub fn roll_locked(config: &config::Config) -> HashSet<String> {
+    config
+        .devices
+        .iter()
+        .filter(|(_, d)| {
+            d.capabilities()
+                .is_some_and(|c| !c.contains(&config::Capability::Roll))
+        })
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Hands a device to its own task, on the Bluetooth runtime if there is one and the device
+/// needs it.
+pub fn spawn_handle(
+    device: Box<dyn Device>,
+    bluetooth_runtime: &Option<tokio::runtime::Handle>,
+    errors: &mpsc::UnboundedSender<actor::TaskError>,
+) -> actor::DeviceHandle {
+    let runtime = match bluetooth_runtime {
+        Some(handle) if device.uses_bluetooth() => handle.clone(),
+        _ => tokio::runtime::Handle::current(),
+    };
+    actor::DeviceHandle::spawn(device, &runtime, errors.clone())
+}
+
+/// The config with an `updateDevices` request applied, checked the same way as a config that's
+/// read from a file.
+pub fn updated_config(
+    config: &config::Config,
+    request: &UpdateDevicesRequest,
+) -> Result<config::Config, Box<dyn Error>> {
+    let mut value = serde_json::to_value(config)?;
+    for (id, device) in request.devices.iter() {
+        let devices = value["devices"].as_object_mut().unwrap();
+        match device {
+            Some(device) => devices.insert(id.clone(), serde_json::to_value(device)?),
+            None => devices.remove(id),
+        };
+    }
+    if let Some(groups) = &request.groups {
+        value["groups"] = serde_json::to_value(groups)?;
+    }
+    let updated = config::parse_config(&value.to_string())?;
+    Ok(config::Config {
+        path: config.path.clone(),
+        ..updated
+    })
+}
+
+#[test]
+fn test_updated_config() {
+    let config = config::parse_config(
+        r#"{
+            "groups": [{"name": "A", "devices": ["cam1", "cam2"]}],
+            "devices": {
+                "cam1": {"type": "dummy", "name": "One"},
+                "cam2": {"type": "dummy", "name": "Two"}
+            }
+        }"#,
+    )
+    .unwrap();
+    let request = |json: &str| match parse_request(json).unwrap() {
+        Request::UpdateDevices(request) => request,
+        request => panic!("unexpected request {:?}", request),
+    };
+
+    let updated = updated_config(
+        &config,
+        &request(
+            r#"{"updateDevices": {
+                "devices": {"cam2": null, "cam3": {"type": "dummy", "name": "Three"}},
+                "groups": [{"name": "A", "devices": ["cam1", "cam3"]}]
+            }}"#,
+        ),
+    )
+    .unwrap();
+    assert_eq!(updated.devices.keys().collect_vec(), vec!["cam1", "cam3"]);
+    assert_eq!(used_device_ids(&updated), vec!["cam1", "cam3"]);
+
+    // Removing a device that's still in a group is rejected, like it is in a config file
+    let removed = updated_config(
+        &config,
+        &request(r#"{"updateDevices": {"devices": {"cam2": null}}}"#),
+    );
+    assert!(removed.is_err());
+}
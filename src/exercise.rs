@@ -0,0 +1,162 @@
+//! `webptz exercise`, which runs one configured device through a fixed routine without starting
+//! the web server, for checking new hardware and cabling. Every backend logs what it sends, so
+//! the output doubles as a protocol trace.
+
+use std::{error::Error, time::Duration};
+
+use crate::{
+    config,
+    device::{Command, Device, Velocity},
+};
+
+const DEFAULT_SPEED: f64 = 0.2;
+/// How long each axis is moved in each direction.
+const SWEEP_DURATION: Duration = Duration::from_secs(2);
+/// Commands are repeated while moving, like the UI does, since some devices stop on their own
+/// if they don't hear from us.
+const COMMAND_INTERVAL: Duration = Duration::from_millis(100);
+const AXES: [&str; 5] = ["pan", "tilt", "roll", "zoom", "focus"];
+const SETTLE_DURATION: Duration = Duration::from_secs(1);
+const USAGE: &str = "usage: webptz exercise [--config <path>] [--speed <0-1>] <device-id>
+
+Connects to the device, moves each axis slowly one way and then the other, triggers autofocus,
+and disconnects. The config defaults to config.json, and the speed to 0.2.";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    config: String,
+    speed: f64,
+    device: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options {
+        config: "config.json".to_owned(),
+        speed: DEFAULT_SPEED,
+        device: String::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => options.config = args.next().ok_or(USAGE)?.clone(),
+            "--speed" => {
+                options.speed = args
+                    .next()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|s| (0.0..=1.0).contains(s))
+                    .ok_or(USAGE)?
+            }
+            device if options.device.is_empty() && !device.starts_with("--") => {
+                options.device = device.to_owned()
+            }
+            _ => return Err(USAGE.into()),
+        }
+    }
+    if options.device.is_empty() {
+        return Err(USAGE.into());
+    }
+    Ok(options)
+}
+
+/// Entry point for `webptz exercise`.
+pub async fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = parse_args(args)?;
+    let config = config::read_config(&options.config)?;
+    let mut device = crate::create_configured_device(&config, &options.device).await?;
+
+    println!("== Connecting to {}", device);
+    device.connect().await?;
+
+    let result = tokio::select! {
+        result = exercise(device.as_mut(), options.speed) => result,
+        _ = tokio::signal::ctrl_c() => Err("interrupted".into()),
+    };
+    // Whatever happened, don't leave the device moving
+    println!("== Stopping");
+    if let Err(e) = device.send_command(Command::default()).await {
+        println!("Error stopping {}: {}", device, e);
+    }
+    println!("== Disconnecting");
+    device.disconnect().await?;
+    result
+}
+
+async fn exercise(device: &mut dyn Device, speed: f64) -> Result<(), Box<dyn Error>> {
+    for name in AXES {
+        for velocity in [speed, -speed] {
+            println!("== Moving {} at {}", name, velocity);
+            hold(device, axis_command(name, Velocity::new(velocity))).await?;
+            device.send_command(Command::default()).await?;
+            tokio::time::sleep(SETTLE_DURATION).await;
+            if let Some(position) = device.position() {
+                println!("== Position: {:?}", position);
+            }
+        }
+    }
+
+    println!("== Triggering autofocus");
+    device
+        .send_command(Command {
+            autofocus: true,
+            ..Default::default()
+        })
+        .await?;
+    tokio::time::sleep(SETTLE_DURATION).await;
+    device.send_command(Command::default()).await?;
+
+    let telemetry = device.poll_telemetry().await?;
+    if !telemetry.is_empty() {
+        println!("== Telemetry: {}", serde_json::Value::Object(telemetry));
+    }
+    println!("== Done");
+    Ok(())
+}
+
+fn axis_command(axis: &str, velocity: Velocity) -> Command {
+    let mut command = Command::default();
+    match axis {
+        "pan" => command.pan = velocity,
+        "tilt" => command.tilt = velocity,
+        "roll" => command.roll = velocity,
+        "zoom" => command.zoom = velocity,
+        "focus" => command.focus = velocity,
+        _ => unreachable!(),
+    }
+    command
+}
+
+/// Keeps sending a command for the length of a sweep.
+async fn hold(device: &mut dyn Device, command: Command) -> Result<(), Box<dyn Error>> {
+    let mut interval = tokio::time::interval(COMMAND_INTERVAL);
+    let end = tokio::time::Instant::now() + SWEEP_DURATION;
+    while tokio::time::Instant::now() < end {
+        interval.tick().await;
+        device.send_command(command).await?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |s: &str| -> Vec<String> { s.split_whitespace().map(str::to_owned).collect() };
+    assert_eq!(
+        parse_args(&args("ronin1")).unwrap(),
+        Options {
+            config: "config.json".to_owned(),
+            speed: DEFAULT_SPEED,
+            device: "ronin1".to_owned(),
+        }
+    );
+    assert_eq!(
+        parse_args(&args("--config show.json lanc1 --speed 0.5")).unwrap(),
+        Options {
+            config: "show.json".to_owned(),
+            speed: 0.5,
+            device: "lanc1".to_owned(),
+        }
+    );
+    assert!(parse_args(&args("")).is_err());
+    assert!(parse_args(&args("ronin1 lanc1")).is_err());
+    assert!(parse_args(&args("ronin1 --speed 2")).is_err());
+    assert!(parse_args(&args("ronin1 --config")).is_err());
+}
@@ -0,0 +1,768 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    ops::ControlFlow,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn, Instrument as _};
+
+use crate::{
+    access, ack, actor, arbitration, calibration, compound, config,
+    device::{self, Adapter, AdapterEvent},
+    devices, expand_tags, history, input, locks, parse_request, presets, preview, supervisor,
+    thumbnails, timecode, CommandRequest, CustomCommandRequest, DeviceStatus, DisconnectRequest,
+    MoveToRequest, Operation, Origin, PushInRequest, ReconnectRequest, RepairRequest,
+    SetPropertyRequest, State, WakeRequest,
+};
+
+mod locking;
+mod polling;
+mod presetting;
+mod reconfiguring;
+
+const DEFAULT_RECALL_DURATION: Duration = Duration::from_secs(2);
+
+/// Everything the command loop keeps track of between operations.
+pub struct Context {
+    config: config::Config,
+    central: Adapter,
+    bluetooth_runtime: Option<tokio::runtime::Handle>,
+    task_error_tx: mpsc::UnboundedSender<actor::TaskError>,
+    /// Sends operations back into the command loop, e.g. for preset recalls.
+    loopback_tx: mpsc::UnboundedSender<Operation>,
+    state_tx: watch::Sender<State>,
+    handles: Vec<actor::DeviceHandle>,
+    deadbands: HashMap<String, f64>,
+    roll_locked: HashSet<String>,
+    last_commands: HashMap<String, device::Command>,
+    /// When each device last had a command, including ones dropped by the deadband
+    command_times: HashMap<String, Instant>,
+    telemetry: HashMap<String, Map<String, Value>>,
+    pub thumbnails: thumbnails::Thumbnails,
+    pub previews: preview::Previews,
+    pub history: history::History,
+    /// Commands sent to each device since its last history sample
+    command_counts: HashMap<String, u32>,
+    calibrations: calibration::Calibrations,
+    arbiter: arbitration::Arbiter,
+    group_locks: locks::GroupLocks,
+    supervisor: supervisor::Supervisor,
+    push_in_tasks: HashMap<String, JoinHandle<()>>,
+    recall_tasks: HashMap<String, JoinHandle<()>>,
+    guide_tasks: HashMap<String, JoinHandle<()>>,
+    guidance: HashMap<String, presets::Guidance>,
+    access: access::Access,
+    pub visibility: Arc<access::Visibility>,
+    input_pipeline: input::Pipeline,
+    timecode_cues: Vec<config::TimecodeCue>,
+    last_timecode: Option<timecode::Timecode>,
+    /// Bluetooth devices that were connected when the adapter went away
+    adapter_lost_devices: Vec<String>,
+    /// Websocket clients that are connected, which locks can be handed off to
+    clients: HashSet<String>,
+}
+
+impl Context {
+    pub fn new(
+        config: config::Config,
+        central: Adapter,
+        bluetooth_runtime: Option<tokio::runtime::Handle>,
+        task_error_tx: mpsc::UnboundedSender<actor::TaskError>,
+        loopback_tx: mpsc::UnboundedSender<Operation>,
+        handles: Vec<actor::DeviceHandle>,
+    ) -> Context {
+        let previews = preview::Previews::default();
+        previews.sync(&handles);
+        let state_tx = watch::Sender::new(State {
+            instance: uuid::Uuid::new_v4().to_string(),
+            groups: config.groups.clone(),
+            devices: HashMap::new(),
+            default_controls: crate::serialize_controls(&config.default_controls),
+        });
+        let context = Context {
+            deadbands: devices::deadbands(&config),
+            roll_locked: devices::roll_locked(&config),
+            last_commands: HashMap::new(),
+            command_times: HashMap::new(),
+            telemetry: HashMap::new(),
+            thumbnails: thumbnails::Thumbnails::default(),
+            previews,
+            history: history::History::default(),
+            command_counts: HashMap::new(),
+            calibrations: calibration::Calibrations::default(),
+            arbiter: arbitration::Arbiter::new(&config.arbitration.clone().unwrap_or_default()),
+            group_locks: locks::GroupLocks::default(),
+            supervisor: supervisor::Supervisor::default(),
+            push_in_tasks: HashMap::new(),
+            recall_tasks: HashMap::new(),
+            guide_tasks: HashMap::new(),
+            guidance: HashMap::new(),
+            access: access::Access::new(&config.access, &config.groups, &config.devices),
+            visibility: Arc::new(access::Visibility::new(&config.visibility, &config.groups)),
+            input_pipeline: input::Pipeline::new(
+                &config.inputs,
+                &config.input_actions,
+                &config.groups,
+            ),
+            timecode_cues: config
+                .timecode
+                .as_ref()
+                .map(|t| t.cues.clone())
+                .unwrap_or_default(),
+            last_timecode: None,
+            adapter_lost_devices: vec![],
+            clients: HashSet::new(),
+            config,
+            central,
+            bluetooth_runtime,
+            task_error_tx,
+            loopback_tx,
+            state_tx,
+            handles,
+        };
+        context.broadcast();
+        context
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<State> {
+        self.state_tx.subscribe()
+    }
+
+    pub fn has_ramps(&self) -> bool {
+        self.input_pipeline.has_ramps()
+    }
+
+    fn device_status(&self) -> HashMap<String, DeviceStatus> {
+        self.handles
+            .iter()
+            .map(|h| {
+                let mut values = self.telemetry.get(&h.id).cloned().unwrap_or_default();
+                values.extend(h.telemetry.clone());
+                (
+                    h.id.clone(),
+                    DeviceStatus {
+                        id: h.id.clone(),
+                        name: h.name.clone(),
+                        connected: h.connected,
+                        reconnecting: self.supervisor.is_reconnecting(&h.id),
+                        repairable: h.repairable,
+                        wakeable: h.wakeable,
+                        telemetry: (!values.is_empty()).then_some(values),
+                        guidance: self.guidance.get(&h.id).copied(),
+                        tags: self.config.tags.get(&h.id).cloned().unwrap_or_default(),
+                        thumbnail: self.thumbnails.url(&h.id),
+                        preview: h.live_view.as_ref().map(|_| preview::url(&h.id)),
+                        calibrating: self.calibrations.is_calibrating(&h.id),
+                        last_error: h.last_error.clone(),
+                        trim: self.config.trim.get(&h.id).copied(),
+                        lens: h.lens.clone(),
+                        position: h.position,
+                        owner: self.arbiter.owner(&h.id).map(str::to_owned),
+                        locked_by: self.group_locks.locked_by(&h.id).map(str::to_owned),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Sends every client the devices' current state.
+    fn broadcast(&self) {
+        self.state_tx
+            .send_modify(|s| s.devices = self.device_status());
+    }
+
+    /// Like `broadcast`, for operations that may have changed the groups too.
+    fn broadcast_groups(&self) {
+        self.state_tx.send_modify(|s| {
+            s.groups = self.config.groups.clone();
+            s.devices = self.device_status();
+        });
+    }
+
+    /// Narrows the operation down to the devices its sender is allowed to touch, failing the
+    /// others. Returns whether there's anything left to carry out.
+    pub fn admit(
+        &self,
+        operation: &mut Operation,
+        origin: Option<&Origin>,
+        ack: &mut ack::Ack,
+    ) -> bool {
+        if let Some(devices) = operation.devices_mut() {
+            expand_tags(devices, &self.config.tags);
+            ack.target(devices.iter());
+        }
+        if let Some(device) = operation.device() {
+            ack.target([&device.to_owned()]);
+        }
+        if let Some(Origin { source, .. }) = origin {
+            if let Some(devices) = operation.devices_mut() {
+                let denied = self.access.retain_allowed(source, devices);
+                if !denied.is_empty() {
+                    info!("{} isn't allowed to control {:?}", source, denied);
+                }
+                for device in denied {
+                    ack.fail(&device, "not allowed");
+                }
+            }
+            if let Some(device) = operation.device() {
+                if !self.access.allows(source, device) {
+                    info!("{} isn't allowed to control {}", source, device);
+                    ack.fail(device, "not allowed");
+                    return false;
+                }
+            }
+        }
+        let client = origin.map(|o| o.client.as_str());
+        if let Some(devices) = operation.devices_mut() {
+            let (allowed, locked): (Vec<String>, Vec<String>) = devices
+                .drain(..)
+                .partition(|d| self.calibrations.allows(d, client));
+            if !locked.is_empty() {
+                info!("Skipping {:?}, which are being calibrated", locked);
+            }
+            for device in locked {
+                ack.fail(&device, "being calibrated");
+            }
+            *devices = allowed;
+        }
+        if let Some(device) = operation.device() {
+            if !self.calibrations.allows(device, client) {
+                info!("Skipping {}, which is being calibrated", device);
+                ack.fail(device, "being calibrated");
+                return false;
+            }
+        }
+        if let Some(devices) = operation.devices_mut() {
+            let (allowed, locked): (Vec<String>, Vec<String>) = devices
+                .drain(..)
+                .partition(|d| self.group_locks.allows(d, client));
+            if !locked.is_empty() {
+                info!("Skipping {:?}, which are locked by another client", locked);
+            }
+            for device in locked {
+                let holder = self.group_locks.locked_by(&device).unwrap_or_default();
+                ack.fail(&device, format!("locked by {}", holder));
+            }
+            *devices = allowed;
+        }
+        if let Some(device) = operation.device() {
+            if !self.group_locks.allows(device, client) {
+                let holder = self.group_locks.locked_by(device).unwrap_or_default();
+                info!("Skipping {}, which is locked by {}", device, holder);
+                ack.fail(device, format!("locked by {}", holder));
+                return false;
+            }
+        }
+        let mut named = operation.devices_mut().cloned().unwrap_or_default();
+        named.extend(operation.device().map(str::to_owned));
+        for device in named
+            .iter()
+            .filter(|d| !self.handles.iter().any(|h| h.id == **d))
+        {
+            ack.fail(device, "unknown device");
+        }
+        true
+    }
+
+    /// Carries out an operation, returning whether to keep going.
+    pub async fn handle(
+        &mut self,
+        operation: Operation,
+        origin: Option<Origin>,
+        ack: &mut ack::Ack,
+    ) -> Result<ControlFlow<()>, Box<dyn Error>> {
+        let origin = origin.as_ref();
+        match operation {
+            Operation::Command(request) => self.command(request, origin, ack),
+            Operation::Disconnect(request) => self.disconnect(request, ack).await,
+            Operation::Reconnect(request) => self.reconnect(request, ack).await,
+            Operation::Repair(request) => self.repair(request, ack).await,
+            Operation::Wake(request) => self.wake(request, ack).await,
+            Operation::PushIn(request) => self.push_in(request),
+            Operation::MoveTo(request) => self.move_to(request, ack).await,
+            Operation::SetProperty(request) => self.set_property(request, ack).await,
+            Operation::CustomCommand(request) => self.custom_command(request, ack).await,
+            Operation::SavePreset(request) => self.save_preset(request, ack).await?,
+            Operation::RecallPreset(request) => self.recall_preset(request, ack).await,
+            Operation::GuidePreset(request) => self.guide_preset(request, ack),
+            Operation::Guidance(device, update) => self.guidance(device, update),
+            Operation::Shutdown => {
+                self.shutdown().await;
+                return Ok(ControlFlow::Break(()));
+            }
+            Operation::Adapter(AdapterEvent::Lost) => self.adapter_lost().await,
+            Operation::Adapter(AdapterEvent::Restored(adapter)) => {
+                self.adapter_restored(adapter).await
+            }
+            Operation::Health(report) => {
+                self.telemetry
+                    .entry(report.device)
+                    .or_default()
+                    .extend(report.values);
+                self.broadcast();
+            }
+            Operation::TaskError(error) => {
+                if let Some(handle) = self.handles.iter_mut().find(|h| h.id == error.device) {
+                    handle.last_error = Some(error.report);
+                }
+                self.broadcast();
+            }
+            Operation::Input(event) => self.input(event, origin),
+            Operation::RampInputs => self.ramp_inputs(),
+            Operation::Timecode(current) => self.timecode(current),
+            Operation::Watchdog => self.watchdog(),
+            Operation::Supervise => self.supervise().await,
+            Operation::PollPositions => self.poll_positions(),
+            Operation::PollTelemetry => self.poll_telemetry().await,
+            Operation::PollThumbnails => self.poll_thumbnails().await,
+            Operation::SaveDefaultControls(request) => self.save_default_controls(request).await?,
+            Operation::Calibrate(request) => self.calibrate(request, origin, ack),
+            Operation::EndCalibration(request) => self.end_calibration(request),
+            Operation::Trim(request) => self.trim(request).await?,
+            Operation::UpdateDevices(request) => {
+                let updated = devices::updated_config(&self.config, &request);
+                self.reconfigure(updated, false, origin, ack).await?
+            }
+            Operation::ReloadConfig(updated) => {
+                self.reconfigure(Ok(*updated), true, origin, ack).await?
+            }
+            Operation::Lock(request) => self.lock(request, origin, ack),
+            Operation::Unlock(request) => self.unlock(request, origin, ack),
+            Operation::Handoff(request) => self.handoff(request, origin, ack),
+            Operation::ClientConnected(client) => {
+                self.clients.insert(client);
+            }
+            Operation::ClientDisconnected(client) => {
+                self.clients.remove(&client);
+            }
+            Operation::Sourced(..) => {
+                debug!("Ignoring operation with more than one source");
+                ack.reject("more than one source");
+            }
+            Operation::Acknowledged(..) => {
+                debug!("Ignoring operation acknowledged more than once");
+                ack.reject("acknowledged more than once");
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    fn command(&mut self, request: CommandRequest, origin: Option<&Origin>, ack: &mut ack::Ack) {
+        debug!(
+            "== Received command {:?} for cameras {:?} ==",
+            request.command, request.devices
+        );
+        let now = Instant::now();
+        let owners = request
+            .devices
+            .iter()
+            .map(|d| self.arbiter.owner(d).map(str::to_owned))
+            .collect_vec();
+        let targets = self
+            .handles
+            .iter()
+            .filter(|h| request.devices.contains(&h.id))
+            // Commands from webptz itself, like preset recalls, aren't arbitrated
+            .filter_map(|h| match origin {
+                None => Some((h, request.command)),
+                Some(origin) => match self.arbiter.arbitrate(&h.id, origin, request.command, now) {
+                    Ok(command) => Some((h, command)),
+                    Err(owner) => {
+                        debug!(device = %h.id, "Controlled by {}", owner);
+                        ack.fail(&h.id, format!("controlled by {}", owner));
+                        None
+                    }
+                },
+            })
+            .inspect(|(h, _)| {
+                self.command_times.insert(h.id.clone(), now);
+            })
+            .map(|(h, command)| match self.roll_locked.contains(&h.id) {
+                true => (
+                    h,
+                    device::Command {
+                        roll: device::Velocity::STOP,
+                        ..command
+                    },
+                ),
+                false => (h, command),
+            })
+            .filter(|(h, command)| {
+                let epsilon = self.deadbands.get(&h.id).copied().unwrap_or(0.0);
+                let redundant = self
+                    .last_commands
+                    .get(&h.id)
+                    .is_some_and(|prev| prev.within_deadband(command, epsilon));
+                // Recording is only started or stopped once, and photos and focus points only
+                // taken once, not resent with later commands like a trim change
+                if !redundant {
+                    let command = device::Command {
+                        record: None,
+                        shutter: false,
+                        focus_point: None,
+                        ..*command
+                    };
+                    self.last_commands.insert(h.id.clone(), command);
+                }
+                !redundant
+            })
+            .map(|(h, command)| {
+                let mounting = self.config.mounting.get(&h.id).copied().unwrap_or_default();
+                let trim = self.config.trim.get(&h.id).copied().unwrap_or_default();
+                (h, trim.apply(mounting.apply(command)))
+            })
+            .collect_vec();
+        for ((handle, _), sent) in targets.iter().zip(actor::dispatch(&targets)) {
+            *self.command_counts.entry(handle.id.clone()).or_default() += 1;
+            match handle.connected {
+                true => ack.wait_for(&handle.id, sent),
+                false => ack.device_error(&handle.id, &device::DeviceError::NotConnected),
+            }
+        }
+        let owners_changed = request
+            .devices
+            .iter()
+            .zip(owners)
+            .any(|(d, owner)| self.arbiter.owner(d) != owner.as_deref());
+        if owners_changed {
+            self.broadcast();
+        }
+        debug!("== Command dispatched ==");
+    }
+
+    async fn disconnect(&mut self, request: DisconnectRequest, ack: &mut ack::Ack) {
+        info!("Disconnecting cameras {:?}", request.devices);
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            let moving = self
+                .last_commands
+                .remove(&handle.id)
+                .is_some_and(|c| c != device::Command::default());
+            self.supervisor.pause(&handle.id);
+            let mut device = match moving {
+                true => handle.stop_and_lock().await,
+                false => handle.lock().await,
+            };
+            if let Err(e) = device.disconnect().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error disconnecting: {}", e);
+                handle.record_error(&e);
+                ack.device_error(&handle.id, &e);
+            }
+            handle.update(&**device);
+        }
+        self.broadcast_groups();
+    }
+
+    async fn reconnect(&mut self, request: ReconnectRequest, ack: &mut ack::Ack) {
+        info!("Reconnecting cameras {:?}", request.devices);
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            self.last_commands.remove(&handle.id);
+            self.supervisor.resume(&handle.id);
+            let mut device = handle.lock().await;
+            if let Err(e) = device.reconnect().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error reconnecting: {}", e);
+                handle.record_error(&e);
+                ack.device_error(&handle.id, &e);
+            }
+            handle.update(&**device);
+        }
+        self.broadcast_groups();
+    }
+
+    async fn repair(&mut self, request: RepairRequest, ack: &mut ack::Ack) {
+        info!("Re-pairing cameras {:?}", request.devices);
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            self.last_commands.remove(&handle.id);
+            self.supervisor.resume(&handle.id);
+            let mut device = handle.lock().await;
+            if let Err(e) = device.repair().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error re-pairing: {}", e);
+                handle.record_error(&e);
+                ack.device_error(&handle.id, &e);
+            }
+            handle.update(&**device);
+        }
+        self.broadcast_groups();
+    }
+
+    async fn wake(&mut self, request: WakeRequest, ack: &mut ack::Ack) {
+        info!("Waking cameras {:?}", request.devices);
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            self.supervisor.resume(&handle.id);
+            let mut device = handle.lock().await;
+            if let Err(e) = device.wake().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error waking: {}", e);
+                handle.record_error(&e);
+                ack.device_error(&handle.id, &e);
+            }
+            handle.update(&**device);
+        }
+        self.broadcast();
+    }
+
+    fn push_in(&mut self, request: PushInRequest) {
+        info!(
+            "Pushing in cameras {:?} at {} for {}ms",
+            request.devices, request.zoom, request.duration_ms
+        );
+        for id in request.devices {
+            let task = tokio::spawn(compound::push_in(
+                id.clone(),
+                request.zoom,
+                Duration::from_millis(request.duration_ms),
+                self.config.push_in.get(&id).cloned(),
+                self.loopback_tx.clone(),
+            ));
+            if let Some(previous) = self.push_in_tasks.insert(id, task) {
+                previous.abort();
+            }
+        }
+    }
+
+    async fn move_to(&mut self, request: MoveToRequest, ack: &mut ack::Ack) {
+        let target = device::MoveTo {
+            pan: request.pan.map(device::AngleDeg::new),
+            tilt: request.tilt.map(device::AngleDeg::new),
+            roll: request.roll.map(device::AngleDeg::new),
+            duration: request
+                .duration_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_RECALL_DURATION),
+        };
+        let zoom = request.zoom.map(device::ZoomRatio::new);
+        info!(
+            "Moving cameras {:?} to {:?}, zoom {:?}",
+            request.devices, target, zoom
+        );
+        let angles = [target.pan, target.tilt, target.roll];
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            if let Some(previous) = self.recall_tasks.remove(&handle.id) {
+                previous.abort();
+            }
+            if angles.iter().any(Option::is_some) {
+                let mut device = handle.lock().await;
+                let moved = device.move_to(target).instrument(handle.span.clone()).await;
+                if let Err(e) = moved {
+                    warn!(device = %handle.id, "Error moving to position: {}", e);
+                    handle.record_error(&e);
+                    ack.device_error(&handle.id, &e);
+                }
+            }
+            // Zoom is steered there with commands, like a preset recall
+            if zoom.is_some() {
+                let task = tokio::spawn(presets::recall(
+                    handle.id.clone(),
+                    device::Position {
+                        zoom,
+                        ..Default::default()
+                    },
+                    target.duration,
+                    self.config
+                        .mounting
+                        .get(&handle.id)
+                        .copied()
+                        .unwrap_or_default(),
+                    handle.position_reader(),
+                    self.loopback_tx.clone(),
+                ));
+                self.recall_tasks.insert(handle.id.clone(), task);
+            }
+        }
+        self.broadcast();
+    }
+
+    async fn set_property(&mut self, request: SetPropertyRequest, ack: &mut ack::Ack) {
+        info!(
+            "Setting {:?} to {} on cameras {:?}",
+            request.property, request.value, request.devices
+        );
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            let mut device = handle.lock().await;
+            let set = device.set_property(request.property, request.value);
+            if let Err(e) = set.instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error setting {:?}: {}", request.property, e);
+                handle.record_error(&e);
+                ack.device_error(&handle.id, &e);
+            }
+            handle.update(&**device);
+        }
+        self.broadcast();
+    }
+
+    async fn custom_command(&mut self, request: CustomCommandRequest, ack: &mut ack::Ack) {
+        info!(
+            "Sending custom command {} to devices {:?}",
+            request.name, request.devices
+        );
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| request.devices.contains(&h.id))
+        {
+            let mut device = handle.lock().await;
+            let sent = device.custom_command(&request.name);
+            if let Err(e) = sent.instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error sending {}: {}", request.name, e);
+                handle.record_error(&e);
+                ack.device_error(&handle.id, &e);
+            }
+        }
+    }
+
+    async fn shutdown(&mut self) {
+        info!("Shutting down...");
+        self.state_tx.send_modify(|s| {
+            s.groups = vec![];
+            s.devices = HashMap::new();
+        });
+        for handle in self.handles.iter().filter(|h| h.connected) {
+            let moving = self
+                .last_commands
+                .get(&handle.id)
+                .is_some_and(|c| *c != device::Command::default());
+            let mut device = match moving {
+                true => handle.stop_and_lock().await,
+                false => handle.lock().await,
+            };
+            if let Err(e) = device.disconnect().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error disconnecting: {}", e);
+            }
+        }
+    }
+
+    async fn adapter_lost(&mut self) {
+        warn!("Bluetooth adapter lost, marking Bluetooth devices disconnected");
+        for handle in self
+            .handles
+            .iter_mut()
+            .filter(|h| h.uses_bluetooth && h.connected)
+        {
+            self.adapter_lost_devices.push(handle.id.clone());
+            let mut device = handle.lock().await;
+            if let Err(e) = device.disconnect().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error disconnecting: {}", e);
+            }
+            handle.update(&**device);
+        }
+        self.broadcast();
+    }
+
+    async fn adapter_restored(&mut self, adapter: Adapter) {
+        info!("Bluetooth adapter restored, reconnecting Bluetooth devices");
+        self.central = adapter.clone();
+        for handle in self.handles.iter_mut().filter(|h| h.uses_bluetooth) {
+            let mut device = handle.lock().await;
+            device.set_adapter(&adapter);
+            if !self.adapter_lost_devices.contains(&handle.id) {
+                continue;
+            }
+            if let Err(e) = device.connect().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error reconnecting: {}", e);
+                handle.record_error(&e);
+            }
+            handle.update(&**device);
+        }
+        self.adapter_lost_devices.clear();
+        self.broadcast();
+    }
+
+    fn input(&mut self, event: input::InputEvent, origin: Option<&Origin>) {
+        // Events injected by web clients count as coming from that client
+        let origin = origin
+            .cloned()
+            .unwrap_or_else(|| Origin::new(&event.source));
+        for request in self.input_pipeline.handle(&event, Instant::now()) {
+            let operation = Operation::Command(request).sent_by(&origin);
+            if self.loopback_tx.send(operation).is_err() {
+                break;
+            }
+        }
+        for request in self.input_pipeline.actions(&event) {
+            // Actions were validated on startup
+            let request = parse_request(&request.to_string()).unwrap();
+            let operation = Operation::from(request).sent_by(&origin);
+            if self.loopback_tx.send(operation).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn ramp_inputs(&mut self) {
+        for (source, request) in self.input_pipeline.ramp(Instant::now()) {
+            let operation = Operation::Command(request).sent_by(&Origin::new(&source));
+            if self.loopback_tx.send(operation).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn timecode(&mut self, current: timecode::Timecode) {
+        for cue in timecode::due_cues(&self.timecode_cues, self.last_timecode, current) {
+            info!("== Timecode cue at {} ==", cue.at);
+            // Cues were validated on startup
+            let request = parse_request(&cue.request.to_string()).unwrap();
+            let operation = Operation::from(request).sent_by(&Origin::new("timecode"));
+            if self.loopback_tx.send(operation).is_err() {
+                break;
+            }
+        }
+        self.last_timecode = Some(current);
+    }
+
+    fn watchdog(&mut self) {
+        let Some(watchdog_config) = &self.config.watchdog else {
+            return;
+        };
+        let timeout = Duration::from_millis(watchdog_config.timeout_ms);
+        let targets = self
+            .handles
+            .iter()
+            .filter(|h| {
+                self.last_commands
+                    .get(&h.id)
+                    .is_some_and(|c| *c != device::Command::default())
+                    && self
+                        .command_times
+                        .get(&h.id)
+                        .is_none_or(|t| t.elapsed() >= timeout)
+            })
+            .map(|h| (h, device::Command::default()))
+            .collect_vec();
+        for (handle, command) in targets.iter() {
+            warn!(device = %handle.id, "No commands for {:?}, stopping", timeout);
+            self.last_commands.insert(handle.id.clone(), *command);
+        }
+        actor::dispatch(&targets);
+    }
+}
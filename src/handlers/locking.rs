@@ -0,0 +1,186 @@
+use std::time::{Duration, Instant};
+
+use itertools::Itertools;
+use tracing::{info, warn};
+
+use super::Context;
+use crate::{
+    ack, actor, device, CalibrateRequest, EndCalibrationRequest, HandoffRequest, LockRequest,
+    Origin, UnlockRequest,
+};
+
+const DEFAULT_CALIBRATION_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_LOCK_TIMEOUT_SECS: u64 = 300;
+
+impl Context {
+    pub(super) fn calibrate(
+        &mut self,
+        request: CalibrateRequest,
+        origin: Option<&Origin>,
+        ack: &mut ack::Ack,
+    ) {
+        let Some(origin) = origin else {
+            info!("Calibration needs a client to lock {} to", request.device);
+            ack.reject("calibration needs a client");
+            return;
+        };
+        let Some(handle) = self.handles.iter().find(|h| h.id == request.device) else {
+            warn!("Can't calibrate unknown device {}", request.device);
+            return;
+        };
+        let timeout = Duration::from_secs(
+            request
+                .timeout_secs
+                .unwrap_or(DEFAULT_CALIBRATION_TIMEOUT_SECS),
+        );
+        let now = Instant::now();
+        let was_calibrating = self.calibrations.is_calibrating(&handle.id);
+        match self
+            .calibrations
+            .start(&handle.id, &origin.client, timeout, now)
+        {
+            Ok(_) => info!(
+                "{} locked {} for calibration for {:?}",
+                origin.client, handle.id, timeout
+            ),
+            Err(e) => {
+                warn!("{}", e);
+                ack.fail(&handle.id, e);
+                return;
+            }
+        }
+        if !was_calibrating {
+            // Whatever was moving the device before shouldn't keep it moving
+            for task in [&mut self.push_in_tasks, &mut self.recall_tasks] {
+                if let Some(previous) = task.remove(&handle.id) {
+                    previous.abort();
+                }
+            }
+            self.last_commands.remove(&handle.id);
+            actor::dispatch(&[(handle, device::Command::default())]);
+            self.broadcast();
+        }
+    }
+
+    pub(super) fn end_calibration(&mut self, request: EndCalibrationRequest) {
+        if !self.calibrations.end(&request.device) {
+            return;
+        }
+        info!("Calibration of {} ended", request.device);
+        if let Some(handle) = self.handles.iter().find(|h| h.id == request.device) {
+            self.last_commands.remove(&handle.id);
+            actor::dispatch(&[(handle, device::Command::default())]);
+        }
+        self.broadcast();
+    }
+
+    pub(super) fn lock(
+        &mut self,
+        request: LockRequest,
+        origin: Option<&Origin>,
+        ack: &mut ack::Ack,
+    ) {
+        let Some(origin) = origin else {
+            info!("Locking needs a client to lock {} to", request.group);
+            ack.reject("locking needs a client");
+            return;
+        };
+        let Some(group) = self.config.groups.iter().find(|g| g.name == request.group) else {
+            warn!("Can't lock unknown group {}", request.group);
+            ack.reject(format!("unknown group {}", request.group));
+            return;
+        };
+        let denied = group
+            .devices
+            .iter()
+            .filter(|d| !self.access.allows(&origin.source, d))
+            .join(", ");
+        if !denied.is_empty() {
+            info!("{} isn't allowed to lock {}", origin.source, denied);
+            ack.reject(format!("not allowed to control {}", denied));
+            return;
+        }
+        let timeout =
+            Duration::from_secs(request.timeout_secs.unwrap_or(DEFAULT_LOCK_TIMEOUT_SECS));
+        let was_locked = group
+            .devices
+            .iter()
+            .any(|d| self.group_locks.locked_by(d).is_some());
+        match self
+            .group_locks
+            .lock(group, &origin.client, timeout, Instant::now())
+        {
+            Ok(_) => info!("{} locked {} for {:?}", origin.client, group.name, timeout),
+            Err(e) => {
+                warn!("{}", e);
+                ack.reject(e);
+                return;
+            }
+        }
+        if !was_locked {
+            self.broadcast();
+        }
+    }
+
+    pub(super) fn unlock(
+        &mut self,
+        request: UnlockRequest,
+        origin: Option<&Origin>,
+        ack: &mut ack::Ack,
+    ) {
+        let client = origin.map(|o| o.client.as_str());
+        match self.group_locks.unlock(&request.group, client) {
+            Ok(false) => return,
+            Ok(true) => info!("{} unlocked", request.group),
+            Err(holder) => {
+                info!("{} is locked by {}", request.group, holder);
+                ack.reject(format!("locked by {}", holder));
+                return;
+            }
+        }
+        self.broadcast();
+    }
+
+    pub(super) fn handoff(
+        &mut self,
+        request: HandoffRequest,
+        origin: Option<&Origin>,
+        ack: &mut ack::Ack,
+    ) {
+        let Some(origin) = origin else {
+            info!("Handing off needs a client to hand off from");
+            ack.reject("handing off needs a client");
+            return;
+        };
+        if request.to == origin.client {
+            ack.reject("can't hand off to the same client");
+            return;
+        }
+        if !self.clients.contains(&request.to) {
+            info!("Can't hand off to {}, which isn't connected", request.to);
+            ack.reject(format!("{} isn't connected", request.to));
+            return;
+        }
+        // Both happen before anything else is handled, so there's no moment where neither
+        // client holds the locks
+        let groups = self.group_locks.hand_off(&origin.client, &request.to);
+        let devices = self.calibrations.hand_off(&origin.client, &request.to);
+        if groups.is_empty() && devices.is_empty() {
+            info!("{} has nothing to hand off", origin.client);
+            ack.reject("nothing to hand off");
+            return;
+        }
+        info!(
+            "{} handed {:?} and calibration of {:?} off to {}",
+            origin.client, groups, devices, request.to
+        );
+        let locked = self
+            .config
+            .groups
+            .iter()
+            .filter(|g| groups.contains(&g.name))
+            .flat_map(|g| g.devices.iter());
+        ack.target(locked.chain(devices.iter()));
+        self.broadcast();
+    }
+}
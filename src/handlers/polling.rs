@@ -0,0 +1,203 @@
+use std::{collections::HashMap, time::Instant};
+
+use serde_json::{Map, Value};
+use tracing::{info, warn, Instrument as _};
+
+use super::Context;
+use crate::{actor, device, TELEMETRY_POLL_INTERVAL};
+
+impl Context {
+    /// Ends the locks and claims that have run out, and reconnects devices that have dropped
+    /// their connection.
+    pub(super) async fn supervise(&mut self) {
+        let mut changed = false;
+        let now = Instant::now();
+        for id in self.calibrations.expire(now) {
+            warn!("Calibration of {} timed out", id);
+            changed = true;
+            if let Some(handle) = self.handles.iter().find(|h| h.id == id) {
+                self.last_commands.remove(&handle.id);
+                actor::dispatch(&[(handle, device::Command::default())]);
+            }
+        }
+        for group in self.group_locks.expire(now) {
+            info!("Lock on {} timed out", group);
+            changed = true;
+        }
+        for id in self.arbiter.expire(now) {
+            changed = true;
+            let Some(handle) = self.handles.iter().find(|h| h.id == id) else {
+                continue;
+            };
+            // Whatever the clients that are still moving the device add up to
+            if let Some(command) = self.arbiter.combined(&id) {
+                let mounting = self.config.mounting.get(&id).copied().unwrap_or_default();
+                let trim = self.config.trim.get(&id).copied().unwrap_or_default();
+                self.last_commands.insert(id.clone(), command);
+                actor::dispatch(&[(handle, trim.apply(mounting.apply(command)))]);
+            }
+        }
+        for handle in self.handles.iter_mut() {
+            // Bluetooth devices are reconnected once the adapter comes back
+            if handle.uses_bluetooth && !self.adapter_lost_devices.is_empty() {
+                continue;
+            }
+            // A device that's busy sending a command is still alive
+            let Some(mut device) = handle.try_lock() else {
+                continue;
+            };
+            let span = handle.span.clone();
+            if device.check_connection().instrument(span.clone()).await {
+                continue;
+            }
+            if !self.supervisor.should_reconnect(&handle.id, now) {
+                changed |= handle.update(&**device);
+                continue;
+            }
+            changed = true;
+            warn!(device = %handle.id, "Connection lost, reconnecting");
+            self.last_commands.remove(&handle.id);
+            let result = match device.is_connected() {
+                true => device.reconnect().instrument(span).await,
+                false => device.connect().instrument(span).await,
+            };
+            match result {
+                Ok(_) => self.supervisor.reconnected(&handle.id),
+                Err(e) => {
+                    warn!(device = %handle.id, "Error reconnecting: {}", e);
+                    handle.record_error(&e);
+                    self.supervisor.reconnect_failed(&handle.id, now);
+                }
+            }
+            handle.update(&**device);
+        }
+        if changed {
+            self.broadcast();
+        }
+    }
+
+    pub(super) fn poll_positions(&mut self) {
+        let mut changed = false;
+        for handle in self.handles.iter_mut().filter(|h| h.connected) {
+            // Busy devices are polled next time around
+            let Some(device) = handle.try_lock() else {
+                continue;
+            };
+            changed |= handle.update(&**device);
+        }
+        if changed {
+            self.broadcast();
+        }
+    }
+
+    /// Polls the devices' telemetry, and records a history sample for each of them.
+    pub(super) async fn poll_telemetry(&mut self) {
+        let rssi_warn_below = self
+            .config
+            .bluetooth
+            .as_ref()
+            .and_then(|b| b.rssi_warn_below);
+        let mut changed = false;
+        let mut positions = HashMap::new();
+        for handle in self.handles.iter().filter(|h| h.connected) {
+            // Busy devices are polled next time around
+            let Some(mut device) = handle.try_lock() else {
+                continue;
+            };
+            if let Some(position) = device.position() {
+                positions.insert(handle.id.clone(), position);
+            }
+            match device
+                .poll_telemetry()
+                .instrument(handle.span.clone())
+                .await
+            {
+                Ok(values) if values.is_empty() => (),
+                Ok(values) => {
+                    let telemetry = self.telemetry.entry(handle.id.clone()).or_default();
+                    telemetry.extend(values);
+                    if let Some(threshold) = rssi_warn_below {
+                        flag_weak_signal(&handle.id, telemetry, threshold);
+                    }
+                    changed = true;
+                }
+                Err(e) => warn!(device = %handle.id, "Error polling telemetry: {}", e),
+            }
+        }
+        for handle in self.handles.iter() {
+            let mut sample = self.telemetry.get(&handle.id).cloned().unwrap_or_default();
+            sample.extend(handle.telemetry.clone());
+            if let Some(Value::Object(position)) = positions
+                .get(&handle.id)
+                .map(|p| serde_json::to_value(p).unwrap())
+            {
+                sample.extend(position);
+            }
+            let commands = self.command_counts.remove(&handle.id).unwrap_or(0);
+            sample.insert(
+                "commandRate".to_owned(),
+                (commands as f64 / TELEMETRY_POLL_INTERVAL.as_secs_f64()).into(),
+            );
+            sample.insert("connected".to_owned(), handle.connected.into());
+            self.history.record(&handle.id, sample);
+        }
+        if changed {
+            self.broadcast();
+        }
+    }
+
+    pub(super) async fn poll_thumbnails(&mut self) {
+        let mut changed = false;
+        for handle in self.handles.iter() {
+            if !handle.connected {
+                // Don't leave a stale image up for a camera that's gone
+                changed |= self.thumbnails.remove(&handle.id);
+                continue;
+            }
+            let Some(mut device) = handle.try_lock() else {
+                continue;
+            };
+            if !device.supports_thumbnails() {
+                continue;
+            }
+            match device.thumbnail().instrument(handle.span.clone()).await {
+                Ok(jpeg) => {
+                    self.thumbnails.update(&handle.id, jpeg);
+                    changed = true;
+                }
+                Err(e) => warn!(device = %handle.id, "Error getting thumbnail: {}", e),
+            }
+        }
+        if changed {
+            self.broadcast();
+        }
+    }
+}
+
+/// Sets `signalLow` in the telemetry of a device that reports its RSSI, warning when the signal
+/// first drops below `threshold`.
+fn flag_weak_signal(device: &str, telemetry: &mut Map<String, Value>, threshold: i64) {
+    let Some(rssi) = telemetry.get("rssi").and_then(Value::as_i64) else {
+        return;
+    };
+    let low = rssi < threshold;
+    let was_low = telemetry.get("signalLow") == Some(&Value::Bool(true));
+    if low && !was_low {
+        warn!(device = %device, "Bluetooth signal is weak: {} dBm", rssi);
+    }
+    telemetry.insert("signalLow".to_owned(), low.into());
+}
+
+#[test]
+fn test_flag_weak_signal() {
+    let mut telemetry = Map::new();
+    flag_weak_signal("ronin1", &mut telemetry, -80);
+    assert_eq!(telemetry.get("signalLow"), None);
+
+    telemetry.insert("rssi".to_owned(), (-85).into());
+    flag_weak_signal("ronin1", &mut telemetry, -80);
+    assert_eq!(telemetry.get("signalLow"), Some(&Value::Bool(true)));
+    telemetry.insert("rssi".to_owned(), (-60).into());
+    flag_weak_signal("ronin1", &mut telemetry, -80);
+    assert_eq!(telemetry.get("signalLow"), Some(&Value::Bool(false)));
+}
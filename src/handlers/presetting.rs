@@ -0,0 +1,153 @@
+use std::{error::Error, time::Duration};
+
+use tracing::{info, warn, Instrument as _};
+
+use super::{Context, DEFAULT_RECALL_DURATION};
+use crate::{ack, config, presets, GuidePresetRequest, PresetRequest, RecallPresetRequest};
+
+impl Context {
+    /// Saves a preset on the device, or its current position in the config for devices that
+    /// don't keep presets themselves.
+    pub(super) async fn save_preset(
+        &mut self,
+        request: PresetRequest,
+        ack: &mut ack::Ack,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(handle) = self.handles.iter().find(|h| h.id == request.device) else {
+            warn!("Can't save preset for unknown device {}", request.device);
+            return Ok(());
+        };
+        let mut device = handle.lock().await;
+        if device.supports_presets() {
+            info!(device = %request.device, "Saving preset {}", request.slot);
+            let saved = device.save_preset(request.slot);
+            if let Err(e) = saved.instrument(handle.span.clone()).await {
+                warn!(device = %request.device, "Error saving preset: {}", e);
+                ack.device_error(&request.device, &e);
+            }
+            return Ok(());
+        }
+        let Some(position) = device.position() else {
+            warn!(device = %request.device, "No position feedback, can't save preset");
+            ack.fail(&request.device, "no position feedback");
+            return Ok(());
+        };
+        info!(
+            "Saving preset {} for {}: {:?}",
+            request.slot, request.device, position
+        );
+        self.config
+            .presets
+            .entry(request.device)
+            .or_default()
+            .insert(request.slot, position);
+        config::save_config(&self.config).await
+    }
+
+    pub(super) async fn recall_preset(&mut self, request: RecallPresetRequest, ack: &mut ack::Ack) {
+        let Some(handle) = self.handles.iter().find(|h| h.id == request.device) else {
+            warn!("Can't recall preset for unknown device {}", request.device);
+            return;
+        };
+        let mut device = handle.lock().await;
+        if device.supports_presets() {
+            info!(device = %request.device, "Recalling preset {}", request.slot);
+            if let Some(previous) = self.recall_tasks.remove(&request.device) {
+                previous.abort();
+            }
+            let recalled = device.recall_preset(request.slot);
+            if let Err(e) = recalled.instrument(handle.span.clone()).await {
+                warn!(device = %request.device, "Error recalling preset: {}", e);
+                ack.device_error(&request.device, &e);
+            }
+            return;
+        }
+        drop(device);
+        let Some(target) = self
+            .config
+            .presets
+            .get(&request.device)
+            .and_then(|p| p.get(&request.slot))
+        else {
+            info!("No preset {} for {}", request.slot, request.device);
+            ack.fail(&request.device, format!("no preset {}", request.slot));
+            return;
+        };
+        info!("Recalling preset {} for {}", request.slot, request.device);
+        let trim = self.config.trim.get(&request.device).copied();
+        let task = tokio::spawn(presets::recall(
+            request.device.clone(),
+            trim.unwrap_or_default().offset(*target),
+            request
+                .duration_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_RECALL_DURATION),
+            self.config
+                .mounting
+                .get(&request.device)
+                .copied()
+                .unwrap_or_default(),
+            handle.position_reader(),
+            self.loopback_tx.clone(),
+        ));
+        if let Some(previous) = self.recall_tasks.insert(request.device, task) {
+            previous.abort();
+        }
+    }
+
+    /// Starts publishing guidance toward a saved preset, or stops it when no slot is given.
+    pub(super) fn guide_preset(&mut self, request: GuidePresetRequest, ack: &mut ack::Ack) {
+        if let Some(previous) = self.guide_tasks.remove(&request.device) {
+            previous.abort();
+        }
+        let Some(slot) = request.slot else {
+            if self.guidance.remove(&request.device).is_some() {
+                self.broadcast();
+            }
+            return;
+        };
+        let Some(handle) = self.handles.iter().find(|h| h.id == request.device) else {
+            warn!(
+                "Can't guide to preset for unknown device {}",
+                request.device
+            );
+            return;
+        };
+        let Some(target) = self
+            .config
+            .presets
+            .get(&request.device)
+            .and_then(|p| p.get(&slot))
+        else {
+            info!("No preset {} for {}", slot, request.device);
+            ack.fail(&request.device, format!("no preset {}", slot));
+            return;
+        };
+        let trim = self.config.trim.get(&request.device).copied();
+        let task = tokio::spawn(presets::guide(
+            request.device.clone(),
+            slot,
+            trim.unwrap_or_default().offset(*target),
+            self.config
+                .mounting
+                .get(&request.device)
+                .copied()
+                .unwrap_or_default(),
+            handle.position_reader(),
+            self.loopback_tx.clone(),
+        ));
+        self.guide_tasks.insert(request.device, task);
+    }
+
+    pub(super) fn guidance(&mut self, device: String, update: Option<presets::Guidance>) {
+        // Updates can still arrive after the task is stopped
+        if !self.guide_tasks.contains_key(&device) {
+            return;
+        }
+        match update {
+            Some(update) => self.guidance.insert(device, update),
+            None => self.guidance.remove(&device),
+        };
+        self.broadcast();
+    }
+}
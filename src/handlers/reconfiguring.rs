@@ -0,0 +1,302 @@
+use std::{collections::HashSet, error::Error};
+
+use indexmap::IndexMap;
+use itertools::Itertools;
+use tracing::{info, warn, Instrument as _};
+
+use super::Context;
+use crate::{
+    access, ack, actor, arbitration, config, device, devices, input, secrets, serialize_controls,
+    Mappings, Origin, TrimRequest,
+};
+
+impl Context {
+    pub(super) async fn save_default_controls(
+        &mut self,
+        mut request: Vec<Mappings>,
+    ) -> Result<(), Box<dyn Error>> {
+        info!("Saving button mappings...");
+        let last_nonempty = request.iter().rposition(|x| !x.is_empty());
+        self.config.default_controls = match last_nonempty {
+            Some(idx) => {
+                request.truncate(idx + 1);
+                Some(request)
+            }
+            None => None,
+        };
+        config::save_config(&self.config).await?;
+        self.state_tx.send_modify(|s| {
+            s.default_controls = serialize_controls(&self.config.default_controls);
+        });
+        Ok(())
+    }
+
+    pub(super) async fn trim(&mut self, request: TrimRequest) -> Result<(), Box<dyn Error>> {
+        let Some(handle) = self.handles.iter().find(|h| h.id == request.device) else {
+            warn!("Can't trim unknown device {}", request.device);
+            return Ok(());
+        };
+        info!("Trimming {}: {:?}", request.device, request.trim);
+        match request.trim.is_zero() {
+            true => self.config.trim.shift_remove(&request.device),
+            false => self
+                .config
+                .trim
+                .insert(request.device.clone(), request.trim),
+        };
+        config::save_config(&self.config).await?;
+        // Resend whatever the device is doing, so a new bias applies straight away
+        let command = self
+            .last_commands
+            .get(&handle.id)
+            .copied()
+            .unwrap_or_default();
+        let mounting = self
+            .config
+            .mounting
+            .get(&handle.id)
+            .copied()
+            .unwrap_or_default();
+        actor::dispatch(&[(handle, request.trim.apply(mounting.apply(command)))]);
+        self.broadcast();
+        Ok(())
+    }
+
+    /// Switches to an updated config, from an `updateDevices` request or the config file being
+    /// edited. Devices that were removed or changed are disconnected, and new ones connected.
+    pub(super) async fn reconfigure(
+        &mut self,
+        updated: Result<config::Config, Box<dyn Error>>,
+        reloaded: bool,
+        origin: Option<&Origin>,
+        ack: &mut ack::Ack,
+    ) -> Result<(), Box<dyn Error>> {
+        let updated = match updated {
+            Ok(updated) => updated,
+            Err(e) => {
+                warn!("Invalid device update: {}", e);
+                ack.reject(e);
+                return Ok(());
+            }
+        };
+        // Includes the file changing because we saved it
+        if reloaded
+            && serde_json::to_value(&updated).ok() == serde_json::to_value(&self.config).ok()
+        {
+            return Ok(());
+        }
+        if reloaded {
+            info!("Config file changed, applying it");
+        }
+        let used = devices::used_device_ids(&updated);
+        let changed = |id: &String| {
+            serde_json::to_value(self.config.devices.get(id)).ok()
+                != serde_json::to_value(updated.devices.get(id)).ok()
+        };
+        let stale = |h: &actor::DeviceHandle| !used.contains(&h.id) || changed(&h.id);
+        if let Some(Origin { source, .. }) = origin {
+            let denied =
+                denied_changes(source, &self.config, &updated, &self.visibility).join(", ");
+            if !denied.is_empty() {
+                info!("{} isn't allowed to change {}", source, denied);
+                ack.reject(format!("not allowed to change {}", denied));
+                return Ok(());
+            }
+        }
+        let added: IndexMap<&String, &config::DeviceConfig> = used
+            .iter()
+            .filter(|id| self.handles.iter().all(|h| h.id != **id) || changed(id))
+            .map(|id| (id, &updated.devices[id]))
+            .collect();
+        let added: IndexMap<String, config::DeviceConfig> = match secrets::resolve(
+            &added,
+            updated
+                .secrets_file
+                .as_deref()
+                .unwrap_or(secrets::DEFAULT_PATH.as_ref()),
+        ) {
+            Ok(added) => added,
+            Err(e) => {
+                warn!("Couldn't resolve secrets for new devices: {}", e);
+                ack.reject(e);
+                return Ok(());
+            }
+        };
+        let (removed, kept): (Vec<_>, Vec<_>) = self.handles.drain(..).partition(stale);
+        self.handles = kept;
+        for handle in removed {
+            info!("Removing {}", handle.id);
+            let mut device = handle.stop_and_lock().await;
+            if let Err(e) = device.disconnect().instrument(handle.span.clone()).await {
+                warn!(device = %handle.id, "Error disconnecting: {}", e);
+            }
+            self.last_commands.remove(&handle.id);
+            self.command_times.remove(&handle.id);
+            self.telemetry.remove(&handle.id);
+            self.guidance.remove(&handle.id);
+            self.thumbnails.remove(&handle.id);
+            self.calibrations.end(&handle.id);
+            self.arbiter.remove(&handle.id);
+            self.supervisor.resume(&handle.id);
+            for tasks in [
+                &mut self.push_in_tasks,
+                &mut self.recall_tasks,
+                &mut self.guide_tasks,
+            ] {
+                if let Some(task) = tasks.remove(&handle.id) {
+                    task.abort();
+                }
+            }
+        }
+        for (id, device_config) in added.iter() {
+            info!("Adding {}", id);
+            let mut device = devices::create_device(id, device_config, &self.central);
+            let connected = device.connect().instrument(device::span(id)).await;
+            let mut handle =
+                devices::spawn_handle(device, &self.bluetooth_runtime, &self.task_error_tx);
+            // Left to the supervisor to retry, like a device that dropped its connection
+            if let Err(e) = connected {
+                warn!(device = %id, "Error connecting: {}", e);
+                handle.record_error(&e);
+                ack.device_error(id, &e);
+            }
+            self.handles.push(handle);
+        }
+        self.handles.sort_by(|a, b| a.id.cmp(&b.id));
+        self.previews.sync(&self.handles);
+        self.config = updated;
+        self.deadbands = devices::deadbands(&self.config);
+        self.roll_locked = devices::roll_locked(&self.config);
+        self.access = access::Access::new(
+            &self.config.access,
+            &self.config.groups,
+            &self.config.devices,
+        );
+        self.arbiter =
+            arbitration::Arbiter::new(&self.config.arbitration.clone().unwrap_or_default());
+        self.visibility
+            .update(&self.config.visibility, &self.config.groups);
+        self.input_pipeline = input::Pipeline::new(
+            &self.config.inputs,
+            &self.config.input_actions,
+            &self.config.groups,
+        );
+        if !reloaded {
+            config::save_config(&self.config).await?;
+        }
+        self.broadcast_groups();
+        Ok(())
+    }
+}
+
+/// The devices `source` would change by replacing `old` with `new` but isn't allowed to: ones
+/// that are added, removed, changed, or moved between groups. Access and visibility rules can be
+/// keyed by group, so a device has to be allowed under the rules of both configs, otherwise it
+/// could be moved out of a restricted group and be open to everyone, or a hidden device moved
+/// into a group the source can see.
+fn denied_changes(
+    source: &str,
+    old: &config::Config,
+    new: &config::Config,
+    visibility: &access::Visibility,
+) -> Vec<String> {
+    let old_access = access::Access::new(&old.access, &old.groups, &old.devices);
+    let new_access = access::Access::new(&new.access, &new.groups, &new.devices);
+    let groups_of = |config: &config::Config, id: &String| {
+        config
+            .groups
+            .iter()
+            .filter(|g| g.devices.contains(id))
+            .map(|g| g.name.clone())
+            .collect::<HashSet<_>>()
+    };
+    old.devices
+        .keys()
+        .chain(new.devices.keys())
+        .unique()
+        .filter(|id| {
+            serde_json::to_value(old.devices.get(*id)).ok()
+                != serde_json::to_value(new.devices.get(*id)).ok()
+                || groups_of(old, id) != groups_of(new, id)
+        })
+        .filter(|id| {
+            !old_access.allows(source, id)
+                || !new_access.allows(source, id)
+                || !visibility.shows(source, id)
+        })
+        .cloned()
+        .collect()
+}
+
+#[test]
+fn test_denied_changes() {
+    let config = config::parse_config(
+        r#"{
+            "groups": [
+                {"name": "stage", "devices": ["cam1", "cam2"]},
+                {"name": "floor", "devices": ["cam3", "cam4"]}
+            ],
+            "devices": {
+                "cam1": {"type": "dummy", "name": "One"},
+                "cam2": {"type": "dummy", "name": "Two"},
+                "cam3": {"type": "dummy", "name": "Three"},
+                "cam4": {"type": "dummy", "name": "Four"}
+            },
+            "access": {"stage": ["token:director"]},
+            "visibility": {"token:volunteer": ["cam3"]}
+        }"#,
+    )
+    .unwrap();
+    let visibility = access::Visibility::new(&config.visibility, &config.groups);
+    let updated = |json: &str| {
+        let request = match crate::parse_request(json).unwrap() {
+            crate::Request::UpdateDevices(request) => request,
+            request => panic!("unexpected request {:?}", request),
+        };
+        devices::updated_config(&config, &request).unwrap()
+    };
+
+    // Moving cam2 out of the restricted group would leave it open to everyone
+    let regrouped = updated(
+        r#"{"updateDevices": {"groups": [
+            {"name": "stage", "devices": ["cam1"]},
+            {"name": "floor", "devices": ["cam2", "cam3", "cam4"]}
+        ]}}"#,
+    );
+    assert_eq!(
+        denied_changes("token:volunteer", &config, &regrouped, &visibility),
+        vec!["cam2"]
+    );
+    assert!(denied_changes("token:director", &config, &regrouped, &visibility).is_empty());
+
+    // Added devices have to be allowed under the new rules
+    let added = updated(
+        r#"{"updateDevices": {
+            "devices": {"cam5": {"type": "dummy", "name": "Five"}},
+            "groups": [
+                {"name": "stage", "devices": ["cam1", "cam2", "cam5"]},
+                {"name": "floor", "devices": ["cam3", "cam4"]}
+            ]
+        }}"#,
+    );
+    assert_eq!(
+        denied_changes("token:volunteer", &config, &added, &visibility),
+        vec!["cam5"]
+    );
+
+    // Nor can a device the source can't see be moved around
+    let moved = updated(
+        r#"{"updateDevices": {"groups": [
+            {"name": "stage", "devices": ["cam1", "cam2"]},
+            {"name": "floor", "devices": ["cam3"]},
+            {"name": "booth", "devices": ["cam3", "cam4"]}
+        ]}}"#,
+    );
+    assert_eq!(
+        denied_changes("token:volunteer", &config, &moved, &visibility),
+        vec!["cam4"]
+    );
+    let renamed =
+        updated(r#"{"updateDevices": {"devices": {"cam3": {"type": "dummy", "name": "Wide"}}}}"#);
+    assert!(denied_changes("token:volunteer", &config, &renamed, &visibility).is_empty());
+}
@@ -0,0 +1,109 @@
+#[cfg(feature = "health")]
+use std::{error::Error, time::Duration};
+
+#[cfg(feature = "health")]
+use reqwest::Client;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+#[cfg(feature = "health")]
+use tracing::warn;
+
+use crate::config::HealthCheckConfig;
+
+#[cfg(feature = "health")]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct HealthReport {
+    pub device: String,
+    pub values: Map<String, Value>,
+}
+
+/// Periodically fetches a camera's JSON health endpoint and reports the configured fields.
+#[cfg(feature = "health")]
+pub async fn poll_health(
+    device: String,
+    config: HealthCheckConfig,
+    reports: mpsc::UnboundedSender<HealthReport>,
+) {
+    let client = Client::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let values = match fetch_health(&client, &config).await {
+            Ok(values) => values,
+            Err(e) => {
+                warn!(device = %device, "Health check failed: {}", e);
+                Map::from_iter([("reachable".to_owned(), Value::Bool(false))])
+            }
+        };
+        let report = HealthReport {
+            device: device.clone(),
+            values,
+        };
+        if reports.send(report).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(not(feature = "health"))]
+pub async fn poll_health(
+    device: String,
+    _config: HealthCheckConfig,
+    _reports: mpsc::UnboundedSender<HealthReport>,
+) {
+    tracing::info!(
+        "Not checking the health of {}, this build of webptz doesn't include the `health` feature",
+        device
+    );
+}
+
+#[cfg(feature = "health")]
+async fn fetch_health(
+    client: &Client,
+    config: &HealthCheckConfig,
+) -> Result<Map<String, Value>, Box<dyn Error>> {
+    let text = client
+        .get(&config.url)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let body: Value = serde_json::from_str(&text)?;
+    Ok(extract_fields(&body, config))
+}
+
+#[cfg(feature = "health")]
+fn extract_fields(body: &Value, config: &HealthCheckConfig) -> Map<String, Value> {
+    let mut values = Map::from_iter([("reachable".to_owned(), Value::Bool(true))]);
+    for (name, pointer) in config.fields.iter() {
+        if let Some(v) = body.pointer(pointer) {
+            values.insert(name.clone(), v.clone());
+        }
+    }
+    values
+}
+
+#[cfg(feature = "health")]
+#[test]
+fn test_extract_fields() {
+    let config: HealthCheckConfig = serde_json::from_value(serde_json::json!({
+        "url": "http://camera/status",
+        "fields": {
+            "temperature": "/system/temp",
+            "storageRemaining": "/media/0/remaining",
+            "streaming": "/missing",
+        },
+    }))
+    .unwrap();
+    let body = serde_json::json!({
+        "system": { "temp": 41.5 },
+        "media": [{ "remaining": 3600 }],
+    });
+    assert_eq!(
+        Value::Object(extract_fields(&body, &config)),
+        serde_json::json!({ "reachable": true, "temperature": 41.5, "storageRemaining": 3600 })
+    );
+}
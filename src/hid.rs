@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use tokio::{io::AsyncReadExt as _, sync::mpsc};
+use tracing::{info, warn};
+
+use crate::{config::HidConfig, input::InputEvent};
+
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+const MAX_REPORT_SIZE: usize = 64;
+
+/// Reads input reports from a raw HID device and reports every key that changes state,
+/// reopening the device if it's unplugged.
+pub async fn listen(id: String, config: HidConfig, events: mpsc::UnboundedSender<InputEvent>) {
+    let path = config.path;
+    loop {
+        let mut file = match tokio::fs::File::open(&path).await {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("HID {}: Couldn't open {}: {}", id, path.display(), e);
+                tokio::time::sleep(REOPEN_DELAY).await;
+                continue;
+            }
+        };
+        info!("HID {}: Listening on {}", id, path.display());
+        let mut previous: Vec<u8> = vec![];
+        let mut report = [0u8; MAX_REPORT_SIZE];
+        loop {
+            // Each read returns exactly one report
+            let n = match file.read(&mut report).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("HID {}: Error reading {}: {}", id, path.display(), e);
+                    break;
+                }
+            };
+            // The first report is only used as a baseline, since besides keys, reports often
+            // contain other constant data (e.g. a unit ID)
+            if previous.is_empty() {
+                previous = report[..n].to_vec();
+                continue;
+            }
+            for (key, pressed) in changed_keys(&previous, &report[..n]) {
+                info!(
+                    "HID {}: key{} {}",
+                    id,
+                    key,
+                    if pressed { "down" } else { "up" }
+                );
+                let event = InputEvent {
+                    source: id.clone(),
+                    control: format!("key{}", key),
+                    value: if pressed { 1.0 } else { 0.0 },
+                };
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+            previous = report[..n].to_vec();
+        }
+        tokio::time::sleep(REOPEN_DELAY).await;
+    }
+}
+
+/// Bits that differ between two reports, as (bit index, new state).
+fn changed_keys(previous: &[u8], report: &[u8]) -> Vec<(usize, bool)> {
+    report
+        .iter()
+        .enumerate()
+        .flat_map(|(i, byte)| {
+            let changed = byte ^ previous.get(i).copied().unwrap_or(0);
+            (0..8)
+                .filter(move |bit| changed & (1 << bit) != 0)
+                .map(move |bit| (i * 8 + bit, byte & (1 << bit) != 0))
+        })
+        .collect()
+}
+
+#[test]
+fn test_changed_keys() {
+    assert_eq!(changed_keys(&[], &[0x00, 0x00]), vec![]);
+    assert_eq!(
+        changed_keys(&[0x00, 0x00], &[0x01, 0x80]),
+        vec![(0, true), (15, true)]
+    );
+    assert_eq!(
+        changed_keys(&[0x01, 0x80], &[0x01, 0x00]),
+        vec![(15, false)]
+    );
+}
@@ -0,0 +1,152 @@
+//! A rolling record of each device's telemetry, so intermittent problems can be looked into
+//! after the fact by downloading it as CSV.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use indexmap::IndexSet;
+use itertools::Itertools as _;
+use serde_json::{Map, Value};
+
+use crate::{access::Visibility, auth::Client};
+
+/// How many samples are kept per device, which is four hours at one sample every five seconds.
+const LENGTH: usize = 2880;
+
+#[derive(Clone, Default)]
+pub struct History(Arc<RwLock<HashMap<String, VecDeque<Sample>>>>);
+
+struct Sample {
+    /// Milliseconds since the Unix epoch.
+    time: u64,
+    values: Map<String, Value>,
+}
+
+impl History {
+    pub fn record(&self, id: &str, values: Map<String, Value>) {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.push(id, time.as_millis() as u64, values);
+    }
+
+    fn push(&self, id: &str, time: u64, values: Map<String, Value>) {
+        let mut history = self.0.write().unwrap();
+        let samples = history.entry(id.to_owned()).or_default();
+        if samples.len() == LENGTH {
+            samples.pop_front();
+        }
+        samples.push_back(Sample { time, values });
+    }
+
+    /// The device's samples, oldest first, with a column for every value any of them has.
+    fn csv(&self, id: &str) -> Option<String> {
+        let history = self.0.read().unwrap();
+        let samples = history.get(id)?;
+        let columns: IndexSet<&String> = samples.iter().flat_map(|s| s.values.keys()).collect();
+        let mut csv = String::new();
+        csv.push_str(
+            &std::iter::once("time")
+                .chain(columns.iter().map(|c| c.as_str()))
+                .map(escape)
+                .join(","),
+        );
+        csv.push('\n');
+        for sample in samples {
+            let values = columns.iter().map(|c| match sample.values.get(*c) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(s)) => escape(s),
+                Some(Value::Number(n)) => n.to_string(),
+                Some(Value::Bool(b)) => b.to_string(),
+                Some(value) => escape(&value.to_string()),
+            });
+            csv.push_str(
+                &std::iter::once(sample.time.to_string())
+                    .chain(values)
+                    .join(","),
+            );
+            csv.push('\n');
+        }
+        Some(csv)
+    }
+}
+
+/// Quotes a field if it has anything in it that CSV readers would otherwise misread.
+fn escape(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+pub fn router(history: History, visibility: Arc<Visibility>) -> Router {
+    Router::new().route(
+        "/api/devices/:id/history.csv",
+        get(move |id, client| export(history, visibility, id, client)),
+    )
+}
+
+async fn export(
+    history: History,
+    visibility: Arc<Visibility>,
+    Path(id): Path<String>,
+    client: Option<Extension<Client>>,
+) -> Response {
+    if !visibility.shows(&Client::source(client), &id) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match history.csv(&id) {
+        Some(csv) => (
+            [
+                (header::CONTENT_TYPE, "text/csv".to_owned()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}-history.csv\"", id),
+                ),
+            ],
+            csv,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[test]
+fn test_history() {
+    use serde_json::json;
+
+    let values = |v: Value| v.as_object().unwrap().clone();
+    let history = History::default();
+    assert_eq!(history.csv("cam1"), None);
+    history.push(
+        "cam1",
+        1000,
+        values(json!({"battery": 80, "connected": true})),
+    );
+    history.push(
+        "cam1",
+        2000,
+        values(json!({"battery": 79, "mode": "a, b", "pan": 1.5})),
+    );
+    assert_eq!(
+        history.csv("cam1").unwrap(),
+        "time,battery,connected,mode,pan\n\
+         1000,80,true,,\n\
+         2000,79,,\"a, b\",1.5\n"
+    );
+
+    for time in 0..LENGTH as u64 {
+        history.push("cam1", time, Map::new());
+    }
+    assert_eq!(history.0.read().unwrap()["cam1"].len(), LENGTH);
+}
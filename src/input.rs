@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    config::{Group, InputAction, InputAxis, InputBinding, Ramp, ShapingStage},
+    device::{Command, Velocity},
+    CommandRequest,
+};
+
+/// A raw value from a server-side input source (OSC, MIDI, HID, ...). Analog controls are
+/// expected to be normalized to [-1.0, 1.0] and buttons to 0.0/1.0.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InputEvent {
+    pub source: String,
+    pub control: String,
+    pub value: f64,
+}
+
+/// A single transformation applied to an input value before it's routed to groups.
+pub trait Stage: Send {
+    fn apply(&mut self, value: f64) -> f64;
+}
+
+impl Stage for ShapingStage {
+    fn apply(&mut self, value: f64) -> f64 {
+        match *self {
+            ShapingStage::Deadzone { threshold } => {
+                if value.abs() < threshold || threshold >= 1.0 {
+                    0.0
+                } else {
+                    value.signum() * (value.abs() - threshold) / (1.0 - threshold)
+                }
+            }
+            ShapingStage::Curve { exponent } => value.signum() * value.abs().powf(exponent),
+            ShapingStage::Scale { factor } => value * factor,
+            ShapingStage::Invert => -value,
+        }
+    }
+}
+
+/// How often ramping bindings are brought up to date while their buttons are held.
+pub const RAMP_INTERVAL: Duration = Duration::from_millis(50);
+
+impl Ramp {
+    fn value(&self, held: Duration) -> f64 {
+        let attack = Duration::from_millis(self.attack_ms).as_secs_f64();
+        let progress = match attack > 0.0 {
+            true => (held.as_secs_f64() / attack).min(1.0),
+            false => 1.0,
+        };
+        self.start + (self.max - self.start) * progress
+    }
+}
+
+struct Binding {
+    source: String,
+    control: String,
+    axis: InputAxis,
+    stages: Vec<Box<dyn Stage>>,
+    ramp: Option<Ramp>,
+    /// When the button of a ramping binding was pressed.
+    held_since: Option<Instant>,
+    groups: Vec<String>,
+    value: f64,
+}
+
+impl Binding {
+    /// Brings the value up to date with the control's raw value, returning the new value.
+    fn update(&mut self, raw: f64, now: Instant) -> f64 {
+        let raw = match &self.ramp {
+            None => raw,
+            Some(ramp) => match raw > 0.5 {
+                true => ramp.value(now - *self.held_since.get_or_insert(now)),
+                false => {
+                    self.held_since = None;
+                    0.0
+                }
+            },
+        };
+        self.value = self.stages.iter_mut().fold(raw, |v, stage| stage.apply(v));
+        self.value
+    }
+}
+
+/// Turns raw input events into group commands: each configured binding maps one control onto
+/// an axis, shapes the value through its stages, and routes it to its groups. Bindings that
+/// target the same axis of a group are summed.
+pub struct Pipeline {
+    bindings: Vec<Binding>,
+    actions: Vec<(InputAction, f64)>,
+    groups: Vec<Group>,
+}
+
+impl Pipeline {
+    pub fn new(bindings: &[InputBinding], actions: &[InputAction], groups: &[Group]) -> Pipeline {
+        Pipeline {
+            bindings: bindings
+                .iter()
+                .map(|b| Binding {
+                    source: b.source.clone(),
+                    control: b.control.clone(),
+                    axis: b.axis.clone(),
+                    stages: b
+                        .stages
+                        .iter()
+                        .map(|s| Box::new(s.clone()) as Box<dyn Stage>)
+                        .collect(),
+                    ramp: b.ramp.clone(),
+                    held_since: None,
+                    groups: b.groups.clone(),
+                    value: 0.0,
+                })
+                .collect(),
+            actions: actions.iter().map(|a| (a.clone(), 0.0)).collect(),
+            groups: groups.to_vec(),
+        }
+    }
+
+    /// Requests of the actions whose control was just pressed.
+    pub fn actions(&mut self, event: &InputEvent) -> Vec<serde_json::Value> {
+        let mut requests = vec![];
+        for (action, value) in self
+            .actions
+            .iter_mut()
+            .filter(|(a, _)| a.source == event.source && a.control == event.control)
+        {
+            if *value <= 0.5 && event.value > 0.5 {
+                requests.push(action.request.clone());
+            }
+            *value = event.value;
+        }
+        requests
+    }
+
+    pub fn handle(&mut self, event: &InputEvent, now: Instant) -> Vec<CommandRequest> {
+        let mut affected: Vec<String> = vec![];
+        let mut autofocus: Vec<String> = vec![];
+        for binding in self
+            .bindings
+            .iter_mut()
+            .filter(|b| b.source == event.source && b.control == event.control)
+        {
+            let previous = binding.value;
+            let value = binding.update(event.value, now);
+            if binding.axis == InputAxis::Autofocus && previous <= 0.5 && value > 0.5 {
+                autofocus.extend(binding.groups.iter().cloned());
+            }
+            affected.extend(binding.groups.iter().cloned());
+        }
+        self.requests(&affected, &autofocus)
+    }
+
+    /// Whether any binding ramps, and so needs `ramp` to be called every `RAMP_INTERVAL`.
+    pub fn has_ramps(&self) -> bool {
+        self.bindings.iter().any(|b| b.ramp.is_some())
+    }
+
+    /// Moves ramping bindings whose buttons are held along, with the source each request is
+    /// from. Nothing is sent once they've reached their maximum.
+    pub fn ramp(&mut self, now: Instant) -> Vec<(String, CommandRequest)> {
+        let mut ramped: Vec<(String, Vec<String>)> = vec![];
+        for binding in self.bindings.iter_mut().filter(|b| b.held_since.is_some()) {
+            let previous = binding.value;
+            if binding.update(1.0, now) != previous {
+                ramped.push((binding.source.clone(), binding.groups.clone()));
+            }
+        }
+        ramped
+            .into_iter()
+            .flat_map(|(source, groups)| {
+                self.requests(&groups, &[])
+                    .into_iter()
+                    .map(move |r| (source.clone(), r))
+            })
+            .collect()
+    }
+
+    fn requests(&self, affected: &[String], autofocus: &[String]) -> Vec<CommandRequest> {
+        self.groups
+            .iter()
+            .filter(|g| affected.contains(&g.name))
+            .map(|g| {
+                let mut command = self.group_command(&g.name);
+                command.autofocus = autofocus.contains(&g.name);
+                CommandRequest {
+                    devices: g.devices.clone(),
+                    command,
+                }
+            })
+            .collect()
+    }
+
+    fn group_command(&self, group: &str) -> Command {
+        let mut values: HashMap<&InputAxis, f64> = HashMap::new();
+        for binding in self
+            .bindings
+            .iter()
+            .filter(|b| b.groups.iter().any(|g| g == group))
+        {
+            *values.entry(&binding.axis).or_default() += binding.value;
+        }
+        let value = |axis: InputAxis| Velocity::new(values.get(&axis).copied().unwrap_or(0.0));
+        Command {
+            pan: value(InputAxis::Pan),
+            tilt: value(InputAxis::Tilt),
+            roll: value(InputAxis::Roll),
+            zoom: value(InputAxis::Zoom),
+            focus: value(InputAxis::Focus),
+            iris: value(InputAxis::Iris),
+            autofocus: false,
+            record: None,
+            shutter: false,
+            focus_point: None,
+        }
+    }
+}
+
+#[test]
+fn test_pipeline() {
+    let bindings: Vec<InputBinding> = serde_json::from_value(serde_json::json!([
+        {
+            "source": "osc",
+            "control": "/x",
+            "axis": "pan",
+            "stages": [{ "type": "deadzone", "threshold": 0.5 }, { "type": "invert" }],
+            "groups": ["a"],
+        },
+        { "source": "osc", "control": "/nudge", "axis": "pan", "groups": ["a"] },
+        { "source": "osc", "control": "/af", "axis": "autofocus", "groups": ["a", "b"] },
+        {
+            "source": "osc",
+            "control": "/wide",
+            "axis": "zoom",
+            "stages": [{ "type": "invert" }],
+            "ramp": { "attackMs": 1000, "start": 0.5 },
+            "groups": ["b"],
+        },
+    ]))
+    .unwrap();
+    let groups = vec![
+        Group {
+            name: "a".to_owned(),
+            devices: vec!["cam1".to_owned()],
+        },
+        Group {
+            name: "b".to_owned(),
+            devices: vec!["cam2".to_owned()],
+        },
+    ];
+    let actions: Vec<InputAction> = serde_json::from_value(serde_json::json!([
+        { "source": "osc", "control": "/af", "request": { "wake": { "devices": ["cam2"] } } },
+    ]))
+    .unwrap();
+    let mut pipeline = Pipeline::new(&bindings, &actions, &groups);
+    let now = Instant::now();
+    let event = |control: &str, value: f64| InputEvent {
+        source: "osc".to_owned(),
+        control: control.to_owned(),
+        value,
+    };
+
+    assert!(pipeline.handle(&event("/unbound", 1.0), now).is_empty());
+
+    let requests = pipeline.handle(&event("/x", 0.75), now);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].devices, vec!["cam1"]);
+    assert_eq!(requests[0].command.pan.get(), -0.5);
+
+    let requests = pipeline.handle(&event("/nudge", 0.25), now);
+    assert_eq!(requests[0].command.pan.get(), -0.25);
+
+    assert_eq!(pipeline.actions(&event("/af", 1.0)).len(), 1);
+    let requests = pipeline.handle(&event("/af", 1.0), now);
+    assert_eq!(requests.len(), 2);
+    assert!(requests.iter().all(|r| r.command.autofocus));
+    let requests = pipeline.handle(&event("/af", 1.0), now);
+    assert!(requests.iter().all(|r| !r.command.autofocus));
+    assert!(pipeline.actions(&event("/af", 1.0)).is_empty());
+
+    // Holding a ramped button speeds up until it reaches the maximum
+    assert!(pipeline.has_ramps());
+    let requests = pipeline.handle(&event("/wide", 1.0), now);
+    assert_eq!(requests[0].command.zoom.get(), -0.5);
+    let requests = pipeline.ramp(now + Duration::from_millis(500));
+    assert_eq!(requests[0].0, "osc");
+    assert_eq!(requests[0].1.devices, vec!["cam2"]);
+    assert_eq!(requests[0].1.command.zoom.get(), -0.75);
+    assert_eq!(
+        pipeline.ramp(now + Duration::from_secs(2))[0]
+            .1
+            .command
+            .zoom
+            .get(),
+        -1.0
+    );
+    assert!(pipeline.ramp(now + Duration::from_secs(3)).is_empty());
+    let requests = pipeline.handle(&event("/wide", 0.0), now + Duration::from_secs(3));
+    assert_eq!(requests[0].command.zoom.get(), 0.0);
+    assert!(pipeline.ramp(now + Duration::from_secs(4)).is_empty());
+}
@@ -0,0 +1,1192 @@
+//! Controls PTZ cameras, gimbals, and lenses from a browser, a gamepad, or anything else that can
+//! send it requests. The `webptz` binary is a thin wrapper around `Server`.
+
+use axum::extract::ws::{Message, WebSocket};
+use axum::extract::{ConnectInfo, Query, WebSocketUpgrade};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::routing::any;
+use axum::Extension;
+use axum::Router;
+#[cfg(not(debug_assertions))]
+use axum_embed::ServeEmbed;
+use axum_extra::{headers, TypedHeader};
+use config::{Group, Mappings};
+use device::{AdapterEvent, Device};
+use futures::{SinkExt as _, StreamExt};
+use health::HealthReport;
+use indexmap::IndexMap;
+use itertools::Itertools;
+#[cfg(not(debug_assertions))]
+use rust_embed::RustEmbed;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::net::SocketAddr;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal;
+use tokio::sync::mpsc;
+use tokio::sync::watch;
+use tower_http::services::ServeDir;
+use tower_http::set_header::SetResponseHeaderLayer;
+use tracing::{debug, info, warn};
+
+mod access;
+mod ack;
+mod actor;
+mod api;
+mod arbitration;
+mod auth;
+mod broadcast;
+mod calibration;
+mod compound;
+pub mod config;
+pub mod ctl;
+pub mod device;
+mod devices;
+pub mod exercise;
+mod handlers;
+mod health;
+mod hid;
+mod history;
+mod input;
+mod line;
+mod listeners;
+mod locks;
+pub mod logs;
+mod mqtt;
+mod osc;
+mod panel;
+mod presets;
+mod preview;
+mod reload;
+pub mod runtime;
+pub mod scan;
+pub mod secrets;
+pub mod send;
+pub mod simulate;
+mod supervisor;
+mod thumbnails;
+mod timecode;
+mod tls;
+
+pub use devices::{create_configured_device, create_device};
+
+enum Operation {
+    Command(CommandRequest),
+    Disconnect(DisconnectRequest),
+    Reconnect(ReconnectRequest),
+    Repair(RepairRequest),
+    Wake(WakeRequest),
+    PushIn(PushInRequest),
+    MoveTo(MoveToRequest),
+    SetProperty(SetPropertyRequest),
+    CustomCommand(CustomCommandRequest),
+    SavePreset(PresetRequest),
+    RecallPreset(RecallPresetRequest),
+    GuidePreset(GuidePresetRequest),
+    Guidance(String, Option<presets::Guidance>),
+    Shutdown,
+    SaveDefaultControls(Vec<Mappings>),
+    Adapter(AdapterEvent),
+    Health(HealthReport),
+    TaskError(actor::TaskError),
+    PollTelemetry,
+    PollPositions,
+    PollThumbnails,
+    Supervise,
+    Watchdog,
+    Input(input::InputEvent),
+    RampInputs,
+    Timecode(timecode::Timecode),
+    /// An operation from a web client or input source, which is only applied to the devices
+    /// that source is allowed to control.
+    Sourced(Origin, Box<Operation>),
+    /// An operation whose sender wants to hear how it went.
+    Acknowledged(ack::Ack, Box<Operation>),
+    Calibrate(CalibrateRequest),
+    EndCalibration(EndCalibrationRequest),
+    Trim(TrimRequest),
+    UpdateDevices(UpdateDevicesRequest),
+    Lock(LockRequest),
+    Unlock(UnlockRequest),
+    Handoff(HandoffRequest),
+    /// A websocket client connected, so locks can be handed to it.
+    ClientConnected(String),
+    ClientDisconnected(String),
+    /// The config file was edited.
+    ReloadConfig(Box<config::Config>),
+}
+
+/// Who sent an operation: the source named in access rules, and the particular client within it
+/// that calibration locks are held by. Sources without separate connections (HTTP, OSC, ...)
+/// count as a single client.
+#[derive(Debug, Clone, PartialEq)]
+struct Origin {
+    source: String,
+    client: String,
+}
+
+impl Origin {
+    fn new(source: &str) -> Origin {
+        Origin {
+            source: source.to_owned(),
+            client: source.to_owned(),
+        }
+    }
+}
+
+const TAG_PREFIX: &str = "tag:";
+const TELEMETRY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+const POSITION_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SUPERVISE_INTERVAL: Duration = Duration::from_secs(1);
+const WATCHDOG_MIN_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct State {
+    instance: String,
+    groups: Vec<Group>,
+    devices: HashMap<String, DeviceStatus>,
+    /// Kept pre-serialized, since it rarely changes but is sent along with every update.
+    default_controls: Option<Box<RawValue>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DeviceStatus {
+    id: String,
+    name: String,
+    connected: bool,
+    reconnecting: bool,
+    repairable: bool,
+    wakeable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    telemetry: Option<Map<String, Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    guidance: Option<presets::Guidance>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// URL of the device's latest preview image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<String>,
+    /// URL of the device's live view as MJPEG, for cameras that can stream one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<String>,
+    /// Whether the device is locked to a single client for calibration.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    calibrating: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<actor::ErrorReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trim: Option<config::Trim>,
+    /// The attached lens, for cameras that report it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lens: Option<String>,
+    /// Where the device is pointing, for devices with position feedback.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    position: Option<device::Position>,
+    /// The client whose commands the device is following while it's being moved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    /// The client that locked one of the device's groups, which is the only one that can
+    /// control it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locked_by: Option<String>,
+}
+
+#[cfg(not(debug_assertions))]
+#[derive(RustEmbed, Clone)]
+#[folder = "http/"]
+struct Assets;
+
+/// Connects to the configured devices and serves the web UI and the other control interfaces,
+/// for use from other programs as well as by the `webptz` binary. Requests can be sent before
+/// it's running, and are handled once it's connected to its devices.
+pub struct Server {
+    config: config::Config,
+    logs: logs::Logs,
+    command_tx: mpsc::UnboundedSender<Operation>,
+    command_rx: mpsc::UnboundedReceiver<Operation>,
+}
+
+impl Server {
+    pub fn new(config: config::Config) -> Server {
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        Server {
+            config,
+            logs: logs::Logs::new(),
+            command_tx,
+            command_rx,
+        }
+    }
+
+    /// Serves these logs on `/logs`, rather than ones nothing is recorded to.
+    pub fn with_logs(self, logs: logs::Logs) -> Server {
+        Server { logs, ..self }
+    }
+
+    pub fn controller(&self) -> Controller {
+        Controller(self.command_tx.clone())
+    }
+
+    /// Runs until it's shut down by a signal or a `Controller`.
+    pub async fn run(self) -> Result<(), Box<dyn Error>> {
+        run(self.config, self.logs, self.command_tx, self.command_rx).await
+    }
+}
+
+/// Sends requests to a `Server`. These are trusted like the server's own operations, so access
+/// rules and calibration locks don't apply to them.
+#[derive(Clone)]
+pub struct Controller(mpsc::UnboundedSender<Operation>);
+
+impl Controller {
+    pub fn command(
+        &self,
+        devices: Vec<String>,
+        command: device::Command,
+    ) -> Result<(), Box<dyn Error>> {
+        self.send(Operation::Command(CommandRequest { devices, command }))
+    }
+
+    /// Sends a request in the websocket format, e.g. `{"recallPreset": {"device": "cam1", "slot": 1}}`.
+    pub fn request(&self, json: &str) -> Result<(), Box<dyn Error>> {
+        self.send(parse_request(json)?.into())
+    }
+
+    /// Stops the server, disconnecting its devices first.
+    pub fn shutdown(&self) -> Result<(), Box<dyn Error>> {
+        self.send(Operation::Shutdown)
+    }
+
+    fn send(&self, operation: Operation) -> Result<(), Box<dyn Error>> {
+        self.0
+            .send(operation)
+            .map_err(|_| "server has stopped".into())
+    }
+}
+
+async fn run(
+    config: config::Config,
+    logs: logs::Logs,
+    command_tx: mpsc::UnboundedSender<Operation>,
+    mut command_rx: mpsc::UnboundedReceiver<Operation>,
+) -> Result<(), Box<dyn Error>> {
+    // Before anything connects, so a bad config doesn't leave devices half set up
+    validate(&config)?;
+    let runtime_config = config.runtime.clone().unwrap_or_default();
+    let central = device::bluetooth_adapter().await?;
+
+    // Resolved together so the secrets file is only opened once
+    let (device_configs, auth, mqtt_config): (
+        IndexMap<String, config::DeviceConfig>,
+        Option<config::AuthConfig>,
+        _,
+    ) = secrets::resolve(
+        &(&config.devices, &config.auth, &config.mqtt),
+        config
+            .secrets_file
+            .as_deref()
+            .unwrap_or(secrets::DEFAULT_PATH.as_ref()),
+    )?;
+    let mut devices: Vec<Box<dyn Device>> = devices::used_device_ids(&config)
+        .iter()
+        .map(|id| match device_configs.get(id) {
+            Some(device_config) => Ok(devices::create_device(id, device_config, &central)),
+            None => Err(format!("no config for device {}", id)),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let connect_errors = devices::connect_devices(&mut devices).await;
+    let bluetooth_runtime = match runtime_config.bluetooth_thread {
+        true => Some(runtime::spawn_bluetooth_runtime()?),
+        false => None,
+    };
+    let (task_error_tx, task_error_rx) = mpsc::unbounded_channel::<actor::TaskError>();
+    forward_operations(task_error_rx, command_tx.clone(), Operation::TaskError);
+    let mut handles: Vec<actor::DeviceHandle> = devices
+        .into_iter()
+        .map(|d| devices::spawn_handle(d, &bluetooth_runtime, &task_error_tx))
+        .collect();
+    for handle in handles.iter_mut() {
+        if let Some(e) = connect_errors.get(&handle.id) {
+            handle.record_error(e);
+        }
+    }
+
+    let (adapter_tx, adapter_rx) = mpsc::unbounded_channel::<AdapterEvent>();
+    #[cfg(feature = "bluetooth")]
+    tokio::spawn(device::ble::monitor_adapter(
+        central.clone(),
+        config.bluetooth.clone().and_then(|b| b.reset_command),
+        adapter_tx,
+    ));
+    #[cfg(not(feature = "bluetooth"))]
+    drop(adapter_tx);
+    forward_operations(adapter_rx, command_tx.clone(), Operation::Adapter);
+    let (health_tx, health_rx) = mpsc::unbounded_channel::<HealthReport>();
+    for (id, health_config) in config.health_checks.iter() {
+        tokio::spawn(health::poll_health(
+            id.clone(),
+            health_config.clone(),
+            health_tx.clone(),
+        ));
+    }
+    forward_operations(health_rx, command_tx.clone(), Operation::Health);
+    let (input_tx, input_rx) = mpsc::unbounded_channel::<input::InputEvent>();
+    for (id, panel_config) in config.panels.iter() {
+        tokio::spawn(panel::listen(
+            id.clone(),
+            panel_config.clone(),
+            input_tx.clone(),
+        ));
+    }
+    for (id, hid_config) in config.hid.iter() {
+        tokio::spawn(hid::listen(
+            id.clone(),
+            hid_config.clone(),
+            input_tx.clone(),
+        ));
+    }
+    forward_operations(input_rx, command_tx.clone(), Operation::Input);
+    if let Some(line_config) = &config.line_protocol {
+        tokio::spawn(line::listen(
+            line_config.clone(),
+            auth.clone(),
+            command_tx.clone(),
+        ));
+    }
+    if let Some(path) = config.path.clone() {
+        let command_tx = command_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = reload::watch(path, command_tx).await {
+                warn!("Not watching the config file for changes: {}", e);
+            }
+        });
+    }
+    if let Some(osc_config) = &config.osc {
+        tokio::spawn(osc::listen(
+            osc_config.clone(),
+            config.groups.clone(),
+            command_tx.clone(),
+        ));
+    }
+    if let Some(timecode_config) = &config.timecode {
+        let (timecode_tx, timecode_rx) = mpsc::unbounded_channel::<timecode::Timecode>();
+        tokio::spawn(timecode::listen(
+            timecode_config.midi_device.clone(),
+            timecode_tx,
+        ));
+        forward_operations(timecode_rx, command_tx.clone(), Operation::Timecode);
+    }
+    let poll_tx = command_tx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TELEMETRY_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if poll_tx.send(Operation::PollTelemetry).is_err() {
+                break;
+            }
+        }
+    });
+
+    let position_tx = command_tx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POSITION_POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            if position_tx.send(Operation::PollPositions).is_err() {
+                break;
+            }
+        }
+    });
+
+    if let Some(thumbnail_config) = &config.thumbnails {
+        let thumbnail_tx = command_tx.clone();
+        let period = Duration::from_secs(thumbnail_config.interval_secs);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if thumbnail_tx.send(Operation::PollThumbnails).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let supervise_tx = command_tx.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SUPERVISE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if supervise_tx.send(Operation::Supervise).is_err() {
+                break;
+            }
+        }
+    });
+
+    if let Some(watchdog_config) = &config.watchdog {
+        let watchdog_tx = command_tx.clone();
+        let period =
+            Duration::from_millis(watchdog_config.timeout_ms / 5).max(WATCHDOG_MIN_INTERVAL);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if watchdog_tx.send(Operation::Watchdog).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let tls = config.tls.as_ref().map(tls::acceptor).transpose()?;
+    let listen_addresses = config.listen_addresses();
+    let mut context = handlers::Context::new(
+        config,
+        central,
+        bluetooth_runtime,
+        task_error_tx,
+        command_tx.clone(),
+        handles,
+    );
+    let state_rx = context.subscribe();
+
+    if context.has_ramps() {
+        let ramp_tx = command_tx.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(input::RAMP_INTERVAL);
+            loop {
+                interval.tick().await;
+                if ramp_tx.send(Operation::RampInputs).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    if let Some(mqtt_config) = mqtt_config {
+        tokio::spawn(mqtt::bridge(
+            mqtt_config,
+            command_tx.clone(),
+            state_rx.clone(),
+        ));
+    }
+
+    // Routes that keep their own state, served behind the same authentication as `/control`
+    let routes = thumbnails::router(context.thumbnails.clone(), context.visibility.clone())
+        .merge(logs::router(logs, context.visibility.clone()))
+        .merge(history::router(
+            context.history.clone(),
+            context.visibility.clone(),
+        ))
+        .merge(preview::router(
+            context.previews.clone(),
+            context.visibility.clone(),
+        ));
+    tokio::spawn(web_server(
+        listen_addresses,
+        tls,
+        auth,
+        command_tx,
+        state_rx,
+        routes,
+        context.visibility.clone(),
+    ));
+
+    while let Some(operation) = command_rx.recv().await {
+        let (mut ack, operation) = match operation {
+            Operation::Acknowledged(ack, operation) => (ack, *operation),
+            operation => (ack::Ack::default(), operation),
+        };
+        let (origin, mut operation) = match operation {
+            Operation::Sourced(origin, operation) => (Some(origin), *operation),
+            operation => (None, operation),
+        };
+        if !context.admit(&mut operation, origin.as_ref(), &mut ack) {
+            continue;
+        }
+        if context
+            .handle(operation, origin, &mut ack)
+            .await?
+            .is_break()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Feeds events from a background task into the command loop.
+fn forward_operations<T: Send + 'static>(
+    mut rx: mpsc::UnboundedReceiver<T>,
+    command_tx: mpsc::UnboundedSender<Operation>,
+    to_operation: fn(T) -> Operation,
+) {
+    tokio::spawn(async move {
+        while let Some(x) = rx.recv().await {
+            if command_tx.send(to_operation(x)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Checks the parts of a config that `config::parse_config` can't, like the requests sent by
+/// input actions and timecode cues.
+pub fn validate(config: &config::Config) -> Result<(), Box<dyn Error>> {
+    for action in config.input_actions.iter() {
+        parse_request(&action.request.to_string()).map_err(|e| {
+            format!(
+                "invalid request for input action {} {}: {}",
+                action.source, action.control, e
+            )
+        })?;
+    }
+    let cues = config.timecode.iter().flat_map(|t| t.cues.iter());
+    for cue in cues {
+        parse_request(&cue.request.to_string())
+            .map_err(|e| format!("invalid request for timecode cue at {}: {}", cue.at, e))?;
+    }
+    Ok(())
+}
+
+async fn web_server(
+    addresses: Vec<SocketAddr>,
+    tls: Option<tokio_native_tls::TlsAcceptor>,
+    auth: Option<config::AuthConfig>,
+    command_tx: mpsc::UnboundedSender<Operation>,
+    state_rx: watch::Receiver<State>,
+    routes: Router,
+    visibility: Arc<access::Visibility>,
+) {
+    let assets_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("http");
+
+    #[cfg(debug_assertions)]
+    let file_server = ServeDir::new(assets_dir).append_index_html_on_directories(true);
+
+    #[cfg(not(debug_assertions))]
+    let file_server = ServeEmbed::<Assets>::new();
+
+    let listeners = listeners::Listeners::default();
+    let cloned_tx = command_tx.clone();
+    let cloned_rx = broadcast::serialize_states(state_rx.clone(), visibility.clone());
+    let mut control = Router::new()
+        .route(
+            "/control",
+            any(|ws, user_agent, info, client, query| {
+                ws_handler(cloned_tx, cloned_rx, ws, user_agent, info, client, query)
+            }),
+        )
+        .merge(api::router(
+            command_tx.clone(),
+            state_rx.clone(),
+            visibility,
+        ))
+        .merge(routes)
+        .merge(listeners::router(listeners.clone()));
+    if let Some(auth) = auth {
+        control = control.route_layer(middleware::from_fn_with_state(
+            Arc::new(auth),
+            auth::require,
+        ));
+    }
+    let app = Router::new()
+        .fallback_service(file_server)
+        .layer(SetResponseHeaderLayer::overriding(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-cache"),
+        ))
+        .merge(control);
+
+    let bound = listeners.bind_all(&addresses).await;
+    if bound.iter().all(Option::is_none) {
+        warn!("Failed to bind to any of {:?}", addresses);
+        // The loop may already have exited, in which case there's nothing left to stop
+        let _ = command_tx.send(Operation::Shutdown);
+        return;
+    }
+    let serving = addresses.into_iter().zip(bound).map(|(address, listener)| {
+        let app = app.clone();
+        let tls = tls.clone();
+        let listeners = listeners.clone();
+        tokio::spawn(async move {
+            let serve = |listener| {
+                let app = app.clone();
+                let tls = tls.clone();
+                async move {
+                    match tls {
+                        Some(acceptor) => {
+                            tls::serve(listener, acceptor, app, std::future::pending()).await
+                        }
+                        None => {
+                            let service = app.into_make_service_with_connect_info::<SocketAddr>();
+                            if let Err(e) = axum::serve(listener, service).await {
+                                warn!("Error serving: {}", e);
+                            }
+                        }
+                    }
+                }
+            };
+            listeners
+                .keep_serving(address, listener, serve, shutdown_signal())
+                .await
+        })
+    });
+    // Listeners fail independently, so only stop once they've all been told to
+    futures::future::join_all(serving.collect_vec()).await;
+    let _ = command_tx.send(Operation::Shutdown);
+}
+
+fn serialize_controls(controls: &Option<Vec<Mappings>>) -> Option<Box<RawValue>> {
+    controls
+        .as_ref()
+        .map(|c| serde_json::value::to_raw_value(c).unwrap())
+}
+
+async fn ws_handler(
+    command_tx: mpsc::UnboundedSender<Operation>,
+    state_rx: watch::Receiver<Arc<broadcast::Snapshot>>,
+    ws: WebSocketUpgrade,
+    user_agent: Option<TypedHeader<headers::UserAgent>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    client: Option<Extension<auth::Client>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let topics = match broadcast::parse_topics(query.get("topics").map(String::as_str)) {
+        Ok(x) => x,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
+        user_agent.to_string()
+    } else {
+        String::from("Unknown browser")
+    };
+    let source = auth::Client::source(client);
+    // Each connection is its own client, even when several share a source. The ID is made up
+    // here rather than taken from the client, so nobody can claim another client's locks.
+    let id = &uuid::Uuid::new_v4().simple().to_string()[..8];
+    let origin = Origin {
+        client: format!("{}@{}", source, id),
+        source,
+    };
+    info!("`{user_agent}` at {addr} connected as {}.", origin.client);
+    let view = broadcast::View {
+        topics,
+        source: origin.source.clone(),
+    };
+    // finalize the upgrade process by returning upgrade callback.
+    ws.on_upgrade(move |socket| handle_socket(command_tx, state_rx, socket, addr, origin, view))
+        .into_response()
+}
+
+async fn handle_socket(
+    command_tx: mpsc::UnboundedSender<Operation>,
+    mut state_rx: watch::Receiver<Arc<broadcast::Snapshot>>,
+    socket: WebSocket,
+    who: SocketAddr,
+    origin: Origin,
+    view: broadcast::View,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let (result_tx, mut result_rx) = mpsc::unbounded_channel::<String>();
+    // Tells the client who it is, so it can be named in a handoff
+    let _ = command_tx.send(Operation::ClientConnected(origin.client.clone()));
+    let _ = result_tx.send(serde_json::json!({ "client": origin.client }).to_string());
+    let client = origin.client.clone();
+    let disconnected_tx = command_tx.clone();
+
+    let mut send_task = tokio::spawn(async move {
+        let mut sent: Option<Arc<broadcast::Snapshot>> = None;
+        loop {
+            let snapshot = state_rx.borrow_and_update().clone();
+            let json = match &sent {
+                None => Some(snapshot.full(&view)),
+                Some(previous) => snapshot.update_from(previous, &view),
+            };
+            if let Some(json) = json {
+                if let Err(e) = sender.send(Message::Text(json)).await {
+                    warn!("failed to send state update: {e}");
+                    break;
+                }
+            }
+            sent = Some(snapshot);
+            tokio::select! {
+                changed = state_rx.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+                Some(result) = result_rx.recv() => {
+                    if let Err(e) = sender.send(Message::Text(result)).await {
+                        warn!("failed to send request result: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            if process_message(command_tx.clone(), &result_tx, msg, who, &origin).is_break() {
+                break;
+            }
+        }
+    });
+
+    tokio::select! {
+        rv_a = (&mut send_task) => {
+            match rv_a {
+                Ok(_) => (),
+                Err(a) => warn!("Error sending messages {a:?}")
+            }
+            recv_task.abort();
+        },
+        rv_b = (&mut recv_task) => {
+            match rv_b {
+                Ok(_) => (),
+                Err(b) => warn!("Error receiving messages {b:?}")
+            }
+            send_task.abort();
+        }
+    }
+
+    let _ = disconnected_tx.send(Operation::ClientDisconnected(client));
+    info!("Websocket context {who} destroyed");
+}
+
+fn process_message(
+    command_tx: mpsc::UnboundedSender<Operation>,
+    result_tx: &mpsc::UnboundedSender<String>,
+    msg: Message,
+    who: SocketAddr,
+    origin: &Origin,
+) -> ControlFlow<(), ()> {
+    match msg {
+        Message::Text(t) => {
+            let envelope: RequestEnvelope = match serde_json::from_str(&t) {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!(">>> {who} sent invalid json: {e}");
+                    // Still answer if there's an ID to answer to
+                    let request_id = serde_json::from_str::<serde_json::Value>(&t)
+                        .ok()
+                        .and_then(|v| v.get("requestId").cloned());
+                    if let Some(request_id) = request_id {
+                        ack::Ack::new(request_id, result_tx.clone())
+                            .reject(format!("invalid request: {}", e));
+                    }
+                    return ControlFlow::Continue(());
+                }
+            };
+            let r = envelope.request;
+            debug!(">>> {who} sent request: {r:?}");
+            let mut operation = Operation::from(r).sent_by(origin);
+            if let Some(request_id) = envelope.request_id {
+                let ack = ack::Ack::new(request_id, result_tx.clone());
+                operation = Operation::Acknowledged(ack, Box::new(operation));
+            }
+            match command_tx.send(operation) {
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("failed to queue command: {e}");
+                    return ControlFlow::Break(());
+                }
+            };
+        }
+        Message::Close(c) => {
+            if let Some(cf) = c {
+                info!(
+                    ">>> {} sent close with code {} and reason `{}`",
+                    who, cf.code, cf.reason
+                );
+            } else {
+                info!(">>> {who} somehow sent close message without CloseFrame");
+            }
+            return ControlFlow::Break(());
+        }
+        _ => (),
+    }
+    ControlFlow::Continue(())
+}
+
+impl From<Request> for Operation {
+    fn from(request: Request) -> Self {
+        match request {
+            Request::Command(x) => Operation::Command(x),
+            Request::Disconnect(x) => Operation::Disconnect(x),
+            Request::Reconnect(x) => Operation::Reconnect(x),
+            Request::Repair(x) => Operation::Repair(x),
+            Request::Wake(x) => Operation::Wake(x),
+            Request::PushIn(x) => Operation::PushIn(x),
+            Request::MoveTo(x) => Operation::MoveTo(x),
+            Request::SetProperty(x) => Operation::SetProperty(x),
+            Request::CustomCommand(x) => Operation::CustomCommand(x),
+            Request::SavePreset(x) => Operation::SavePreset(x),
+            Request::RecallPreset(x) => Operation::RecallPreset(x),
+            Request::GuidePreset(x) => Operation::GuidePreset(x),
+            Request::Input(x) => Operation::Input(x),
+            Request::SaveDefaultControls(x) => Operation::SaveDefaultControls(x),
+            Request::Calibrate(x) => Operation::Calibrate(x),
+            Request::EndCalibration(x) => Operation::EndCalibration(x),
+            Request::Trim(x) => Operation::Trim(x),
+            Request::UpdateDevices(x) => Operation::UpdateDevices(x),
+            Request::Lock(x) => Operation::Lock(x),
+            Request::Unlock(x) => Operation::Unlock(x),
+            Request::Handoff(x) => Operation::Handoff(x),
+        }
+    }
+}
+
+impl Operation {
+    /// The devices the operation is addressed to, for operations that take a list of them.
+    fn devices_mut(&mut self) -> Option<&mut Vec<String>> {
+        match self {
+            Operation::Command(x) => Some(&mut x.devices),
+            Operation::Disconnect(x) => Some(&mut x.devices),
+            Operation::Reconnect(x) => Some(&mut x.devices),
+            Operation::Repair(x) => Some(&mut x.devices),
+            Operation::Wake(x) => Some(&mut x.devices),
+            Operation::PushIn(x) => Some(&mut x.devices),
+            Operation::MoveTo(x) => Some(&mut x.devices),
+            Operation::SetProperty(x) => Some(&mut x.devices),
+            Operation::CustomCommand(x) => Some(&mut x.devices),
+            _ => None,
+        }
+    }
+
+    /// The device the operation is addressed to, for operations that take a single one.
+    fn device(&self) -> Option<&str> {
+        match self {
+            Operation::SavePreset(x) => Some(&x.device),
+            Operation::RecallPreset(x) => Some(&x.device),
+            Operation::GuidePreset(x) => Some(&x.device),
+            Operation::Calibrate(x) => Some(&x.device),
+            Operation::EndCalibration(x) => Some(&x.device),
+            Operation::Trim(x) => Some(&x.device),
+            _ => None,
+        }
+    }
+
+    fn sent_by(self, origin: &Origin) -> Operation {
+        Operation::Sourced(origin.clone(), Box::new(self))
+    }
+}
+
+/// Replaces `tag:<name>` entries in a device list with every device that has the tag.
+fn expand_tags(devices: &mut Vec<String>, tags: &IndexMap<String, Vec<String>>) {
+    if !devices.iter().any(|d| d.starts_with(TAG_PREFIX)) {
+        return;
+    }
+    *devices = devices
+        .iter()
+        .flat_map(|d| match d.strip_prefix(TAG_PREFIX) {
+            None => vec![d.clone()],
+            Some(tag) => tags
+                .iter()
+                .filter(|(_, t)| t.iter().any(|t| t == tag))
+                .map(|(id, _)| id.clone())
+                .collect(),
+        })
+        .unique()
+        .collect();
+}
+
+#[test]
+fn test_expand_tags() {
+    let tags = IndexMap::from([
+        ("cam1".to_owned(), vec!["balcony".to_owned()]),
+        ("cam2".to_owned(), vec!["stage-left".to_owned()]),
+        (
+            "cam3".to_owned(),
+            vec!["balcony".to_owned(), "stage-left".to_owned()],
+        ),
+    ]);
+    let expand = |devices: &[&str]| {
+        let mut devices = devices.iter().map(|&d| d.to_owned()).collect();
+        expand_tags(&mut devices, &tags);
+        devices
+    };
+    assert_eq!(expand(&["tag:balcony"]), vec!["cam1", "cam3"]);
+    assert_eq!(
+        expand(&["cam2", "tag:stage-left", "tag:none"]),
+        vec!["cam2", "cam3"]
+    );
+    assert_eq!(expand(&["cam1"]), vec!["cam1"]);
+}
+
+fn parse_request(text: &str) -> Result<Request, serde_json::Error> {
+    serde_json::from_str(text)
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_parse_request_arbitrary_input(text in "\\PC*") {
+        let _ = parse_request(&text);
+    }
+
+    #[test]
+    fn test_parse_request_clamps_command(
+        pan in proptest::num::f64::NORMAL,
+        tilt in proptest::num::f64::NORMAL,
+        zoom in proptest::num::f64::ANY,
+    ) {
+        let zoom = if zoom.is_finite() { zoom } else { 0.0 };
+        let json = serde_json::json!({
+            "command": {
+                "devices": ["a"],
+                "pan": pan,
+                "tilt": tilt,
+                "roll": 0.0,
+                "zoom": zoom,
+                "focus": -zoom,
+                "autofocus": false,
+            }
+        });
+        let Ok(Request::Command(request)) = parse_request(&json.to_string()) else {
+            panic!("failed to parse {}", json);
+        };
+        let c = request.command;
+        for v in [c.pan, c.tilt, c.roll, c.zoom, c.focus] {
+            proptest::prop_assert!((-1.0..=1.0).contains(&v.get()));
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+enum Request {
+    Command(CommandRequest),
+    Disconnect(DisconnectRequest),
+    Reconnect(ReconnectRequest),
+    Repair(RepairRequest),
+    Wake(WakeRequest),
+    PushIn(PushInRequest),
+    MoveTo(MoveToRequest),
+    SetProperty(SetPropertyRequest),
+    CustomCommand(CustomCommandRequest),
+    SavePreset(PresetRequest),
+    RecallPreset(RecallPresetRequest),
+    GuidePreset(GuidePresetRequest),
+    Input(input::InputEvent),
+    SaveDefaultControls(Vec<Mappings>),
+    Calibrate(CalibrateRequest),
+    EndCalibration(EndCalibrationRequest),
+    Trim(TrimRequest),
+    UpdateDevices(UpdateDevicesRequest),
+    Lock(LockRequest),
+    Unlock(UnlockRequest),
+    Handoff(HandoffRequest),
+}
+
+/// A request as sent over the websocket. Requests with a `requestId` (any JSON value) are
+/// answered with a `result` message once they've been carried out.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RequestEnvelope {
+    request_id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[test]
+fn test_request_envelope() {
+    let envelope: RequestEnvelope =
+        serde_json::from_str(r#"{"requestId": 3, "reconnect": {"devices": ["cam1"]}}"#).unwrap();
+    assert_eq!(envelope.request_id, Some(serde_json::json!(3)));
+    assert!(matches!(envelope.request, Request::Reconnect(_)));
+    let envelope: RequestEnvelope =
+        serde_json::from_str(r#"{"savePreset": {"device": "cam1", "slot": 1}}"#).unwrap();
+    assert_eq!(envelope.request_id, None);
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CommandRequest {
+    devices: Vec<String>,
+    #[serde(flatten)]
+    command: device::Command,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DisconnectRequest {
+    devices: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReconnectRequest {
+    devices: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RepairRequest {
+    devices: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct WakeRequest {
+    devices: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PushInRequest {
+    devices: Vec<String>,
+    zoom: f64,
+    duration_ms: u64,
+}
+
+/// Moves gimbals to absolute angles in degrees, in each device's own axes, and zooms cameras
+/// that report their zoom to a point between wide (0) and tele (1).
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct MoveToRequest {
+    devices: Vec<String>,
+    pan: Option<f64>,
+    tilt: Option<f64>,
+    roll: Option<f64>,
+    zoom: Option<f64>,
+    duration_ms: Option<u64>,
+}
+
+/// Changes an exposure setting on cameras, e.g. to match them to each other.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SetPropertyRequest {
+    devices: Vec<String>,
+    property: device::Property,
+    value: f64,
+}
+
+/// Fires a command configured for each device by name, e.g. to power LANC cameras off.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CustomCommandRequest {
+    devices: Vec<String>,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PresetRequest {
+    device: String,
+    slot: u32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RecallPresetRequest {
+    device: String,
+    slot: u32,
+    duration_ms: Option<u64>,
+}
+
+/// Starts publishing guidance toward a saved preset, or stops it when no slot is given.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GuidePresetRequest {
+    device: String,
+    slot: Option<u32>,
+}
+
+/// Locks a device to the requesting client for `timeoutSecs` (60 by default). Sending it again
+/// before the lock runs out extends it.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CalibrateRequest {
+    device: String,
+    timeout_secs: Option<u64>,
+}
+
+/// Replaces a device's trim. A trim that's all zeros removes it.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TrimRequest {
+    device: String,
+    #[serde(flatten)]
+    trim: config::Trim,
+}
+
+/// Adds, replaces, or removes devices while running. Devices that end up in a group are created
+/// and connected, and the ones that are removed, dropped from every group, or changed are
+/// disconnected first.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UpdateDevicesRequest {
+    /// New or replaced devices by ID, or `null` to remove one.
+    #[serde(default)]
+    devices: IndexMap<String, Option<config::DeviceConfig>>,
+    /// Replaces every group, if given.
+    groups: Option<Vec<Group>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct EndCalibrationRequest {
+    device: String,
+}
+
+/// Locks a group to the client that sent it, until it's unlocked or `timeoutSecs` runs out.
+/// Sending it again before the lock runs out extends it.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LockRequest {
+    group: String,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UnlockRequest {
+    group: String,
+}
+
+/// Passes the group locks and calibrations held by the client that sent it to another client,
+/// e.g. at a shift change.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct HandoffRequest {
+    to: String,
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {
+            info!("Ctrl+C received");
+        },
+        _ = terminate => {
+            info!("Terminate received");
+        },
+    }
+}
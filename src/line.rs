@@ -0,0 +1,235 @@
+//! A newline-delimited TCP control protocol for Bitfocus Companion's Generic TCP module and
+//! hardware controllers that can't speak websockets. Each line is one request, answered with
+//! `OK` or `ERR <reason>`:
+//!
+//! ```text
+//! AUTH s3cret
+//! CMD cam1,cam2 pan=0.5 tilt=-0.2
+//! STOP cam1
+//! RECALL cam1 3 2000
+//! SAVE cam1 3
+//! RECONNECT cam1
+//! ```
+
+use std::{error::Error, net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    auth,
+    config::{AuthConfig, LineProtocolConfig},
+    device::{Command, Velocity},
+    CommandRequest, Operation, Origin, PresetRequest, RecallPresetRequest, ReconnectRequest,
+    Request,
+};
+
+const SOURCE: &str = "tcp";
+const REBIND_DELAY: Duration = Duration::from_secs(5);
+
+pub async fn listen(
+    config: LineProtocolConfig,
+    auth: Option<AuthConfig>,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) {
+    loop {
+        match TcpListener::bind(("0.0.0.0", config.port)).await {
+            Ok(listener) => {
+                info!("TCP control: Listening on port {}", config.port);
+                loop {
+                    let (stream, addr) = match listener.accept().await {
+                        Ok(x) => x,
+                        Err(e) => {
+                            warn!("TCP control: Error accepting connection: {}", e);
+                            continue;
+                        }
+                    };
+                    let auth = auth.clone();
+                    let command_tx = command_tx.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(stream, addr, auth, command_tx).await {
+                            warn!("TCP control: {}: {}", addr, e);
+                        }
+                        info!("TCP control: {} disconnected", addr);
+                    });
+                }
+            }
+            Err(e) => warn!("TCP control: Failed to bind to port {}: {}", config.port, e),
+        }
+        tokio::time::sleep(REBIND_DELAY).await;
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    addr: SocketAddr,
+    auth: Option<AuthConfig>,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) -> Result<(), Box<dyn Error>> {
+    info!("TCP control: {} connected", addr);
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    // Without credentials to check, everyone is already in
+    let mut origin = match auth {
+        Some(_) => None,
+        None => Some(client_origin(SOURCE, addr)),
+    };
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let reply = match (line.split_once(' '), &origin, &auth) {
+            (Some(("AUTH", token)), _, Some(auth)) => match auth::check_token(auth, token.trim()) {
+                Some(auth::Client(name)) => {
+                    // Tokens without a name are shared with web clients, but these aren't those
+                    let source = if name == "web" { SOURCE } else { &name };
+                    origin = Some(client_origin(source, addr));
+                    "OK".to_owned()
+                }
+                None => "ERR invalid token".to_owned(),
+            },
+            // Harmless, so that the same Companion setup works with and without auth
+            (Some(("AUTH", _)), _, None) => "OK".to_owned(),
+            (_, None, _) => "ERR not authenticated".to_owned(),
+            (_, Some(origin), _) => match parse_line(line) {
+                Ok(request) => {
+                    debug!("TCP control: {} sent request {:?}", addr, request);
+                    if command_tx
+                        .send(Operation::from(request).sent_by(origin))
+                        .is_err()
+                    {
+                        return Ok(());
+                    }
+                    "OK".to_owned()
+                }
+                Err(e) => format!("ERR {}", e),
+            },
+        };
+        writer
+            .write_all(format!("{}\r\n", reply).as_bytes())
+            .await?;
+    }
+    Ok(())
+}
+
+fn client_origin(source: &str, addr: SocketAddr) -> Origin {
+    Origin {
+        source: source.to_owned(),
+        client: format!("{}@{}", source, addr),
+    }
+}
+
+fn parse_line(line: &str) -> Result<Request, String> {
+    let mut words = line.split_whitespace();
+    let verb = words.next().unwrap_or_default().to_ascii_uppercase();
+    let mut devices = || -> Result<Vec<String>, String> {
+        let devices = words.next().ok_or("missing devices")?;
+        Ok(devices.split(',').map(str::to_owned).collect())
+    };
+    let request = match verb.as_str() {
+        "CMD" => {
+            let devices = devices()?;
+            let mut command = Command::default();
+            for arg in words.by_ref() {
+                let (axis, value) = arg
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected axis=value, got {}", arg))?;
+                let value: f64 = value
+                    .parse()
+                    .map_err(|_| format!("invalid value for {}: {}", axis, value))?;
+                let velocity = Velocity::new(value);
+                match axis {
+                    "pan" => command.pan = velocity,
+                    "tilt" => command.tilt = velocity,
+                    "roll" => command.roll = velocity,
+                    "zoom" => command.zoom = velocity,
+                    "focus" => command.focus = velocity,
+                    "iris" => command.iris = velocity,
+                    "autofocus" => command.autofocus = value != 0.0,
+                    "record" => command.record = Some(value != 0.0),
+                    "shutter" => command.shutter = value != 0.0,
+                    _ => return Err(format!("unknown axis {}", axis)),
+                }
+            }
+            Request::Command(CommandRequest { devices, command })
+        }
+        "STOP" => Request::Command(CommandRequest {
+            devices: devices()?,
+            command: Command::default(),
+        }),
+        "RECONNECT" => Request::Reconnect(ReconnectRequest {
+            devices: devices()?,
+        }),
+        "SAVE" | "RECALL" => {
+            let device = words.next().ok_or("missing device")?.to_owned();
+            let slot = words
+                .next()
+                .ok_or("missing slot")?
+                .parse()
+                .map_err(|_| "invalid slot")?;
+            match verb.as_str() {
+                "SAVE" => Request::SavePreset(PresetRequest { device, slot }),
+                _ => {
+                    let duration_ms = words
+                        .next()
+                        .map(|d| d.parse().map_err(|_| "invalid duration"))
+                        .transpose()?;
+                    Request::RecallPreset(RecallPresetRequest {
+                        device,
+                        slot,
+                        duration_ms,
+                    })
+                }
+            }
+        }
+        _ => return Err(format!("unknown command {}", verb)),
+    };
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected {}", extra));
+    }
+    Ok(request)
+}
+
+#[test]
+fn test_parse_line() {
+    let Ok(Request::Command(request)) = parse_line("CMD dev1,tag:stage pan=0.5 tilt=-2") else {
+        panic!("expected a command");
+    };
+    assert_eq!(request.devices, vec!["dev1", "tag:stage"]);
+    assert_eq!(request.command.pan.get(), 0.5);
+    assert_eq!(request.command.tilt.get(), -1.0);
+    assert_eq!(request.command.zoom.get(), 0.0);
+
+    let Ok(Request::Command(request)) = parse_line("stop dev1") else {
+        panic!("expected a command");
+    };
+    assert_eq!(request.command, Command::default());
+
+    let Ok(Request::RecallPreset(request)) = parse_line("RECALL dev1 3 2000") else {
+        panic!("expected a recall");
+    };
+    assert_eq!(
+        (request.device.as_str(), request.slot, request.duration_ms),
+        ("dev1", 3, Some(2000))
+    );
+    assert!(matches!(
+        parse_line("SAVE dev1 3"),
+        Ok(Request::SavePreset(_))
+    ));
+    assert!(matches!(
+        parse_line("RECONNECT dev1"),
+        Ok(Request::Reconnect(_))
+    ));
+
+    assert!(parse_line("CMD").is_err());
+    assert!(parse_line("CMD dev1 pan").is_err());
+    assert!(parse_line("CMD dev1 spin=1").is_err());
+    assert!(parse_line("RECALL dev1").is_err());
+    assert!(parse_line("SAVE dev1 3 4").is_err());
+    assert!(parse_line("JUMP dev1").is_err());
+}
@@ -0,0 +1,156 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{routing::get, Json, Router};
+use indexmap::IndexMap;
+use serde::Serialize;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+const REBIND_DELAY: Duration = Duration::from_secs(5);
+/// How often listeners on a specific address check that it's still assigned to an interface.
+const ADDRESS_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerStatus {
+    bound: bool,
+    /// Why the listener isn't bound.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Whether each address the control API listens on is currently bound.
+#[derive(Clone, Default)]
+pub struct Listeners(Arc<RwLock<IndexMap<SocketAddr, ListenerStatus>>>);
+
+impl Listeners {
+    fn set(&self, address: SocketAddr, error: Option<String>) {
+        let status = ListenerStatus {
+            bound: error.is_none(),
+            error,
+        };
+        self.0.write().unwrap().insert(address, status);
+    }
+
+    fn statuses(&self) -> IndexMap<String, ListenerStatus> {
+        self.0
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(address, status)| (address.to_string(), status.clone()))
+            .collect()
+    }
+
+    /// Binds every address once, so that callers can tell whether any of them came up.
+    pub async fn bind_all(&self, addresses: &[SocketAddr]) -> Vec<Option<TcpListener>> {
+        let mut bound = vec![];
+        for &address in addresses {
+            match TcpListener::bind(address).await {
+                Ok(listener) => {
+                    info!("listening on {}", address);
+                    self.set(address, None);
+                    bound.push(Some(listener));
+                }
+                Err(e) => {
+                    warn!("Failed to bind to {}: {}", address, e);
+                    self.set(address, Some(e.to_string()));
+                    bound.push(None);
+                }
+            }
+        }
+        bound
+    }
+
+    /// Serves on `address` until `shutdown` completes, binding it again whenever it fails to
+    /// bind or its address disappears from the machine's interfaces.
+    pub async fn keep_serving<F, S>(
+        &self,
+        address: SocketAddr,
+        mut listener: Option<TcpListener>,
+        serve: F,
+        shutdown: impl std::future::Future<Output = ()>,
+    ) where
+        F: Fn(TcpListener) -> S,
+        S: std::future::Future<Output = ()>,
+    {
+        tokio::pin!(shutdown);
+        loop {
+            let bound = match listener.take() {
+                Some(l) => l,
+                None => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(REBIND_DELAY) => {}
+                        _ = &mut shutdown => return,
+                    }
+                    match TcpListener::bind(address).await {
+                        Ok(l) => {
+                            info!("listening on {} again", address);
+                            self.set(address, None);
+                            l
+                        }
+                        Err(e) => {
+                            self.set(address, Some(e.to_string()));
+                            continue;
+                        }
+                    }
+                }
+            };
+            tokio::select! {
+                _ = serve(bound) => {
+                    self.set(address, Some("stopped serving".to_owned()));
+                }
+                _ = address_lost(address.ip()) => {
+                    info!("{} is no longer assigned to an interface, unbinding", address);
+                    self.set(address, Some("address not assigned".to_owned()));
+                }
+                _ = &mut shutdown => return,
+            }
+        }
+    }
+}
+
+/// Completes once the address can no longer be bound, e.g. because its interface went down.
+/// Never completes for the unspecified address.
+async fn address_lost(ip: IpAddr) {
+    if ip.is_unspecified() {
+        return std::future::pending().await;
+    }
+    loop {
+        tokio::time::sleep(ADDRESS_CHECK_INTERVAL).await;
+        if !is_assigned(ip) {
+            return;
+        }
+    }
+}
+
+fn is_assigned(ip: IpAddr) -> bool {
+    std::net::UdpSocket::bind((ip, 0)).is_ok()
+}
+
+pub fn router(listeners: Listeners) -> Router {
+    Router::new().route(
+        "/api/listeners",
+        get(move || async move { Json(listeners.statuses()) }),
+    )
+}
+
+#[test]
+fn test_listeners() {
+    let listeners = Listeners::default();
+    let lan: SocketAddr = "192.0.2.10:8000".parse().unwrap();
+    let wifi: SocketAddr = "127.0.0.1:8000".parse().unwrap();
+    listeners.set(lan, Some("address not assigned".to_owned()));
+    listeners.set(wifi, None);
+    assert_eq!(
+        serde_json::to_string(&listeners.statuses()).unwrap(),
+        r#"{"192.0.2.10:8000":{"bound":false,"error":"address not assigned"},"127.0.0.1:8000":{"bound":true}}"#
+    );
+
+    assert!(is_assigned("127.0.0.1".parse().unwrap()));
+    // Reserved for documentation, so never assigned
+    assert!(!is_assigned("192.0.2.10".parse().unwrap()));
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::config::Group;
+
+struct Lock {
+    client: String,
+    devices: Vec<String>,
+    expires: Instant,
+}
+
+/// Groups locked to a single client, so other operators can't move its cameras. Unlike
+/// calibration, the devices keep doing whatever they were doing, and webptz's own operations
+/// (like preset recalls the holder started) still reach them.
+#[derive(Default)]
+pub struct GroupLocks {
+    locks: HashMap<String, Lock>,
+}
+
+impl GroupLocks {
+    /// Locks the group to the client, or extends the client's existing lock. Fails if another
+    /// client holds a lock on any of the group's devices.
+    pub fn lock(
+        &mut self,
+        group: &Group,
+        client: &str,
+        timeout: Duration,
+        now: Instant,
+    ) -> Result<(), String> {
+        for device in group.devices.iter() {
+            if let Some(holder) = self.holder(device, now).filter(|h| *h != client) {
+                return Err(format!("{} is locked by {}", device, holder));
+            }
+        }
+        let lock = Lock {
+            client: client.to_owned(),
+            devices: group.devices.clone(),
+            expires: now + timeout,
+        };
+        self.locks.insert(group.name.clone(), lock);
+        Ok(())
+    }
+
+    /// Releases the group if the client (or webptz itself, without one) may. Returns the client
+    /// holding the lock when it may not.
+    pub fn unlock(&mut self, group: &str, client: Option<&str>) -> Result<bool, String> {
+        match self.locks.get(group) {
+            None => Ok(false),
+            Some(lock) if client.is_some_and(|c| c != lock.client) => Err(lock.client.clone()),
+            Some(_) => Ok(self.locks.remove(group).is_some()),
+        }
+    }
+
+    /// Whether an operation from the client may touch the device. Operations from webptz
+    /// itself always may.
+    pub fn allows(&self, device: &str, client: Option<&str>) -> bool {
+        let Some(client) = client else {
+            return true;
+        };
+        self.locks
+            .values()
+            .filter(|lock| lock.devices.iter().any(|d| d == device))
+            .all(|lock| lock.client == client)
+    }
+
+    /// The client holding a lock on the device.
+    pub fn locked_by(&self, device: &str) -> Option<&str> {
+        self.locks
+            .values()
+            .find(|lock| lock.devices.iter().any(|d| d == device))
+            .map(|lock| lock.client.as_str())
+    }
+
+    /// Passes every lock the client holds to another client, keeping when they run out. Returns
+    /// the groups that changed hands.
+    pub fn hand_off(&mut self, from: &str, to: &str) -> Vec<String> {
+        let mut groups = vec![];
+        for (group, lock) in self.locks.iter_mut().filter(|(_, l)| l.client == from) {
+            lock.client = to.to_owned();
+            groups.push(group.clone());
+        }
+        groups.sort();
+        groups
+    }
+
+    fn holder(&self, device: &str, now: Instant) -> Option<&str> {
+        self.locks
+            .values()
+            .filter(|lock| lock.expires > now)
+            .find(|lock| lock.devices.iter().any(|d| d == device))
+            .map(|lock| lock.client.as_str())
+    }
+
+    /// Releases the locks that have run out, returning their groups.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let expired: Vec<String> = self
+            .locks
+            .iter()
+            .filter(|(_, lock)| lock.expires <= now)
+            .map(|(group, _)| group.clone())
+            .collect();
+        for group in expired.iter() {
+            self.locks.remove(group);
+        }
+        expired
+    }
+}
+
+#[test]
+fn test_group_locks() {
+    let stage = Group {
+        name: "Stage".to_owned(),
+        devices: vec!["cam1".to_owned(), "cam2".to_owned()],
+    };
+    let wide = Group {
+        name: "Wide".to_owned(),
+        devices: vec!["cam2".to_owned(), "cam3".to_owned()],
+    };
+    let mut locks = GroupLocks::default();
+    let now = Instant::now();
+    let timeout = Duration::from_secs(300);
+    locks.lock(&stage, "op1", timeout, now).unwrap();
+
+    assert!(locks.allows("cam1", Some("op1")));
+    assert!(!locks.allows("cam2", Some("op2")));
+    assert!(locks.allows("cam3", Some("op2")));
+    assert!(locks.allows("cam1", None));
+    assert_eq!(locks.locked_by("cam2"), Some("op1"));
+
+    // Groups that share a device with a locked one can't be locked by anyone else
+    assert!(locks.lock(&wide, "op2", timeout, now).is_err());
+    locks.lock(&wide, "op1", timeout, now).unwrap();
+    assert_eq!(locks.unlock("Wide", Some("op2")), Err("op1".to_owned()));
+    assert_eq!(locks.unlock("Wide", Some("op1")), Ok(true));
+    assert_eq!(locks.unlock("Wide", Some("op1")), Ok(false));
+
+    // Locks that ran out can be taken over before they're expired
+    locks.lock(&wide, "op2", timeout, now + timeout).unwrap();
+    assert_eq!(locks.expire(now + timeout), vec!["Stage"]);
+    assert_eq!(locks.locked_by("cam1"), None);
+    assert_eq!(locks.locked_by("cam2"), Some("op2"));
+}
+
+#[test]
+fn test_hand_off_group_locks() {
+    let stage = Group {
+        name: "Stage".to_owned(),
+        devices: vec!["cam1".to_owned()],
+    };
+    let wide = Group {
+        name: "Wide".to_owned(),
+        devices: vec!["cam2".to_owned()],
+    };
+    let mut locks = GroupLocks::default();
+    let now = Instant::now();
+    let timeout = Duration::from_secs(300);
+    locks.lock(&stage, "op1", timeout, now).unwrap();
+    locks.lock(&wide, "op2", timeout, now).unwrap();
+
+    assert_eq!(locks.hand_off("op1", "op3"), vec!["Stage"]);
+    assert!(locks.allows("cam1", Some("op3")));
+    assert!(!locks.allows("cam1", Some("op1")));
+    assert_eq!(locks.locked_by("cam2"), Some("op2"));
+    assert!(locks.hand_off("op1", "op3").is_empty());
+
+    // The lock still runs out when it would have for the old holder
+    let mut expired = locks.expire(now + timeout);
+    expired.sort();
+    assert_eq!(expired, vec!["Stage", "Wide"]);
+}
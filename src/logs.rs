@@ -0,0 +1,289 @@
+//! Streams log lines to web clients over `/logs`, so problems can be looked into from a browser
+//! without a shell on the machine running webptz.
+
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Write as _},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        WebSocketUpgrade,
+    },
+    response::Response,
+    routing::any,
+    Extension, Router,
+};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Subscriber,
+};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+use crate::{access::Visibility, auth::Client};
+
+/// How many lines new clients are sent from before they connected.
+const HISTORY: usize = 200;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LogLine {
+    /// Milliseconds since the Unix epoch.
+    pub time: u64,
+    pub level: String,
+    pub target: String,
+    /// The spans the event happened in, like `device{id="camera1"}`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub spans: String,
+    pub message: String,
+    /// The device the line is about, from a `device` field or the innermost `device` span.
+    #[serde(skip)]
+    pub device: Option<String>,
+}
+
+/// Recent log lines, and a channel for the ones still to come.
+#[derive(Clone)]
+pub struct Logs {
+    history: Arc<Mutex<VecDeque<Arc<LogLine>>>>,
+    lines: broadcast::Sender<Arc<LogLine>>,
+}
+
+impl Logs {
+    pub fn new() -> Logs {
+        Logs {
+            history: Default::default(),
+            lines: broadcast::channel(HISTORY).0,
+        }
+    }
+
+    /// A layer that records every event that gets past the filter into these logs.
+    pub fn layer(&self) -> LogLayer {
+        LogLayer { logs: self.clone() }
+    }
+
+    fn push(&self, line: LogLine) {
+        let line = Arc::new(line);
+        // The history is locked while sending so that a new subscriber sees every line once
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY {
+            history.pop_front();
+        }
+        history.push_back(line.clone());
+        let _ = self.lines.send(line);
+    }
+
+    fn subscribe(&self) -> (Vec<Arc<LogLine>>, broadcast::Receiver<Arc<LogLine>>) {
+        let history = self.history.lock().unwrap();
+        (history.iter().cloned().collect(), self.lines.subscribe())
+    }
+}
+
+impl Default for Logs {
+    fn default() -> Logs {
+        Logs::new()
+    }
+}
+
+pub struct LogLayer {
+    logs: Logs,
+}
+
+/// A span's fields, formatted when it's created, and the device ID of `device` spans.
+struct SpanFields(String, Option<String>);
+
+impl<S> Layer<S> for LogLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            let device = fields.id.filter(|_| span.name() == "device");
+            span.extensions_mut()
+                .insert(SpanFields(fields.rest, device));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let device = fields.device.or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<SpanFields>()?.1.clone())
+        });
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| match span.extensions().get::<SpanFields>() {
+                        Some(SpanFields(f, _)) if !f.is_empty() => {
+                            format!("{}{{{}}}", span.name(), f.trim_start())
+                        }
+                        _ => span.name().to_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(":")
+            })
+            .unwrap_or_default();
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.logs.push(LogLine {
+            time: time.as_millis() as u64,
+            level: event.metadata().level().to_string(),
+            target: event.metadata().target().to_owned(),
+            spans,
+            message: format!("{}{}", fields.message, fields.rest),
+            device,
+        });
+    }
+}
+
+/// Formats fields the way the console does: the message, then ` key=value` for the rest. The
+/// `device` and `id` fields are also kept as they are, to tell which device a line is about.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+    device: Option<String>,
+    id: Option<String>,
+}
+
+impl Fields {
+    fn keep(&mut self, field: &Field, value: String) {
+        match field.name() {
+            "device" => self.device = Some(value),
+            "id" => self.id = Some(value),
+            _ => (),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.keep(field, value.to_owned());
+        match field.name() {
+            "message" => self.message.push_str(value),
+            name => write!(self.rest, " {}={:?}", name, value).unwrap(),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.keep(field, format!("{:?}", value));
+        match field.name() {
+            "message" => write!(self.message, "{:?}", value).unwrap(),
+            name => write!(self.rest, " {}={:?}", name, value).unwrap(),
+        }
+    }
+}
+
+pub fn router(logs: Logs, visibility: Arc<Visibility>) -> Router {
+    Router::new().route(
+        "/logs",
+        any(move |ws, client| stream(logs, visibility, ws, client)),
+    )
+}
+
+async fn stream(
+    logs: Logs,
+    visibility: Arc<Visibility>,
+    ws: WebSocketUpgrade,
+    client: Option<Extension<Client>>,
+) -> Response {
+    let source = Client::source(client);
+    ws.on_upgrade(move |socket| send_lines(logs, visibility, source, socket))
+}
+
+/// Sends recent lines, then each new one until the client goes away, leaving out lines about
+/// devices the client can't see. Clients that fall too far behind skip ahead rather than
+/// holding up logging.
+async fn send_lines(
+    logs: Logs,
+    visibility: Arc<Visibility>,
+    source: String,
+    mut socket: WebSocket,
+) {
+    let shown = |line: &LogLine| visibility.shows_log(&source, line.device.as_deref());
+    let (history, mut lines) = logs.subscribe();
+    for line in history.iter().filter(|l| shown(l)) {
+        if send(&mut socket, line).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        let line = tokio::select! {
+            line = lines.recv() => line,
+            // Notice clients closing even when nothing is being logged
+            message = socket.recv() => match message {
+                Some(Ok(_)) => continue,
+                _ => return,
+            },
+        };
+        match line {
+            Ok(line) if !shown(&line) => continue,
+            Ok(line) => {
+                if send(&mut socket, &line).await.is_err() {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, line: &LogLine) -> Result<(), axum::Error> {
+    socket
+        .send(Message::Text(serde_json::to_string(line).unwrap()))
+        .await
+}
+
+#[test]
+fn test_log_layer() {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    let logs = Logs::new();
+    let subscriber = tracing_subscriber::registry().with(logs.layer());
+    tracing::subscriber::with_default(subscriber, || {
+        let _span = tracing::info_span!("device", id = "camera1").entered();
+        tracing::warn!(packet = "ff", "Sending {}", 1);
+    });
+    let (history, _) = logs.subscribe();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].level, "WARN");
+    assert_eq!(history[0].spans, "device{id=\"camera1\"}");
+    assert_eq!(history[0].message, "Sending 1 packet=\"ff\"");
+    assert_eq!(history[0].device.as_deref(), Some("camera1"));
+}
+
+#[test]
+fn test_log_visibility() {
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    let logs = Logs::new();
+    let subscriber = tracing_subscriber::registry().with(logs.layer());
+    tracing::subscriber::with_default(subscriber, || {
+        let id = "camera2";
+        tracing::warn!(device = %id, "Error reconnecting");
+        tracing::info!("Sending custom command to devices [\"camera2\"]");
+    });
+    let (history, _) = logs.subscribe();
+    assert_eq!(history[0].device.as_deref(), Some("camera2"));
+    assert_eq!(history[1].device, None);
+
+    let visibility = Visibility::new(
+        &indexmap::IndexMap::from([("token:booth".to_owned(), vec!["camera1".to_owned()])]),
+        &[],
+    );
+    let shown = |source: &str, line: &LogLine| visibility.shows_log(source, line.device.as_deref());
+    assert!(!shown("token:booth", &history[0]));
+    assert!(!shown("token:booth", &history[1]));
+    assert!(shown("token:admin", &history[0]));
+    assert!(shown("token:admin", &history[1]));
+}
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use tokio::sync::{mpsc, watch, Notify};
+use tracing::{debug, info, warn};
+
+use crate::{config::MqttConfig, parse_request, DeviceStatus, Operation, Origin, State};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+/// What requests from the broker count as in access rules.
+const SOURCE: &str = "mqtt";
+
+/// Bridges webptz to an MQTT broker for Node-RED, Home Assistant, and the like:
+///
+/// - `<prefix>/available` is `online` while connected and `offline` otherwise
+/// - `<prefix>/devices/<id>` is the device's status, as JSON, whenever it changes
+/// - Anything published to `<prefix>/request` is handled like a websocket request
+///
+/// Statuses are retained so that new subscribers see them straight away.
+pub async fn bridge(
+    config: MqttConfig,
+    command_tx: mpsc::UnboundedSender<Operation>,
+    state_rx: watch::Receiver<State>,
+) {
+    let prefix = config.topic_prefix.trim_end_matches('/').to_owned();
+    let available_topic = format!("{}/available", prefix);
+    let request_topic = format!("{}/request", prefix);
+
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    options.set_last_will(LastWill::new(
+        &available_topic,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(
+            username,
+            config.password.as_deref().map_or("", String::as_str),
+        );
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+    let connected = Arc::new(Notify::new());
+    tokio::spawn(publish_statuses(
+        client.clone(),
+        prefix.clone(),
+        state_rx,
+        connected.clone(),
+    ));
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("MQTT: Connected to {}:{}", config.host, config.port);
+                let subscribed = client
+                    .subscribe(&request_topic, QoS::AtLeastOnce)
+                    .await
+                    .and(
+                        client
+                            .publish(&available_topic, QoS::AtLeastOnce, true, "online")
+                            .await,
+                    );
+                if let Err(e) = subscribed {
+                    warn!("MQTT: {}", e);
+                }
+                // The broker may have missed updates while we were away
+                connected.notify_one();
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) if publish.topic == request_topic => {
+                let Some(operation) = parse_message(&publish.payload) else {
+                    continue;
+                };
+                if command_tx.send(operation).is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("MQTT: {}", e);
+                if command_tx.is_closed() {
+                    return;
+                }
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+fn parse_message(payload: &[u8]) -> Option<Operation> {
+    let text = match std::str::from_utf8(payload) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("MQTT: Request isn't text: {}", e);
+            return None;
+        }
+    };
+    match parse_request(text) {
+        Ok(request) => {
+            debug!("MQTT: Received request {:?}", request);
+            Some(Operation::from(request).sent_by(&Origin::new(SOURCE)))
+        }
+        Err(e) => {
+            warn!("MQTT: Invalid request: {}", e);
+            None
+        }
+    }
+}
+
+async fn publish_statuses(
+    client: AsyncClient,
+    prefix: String,
+    mut state_rx: watch::Receiver<State>,
+    connected: Arc<Notify>,
+) {
+    let mut published: HashMap<String, String> = HashMap::new();
+    loop {
+        let updates = device_updates(&state_rx.borrow_and_update().devices, &mut published);
+        for (id, payload) in updates {
+            let topic = format!("{}/devices/{}", prefix, id);
+            if client
+                .publish(topic, QoS::AtLeastOnce, true, payload)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        tokio::select! {
+            changed = state_rx.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = connected.notified() => published.clear(),
+        }
+    }
+}
+
+/// Serializes the statuses that changed since they were last published.
+fn device_updates(
+    devices: &HashMap<String, DeviceStatus>,
+    published: &mut HashMap<String, String>,
+) -> Vec<(String, String)> {
+    let mut updates = vec![];
+    for (id, status) in devices.iter() {
+        let payload = serde_json::to_string(status).unwrap();
+        if published.get(id) != Some(&payload) {
+            published.insert(id.clone(), payload.clone());
+            updates.push((id.clone(), payload));
+        }
+    }
+    updates
+}
+
+#[test]
+fn test_device_updates() {
+    let status = |id: &str, connected| DeviceStatus {
+        id: id.to_owned(),
+        name: id.to_owned(),
+        connected,
+        reconnecting: false,
+        repairable: false,
+        wakeable: false,
+        telemetry: None,
+        guidance: None,
+        tags: vec![],
+        thumbnail: None,
+        preview: None,
+        calibrating: false,
+        last_error: None,
+        trim: None,
+        lens: None,
+        position: None,
+        owner: None,
+        locked_by: None,
+    };
+    let mut published = HashMap::new();
+    let devices = HashMap::from([
+        ("cam1".to_owned(), status("cam1", true)),
+        ("cam2".to_owned(), status("cam2", true)),
+    ]);
+    assert_eq!(device_updates(&devices, &mut published).len(), 2);
+    assert!(device_updates(&devices, &mut published).is_empty());
+
+    let devices = HashMap::from([
+        ("cam1".to_owned(), status("cam1", true)),
+        ("cam2".to_owned(), status("cam2", false)),
+    ]);
+    let updates = device_updates(&devices, &mut published);
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0].0, "cam2");
+    assert!(updates[0].1.contains(r#""connected":false"#));
+
+    assert!(parse_message(br#"{"recallPreset": {"device": "cam1", "slot": 2}}"#).is_some());
+    assert!(parse_message(b"nope").is_none());
+}
@@ -0,0 +1,256 @@
+use std::{collections::HashMap, error::Error, time::Duration};
+
+use tokio::{net::UdpSocket, sync::mpsc};
+use tracing::{info, warn};
+
+use crate::{
+    config::{Group, OscConfig},
+    device::{Command, Velocity},
+    input::InputEvent,
+    CommandRequest, Operation, Origin,
+};
+
+const SOURCE: &str = "osc";
+const REBIND_DELAY: Duration = Duration::from_secs(5);
+/// Big enough for any single UDP datagram.
+const MAX_PACKET_SIZE: usize = 65536;
+
+/// Receives OSC messages from control surfaces (TouchOSC, lighting consoles, ...) and turns them
+/// into commands or input events.
+pub async fn listen(
+    config: OscConfig,
+    groups: Vec<Group>,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) {
+    let mut router = Router::new(&config.prefix, groups);
+    loop {
+        if let Err(e) = run(&config, &mut router, &command_tx).await {
+            warn!("OSC: {}", e);
+        }
+        if command_tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(REBIND_DELAY).await;
+    }
+}
+
+async fn run(
+    config: &OscConfig,
+    router: &mut Router,
+    command_tx: &mpsc::UnboundedSender<Operation>,
+) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind(("0.0.0.0", config.port)).await?;
+    info!("OSC: Listening on port {}", config.port);
+    let mut buf = vec![0; MAX_PACKET_SIZE];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        let messages = match decode_packet(&buf[..len]) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("OSC: Invalid packet from {}: {}", from, e);
+                continue;
+            }
+        };
+        for (address, value) in messages {
+            if command_tx.send(router.route(&address, value)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Keeps the last value of every axis for each device or group, since OSC controls send one
+/// axis at a time but commands set all of them.
+struct Router {
+    prefix: String,
+    groups: Vec<Group>,
+    commands: HashMap<String, Command>,
+}
+
+impl Router {
+    fn new(prefix: &str, groups: Vec<Group>) -> Router {
+        Router {
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            groups,
+            commands: HashMap::new(),
+        }
+    }
+
+    fn route(&mut self, address: &str, value: f64) -> Operation {
+        let input = || {
+            Operation::Input(InputEvent {
+                source: SOURCE.to_owned(),
+                control: address.to_owned(),
+                value,
+            })
+        };
+        let Some(path) = address
+            .strip_prefix(&self.prefix)
+            .and_then(|p| p.strip_prefix('/'))
+        else {
+            return input();
+        };
+        let parts: Vec<&str> = path.split('/').collect();
+        let (target, devices, axis) = match parts[..] {
+            ["group", name, axis] => {
+                let Some(group) = self.groups.iter().find(|g| g.name == name) else {
+                    return input();
+                };
+                (format!("group/{}", name), group.devices.clone(), axis)
+            }
+            [device, axis] => (device.to_owned(), vec![device.to_owned()], axis),
+            _ => return input(),
+        };
+        let command = self.commands.entry(target).or_default();
+        let velocity = Velocity::new(value);
+        match axis {
+            "pan" => command.pan = velocity,
+            "tilt" => command.tilt = velocity,
+            "roll" => command.roll = velocity,
+            "zoom" => command.zoom = velocity,
+            "focus" => command.focus = velocity,
+            "iris" => command.iris = velocity,
+            "autofocus" => command.autofocus = value >= 0.5,
+            _ => return input(),
+        }
+        let request = CommandRequest {
+            devices,
+            command: *command,
+        };
+        Operation::Command(request).sent_by(&Origin::new(SOURCE))
+    }
+}
+
+/// Decodes a message or bundle into `(address, value)` pairs, using each message's first
+/// numeric or boolean argument. Messages without one (e.g. buttons that only send their address)
+/// count as 1.
+fn decode_packet(packet: &[u8]) -> Result<Vec<(String, f64)>, Box<dyn Error>> {
+    if let Some(mut rest) = packet.strip_prefix(b"#bundle\0") {
+        // Time tags are ignored, and everything is applied as soon as it arrives
+        rest = rest.get(8..).ok_or("truncated bundle")?;
+        let mut messages = vec![];
+        while !rest.is_empty() {
+            let size = i32::from_be_bytes(take::<4>(&mut rest)?) as usize;
+            let element = rest.get(..size).ok_or("truncated bundle element")?;
+            messages.extend(decode_packet(element)?);
+            rest = &rest[size..];
+        }
+        return Ok(messages);
+    }
+    let mut rest = packet;
+    let address = read_string(&mut rest)?;
+    if !address.starts_with('/') {
+        return Err(format!("invalid address {}", address).into());
+    }
+    let tags = match rest.is_empty() {
+        true => String::new(),
+        false => read_string(&mut rest)?,
+    };
+    let mut value = None;
+    for tag in tags.chars().skip_while(|&c| c == ',') {
+        let arg = match tag {
+            'f' => f32::from_be_bytes(take::<4>(&mut rest)?) as f64,
+            'i' => i32::from_be_bytes(take::<4>(&mut rest)?) as f64,
+            'd' => f64::from_be_bytes(take::<8>(&mut rest)?),
+            'h' => i64::from_be_bytes(take::<8>(&mut rest)?) as f64,
+            'T' => 1.0,
+            'F' => 0.0,
+            's' => {
+                read_string(&mut rest)?;
+                continue;
+            }
+            _ => break,
+        };
+        value = Some(arg);
+        break;
+    }
+    Ok(vec![(address, value.unwrap_or(1.0))])
+}
+
+fn take<const N: usize>(rest: &mut &[u8]) -> Result<[u8; N], Box<dyn Error>> {
+    let bytes: [u8; N] = rest
+        .get(..N)
+        .ok_or("truncated argument")?
+        .try_into()
+        .unwrap();
+    *rest = &rest[N..];
+    Ok(bytes)
+}
+
+/// Reads a null-terminated string padded to a multiple of four bytes.
+fn read_string(rest: &mut &[u8]) -> Result<String, Box<dyn Error>> {
+    let end = rest
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or("unterminated string")?;
+    let s = std::str::from_utf8(&rest[..end])?.to_owned();
+    *rest = rest.get((end / 4 + 1) * 4..).unwrap_or_default();
+    Ok(s)
+}
+
+#[test]
+fn test_osc() {
+    let message = |address: &str, value: f32| {
+        let mut bytes = address.as_bytes().to_vec();
+        bytes.resize((address.len() / 4 + 1) * 4, 0);
+        bytes.extend(b",f\0\0");
+        bytes.extend(value.to_be_bytes());
+        bytes
+    };
+    assert_eq!(
+        decode_packet(&message("/webptz/cam1/pan", 0.5)).unwrap(),
+        vec![("/webptz/cam1/pan".to_owned(), 0.5)]
+    );
+    let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+    for (address, value) in [("/webptz/group/stage/zoom", -1.0), ("/fader1", 0.25)] {
+        let element = message(address, value);
+        bundle.extend((element.len() as i32).to_be_bytes());
+        bundle.extend(element);
+    }
+    assert_eq!(
+        decode_packet(&bundle).unwrap(),
+        vec![
+            ("/webptz/group/stage/zoom".to_owned(), -1.0),
+            ("/fader1".to_owned(), 0.25)
+        ]
+    );
+    assert_eq!(
+        decode_packet(b"/af\0").unwrap(),
+        vec![("/af".to_owned(), 1.0)]
+    );
+    assert!(decode_packet(b"/webptz/cam1/pan\0\0\0\0,f\0\0\0\0").is_err());
+
+    let mut router = Router::new(
+        "/webptz/",
+        vec![Group {
+            name: "stage".to_owned(),
+            devices: vec!["cam1".to_owned(), "cam2".to_owned()],
+        }],
+    );
+    router.route("/webptz/cam1/pan", 0.5);
+    let Operation::Sourced(origin, operation) = router.route("/webptz/cam1/tilt", -0.25) else {
+        panic!("expected a command");
+    };
+    let Operation::Command(request) = *operation else {
+        panic!("expected a command");
+    };
+    assert_eq!(origin, Origin::new("osc"));
+    assert_eq!(request.devices, vec!["cam1"]);
+    assert_eq!(request.command.pan.get(), 0.5);
+    assert_eq!(request.command.tilt.get(), -0.25);
+
+    let Operation::Sourced(_, operation) = router.route("/webptz/group/stage/zoom", 1.0) else {
+        panic!("expected a command");
+    };
+    let Operation::Command(request) = *operation else {
+        panic!("expected a command");
+    };
+    assert_eq!(request.devices, vec!["cam1", "cam2"]);
+    assert_eq!(request.command.pan.get(), 0.0);
+
+    assert!(matches!(
+        router.route("/webptz/group/nope/zoom", 1.0),
+        Operation::Input(_)
+    ));
+    assert!(matches!(router.route("/fader1", 0.25), Operation::Input(_)));
+}
@@ -0,0 +1,114 @@
+use std::{collections::HashMap, error::Error, time::Duration};
+
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::TcpStream,
+    sync::mpsc,
+};
+use tracing::{info, warn};
+
+use crate::{config::PanelConfig, input::InputEvent};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Connects to a Skaarhoj panel speaking the raw panel protocol and reports its buttons,
+/// joysticks, faders, and encoders as input events, reconnecting whenever the connection drops.
+pub async fn listen(id: String, config: PanelConfig, events: mpsc::UnboundedSender<InputEvent>) {
+    loop {
+        if let Err(e) = run(&id, &config, &events).await {
+            warn!("Panel {}: {}", id, e);
+        }
+        if events.is_closed() {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn run(
+    id: &str,
+    config: &PanelConfig,
+    events: &mpsc::UnboundedSender<InputEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let stream = TcpStream::connect(&config.address).await?;
+    let (reader, mut writer) = stream.into_split();
+    writer.write_all(b"ping\n").await?;
+    info!("Panel {}: Connected to {}", id, config.address);
+
+    let mut decoder = Decoder::new(config.encoder_step);
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line == "ping" {
+            writer.write_all(b"ack\n").await?;
+            continue;
+        }
+        if let Some((control, value)) = decoder.decode(line) {
+            let event = InputEvent {
+                source: id.to_owned(),
+                control,
+                value,
+            };
+            if events.send(event).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Err("connection closed".into())
+}
+
+/// Turns hardware component (HWC) messages into normalized values. Encoders are relative, so
+/// each one keeps a running value that detents move up or down.
+struct Decoder {
+    encoder_step: f64,
+    encoders: HashMap<String, f64>,
+}
+
+impl Decoder {
+    fn new(encoder_step: f64) -> Self {
+        Decoder {
+            encoder_step,
+            encoders: HashMap::new(),
+        }
+    }
+
+    fn decode(&mut self, line: &str) -> Option<(String, f64)> {
+        let (control, state) = line.split_once('=')?;
+        if !control.starts_with("HWC#") {
+            return None;
+        }
+        // Buttons can report which edge of a four-way button was pressed, e.g. `Down.2`
+        let state = state.split('.').next().unwrap_or(state);
+        let value = match state.split_once(':') {
+            None if state == "Down" => 1.0,
+            None if state == "Up" => 0.0,
+            None => return None,
+            Some(("Abs", x)) => x.parse::<f64>().ok()? / 1000.0,
+            Some(("Speed", x)) => (x.parse::<f64>().ok()? / 500.0).clamp(-1.0, 1.0),
+            Some(("Enc", x)) => {
+                let value = self.encoders.entry(control.to_owned()).or_default();
+                *value = (*value + x.parse::<f64>().ok()? * self.encoder_step).clamp(-1.0, 1.0);
+                *value
+            }
+            Some(_) => return None,
+        };
+        Some((control.to_owned(), value))
+    }
+}
+
+#[test]
+fn test_decode_panel_messages() {
+    let mut decoder = Decoder::new(0.25);
+    let decode = |decoder: &mut Decoder, line| decoder.decode(line).map(|(_, v)| v);
+    assert_eq!(decode(&mut decoder, "HWC#12=Down"), Some(1.0));
+    assert_eq!(decode(&mut decoder, "HWC#12=Up.4"), Some(0.0));
+    assert_eq!(decode(&mut decoder, "HWC#3=Abs:250"), Some(0.25));
+    assert_eq!(decode(&mut decoder, "HWC#7=Speed:-500"), Some(-1.0));
+    assert_eq!(decode(&mut decoder, "HWC#9=Enc:2"), Some(0.5));
+    assert_eq!(decode(&mut decoder, "HWC#9=Enc:-1"), Some(0.25));
+    assert_eq!(decode(&mut decoder, "_model=SK_RACKFUSIONLIVE"), None);
+    assert_eq!(
+        decoder.decode("HWC#3=Abs:1000"),
+        Some(("HWC#3".to_owned(), 1.0))
+    );
+}
@@ -0,0 +1,287 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::{
+    actor::PositionReader,
+    config::Mounting,
+    device::{AngleDeg, Command, Position, Velocity},
+    CommandRequest, Operation,
+};
+
+const STEP_INTERVAL: Duration = Duration::from_millis(100);
+const GUIDE_INTERVAL: Duration = Duration::from_millis(200);
+/// How long a recall keeps correcting after its planned duration before giving up.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(3);
+const ANGLE: Axis = Axis {
+    full_speed: 30.0,
+    tolerance: 0.5,
+    wraps: true,
+};
+const ZOOM: Axis = Axis {
+    full_speed: 0.25,
+    tolerance: 0.01,
+    wraps: false,
+};
+
+struct Axis {
+    /// Error at which the axis is driven at full speed.
+    full_speed: f64,
+    tolerance: f64,
+    /// Whether values wrap around at ±180, like angles.
+    wraps: bool,
+}
+
+impl Axis {
+    fn difference(&self, a: f64, b: f64) -> f64 {
+        match self.wraps {
+            true => AngleDeg::new(a - b).get(),
+            false => a - b,
+        }
+    }
+
+    /// Speed towards the eased setpoint between `start` and `target`, or `None` if the axis
+    /// isn't reported or has arrived.
+    fn speed(
+        &self,
+        start: Option<f64>,
+        target: Option<f64>,
+        current: Option<f64>,
+        eased: f64,
+    ) -> Option<Velocity> {
+        let (start, target, current) = (start?, target?, current?);
+        if eased >= 1.0 && self.difference(target, current).abs() < self.tolerance {
+            return None;
+        }
+        let setpoint = start + self.difference(target, start) * eased;
+        Some(Velocity::new(
+            self.difference(setpoint, current) / self.full_speed,
+        ))
+    }
+}
+
+/// Moves a device to a saved position over `duration`, steering it along an eased path from where
+/// it started using its position feedback, and feeding the resulting commands back through the
+/// command loop. Positions are in the device's own axes, so commands are converted back into
+/// the shot's axes for its `mounting` to be applied to as usual.
+pub async fn recall(
+    device: String,
+    target: Position,
+    duration: Duration,
+    mounting: Mounting,
+    position: PositionReader,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) {
+    let Some(start) = position.read().await else {
+        warn!(device = %device, "No position feedback, can't recall preset");
+        return;
+    };
+    let begin = Instant::now();
+    let mut interval = tokio::time::interval(STEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let elapsed = begin.elapsed();
+        let progress = match duration.is_zero() {
+            true => 1.0,
+            false => elapsed.as_secs_f64() / duration.as_secs_f64(),
+        };
+        let command = match position.read().await {
+            Some(current) => steer(&start, &target, &current, progress),
+            None => None,
+        };
+        let done = command.is_none() || elapsed >= duration + SETTLE_TIMEOUT;
+        let request = CommandRequest {
+            devices: vec![device.clone()],
+            command: mounting
+                .inverse()
+                .apply(command.filter(|_| !done).unwrap_or_default()),
+        };
+        if command_tx.send(Operation::Command(request)).is_err() || done {
+            return;
+        }
+    }
+}
+
+/// The command that moves a device towards where it should be `progress` (0 to 1) of the way
+/// from `start` to `target`, or `None` once every axis it reports has arrived.
+fn steer(
+    start: &Position,
+    target: &Position,
+    current: &Position,
+    progress: f64,
+) -> Option<Command> {
+    let eased = smoothstep(progress.clamp(0.0, 1.0));
+    let angle = |axis: fn(&Position) -> Option<AngleDeg>| {
+        let value = |p| axis(p).map(f64::from);
+        ANGLE.speed(value(start), value(target), value(current), eased)
+    };
+    let pan = angle(|p| p.pan);
+    let tilt = angle(|p| p.tilt);
+    let roll = angle(|p| p.roll);
+    let zoom = ZOOM.speed(
+        start.zoom.map(f64::from),
+        target.zoom.map(f64::from),
+        current.zoom.map(f64::from),
+        eased,
+    );
+    if [pan, tilt, roll, zoom].iter().all(Option::is_none) {
+        return None;
+    }
+    Some(Command {
+        pan: pan.unwrap_or_default(),
+        tilt: tilt.unwrap_or_default(),
+        roll: roll.unwrap_or_default(),
+        zoom: zoom.unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+/// How far a device has to move to reach a preset, in the shot's axes, for overlays that guide
+/// manual moves. Angles are scaled so that 1 is half a turn, and zoom is the difference in zoom
+/// ratio. Axes the device doesn't report are left out.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Guidance {
+    pub slot: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pan: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tilt: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom: Option<f64>,
+}
+
+impl Guidance {
+    fn new(slot: u32, current: &Position, target: &Position, mounting: Mounting) -> Guidance {
+        let angle = |axis: fn(&Position) -> Option<AngleDeg>| match (axis(current), axis(target)) {
+            (Some(current), Some(target)) => Some(target.minus(current) / 180.0),
+            _ => None,
+        };
+        let (pan, tilt, roll) = (angle(|p| p.pan), angle(|p| p.tilt), angle(|p| p.roll));
+        // Mountings only swap and flip axes, so applying one to which axes are known tells which
+        // shot axes are known
+        let known = |axis: Option<f64>| Velocity::new(axis.map_or(0.0, |_| 1.0));
+        let known = mounting.inverse().apply(Command {
+            pan: known(pan),
+            tilt: known(tilt),
+            roll: known(roll),
+            ..Default::default()
+        });
+        let shot = mounting.inverse().apply(Command {
+            pan: Velocity::new(pan.unwrap_or(0.0)),
+            tilt: Velocity::new(tilt.unwrap_or(0.0)),
+            roll: Velocity::new(roll.unwrap_or(0.0)),
+            ..Default::default()
+        });
+        let shot_axis =
+            |known: Velocity, value: Velocity| (!known.is_stopped()).then(|| value.get());
+        Guidance {
+            slot,
+            pan: shot_axis(known.pan, shot.pan),
+            tilt: shot_axis(known.tilt, shot.tilt),
+            roll: shot_axis(known.roll, shot.roll),
+            zoom: match (current.zoom, target.zoom) {
+                (Some(current), Some(target)) => Some(f64::from(target) - f64::from(current)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Follows a device's position and reports how far it is from a preset whenever that changes,
+/// until aborted.
+pub async fn guide(
+    device: String,
+    slot: u32,
+    target: Position,
+    mounting: Mounting,
+    position: PositionReader,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) {
+    let mut last = None;
+    let mut interval = tokio::time::interval(GUIDE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let guidance = position
+            .read()
+            .await
+            .map(|current| Guidance::new(slot, &current, &target, mounting));
+        if guidance == last {
+            continue;
+        }
+        last = guidance;
+        if command_tx
+            .send(Operation::Guidance(device.clone(), guidance))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn smoothstep(x: f64) -> f64 {
+    x * x * (3.0 - 2.0 * x)
+}
+
+#[test]
+fn test_steer() {
+    let position = |pan: f64, zoom: f64| Position {
+        pan: Some(pan.into()),
+        zoom: Some(zoom.into()),
+        ..Default::default()
+    };
+    let start = position(170.0, 0.0);
+    let target = position(-170.0, 0.5);
+
+    // Halfway through, the setpoint is at 180°/0.25 and pan wraps around instead of going back
+    let command = steer(&start, &target, &position(170.0, 0.25), 0.5).unwrap();
+    assert_eq!(command.pan.get(), 10.0 / ANGLE.full_speed);
+    assert_eq!(command.zoom.get(), 0.0);
+
+    // Falling behind is corrected at up to full speed
+    let command = steer(&start, &target, &position(170.0, 0.0), 1.0).unwrap();
+    assert_eq!(command.pan.get(), 20.0 / ANGLE.full_speed);
+    assert_eq!(command.zoom.get(), 1.0);
+
+    // Axes that have arrived stop, and the recall finishes once all of them have
+    let command = steer(&start, &target, &position(-170.2, 0.4), 1.0).unwrap();
+    assert!(command.pan.is_stopped());
+    assert!(command.zoom.get() > 0.0);
+    assert_eq!(steer(&start, &target, &position(-170.2, 0.5), 1.0), None);
+
+    // Axes without feedback are left alone
+    let zoom_only = Position {
+        zoom: Some(0.5.into()),
+        ..Default::default()
+    };
+    assert_eq!(steer(&start, &zoom_only, &zoom_only, 1.0), None);
+}
+
+#[test]
+fn test_guidance() {
+    let position = |pan: f64, tilt: f64| Position {
+        pan: Some(pan.into()),
+        tilt: Some(tilt.into()),
+        ..Default::default()
+    };
+    let target = Position {
+        zoom: Some(0.75.into()),
+        ..position(-170.0, 45.0)
+    };
+
+    let guidance = Guidance::new(2, &position(170.0, 0.0), &target, Mounting::Upright);
+    assert_eq!(guidance.pan, Some(20.0 / 180.0));
+    assert_eq!(guidance.tilt, Some(0.25));
+    assert_eq!(guidance.roll, None);
+    assert_eq!(guidance.zoom, None);
+
+    // Sideways, the device's tilt is the shot's pan
+    let guidance = Guidance::new(2, &position(170.0, 0.0), &target, Mounting::SidewaysLeft);
+    assert_eq!(guidance.tilt.map(f64::abs), Some(20.0 / 180.0));
+    assert_eq!(guidance.pan.map(f64::abs), Some(0.25));
+    assert_eq!(guidance.roll, None);
+}
@@ -0,0 +1,108 @@
+//! Live views from cameras that can stream them, re-served as MJPEG so the UI can show a framing
+//! preview next to the controls without the camera's own app.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+
+use crate::{access::Visibility, actor::DeviceHandle, auth::Client, device::LiveView};
+
+const BOUNDARY: &str = "frame";
+
+#[derive(Clone, Default)]
+pub struct Previews(Arc<RwLock<HashMap<String, LiveView>>>);
+
+impl Previews {
+    /// Picks up the live views of the current devices, after devices have been added or removed.
+    pub fn sync(&self, handles: &[DeviceHandle]) {
+        *self.0.write().unwrap() = handles
+            .iter()
+            .filter_map(|h| Some((h.id.clone(), h.live_view.clone()?)))
+            .collect();
+    }
+
+    fn get(&self, id: &str) -> Option<LiveView> {
+        self.0.read().unwrap().get(id).cloned()
+    }
+}
+
+/// Where the device's live view is served from.
+pub fn url(id: &str) -> String {
+    format!("/preview/{}", id)
+}
+
+pub fn router(previews: Previews, visibility: Arc<Visibility>) -> Router {
+    Router::new().route(
+        "/preview/:id",
+        get(move |id, client| preview(previews, visibility, id, client)),
+    )
+}
+
+/// Streams frames as they come in, starting with the latest one. Frames that come in faster
+/// than the client can take them are skipped.
+async fn preview(
+    previews: Previews,
+    visibility: Arc<Visibility>,
+    Path(id): Path<String>,
+    client: Option<Extension<Client>>,
+) -> Response {
+    if !visibility.shows(&Client::source(client), &id) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(live_view) = previews.get(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut frames = live_view.subscribe();
+    frames.mark_changed();
+    let parts = futures::stream::unfold(frames, |mut frames| async move {
+        loop {
+            frames.changed().await.ok()?;
+            let frame = frames.borrow_and_update().clone();
+            if let Some(jpeg) = frame {
+                return Some((Ok::<_, Infallible>(part(&jpeg)), frames));
+            }
+        }
+    });
+    (
+        [(
+            header::CONTENT_TYPE,
+            format!("multipart/x-mixed-replace; boundary={}", BOUNDARY),
+        )],
+        Body::from_stream(parts),
+    )
+        .into_response()
+}
+
+/// One frame of the multipart stream.
+fn part(jpeg: &[u8]) -> Vec<u8> {
+    let header = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        BOUNDARY,
+        jpeg.len()
+    );
+    [header.as_bytes(), jpeg, b"\r\n"].concat()
+}
+
+#[test]
+fn test_part() {
+    assert_eq!(
+        part(&[0xff, 0xd8, 0xff, 0xd9]),
+        [
+            &b"--frame\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\n\r\n"[..],
+            &[0xff, 0xd8, 0xff, 0xd9],
+            b"\r\n",
+        ]
+        .concat()
+    );
+}
@@ -0,0 +1,63 @@
+//! Watches the config file, so that edits to it are applied without restarting.
+
+use std::{error::Error, path::PathBuf, time::Duration};
+
+use notify::{EventKind, RecursiveMode, Watcher as _};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{config, Operation};
+
+/// Editors often write a file in several steps, so changes are only read once it's been left
+/// alone for this long.
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+/// Sends the config at `path` to the command loop whenever it changes. The directory is watched
+/// rather than the file, since editors that save by replacing the file would otherwise stop the
+/// watch after the first save.
+pub async fn watch(
+    path: PathBuf,
+    command_tx: mpsc::UnboundedSender<Operation>,
+) -> Result<(), Box<dyn Error>> {
+    let (changed_tx, mut changed_rx) = mpsc::unbounded_channel();
+    let name = path
+        .file_name()
+        .ok_or("config path has no file name")?
+        .to_owned();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+            Ok(event) if event.paths.iter().any(|p| p.file_name() == Some(&name)) => {
+                let _ = changed_tx.send(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Error watching config: {}", e),
+        })?;
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+    info!("Watching {} for changes", path.display());
+
+    while changed_rx.recv().await.is_some() {
+        loop {
+            tokio::select! {
+                Some(()) = changed_rx.recv() => continue,
+                _ = tokio::time::sleep(SETTLE_TIME) => break,
+            }
+        }
+        match config::read_config(&path.to_string_lossy()) {
+            Ok(config) => {
+                if command_tx
+                    .send(Operation::ReloadConfig(Box::new(config)))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            Err(e) => warn!("Not reloading {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
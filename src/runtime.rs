@@ -0,0 +1,87 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use tokio::runtime::{Builder, Handle, Runtime};
+use tracing::warn;
+
+use crate::config::RuntimeConfig;
+
+/// Builds the multi-threaded runtime everything runs on, pinning worker threads to the
+/// configured CPUs.
+pub fn build(config: &RuntimeConfig) -> io::Result<Runtime> {
+    let mut builder = Builder::new_multi_thread();
+    builder.enable_all();
+    if let Some(threads) = config.worker_threads {
+        builder.worker_threads(threads.max(1));
+    }
+    if !config.cpus.is_empty() {
+        let cpus = config.cpus.clone();
+        let next = Arc::new(AtomicUsize::new(0));
+        builder.on_thread_start(move || {
+            let cpu = cpus[next.fetch_add(1, Ordering::Relaxed) % cpus.len()];
+            if let Err(e) = pin_current_thread(cpu) {
+                warn!("Couldn't pin thread to CPU {}: {}", cpu, e);
+            }
+        });
+    }
+    builder.build()
+}
+
+/// Starts a single-threaded runtime on its own thread, so Bluetooth devices don't compete with
+/// everything else for worker threads.
+pub fn spawn_bluetooth_runtime() -> io::Result<Handle> {
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    let handle = runtime.handle().clone();
+    std::thread::Builder::new()
+        .name("bluetooth".to_owned())
+        .spawn(move || runtime.block_on(std::future::pending::<()>()))?;
+    Ok(handle)
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_priority(nice: i32) -> io::Result<()> {
+    // SAFETY: setpriority has no memory safety requirements
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_priority(_nice: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "process priority can only be set on Linux",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> io::Result<()> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such CPU"));
+    }
+    // SAFETY: the CPU set is zero-initialized and only touched through the libc helpers
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "threads can only be pinned on Linux",
+    ))
+}
@@ -0,0 +1,104 @@
+//! `webptz scan`, which lists the Bluetooth peripherals, serial ports, and Lumix cameras this
+//! machine can see, for finding the names, paths, and addresses to put in the config.
+
+use std::{error::Error, time::Duration};
+
+/// How long Lumix cameras are given to answer discovery.
+#[cfg(feature = "lumix")]
+const LUMIX_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Entry point for `webptz scan`. Bluetooth peripherals are scanned for over `duration`.
+#[cfg_attr(not(feature = "bluetooth"), allow(unused_variables))]
+pub async fn run(duration: Duration) -> Result<(), Box<dyn Error>> {
+    #[cfg(feature = "bluetooth")]
+    scan_bluetooth(duration).await?;
+    #[cfg(any(feature = "script", feature = "lanc", feature = "serial"))]
+    list_serial_ports()?;
+    #[cfg(feature = "lumix")]
+    discover_lumix().await?;
+    #[cfg(not(any(
+        feature = "bluetooth",
+        feature = "script",
+        feature = "lanc",
+        feature = "serial",
+        feature = "lumix"
+    )))]
+    println!("This build doesn't include any Bluetooth, serial, or Lumix devices");
+    Ok(())
+}
+
+#[cfg(feature = "bluetooth")]
+async fn scan_bluetooth(duration: Duration) -> Result<(), Box<dyn Error>> {
+    use btleplug::api::{Central as _, Peripheral as _, ScanFilter};
+
+    let adapter = crate::device::bluetooth_adapter().await?;
+    println!("== Scanning for Bluetooth peripherals for {:?}", duration);
+    adapter.start_scan(ScanFilter::default()).await?;
+    tokio::time::sleep(duration).await;
+    adapter.stop_scan().await?;
+    for peripheral in adapter.peripherals().await? {
+        let Some(properties) = peripheral.properties().await? else {
+            continue;
+        };
+        let rssi = properties
+            .rssi
+            .map_or_else(String::new, |r| format!("{} dBm", r));
+        println!(
+            "{}  {:>8}  {}",
+            peripheral.address(),
+            rssi,
+            properties.local_name.as_deref().unwrap_or("(no name)"),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "script", feature = "lanc", feature = "serial"))]
+fn list_serial_ports() -> Result<(), Box<dyn Error>> {
+    use tokio_serial::SerialPortType;
+
+    println!("== Serial ports");
+    for port in tokio_serial::available_ports()? {
+        let description = match port.port_type {
+            SerialPortType::UsbPort(usb) => format!(
+                "USB {:04x}:{:04x} {} {}",
+                usb.vid,
+                usb.pid,
+                usb.manufacturer.unwrap_or_default(),
+                usb.product.unwrap_or_default(),
+            ),
+            SerialPortType::BluetoothPort => "Bluetooth".to_owned(),
+            SerialPortType::PciPort => "PCI".to_owned(),
+            SerialPortType::Unknown => String::new(),
+        };
+        println!("{}  {}", port.port_name, description.trim_end());
+    }
+    Ok(())
+}
+
+/// Lists Lumix cameras along with a config entry for each, ready to paste into `devices`.
+#[cfg(feature = "lumix")]
+async fn discover_lumix() -> Result<(), Box<dyn Error>> {
+    use crate::config::{DeviceConfig, LumixConfig};
+
+    println!("== Lumix cameras");
+    let cameras = crate::device::lumix::discover(LUMIX_DISCOVERY_TIMEOUT).await?;
+    for (i, camera) in cameras.into_iter().enumerate() {
+        let config = DeviceConfig::Lumix(LumixConfig {
+            address: camera.address.clone(),
+            password: None,
+            mac: None,
+            storage_monitor: None,
+            zoom_speeds: None,
+            capabilities: None,
+        });
+        println!(
+            "{}  {}\n    \"lumix{}\": {}",
+            camera.address,
+            camera.name,
+            i + 1,
+            serde_json::to_string(&config)?
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! `webptz send`, which moves configured devices once without starting the web server, for
+//! scripts and quick checks from a shell.
+
+use std::{error::Error, time::Duration};
+
+use crate::{
+    config,
+    device::{Command, Device},
+};
+
+/// Commands are repeated while moving, like the UI does, since some devices stop on their own
+/// if they don't hear from us.
+const COMMAND_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Entry point for `webptz send`. Sends `command` to each device for `duration`, then stops
+/// them.
+pub async fn run(
+    config_path: &str,
+    ids: &[String],
+    command: Command,
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let config = config::read_config(config_path)?;
+    let mut devices = vec![];
+    for id in ids {
+        devices.push(crate::create_configured_device(&config, id).await?);
+    }
+    let mut result = Ok(());
+    for device in devices.iter_mut() {
+        println!("== Connecting to {}", device);
+        result = device.connect().await.map_err(|e| e.into());
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_ok() {
+        println!("== Sending {:?} for {:?}", command, duration);
+        result = tokio::select! {
+            result = hold(&mut devices, command, duration) => result,
+            _ = tokio::signal::ctrl_c() => Err("interrupted".into()),
+        };
+    }
+    // Whatever happened, don't leave anything moving
+    for device in devices.iter_mut().filter(|d| d.is_connected()) {
+        if let Err(e) = device.send_command(Command::default()).await {
+            println!("Error stopping {}: {}", device, e);
+        }
+        println!("== Disconnecting from {}", device);
+        if let Err(e) = device.disconnect().await {
+            println!("Error disconnecting from {}: {}", device, e);
+        }
+    }
+    result
+}
+
+async fn hold(
+    devices: &mut [Box<dyn Device>],
+    command: Command,
+    duration: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let mut interval = tokio::time::interval(COMMAND_INTERVAL);
+    let end = tokio::time::Instant::now() + duration;
+    loop {
+        interval.tick().await;
+        for device in devices.iter_mut() {
+            device.send_command(command).await?;
+        }
+        if tokio::time::Instant::now() >= end {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,582 @@
+//! `webptz simulate-camera`, which pretends to be a camera on localhost so that backends and
+//! the dispatcher can be tested without hardware. The simulators accept whatever a real camera
+//! would, log it, and reply the way the camera does, but only track as much state as it takes to
+//! give plausible answers.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    net::{Ipv4Addr, SocketAddr},
+    time::Instant,
+};
+
+use axum::{extract::Query, routing::get, Router};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream, UdpSocket},
+    sync::broadcast,
+};
+
+const USAGE: &str = "usage: webptz simulate-camera lumix [--http-port <port>]
+       webptz simulate-camera visca [--port <port>]
+
+Runs a simulated camera on 127.0.0.1 until interrupted. The Lumix simulator answers on the ports
+a real camera uses (80 for HTTP, 60606 and 15740), so point a lumix device at 127.0.0.1. Ports
+below 1024 usually need extra privileges; --http-port moves the HTTP one for clients that don't
+need it. The VISCA simulator listens for VISCA over IP on UDP port 52381 by default.";
+
+const LUMIX_DESCRIPTION_PORT: u16 = 60606;
+const LUMIX_PTP_PORT: u16 = 15740;
+const VISCA_PORT: u16 = 52381;
+
+/// A mid-gray 8x8 baseline JPEG, served as the live view. Its only block has every coefficient
+/// zero, and its Huffman tables only have codes for that.
+const GRAY_FRAME: &[u8] = &[
+    0xff, 0xd8, // SOI
+    0xff, 0xdb, 0x00, 0x43, 0x00, // DQT, followed by 64 ones
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    0xff, 0xc0, 0x00, 0x0b, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01, 0x01, 0x11, 0x00, // SOF0
+    0xff, 0xc4, 0x00, 0x14, 0x00, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, // DHT
+    0xff, 0xc4, 0x00, 0x14, 0x10, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, // DHT
+    0xff, 0xda, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3f, 0x00, // SOS
+    0x3f, // Entropy-coded data
+    0xff, 0xd9, // EOI
+];
+
+/// Entry point for `webptz simulate-camera`.
+pub async fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let port_arg = |flag: &str, default: u16| -> Result<u16, Box<dyn Error>> {
+        match &args[1..] {
+            [] => Ok(default),
+            [f, port] if f == flag => Ok(port.parse().map_err(|_| USAGE)?),
+            _ => Err(USAGE.into()),
+        }
+    };
+    let simulator = async {
+        match args.first().map(String::as_str) {
+            Some("lumix") => simulate_lumix(port_arg("--http-port", 80)?).await,
+            Some("visca") => simulate_visca(port_arg("--port", VISCA_PORT)?).await,
+            _ => Err(USAGE.into()),
+        }
+    };
+    tokio::select! {
+        result = simulator => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    }
+}
+
+async fn bind_tcp(port: u16) -> Result<TcpListener, Box<dyn Error>> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(|e| format!("couldn't listen on port {}: {}", port, e).into())
+}
+
+async fn simulate_lumix(http_port: u16) -> Result<(), Box<dyn Error>> {
+    let description = Router::new().route(
+        "/PTPRemote/Server0/ddd",
+        get(|| async {
+            format!(
+                "<?xml version=\"1.0\"?><root xmlns:pana=\"urn:schemas-panasonic-com:pana\">\
+                 <device><friendlyName>Simulated LUMIX</friendlyName>\
+                 <pana:X_PTPPortNo>{}</pana:X_PTPPortNo></device></root>",
+                LUMIX_PTP_PORT
+            )
+        }),
+    );
+    let cam_cgi = Router::new().route(
+        "/cam.cgi",
+        get(|Query(query): Query<HashMap<String, String>>| async move {
+            if query.get("type").is_some_and(|t| t == "lens") {
+                return "ok,3072/256,1024/256,2816/256,0,60,12,on,LUMIX G VARIO 12-60\r\n";
+            }
+            println!("Lumix simulator: Granted access");
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><camrply><result>ok</result></camrply>"
+        }),
+    );
+    let description_listener = bind_tcp(LUMIX_DESCRIPTION_PORT).await?;
+    let cam_cgi_listener = bind_tcp(http_port).await?;
+    let ptp_listener = bind_tcp(LUMIX_PTP_PORT).await?;
+    println!(
+        "Lumix simulator: Listening on 127.0.0.1 ports {}, {}, and {}",
+        http_port, LUMIX_DESCRIPTION_PORT, LUMIX_PTP_PORT
+    );
+    let (event_tx, _) = broadcast::channel(16);
+    tokio::select! {
+        result = axum::serve(description_listener, description) => result?,
+        result = axum::serve(cam_cgi_listener, cam_cgi) => result?,
+        result = serve_ptp(ptp_listener, event_tx) => result?,
+    }
+    Ok(())
+}
+
+async fn serve_ptp(
+    listener: TcpListener,
+    event_tx: broadcast::Sender<Vec<u8>>,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_ptp_connection(stream, event_tx).await {
+                println!("Lumix simulator: {}: {}", addr, e);
+            }
+            println!("Lumix simulator: {} disconnected", addr);
+        });
+    }
+}
+
+/// Serves either half of a PTP/IP session, depending on how the client introduces itself.
+async fn serve_ptp_connection(
+    mut stream: TcpStream,
+    event_tx: broadcast::Sender<Vec<u8>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut session = PtpSession::default();
+    let (packet_type, payload) = read_ptp_packet(&mut stream).await?;
+    if packet_type == PTPIP_INIT_EVENT_REQUEST {
+        println!("Lumix simulator: Event channel opened");
+        stream
+            .write_all(&ptp_packet(PTPIP_INIT_EVENT_ACK, &[]))
+            .await?;
+        let mut event_rx = event_tx.subscribe();
+        let mut buf = [0; 64];
+        loop {
+            tokio::select! {
+                event = event_rx.recv() => match event {
+                    Ok(event) => stream.write_all(&event).await?,
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                },
+                // Only here to notice the client going away
+                read = stream.read(&mut buf) => if read? == 0 {
+                    return Ok(());
+                },
+            }
+        }
+    }
+    let (mut packet_type, mut payload) = (packet_type, payload);
+    loop {
+        let (reply, event) = session.handle(packet_type, &payload);
+        if !reply.is_empty() {
+            stream.write_all(&reply).await?;
+        }
+        if let Some(event) = event {
+            // Nobody may be listening for events, which is fine
+            let _ = event_tx.send(event);
+        }
+        (packet_type, payload) = match read_ptp_packet(&mut stream).await {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+    }
+}
+
+async fn read_ptp_packet(stream: &mut TcpStream) -> std::io::Result<(u32, Vec<u8>)> {
+    let mut header = [0; 8];
+    stream.read_exact(&mut header).await?;
+    let length = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let packet_type = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let mut payload = vec![0; length.saturating_sub(header.len())];
+    stream.read_exact(&mut payload).await?;
+    Ok((packet_type, payload))
+}
+
+const PTPIP_INIT_COMMAND_REQUEST: u32 = 0x01;
+const PTPIP_INIT_COMMAND_ACK: u32 = 0x02;
+const PTPIP_INIT_EVENT_REQUEST: u32 = 0x03;
+const PTPIP_INIT_EVENT_ACK: u32 = 0x04;
+const PTPIP_OPERATION_REQUEST: u32 = 0x06;
+const PTPIP_OPERATION_RESPONSE: u32 = 0x07;
+const PTPIP_EVENT: u32 = 0x08;
+const PTPIP_START_DATA: u32 = 0x09;
+const PTPIP_END_DATA: u32 = 0x0c;
+const PTP_RESPONSE_OK: u16 = 0x2001;
+const PTP_EVENT_DEVICE_PROP_CHANGED: u16 = 0x4006;
+/// Operations are followed by data from the client.
+const PHASE_DATA_OUT: u32 = 0x02;
+
+fn ptp_packet(packet_type: u32, payload: &[u8]) -> Vec<u8> {
+    let mut packet = ((payload.len() + 8) as u32).to_le_bytes().to_vec();
+    packet.extend(packet_type.to_le_bytes());
+    packet.extend(payload);
+    packet
+}
+
+/// The command half of a PTP/IP session.
+#[derive(Default)]
+struct PtpSession {
+    /// The operation waiting for its data phase, as `(opcode, transaction ID, first parameter)`.
+    pending: Option<(u16, u32, u32)>,
+}
+
+impl PtpSession {
+    /// Returns the bytes to send back (if any), and an event to send on the event channel.
+    fn handle(&mut self, packet_type: u32, payload: &[u8]) -> (Vec<u8>, Option<Vec<u8>>) {
+        match packet_type {
+            PTPIP_INIT_COMMAND_REQUEST => {
+                println!("Lumix simulator: Command channel opened");
+                let mut ack = 1u32.to_le_bytes().to_vec();
+                ack.extend([0x42; 16]);
+                ack.extend(
+                    "Simulated LUMIX\0"
+                        .encode_utf16()
+                        .flat_map(|c| c.to_le_bytes()),
+                );
+                ack.extend(0x00010000u32.to_le_bytes());
+                (ptp_packet(PTPIP_INIT_COMMAND_ACK, &ack), None)
+            }
+            PTPIP_OPERATION_REQUEST if payload.len() >= 10 => {
+                let phase = u32::from_le_bytes(payload[0..4].try_into().unwrap());
+                let opcode = u16::from_le_bytes(payload[4..6].try_into().unwrap());
+                let transaction_id = u32::from_le_bytes(payload[6..10].try_into().unwrap());
+                let param1 = payload
+                    .get(10..14)
+                    .map_or(0, |p| u32::from_le_bytes(p.try_into().unwrap()));
+                println!(
+                    "Lumix simulator: Operation {:#06x} ({}) with {:#010x}",
+                    opcode, transaction_id, param1
+                );
+                if phase == PHASE_DATA_OUT {
+                    self.pending = Some((opcode, transaction_id, param1));
+                    return (vec![], None);
+                }
+                let data = match opcode {
+                    // GetStorageIDs, with one card in the first slot and the second empty
+                    0x1004 => Some(
+                        [2u32, 0x00010001, 0x00020000]
+                            .map(u32::to_le_bytes)
+                            .concat(),
+                    ),
+                    // GetStorageInfo, half of 64 GiB free
+                    0x1005 => {
+                        let mut info = [4u16, 2, 0].map(u16::to_le_bytes).concat();
+                        info.extend((64u64 << 30).to_le_bytes());
+                        info.extend((32u64 << 30).to_le_bytes());
+                        info.extend(2000u32.to_le_bytes());
+                        info.extend([0, 0]);
+                        Some(info)
+                    }
+                    // Live view, which has overlay info ahead of the frame
+                    0x9706 => Some([&[0; 32][..], GRAY_FRAME].concat()),
+                    _ => None,
+                };
+                let mut reply = vec![];
+                if let Some(data) = data {
+                    let mut start = transaction_id.to_le_bytes().to_vec();
+                    start.extend((data.len() as u64).to_le_bytes());
+                    reply.extend(ptp_packet(PTPIP_START_DATA, &start));
+                    let end = [&transaction_id.to_le_bytes()[..], &data].concat();
+                    reply.extend(ptp_packet(PTPIP_END_DATA, &end));
+                }
+                reply.extend(operation_response(transaction_id));
+                (reply, None)
+            }
+            PTPIP_START_DATA => (vec![], None),
+            PTPIP_END_DATA => match self.pending.take() {
+                Some((opcode, transaction_id, param1)) => {
+                    println!(
+                        "Lumix simulator: Data for {:#06x} ({}): {}",
+                        opcode,
+                        transaction_id,
+                        hex::encode(payload.get(4..).unwrap_or_default())
+                    );
+                    // Real cameras report the zoom and focus changing as they move
+                    let mut event = PTP_EVENT_DEVICE_PROP_CHANGED.to_le_bytes().to_vec();
+                    event.extend(transaction_id.to_le_bytes());
+                    event.extend(param1.to_le_bytes());
+                    (
+                        operation_response(transaction_id),
+                        Some(ptp_packet(PTPIP_EVENT, &event)),
+                    )
+                }
+                None => {
+                    println!("Lumix simulator: Data without an operation");
+                    (vec![], None)
+                }
+            },
+            _ => {
+                println!(
+                    "Lumix simulator: Ignoring packet {:#04x} {}",
+                    packet_type,
+                    hex::encode(payload)
+                );
+                (vec![], None)
+            }
+        }
+    }
+}
+
+fn operation_response(transaction_id: u32) -> Vec<u8> {
+    let response = [
+        &PTP_RESPONSE_OK.to_le_bytes()[..],
+        &transaction_id.to_le_bytes(),
+    ]
+    .concat();
+    ptp_packet(PTPIP_OPERATION_RESPONSE, &response)
+}
+
+async fn simulate_visca(port: u16) -> Result<(), Box<dyn Error>> {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(|e| format!("couldn't listen on port {}: {}", port, e))?;
+    println!("VISCA simulator: Listening on 127.0.0.1 port {}", port);
+    let mut camera = ViscaCamera::new(Instant::now());
+    let mut buf = [0; 1024];
+    loop {
+        let (len, from): (usize, SocketAddr) = socket.recv_from(&mut buf).await?;
+        for reply in camera.handle_packet(&buf[..len], Instant::now()) {
+            socket.send_to(&reply, from).await?;
+        }
+    }
+}
+
+const VISCA_COMMAND: u16 = 0x0100;
+const VISCA_INQUIRY: u16 = 0x0110;
+const VISCA_REPLY: u16 = 0x0111;
+const VISCA_CONTROL: u16 = 0x0200;
+const VISCA_CONTROL_REPLY: u16 = 0x0201;
+/// Position units moved per second per step of speed.
+const VISCA_UNITS_PER_SPEED: f64 = 40.0;
+const VISCA_PAN_LIMIT: f64 = 2448.0;
+const VISCA_TILT_LIMIT: f64 = 1296.0;
+const VISCA_ZOOM_LIMIT: f64 = 16384.0;
+
+#[derive(Clone, Copy, Default)]
+struct Axis {
+    position: f64,
+    /// Units per second.
+    speed: f64,
+}
+
+impl Axis {
+    fn advance(&mut self, seconds: f64, min: f64, max: f64) {
+        self.position = (self.position + self.speed * seconds).clamp(min, max);
+    }
+}
+
+/// A VISCA camera at address 1 that moves at the speeds it's given, so that position
+/// inquiries have changing answers.
+struct ViscaCamera {
+    pan: Axis,
+    tilt: Axis,
+    zoom: Axis,
+    presets: [(f64, f64, f64); 16],
+    updated: Instant,
+}
+
+impl ViscaCamera {
+    fn new(now: Instant) -> ViscaCamera {
+        ViscaCamera {
+            pan: Axis::default(),
+            tilt: Axis::default(),
+            zoom: Axis::default(),
+            presets: [(0.0, 0.0, 0.0); 16],
+            updated: now,
+        }
+    }
+
+    /// Unwraps a VISCA over IP packet and wraps the replies to it.
+    fn handle_packet(&mut self, packet: &[u8], now: Instant) -> Vec<Vec<u8>> {
+        let Some(header) = packet.get(..8) else {
+            println!("VISCA simulator: Packet too short: {}", hex::encode(packet));
+            return vec![];
+        };
+        let payload_type = u16::from_be_bytes([header[0], header[1]]);
+        let sequence = &header[4..8];
+        let payload = &packet[8..];
+        let (reply_type, replies) = match payload_type {
+            VISCA_COMMAND | VISCA_INQUIRY => (VISCA_REPLY, self.handle(payload, now)),
+            // Resetting the sequence number, which is all control commands are used for
+            VISCA_CONTROL => (VISCA_CONTROL_REPLY, vec![vec![0x01]]),
+            _ => {
+                println!(
+                    "VISCA simulator: Unknown payload type {:#06x}",
+                    payload_type
+                );
+                return vec![];
+            }
+        };
+        replies
+            .into_iter()
+            .map(|reply| {
+                let mut packet = reply_type.to_be_bytes().to_vec();
+                packet.extend((reply.len() as u16).to_be_bytes());
+                packet.extend(sequence);
+                packet.extend(reply);
+                packet
+            })
+            .collect()
+    }
+
+    /// Replies to a VISCA message: an ACK and completion for commands, or the answer to an
+    /// inquiry.
+    fn handle(&mut self, message: &[u8], now: Instant) -> Vec<Vec<u8>> {
+        let seconds = now.duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+        self.pan.advance(seconds, -VISCA_PAN_LIMIT, VISCA_PAN_LIMIT);
+        self.tilt
+            .advance(seconds, -VISCA_TILT_LIMIT, VISCA_TILT_LIMIT);
+        self.zoom.advance(seconds, 0.0, VISCA_ZOOM_LIMIT);
+        println!("VISCA simulator: Received {}", hex::encode(message));
+
+        let ack = vec![vec![0x90, 0x41, 0xff], vec![0x90, 0x51, 0xff]];
+        let speed = |direction: u8, speed: u8, positive: u8, negative: u8| -> f64 {
+            let speed = speed as f64 * VISCA_UNITS_PER_SPEED;
+            match direction {
+                d if d == positive => speed,
+                d if d == negative => -speed,
+                _ => 0.0,
+            }
+        };
+        let variable = |byte: u8| -> f64 {
+            let speed = ((byte & 0x0f) + 1) as f64 * VISCA_UNITS_PER_SPEED * 8.0;
+            match byte >> 4 {
+                2 => speed,
+                3 => -speed,
+                _ => 0.0,
+            }
+        };
+        match message {
+            // Pan-tilt drive
+            [0x81, 0x01, 0x06, 0x01, vv, ww, x, y, 0xff] => {
+                self.pan.speed = speed(*x, *vv, 0x02, 0x01);
+                self.tilt.speed = speed(*y, *ww, 0x01, 0x02);
+                ack
+            }
+            // Home
+            [0x81, 0x01, 0x06, 0x04, 0xff] => {
+                self.pan = Axis::default();
+                self.tilt = Axis::default();
+                ack
+            }
+            // Zoom stop, tele, wide, and their variable speed versions
+            [0x81, 0x01, 0x04, 0x07, p, 0xff] => {
+                self.zoom.speed = match p {
+                    0x02 => variable(0x23),
+                    0x03 => variable(0x33),
+                    p => variable(*p),
+                };
+                ack
+            }
+            // Focus and one push autofocus, which there's nothing to show for
+            [0x81, 0x01, 0x04, 0x08, _, 0xff] | [0x81, 0x01, 0x04, 0x18, 0x01, 0xff] => ack,
+            // Preset set and recall
+            [0x81, 0x01, 0x04, 0x3f, action @ (0x01 | 0x02), slot, 0xff] if *slot < 16 => {
+                let preset = &mut self.presets[*slot as usize];
+                if *action == 0x01 {
+                    *preset = (self.pan.position, self.tilt.position, self.zoom.position);
+                } else {
+                    (self.pan.position, self.tilt.position, self.zoom.position) = *preset;
+                    self.pan.speed = 0.0;
+                    self.tilt.speed = 0.0;
+                    self.zoom.speed = 0.0;
+                }
+                ack
+            }
+            // Pan-tilt position inquiry
+            [0x81, 0x09, 0x06, 0x12, 0xff] => {
+                let mut reply = vec![0x90, 0x50];
+                reply.extend(nibbles(self.pan.position as i16 as u16));
+                reply.extend(nibbles(self.tilt.position as i16 as u16));
+                reply.push(0xff);
+                vec![reply]
+            }
+            // Zoom position inquiry
+            [0x81, 0x09, 0x04, 0x47, 0xff] => {
+                let mut reply = vec![0x90, 0x50];
+                reply.extend(nibbles(self.zoom.position as u16));
+                reply.push(0xff);
+                vec![reply]
+            }
+            // Version inquiry
+            [0x81, 0x09, 0x00, 0x02, 0xff] => {
+                vec![vec![
+                    0x90, 0x50, 0x00, 0x20, 0x05, 0x19, 0x01, 0x00, 0x02, 0xff,
+                ]]
+            }
+            _ => vec![vec![0x90, 0x60, 0x02, 0xff]],
+        }
+    }
+}
+
+/// Splits a value into the four bytes of one nibble each that VISCA uses for positions.
+fn nibbles(value: u16) -> [u8; 4] {
+    [12, 8, 4, 0].map(|shift| ((value >> shift) & 0x0f) as u8)
+}
+
+#[test]
+fn test_simulators() {
+    use std::time::Duration;
+
+    let mut session = PtpSession::default();
+    let (reply, _) = session.handle(PTPIP_INIT_COMMAND_REQUEST, &[]);
+    assert_eq!(&reply[4..8], PTPIP_INIT_COMMAND_ACK.to_le_bytes());
+    // Open session, which has no data phase
+    let open_session = hex::decode("010000000210000000000100010000000000").unwrap();
+    let (reply, event) = session.handle(PTPIP_OPERATION_REQUEST, &open_session);
+    assert_eq!(hex::encode(reply), "0e00000007000000012000000000");
+    assert!(event.is_none());
+    // Zoom start, which is only answered once its data arrives
+    let start_zoom = hex::decode("02000000169407000000810000030000").unwrap();
+    let (reply, _) = session.handle(PTPIP_OPERATION_REQUEST, &start_zoom);
+    assert!(reply.is_empty());
+    let (reply, event) = session.handle(PTPIP_END_DATA, &[7, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(hex::encode(reply), "0e00000007000000012007000000");
+    assert!(event.is_some());
+    // Storage IDs come in a data phase ahead of the response
+    let storage_ids = hex::decode("010000000410080000000000").unwrap();
+    let (reply, _) = session.handle(PTPIP_OPERATION_REQUEST, &storage_ids);
+    assert_eq!(
+        hex::encode(reply),
+        "1400000009000000080000000c00000000000000\
+         180000000c00000008000000020000000100010000000200\
+         0e00000007000000012008000000"
+    );
+
+    let start = Instant::now();
+    let mut camera = ViscaCamera::new(start);
+    let packet = |payload_type: u16, sequence: u32, message: &[u8]| {
+        let mut packet = payload_type.to_be_bytes().to_vec();
+        packet.extend((message.len() as u16).to_be_bytes());
+        packet.extend(sequence.to_be_bytes());
+        packet.extend(message);
+        packet
+    };
+    // Pan right at speed 5 for a second
+    let replies = camera.handle_packet(
+        &packet(
+            VISCA_COMMAND,
+            7,
+            &[0x81, 0x01, 0x06, 0x01, 5, 5, 0x02, 0x03, 0xff],
+        ),
+        start,
+    );
+    assert_eq!(
+        replies,
+        vec![
+            packet(VISCA_REPLY, 7, &[0x90, 0x41, 0xff]),
+            packet(VISCA_REPLY, 7, &[0x90, 0x51, 0xff]),
+        ]
+    );
+    let replies = camera.handle_packet(
+        &packet(VISCA_INQUIRY, 8, &[0x81, 0x09, 0x06, 0x12, 0xff]),
+        start + Duration::from_secs(1),
+    );
+    assert_eq!(
+        replies,
+        vec![packet(
+            VISCA_REPLY,
+            8,
+            &[0x90, 0x50, 0x0, 0x0, 0xc, 0x8, 0x0, 0x0, 0x0, 0x0, 0xff]
+        )]
+    );
+    assert_eq!(
+        camera.handle(&[0x81, 0x01, 0x7e, 0xff], start),
+        vec![vec![0x90, 0x60, 0x02, 0xff]]
+    );
+    assert_eq!(
+        camera.handle_packet(&packet(VISCA_CONTROL, 0, &[0x01]), start),
+        vec![packet(VISCA_CONTROL_REPLY, 0, &[0x01])]
+    );
+}
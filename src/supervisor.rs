@@ -0,0 +1,86 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+struct Retry {
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+/// Tracks which devices have dropped their connection and when the next reconnect attempt for
+/// each of them is due. Devices the user disconnected on purpose are left alone until they're
+/// connected again.
+#[derive(Default)]
+pub struct Supervisor {
+    retries: HashMap<String, Retry>,
+    paused: HashSet<String>,
+}
+
+impl Supervisor {
+    /// Stops reconnecting to a device, e.g. because the user disconnected it.
+    pub fn pause(&mut self, id: &str) {
+        self.paused.insert(id.to_owned());
+        self.retries.remove(id);
+    }
+
+    /// Starts watching a device again after the user has connected it.
+    pub fn resume(&mut self, id: &str) {
+        self.paused.remove(id);
+        self.retries.remove(id);
+    }
+
+    pub fn is_reconnecting(&self, id: &str) -> bool {
+        self.retries.contains_key(id)
+    }
+
+    /// Records that a device has lost its connection, and returns whether a reconnect should be
+    /// attempted right now.
+    pub fn should_reconnect(&mut self, id: &str, now: Instant) -> bool {
+        if self.paused.contains(id) {
+            return false;
+        }
+        let retry = self.retries.entry(id.to_owned()).or_insert(Retry {
+            backoff: INITIAL_BACKOFF,
+            next_attempt: now,
+        });
+        retry.next_attempt <= now
+    }
+
+    pub fn reconnect_failed(&mut self, id: &str, now: Instant) {
+        if let Some(retry) = self.retries.get_mut(id) {
+            retry.next_attempt = now + retry.backoff;
+            retry.backoff = (retry.backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+
+    pub fn reconnected(&mut self, id: &str) {
+        self.retries.remove(id);
+    }
+}
+
+#[test]
+fn test_supervisor_backoff() {
+    let mut supervisor = Supervisor::default();
+    let start = Instant::now();
+
+    assert!(supervisor.should_reconnect("a", start));
+    assert!(supervisor.is_reconnecting("a"));
+    supervisor.reconnect_failed("a", start);
+    assert!(!supervisor.should_reconnect("a", start + Duration::from_millis(500)));
+    assert!(supervisor.should_reconnect("a", start + Duration::from_secs(1)));
+    supervisor.reconnect_failed("a", start + Duration::from_secs(1));
+    assert!(!supervisor.should_reconnect("a", start + Duration::from_secs(2)));
+    assert!(supervisor.should_reconnect("a", start + Duration::from_secs(3)));
+    supervisor.reconnected("a");
+    assert!(!supervisor.is_reconnecting("a"));
+
+    supervisor.pause("b");
+    assert!(!supervisor.should_reconnect("b", start));
+    assert!(!supervisor.is_reconnecting("b"));
+    supervisor.resume("b");
+    assert!(supervisor.should_reconnect("b", start));
+}
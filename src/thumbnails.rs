@@ -0,0 +1,106 @@
+//! Small preview images from each camera, refreshed periodically so the UI can show what each
+//! device is looking at next to its controls.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+
+use crate::{access::Visibility, auth::Client};
+
+#[derive(Clone, Default)]
+pub struct Thumbnails(Arc<RwLock<HashMap<String, Thumbnail>>>);
+
+struct Thumbnail {
+    jpeg: Vec<u8>,
+    /// Bumped with every update, so that the URL in the state changes and browsers refetch it.
+    version: u64,
+}
+
+impl Thumbnails {
+    pub fn update(&self, id: &str, jpeg: Vec<u8>) {
+        let mut thumbnails = self.0.write().unwrap();
+        let version = thumbnails.get(id).map_or(0, |t| t.version + 1);
+        thumbnails.insert(id.to_owned(), Thumbnail { jpeg, version });
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        self.0.write().unwrap().remove(id).is_some()
+    }
+
+    /// Where the device's latest thumbnail is served from, if it has one.
+    pub fn url(&self, id: &str) -> Option<String> {
+        self.0
+            .read()
+            .unwrap()
+            .get(id)
+            .map(|t| format!("/thumbs/{}.jpg?v={}", id, t.version))
+    }
+
+    fn get(&self, id: &str) -> Option<Vec<u8>> {
+        self.0.read().unwrap().get(id).map(|t| t.jpeg.clone())
+    }
+}
+
+pub fn router(thumbnails: Thumbnails, visibility: Arc<Visibility>) -> Router {
+    Router::new().route(
+        "/thumbs/:file",
+        get(move |file, client| thumbnail(thumbnails, visibility, file, client)),
+    )
+}
+
+async fn thumbnail(
+    thumbnails: Thumbnails,
+    visibility: Arc<Visibility>,
+    Path(file): Path<String>,
+    client: Option<Extension<Client>>,
+) -> Response {
+    let source = Client::source(client);
+    let jpeg = file
+        .strip_suffix(".jpg")
+        .filter(|id| visibility.shows(&source, id))
+        .and_then(|id| thumbnails.get(id));
+    match jpeg {
+        Some(jpeg) => ([(header::CONTENT_TYPE, "image/jpeg")], jpeg).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Cuts a JPEG out of a buffer that has other data around it, as live view frames often do.
+#[cfg_attr(not(feature = "lumix"), allow(dead_code))]
+pub fn extract_jpeg(data: &[u8]) -> Option<&[u8]> {
+    let start = data.windows(2).position(|w| w == [0xff, 0xd8])?;
+    let end = data.windows(2).rposition(|w| w == [0xff, 0xd9])? + 2;
+    (end > start).then(|| &data[start..end])
+}
+
+#[test]
+fn test_thumbnails() {
+    let thumbnails = Thumbnails::default();
+    assert_eq!(thumbnails.url("cam1"), None);
+    thumbnails.update("cam1", vec![1]);
+    assert_eq!(
+        thumbnails.url("cam1").as_deref(),
+        Some("/thumbs/cam1.jpg?v=0")
+    );
+    thumbnails.update("cam1", vec![2]);
+    assert_eq!(
+        thumbnails.url("cam1").as_deref(),
+        Some("/thumbs/cam1.jpg?v=1")
+    );
+    assert_eq!(thumbnails.get("cam1"), Some(vec![2]));
+    assert!(thumbnails.remove("cam1"));
+    assert_eq!(thumbnails.url("cam1"), None);
+
+    let frame = [0x00, 0x01, 0xff, 0xd8, 0x10, 0xff, 0xd9, 0x00];
+    assert_eq!(extract_jpeg(&frame), Some(&frame[2..7]));
+    assert_eq!(extract_jpeg(&[0xff, 0xd9, 0xff, 0xd8]), None);
+}
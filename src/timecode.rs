@@ -0,0 +1,265 @@
+use std::{fmt, path::PathBuf, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncReadExt as _, sync::mpsc};
+use tracing::{info, warn};
+
+use crate::config::TimecodeCue;
+
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+}
+
+impl Timecode {
+    fn whole_seconds(&self) -> u32 {
+        (self.hours as u32 * 60 + self.minutes as u32) * 60 + self.seconds as u32
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}:{:02}",
+            self.hours, self.minutes, self.seconds, self.frames
+        )
+    }
+}
+
+impl FromStr for Timecode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .split([':', ';'])
+            .map(|p| p.parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid timecode {:?}: {}", s, e))?;
+        let [hours, minutes, seconds, frames] = parts[..] else {
+            return Err(format!("invalid timecode {:?}: expected HH:MM:SS:FF", s));
+        };
+        if hours > 23 || minutes > 59 || seconds > 59 || frames > 29 {
+            return Err(format!("invalid timecode {:?}: out of range", s));
+        }
+        Ok(Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+        })
+    }
+}
+
+impl TryFrom<String> for Timecode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Timecode> for String {
+    fn from(value: Timecode) -> Self {
+        value.to_string()
+    }
+}
+
+/// Reassembles MIDI timecode from quarter-frame and full-frame messages.
+#[derive(Default)]
+pub struct MtcDecoder {
+    pieces: [u8; 8],
+    received: u8,
+    expecting_quarter_frame: bool,
+    sysex: Option<Vec<u8>>,
+}
+
+impl MtcDecoder {
+    pub fn feed(&mut self, byte: u8) -> Option<Timecode> {
+        if let Some(sysex) = &mut self.sysex {
+            if byte == 0xf7 {
+                let message = self.sysex.take().unwrap();
+                return parse_full_frame(&message);
+            }
+            if byte & 0x80 == 0 {
+                sysex.push(byte);
+                return None;
+            }
+            // Any other status byte aborts the SysEx message
+            self.sysex = None;
+        }
+
+        if byte == 0xf0 {
+            self.sysex = Some(vec![]);
+            self.expecting_quarter_frame = false;
+            return None;
+        }
+        if byte == 0xf1 {
+            self.expecting_quarter_frame = true;
+            return None;
+        }
+        if byte & 0x80 != 0 {
+            // Realtime messages may be interleaved anywhere without affecting the running state
+            if byte < 0xf8 {
+                self.expecting_quarter_frame = false;
+            }
+            return None;
+        }
+        if !self.expecting_quarter_frame {
+            return None;
+        }
+        self.expecting_quarter_frame = false;
+
+        let index = (byte >> 4) as usize;
+        self.pieces[index] = byte & 0x0f;
+        self.received |= 1 << index;
+        // A full timecode is only known once the last piece of a complete sequence arrives. By
+        // then it's two frames old, which gets compensated for here.
+        if index != 7 || self.received != 0xff {
+            return None;
+        }
+        self.received = 0;
+        let p = &self.pieces;
+        let fps = match (p[7] >> 1) & 0x03 {
+            0 => 24,
+            1 => 25,
+            _ => 30,
+        };
+        Some(advance(
+            Timecode {
+                hours: p[6] | ((p[7] & 0x01) << 4),
+                minutes: p[4] | (p[5] << 4),
+                seconds: p[2] | (p[3] << 4),
+                frames: p[0] | (p[1] << 4),
+            },
+            2,
+            fps,
+        ))
+    }
+}
+
+fn parse_full_frame(message: &[u8]) -> Option<Timecode> {
+    match message {
+        [0x7f, _, 0x01, 0x01, hr, mn, sc, fr] => Some(Timecode {
+            hours: hr & 0x1f,
+            minutes: *mn,
+            seconds: *sc,
+            frames: *fr,
+        }),
+        _ => None,
+    }
+}
+
+fn advance(timecode: Timecode, frames: u8, fps: u8) -> Timecode {
+    let total = timecode.whole_seconds() * fps as u32 + (timecode.frames + frames) as u32;
+    let seconds = total / fps as u32;
+    Timecode {
+        hours: ((seconds / 3600) % 24) as u8,
+        minutes: ((seconds / 60) % 60) as u8,
+        seconds: (seconds % 60) as u8,
+        frames: (total % fps as u32) as u8,
+    }
+}
+
+/// Reads MIDI timecode from a raw MIDI device (e.g. `/dev/snd/midiC1D0`), reopening it if it
+/// goes away.
+pub async fn listen(device: PathBuf, timecodes: mpsc::UnboundedSender<Timecode>) {
+    loop {
+        let mut file = match tokio::fs::File::open(&device).await {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Couldn't open MIDI device {}: {}", device.display(), e);
+                tokio::time::sleep(REOPEN_DELAY).await;
+                continue;
+            }
+        };
+        info!("Listening for MIDI timecode on {}", device.display());
+        let mut decoder = MtcDecoder::default();
+        let mut buf = [0u8; 64];
+        loop {
+            let n = match file.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("Error reading MIDI device {}: {}", device.display(), e);
+                    break;
+                }
+            };
+            for timecode in buf[..n].iter().filter_map(|b| decoder.feed(*b)) {
+                if timecodes.send(timecode).is_err() {
+                    return;
+                }
+            }
+        }
+        tokio::time::sleep(REOPEN_DELAY).await;
+    }
+}
+
+/// Cues that were crossed while moving from `last` to `current` during normal playback. Nothing
+/// fires while stopped, after rewinding, or after jumping ahead to a new location.
+pub fn due_cues(
+    cues: &[TimecodeCue],
+    last: Option<Timecode>,
+    current: Timecode,
+) -> impl Iterator<Item = &TimecodeCue> {
+    let playing =
+        last.filter(|last| *last < current && current.whole_seconds() <= last.whole_seconds() + 1);
+    cues.iter()
+        .filter(move |cue| playing.is_some_and(|last| last < cue.at && cue.at <= current))
+}
+
+#[test]
+fn test_mtc_decoder() {
+    let mut decoder = MtcDecoder::default();
+    // 01:02:03:04 at 25fps, sent as quarter frames (with a clock byte in the middle)
+    let pieces = [0x04, 0x10, 0x23, 0x30, 0x42, 0x50, 0x61, 0x72];
+    let mut decoded = vec![];
+    for (i, piece) in pieces.iter().enumerate() {
+        if i == 4 {
+            decoded.extend(decoder.feed(0xf8));
+        }
+        decoded.extend(decoder.feed(0xf1));
+        decoded.extend(decoder.feed(*piece));
+    }
+    assert_eq!(decoded, vec!["01:02:03:06".parse().unwrap()]);
+
+    let full_frame = [0xf0, 0x7f, 0x7f, 0x01, 0x01, 0x21, 0x3b, 0x3b, 0x18, 0xf7];
+    let decoded = full_frame
+        .iter()
+        .filter_map(|b| decoder.feed(*b))
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, vec!["01:59:59:24".parse().unwrap()]);
+}
+
+#[test]
+fn test_due_cues() {
+    let cue = |at: &str| TimecodeCue {
+        at: at.parse().unwrap(),
+        request: serde_json::Value::Null,
+    };
+    let cues = [cue("00:00:10:00"), cue("00:00:10:05")];
+    let tc = |s: &str| s.parse::<Timecode>().unwrap();
+    let due = |last: Option<&str>, current: &str| {
+        due_cues(&cues, last.map(tc), tc(current))
+            .map(|c| c.at.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(due(None, "00:00:10:00"), Vec::<String>::new());
+    assert_eq!(due(Some("00:00:09:24"), "00:00:10:00"), vec!["00:00:10:00"]);
+    assert_eq!(due(Some("00:00:10:00"), "00:00:10:05"), vec!["00:00:10:05"]);
+    assert_eq!(
+        due(Some("00:00:10:05"), "00:00:10:00"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        due(Some("00:00:01:00"), "00:00:10:05"),
+        Vec::<String>::new()
+    );
+}
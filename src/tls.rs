@@ -0,0 +1,132 @@
+use std::{error::Error, future::Future, net::SocketAddr, path::Path};
+
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use tokio::net::TcpListener;
+use tokio_native_tls::TlsAcceptor;
+use tracing::{info, warn};
+
+use crate::config::TlsConfig;
+
+/// Loads the configured certificate, generating a self-signed one first if allowed.
+pub fn acceptor(config: &TlsConfig) -> Result<TlsAcceptor, Box<dyn Error>> {
+    if config.self_signed && !config.cert.exists() && !config.key.exists() {
+        generate_self_signed(&config.cert, &config.key)?;
+        info!(
+            "Generated self-signed certificate {}",
+            config.cert.display()
+        );
+    }
+    let cert = std::fs::read(&config.cert)
+        .map_err(|e| format!("couldn't read {}: {}", config.cert.display(), e))?;
+    let key = std::fs::read(&config.key)
+        .map_err(|e| format!("couldn't read {}: {}", config.key.display(), e))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)?;
+    Ok(TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?))
+}
+
+/// Serves `app` over TLS until `shutdown` completes. Equivalent to `axum::serve`, which doesn't
+/// support TLS itself.
+pub async fn serve(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) {
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, addr) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!("Error accepting connection: {}", e);
+                    continue;
+                }
+            },
+            _ = &mut shutdown => return,
+        };
+        let acceptor = acceptor.clone();
+        let service = app
+            .clone()
+            .layer(Extension(ConnectInfo::<SocketAddr>(addr)));
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            let result = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(service))
+                .with_upgrades()
+                .await;
+            if let Err(e) = result {
+                warn!("Error serving {}: {}", addr, e);
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn generate_self_signed(cert_path: &Path, key_path: &Path) -> Result<(), Box<dyn Error>> {
+    use openssl::{
+        asn1::Asn1Time,
+        bn::{BigNum, MsbOption},
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        x509::{extension::SubjectAlternativeName, X509Builder, X509NameBuilder},
+    };
+
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    let key = PKey::from_ec_key(EcKey::generate(&group)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", "webptz")?;
+    let name = name.build();
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(3650)?;
+
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    let san = SubjectAlternativeName::new()
+        .dns("localhost")
+        .ip("127.0.0.1")
+        .build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(san)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+
+    std::fs::write(cert_path, builder.build().to_pem()?)?;
+    std::fs::write(key_path, key.private_key_to_pem_pkcs8()?)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn generate_self_signed(_cert_path: &Path, _key_path: &Path) -> Result<(), Box<dyn Error>> {
+    Err("self-signed certificates can only be generated on Linux".into())
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_self_signed_certificate() {
+    let dir = std::env::temp_dir().join(format!("webptz-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = TlsConfig {
+        cert: dir.join("cert.pem"),
+        key: dir.join("key.pem"),
+        self_signed: true,
+    };
+    let result = acceptor(&config);
+    std::fs::remove_dir_all(&dir).unwrap();
+    result.unwrap();
+}